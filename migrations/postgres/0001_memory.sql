@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS memory (
+    id BIGSERIAL PRIMARY KEY,
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS memory_scope_nick ON memory (scope, nick, id);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS ignores (
+    scope TEXT NOT NULL,
+    mask TEXT NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT,
+    PRIMARY KEY (scope, mask)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS usage (
+    scope TEXT NOT NULL,
+    day TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, day, nick, channel)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS recall (
+    id BIGSERIAL PRIMARY KEY,
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    content TEXT NOT NULL,
+    -- Little endian f32s
+    embedding BYTEA NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS recall_scope_nick ON recall (scope, nick, id);
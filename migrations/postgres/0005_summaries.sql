@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS summaries (
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    content TEXT NOT NULL,
+    updated_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT,
+    PRIMARY KEY (scope, nick)
+);
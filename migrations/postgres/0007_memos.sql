@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS memos (
+    id BIGSERIAL PRIMARY KEY,
+    scope TEXT NOT NULL,
+    -- Lowercased recipient, since nicks are case insensitive
+    key TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    at BIGINT NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS memos_scope_key ON memos (scope, key);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS reminders (
+    id BIGSERIAL PRIMARY KEY,
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    -- The channel to remind them in, or their nick
+    target TEXT NOT NULL,
+    due BIGINT NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS reminders_scope ON reminders (scope, due);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS karma (
+    scope TEXT NOT NULL,
+    -- Lowercased name, so nick++ and NICK++ count the same
+    key TEXT NOT NULL,
+    name TEXT NOT NULL,
+    score BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, key)
+);
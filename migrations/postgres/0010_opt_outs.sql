@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS opt_outs (
+    scope TEXT NOT NULL,
+    -- As remembered, see memory::identity()
+    nick TEXT NOT NULL,
+    created_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT,
+    PRIMARY KEY (scope, nick)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS locations (
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    location TEXT NOT NULL,
+    PRIMARY KEY (scope, nick)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS channel_log (
+    id BIGSERIAL PRIMARY KEY,
+    scope TEXT NOT NULL,
+    -- Folded the way the network compares channel names
+    channel TEXT NOT NULL,
+    at BIGINT NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS channel_log_scope_channel ON channel_log (scope, channel, id);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS ignores (
+    scope TEXT NOT NULL,
+    mask TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    PRIMARY KEY (scope, mask)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS usage (
+    scope TEXT NOT NULL,
+    day TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, day, nick, channel)
+);
@@ -0,0 +1 @@
+ALTER TABLE usage ADD COLUMN requests INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS recall (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    content TEXT NOT NULL,
+    -- Little endian f32s
+    embedding BLOB NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+CREATE INDEX IF NOT EXISTS recall_scope_nick ON recall (scope, nick, id);
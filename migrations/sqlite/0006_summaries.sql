@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS summaries (
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    content TEXT NOT NULL,
+    updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    PRIMARY KEY (scope, nick)
+);
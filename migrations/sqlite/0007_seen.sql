@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS seen (
+    scope TEXT NOT NULL,
+    -- Lowercased nick, since nicks are case insensitive
+    key TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    -- NULL for quits
+    channel TEXT,
+    activity TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (scope, key)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS reminders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scope TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    -- The channel to remind them in, or their nick
+    target TEXT NOT NULL,
+    due INTEGER NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS reminders_scope ON reminders (scope, due);
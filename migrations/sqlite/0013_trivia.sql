@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS trivia_scores (
+    scope TEXT NOT NULL,
+    -- Lowercased nick, so points follow people whichever way they write it
+    key TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, key)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS word_game_scores (
+    scope TEXT NOT NULL,
+    -- wordle or hangman
+    game TEXT NOT NULL,
+    -- Lowercased nick, so points follow people whichever way they write it
+    key TEXT NOT NULL,
+    nick TEXT NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, game, key)
+);
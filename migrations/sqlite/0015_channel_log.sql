@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS channel_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scope TEXT NOT NULL,
+    -- Folded the way the network compares channel names
+    channel TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    content TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS channel_log_scope_channel ON channel_log (scope, channel, id);
@@ -0,0 +1,76 @@
+use irc::client::prelude::*;
+
+use crate::config::AclConfig;
+use crate::tags::Tags;
+
+const ACCOUNT_PREFIX: &str = "account:";
+
+/// What a user is allowed to do, from least to most trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    Anyone,
+    Trusted,
+    Admin,
+    Owner,
+}
+
+/// Works out the highest privilege the sender of `message` has. Accounts come from the IRCv3
+/// `account` tag, so they only match on servers that support `account-tag`.
+pub fn privilege(acl: &AclConfig, message: &Message, tags: &Tags) -> Privilege {
+    let Some(Prefix::Nickname(nick, user, host)) = &message.prefix else {
+        return Privilege::Anyone;
+    };
+    let hostmask = format!("{}!{}@{}", nick, user, host);
+    let account = tags.account();
+
+    let matches = |entries: &[String]| {
+        entries
+            .iter()
+            .any(|entry| match entry.strip_prefix(ACCOUNT_PREFIX) {
+                Some(name) => account.is_some_and(|account| account.eq_ignore_ascii_case(name)),
+                None => wildcard_match(entry, &hostmask),
+            })
+    };
+
+    if matches(&acl.owners) {
+        Privilege::Owner
+    } else if matches(&acl.admins) {
+        Privilege::Admin
+    } else if matches(&acl.trusted) {
+        Privilege::Trusted
+    } else {
+        Privilege::Anyone
+    }
+}
+
+/// Case insensitive glob match where `*` is any run of characters and `?` is any one.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume from if the most recent `*` needs to swallow another character
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,333 @@
+//! The admin API: sending messages, looking at and wiping memory, usage, and personas, for
+//! dashboards and scripts rather than people on IRC.
+
+use axum::extract::Path;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::delete;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+
+use chrono::Days;
+use chrono::Utc;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use crate::config::Config;
+use crate::export::Conversation;
+use crate::health::health;
+use crate::irc_bot::NetworkState;
+use crate::llm::ChatBackend;
+use crate::memory::Memory;
+use crate::output::queue;
+use crate::output::Outgoing;
+use crate::usage::Spent;
+
+static ADMIN: LazyLock<Admin> = LazyLock::new(Admin::default);
+
+/// How many nicks `/usage` lists.
+const TOP_NICKS: usize = 10;
+
+/// How far back `/usage` adds up.
+const USAGE_DAYS: u64 = 30;
+
+/// Every network, as its connection is started, for the admin API and webhooks to get at.
+#[derive(Default)]
+pub struct Admin {
+    networks: Mutex<BTreeMap<String, Network>>,
+}
+
+#[derive(Clone)]
+pub struct Network {
+    pub config: Arc<Config>,
+    pub backend: Arc<dyn ChatBackend>,
+    pub state: Arc<NetworkState>,
+    pub memory: Arc<Memory>,
+    /// Lines to send, which wait until the network is connected.
+    pub outgoing: mpsc::UnboundedSender<Outgoing>,
+}
+
+pub fn admin() -> &'static Admin {
+    &ADMIN
+}
+
+impl Admin {
+    pub fn register(&self, name: &str, network: Network) {
+        self.networks
+            .lock()
+            .expect("admin lock poisoned")
+            .insert(name.to_string(), network);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Network> {
+        self.networks
+            .lock()
+            .expect("admin lock poisoned")
+            .get(name)
+            .cloned()
+    }
+
+    fn network(&self, name: &str) -> Result<Network, ApiError> {
+        self.get(name)
+            .ok_or_else(|| ApiError::not_found(format!("no network called {}", name)))
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.networks
+            .lock()
+            .expect("admin lock poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Why a request failed, sent back as `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn not_found(message: String) -> Self {
+        Self(StatusCode::NOT_FOUND, message)
+    }
+
+    fn bad_request(message: String) -> Self {
+        Self(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// The admin API, for requests with `token` as their bearer token.
+pub fn routes(token: &str) -> Router {
+    Router::new()
+        .route("/admin/networks", get(networks))
+        .route("/admin/networks/:network/messages", post(send))
+        .route("/admin/networks/:network/memory", delete(forget_all))
+        .route(
+            "/admin/networks/:network/memory/:identity",
+            get(remembered).delete(forget),
+        )
+        .route("/admin/networks/:network/usage", get(usage))
+        .route(
+            "/admin/networks/:network/personas",
+            get(personas).put(set_persona),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(token.to_string()),
+            authorize,
+        ))
+}
+
+async fn authorize(
+    State(token): State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer {
+        Some(bearer) if same(bearer.as_bytes(), token.as_bytes()) => Ok(next.run(request).await),
+        _ => Err(ApiError(
+            StatusCode::UNAUTHORIZED,
+            String::from("missing or wrong bearer token"),
+        )),
+    }
+}
+
+/// Compares without giving away how much of the token was right by how long it took.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn networks() -> Json<serde_json::Value> {
+    Json(json!({ "networks": admin().names() }))
+}
+
+#[derive(Deserialize)]
+struct Message {
+    /// A channel we're in.
+    target: String,
+    /// Sent a line at a time, exactly as it is.
+    text: String,
+}
+
+async fn send(
+    Path(name): Path<String>,
+    Json(message): Json<Message>,
+) -> Result<StatusCode, ApiError> {
+    let network = admin().network(&name)?;
+    if network.state.channels.get(&message.target).is_none() {
+        return Err(ApiError::bad_request(format!(
+            "not in {} on {}",
+            message.target, name
+        )));
+    }
+    if !health()
+        .report()
+        .networks
+        .get(&name)
+        .is_some_and(|network| network.connected)
+    {
+        return Err(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("not connected to {}", name),
+        ));
+    }
+
+    info!(
+        "Saying something in {} on {} for the admin API",
+        message.target, name
+    );
+    // A lone \r ends a line on IRC too, so it mustn't get through to start another command
+    for line in message
+        .text
+        .split(['\r', '\n'])
+        .filter(|line| !line.trim().is_empty())
+    {
+        queue(&network.outgoing, &message.target, line.to_string());
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Memory is kept by folded nick, or by lowercased account for `account:<name>`.
+fn identity(network: &Network, identity: &str) -> String {
+    match identity.starts_with("account:") {
+        true => identity.to_lowercase(),
+        false => network.state.channels.casemapping().fold(identity),
+    }
+}
+
+async fn remembered(
+    Path((name, who)): Path<(String, String)>,
+) -> Result<Json<Conversation>, ApiError> {
+    let network = admin().network(&name)?;
+    network
+        .memory
+        .export(&identity(&network, &who))
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("nothing remembered about {}", who)))
+}
+
+async fn forget(Path((name, who)): Path<(String, String)>) -> Result<StatusCode, ApiError> {
+    let network = admin().network(&name)?;
+    if !network.memory.forget(&identity(&network, &who)).await {
+        return Err(ApiError::not_found(format!(
+            "nothing remembered about {}",
+            who
+        )));
+    }
+
+    info!("Forgot {} for the admin API", who);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn forget_all(Path(name): Path<String>) -> Result<Json<serde_json::Value>, ApiError> {
+    let network = admin().network(&name)?;
+    let forgotten = network.memory.forget_all().await;
+
+    info!("Forgot {} conversations for the admin API", forgotten);
+    Ok(Json(json!({ "forgotten": forgotten })))
+}
+
+#[derive(Serialize)]
+struct Totals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+impl From<Spent> for Totals {
+    fn from(spent: Spent) -> Self {
+        Self {
+            requests: spent.requests,
+            prompt_tokens: spent.usage.prompt_tokens,
+            completion_tokens: spent.usage.completion_tokens,
+        }
+    }
+}
+
+/// What was spent today and over the last 30 days, and by whom.
+async fn usage(Path(name): Path<String>) -> Result<Json<serde_json::Value>, ApiError> {
+    let network = admin().network(&name)?;
+    let ledger = &network.state.ledger;
+    let today = Utc::now().date_naive();
+    let since = today - Days::new(USAGE_DAYS - 1);
+    let top = ledger
+        .top(since, TOP_NICKS)
+        .into_iter()
+        .map(|(nick, spent)| json!({ "nick": nick, "spent": Totals::from(spent) }))
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "today": Totals::from(ledger.spent(today, |_, _| true)),
+        "last_30_days": Totals::from(ledger.spent(since, |_, _| true)),
+        "top_nicks": top,
+    })))
+}
+
+async fn personas(Path(name): Path<String>) -> Result<Json<serde_json::Value>, ApiError> {
+    let network = admin().network(&name)?;
+    let personas = &network.state.personas;
+
+    Ok(Json(json!({
+        "personas": personas.names(),
+        "active": personas.active(),
+    })))
+}
+
+#[derive(Deserialize)]
+struct Switch {
+    /// A channel or a nick.
+    target: String,
+    /// Back to the default without one.
+    persona: Option<String>,
+}
+
+async fn set_persona(
+    Path(name): Path<String>,
+    Json(switch): Json<Switch>,
+) -> Result<StatusCode, ApiError> {
+    let network = admin().network(&name)?;
+    let personas = &network.state.personas;
+    if let Some(persona) = switch.persona.as_deref().filter(|&p| !personas.exists(p)) {
+        return Err(ApiError::bad_request(format!(
+            "no persona called {}",
+            persona
+        )));
+    }
+
+    info!(
+        "Switched {} on {} to {} for the admin API",
+        switch.target,
+        name,
+        switch.persona.as_deref().unwrap_or("the default persona")
+    );
+    personas.set(&switch.target, switch.persona.as_deref());
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,92 @@
+use rand::Rng;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::channels::Line;
+use crate::config::AmbientConfig;
+
+/// What the model says when it has nothing to add.
+pub const PASS: &str = "PASS";
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Decides when pickles joins in on a conversation nobody asked it into: rarely, never twice
+/// in quick succession, and not at all where it's been told to hush. Channels are expected
+/// folded by the network's casemapping.
+pub struct Ambient {
+    pub config: AmbientConfig,
+    /// Channels told to hush with `!ambient off`.
+    hushed: Mutex<HashSet<String>>,
+    /// When we joined in lately, by channel.
+    interjections: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Ambient {
+    pub fn new(config: &AmbientConfig) -> Self {
+        Self {
+            config: config.clone(),
+            hushed: Mutex::new(HashSet::new()),
+            interjections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether to join in after the latest message in `channel`. Counts as joining in from
+    /// now on if so.
+    pub fn should_interject(&self, channel: &str) -> bool {
+        if self.is_hushed(channel) {
+            return false;
+        }
+        if !rand::thread_rng().gen_bool(self.config.probability.clamp(0.0, 1.0)) {
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut interjections = self.interjections.lock().expect("ambient lock poisoned");
+        let recent = interjections.entry(channel.to_string()).or_default();
+        while recent.front().is_some_and(|at| now - *at >= HOUR) {
+            recent.pop_front();
+        }
+        let min_interval = Duration::from_secs(self.config.min_interval_secs);
+        if recent.back().is_some_and(|at| now - *at < min_interval)
+            || recent.len() >= self.config.max_per_hour
+        {
+            debug!("Not joining in in {}, did so too recently", channel);
+            return false;
+        }
+        recent.push_back(now);
+
+        true
+    }
+
+    pub fn is_hushed(&self, channel: &str) -> bool {
+        self.hushed
+            .lock()
+            .expect("ambient lock poisoned")
+            .contains(channel)
+    }
+
+    /// Stops or lets pickles join in in `channel`.
+    pub fn hush(&self, channel: &str, hushed: bool) {
+        let mut hushed_channels = self.hushed.lock().expect("ambient lock poisoned");
+        match hushed {
+            true => hushed_channels.insert(channel.to_string()),
+            false => hushed_channels.remove(channel),
+        };
+    }
+}
+
+/// `lines` the way the model reads them.
+pub fn transcript(lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("<{}> {}", line.nick, line.text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,345 @@
+//! A log of everything said and done in the channels pickles is in, kept for operators to
+//! read and for `--train-markov` and `!tldr` to draw on.
+
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+use chrono::Utc;
+
+use irc::client::prelude::*;
+
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use tracing::*;
+
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::casemap::CaseMapping;
+use crate::channels::Channels;
+use crate::config::ArchiveConfig;
+use crate::config::ArchiveTarget;
+use crate::config::Rotation;
+use crate::ctcp;
+use crate::storage::ArchiveStore;
+use crate::Error;
+
+/// How times are written at the start of each line.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// One line of a channel's log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub at: DateTime<Utc>,
+    /// Folded the way the network compares channel names.
+    pub channel: String,
+    /// What happened, e.g. `<bob> hi` or `*** bob joined`.
+    pub text: String,
+}
+
+impl LogLine {
+    /// A line as `Display` writes it, if it is one.
+    pub fn parse(channel: &str, line: &str) -> Option<Self> {
+        let (time, text) = line.strip_prefix('[')?.split_once("] ")?;
+        let at = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?;
+
+        Some(Self {
+            at: at.and_utc(),
+            channel: channel.to_string(),
+            text: text.to_string(),
+        })
+    }
+}
+
+/// `[2024-01-01 12:00:00] <bob> hi`, in UTC.
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.at.format(TIME_FORMAT), self.text)
+    }
+}
+
+/// The channel logs for one network, in files or the store as `[archive]` says.
+pub struct Archive {
+    scope: String,
+    config: ArchiveConfig,
+    /// Set when the logs go to the store rather than files.
+    store: Option<Arc<dyn ArchiveStore>>,
+}
+
+impl Archive {
+    pub fn new(scope: &str, config: &ArchiveConfig, store: Option<Arc<dyn ArchiveStore>>) -> Self {
+        let store = match (config.to, store) {
+            (ArchiveTarget::Storage, Some(store)) => Some(store),
+            (ArchiveTarget::Storage, None) => {
+                warn!(
+                    "Channel logs go to files in {}, there's no [storage] to put them in",
+                    config.dir.display()
+                );
+                None
+            }
+            (ArchiveTarget::Files, _) => None,
+        };
+
+        Self {
+            scope: scope.to_string(),
+            config: config.clone(),
+            store,
+        }
+    }
+
+    /// Logs `message` in every channel it happened in at `at`. Quits and nick changes happen in
+    /// every channel the nick is in, so this goes before `channels` tracks the message and
+    /// forgets who was where. What people who've opted out of being remembered say isn't kept,
+    /// since the logs end up in `!tldr` and the Markov chain.
+    pub async fn log(
+        &self,
+        message: &Message,
+        channels: &Channels,
+        at: DateTime<Utc>,
+        opted_out: bool,
+    ) {
+        if opted_out && matches!(message.command, Command::PRIVMSG(..) | Command::NOTICE(..)) {
+            return;
+        }
+        let casemapping = channels.casemapping();
+        for (channel, text) in describe(message, channels) {
+            let logged = channels
+                .get(&channel)
+                .is_some_and(|channel_config| channel_config.archive);
+            if !logged {
+                continue;
+            }
+            let line = LogLine {
+                at,
+                channel: casemapping.fold(&channel),
+                text,
+            };
+            let result = match &self.store {
+                Some(store) => store.log_line(&self.scope, &line).await,
+                None => self.write(&line).await.map_err(Error::Archive),
+            };
+            if let Err(e) = result {
+                warn!("Unable to log a line in {}: {}", channel, e);
+            }
+        }
+    }
+
+    /// The last `n` lines logged in `channel`, folded, oldest first.
+    pub async fn recent(&self, channel: &str, n: usize) -> Vec<LogLine> {
+        let result = match &self.store {
+            Some(store) => store.logged(&self.scope, channel, n).await,
+            None => self.read(channel, n).await.map_err(Error::Archive),
+        };
+
+        result.unwrap_or_else(|e| {
+            warn!("Unable to read the log of {}: {}", channel, e);
+            Vec::new()
+        })
+    }
+
+    /// Drops every line `nick` said, in every channel, for when they opt out of being
+    /// remembered.
+    pub async fn forget(&self, nick: &str, casemapping: CaseMapping) {
+        let result = match &self.store {
+            Some(store) => store.forget_said(&self.scope, nick, casemapping).await,
+            None => self
+                .forget_said(nick, casemapping)
+                .await
+                .map_err(Error::Archive),
+        };
+
+        if let Err(e) = result {
+            warn!("Unable to drop what {} said from the logs: {}", nick, e);
+        }
+    }
+
+    fn channel_dir(&self, channel: &str) -> PathBuf {
+        self.config
+            .dir
+            .join(file_name(&self.scope))
+            .join(file_name(channel))
+    }
+
+    async fn write(&self, line: &LogLine) -> Result<(), std::io::Error> {
+        let dir = self.channel_dir(&line.channel);
+        let name = match self.config.rotate {
+            Rotation::Daily => line.at.format("%Y-%m-%d"),
+            Rotation::Weekly => line.at.format("%G-W%V"),
+            Rotation::Monthly => line.at.format("%Y-%m"),
+        };
+        let path = dir.join(format!("{}.log", name));
+        let rotated = !fs::try_exists(&path).await?;
+        if rotated {
+            fs::create_dir_all(&dir).await?;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        file.write_all(format!("{}\n", line).as_bytes()).await?;
+
+        if rotated && self.config.keep > 0 {
+            let files = log_files(&dir).await?;
+            for old in files.iter().rev().skip(self.config.keep) {
+                info!("Removing old log {}", old.display());
+                fs::remove_file(old).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn read(&self, channel: &str, n: usize) -> Result<Vec<LogLine>, std::io::Error> {
+        let dir = self.channel_dir(channel);
+        if !fs::try_exists(&dir).await? {
+            return Ok(Vec::new());
+        }
+
+        // Newest file first, until there's enough
+        let mut lines = Vec::new();
+        for path in log_files(&dir).await?.iter().rev() {
+            if lines.len() >= n {
+                break;
+            }
+            let bytes = fs::read(path).await?;
+            let mut older = String::from_utf8_lossy(&bytes)
+                .lines()
+                .filter_map(|line| LogLine::parse(channel, line))
+                .collect::<Vec<_>>();
+            older.append(&mut lines);
+            lines = older;
+        }
+
+        Ok(lines.split_off(lines.len().saturating_sub(n)))
+    }
+
+    async fn forget_said(
+        &self,
+        nick: &str,
+        casemapping: CaseMapping,
+    ) -> Result<(), std::io::Error> {
+        let dir = self.config.dir.join(file_name(&self.scope));
+        if !fs::try_exists(&dir).await? {
+            return Ok(());
+        }
+
+        let mut channels = fs::read_dir(&dir).await?;
+        while let Some(channel) = channels.next_entry().await? {
+            if !channel.file_type().await?.is_dir() {
+                continue;
+            }
+            for path in log_files(&channel.path()).await? {
+                let bytes = fs::read(&path).await?;
+                let text = String::from_utf8_lossy(&bytes);
+                let (said, kept): (Vec<_>, Vec<_>) = text.lines().partition(|line| {
+                    LogLine::parse("", line)
+                        .is_some_and(|line| said_by(&line.text, nick, casemapping))
+                });
+                if !said.is_empty() {
+                    let kept = kept.iter().map(|line| format!("{}\n", line));
+                    fs::write(&path, kept.collect::<String>()).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `nick` said the logged line `text`, as a message, action or notice.
+pub fn said_by(text: &str, nick: &str, casemapping: CaseMapping) -> bool {
+    let speaker = if let Some(rest) = text.strip_prefix('<') {
+        rest.split_once("> ").map(|(speaker, _)| speaker)
+    } else if let Some(rest) = text.strip_prefix("* ") {
+        rest.split_once(' ').map(|(speaker, _)| speaker)
+    } else if let Some(rest) = text.strip_prefix('-') {
+        rest.split_once("- ").map(|(speaker, _)| speaker)
+    } else {
+        None
+    };
+
+    speaker.is_some_and(|speaker| casemapping.eq(speaker, nick))
+}
+
+/// What `message` looks like in the log of each channel it happened in. Private messages
+/// aren't anyone else's business.
+pub fn describe(message: &Message, channels: &Channels) -> Vec<(String, String)> {
+    let Some(nick) = message.source_nickname() else {
+        return Vec::new();
+    };
+    let casemapping = channels.casemapping();
+    // Only the channels they were in, as far as we know
+    let everywhere = |text: String| {
+        channels
+            .names()
+            .into_iter()
+            .filter(|channel| {
+                channels
+                    .members(channel)
+                    .iter()
+                    .any(|member| casemapping.eq(member, nick))
+            })
+            .map(|channel| (channel, text.clone()))
+            .collect()
+    };
+    let with_reason = |text: String, reason: Option<&str>| match reason {
+        Some(reason) if !reason.is_empty() => format!("{} ({})", text, reason),
+        _ => text,
+    };
+
+    let (channel, text) = match &message.command {
+        Command::PRIVMSG(channel, msg) => match (ctcp::action(msg), ctcp::parse(msg)) {
+            (Some(action), _) => (channel, format!("* {} {}", nick, action)),
+            (None, Some(_)) => return Vec::new(),
+            (None, None) => (channel, format!("<{}> {}", nick, msg)),
+        },
+        Command::NOTICE(channel, msg) => (channel, format!("-{}- {}", nick, msg)),
+        Command::JOIN(channel, ..) => (channel, format!("*** {} joined", nick)),
+        Command::PART(channel, reason) => (
+            channel,
+            with_reason(format!("*** {} left", nick), reason.as_deref()),
+        ),
+        Command::KICK(channel, kicked, reason) => (
+            channel,
+            with_reason(
+                format!("*** {} was kicked by {}", kicked, nick),
+                reason.as_deref(),
+            ),
+        ),
+        Command::TOPIC(channel, Some(topic)) => (
+            channel,
+            format!("*** {} changed the topic to: {}", nick, topic),
+        ),
+        Command::QUIT(reason) => {
+            return everywhere(with_reason(format!("*** {} quit", nick), reason.as_deref()))
+        }
+        Command::NICK(new) => return everywhere(format!("*** {} is now known as {}", nick, new)),
+        _ => return Vec::new(),
+    };
+
+    vec![(channel.clone(), text)]
+}
+
+/// `name` safe to use as one part of a path.
+fn file_name(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
+}
+
+/// The logs in `dir`, oldest first, which their names sort by.
+async fn log_files(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|extension| extension == "log") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
@@ -0,0 +1,770 @@
+//! Working out arithmetic, unit conversions and bases exactly, rather than letting the model
+//! guess at them. `2^10`, `5 ft 11 in to cm`, `100 km/h in mph`, `255 to hex`.
+
+use std::f64::consts;
+
+use crate::Error;
+
+/// Powers of metres, kilograms, seconds, kelvin and bits.
+type Dims = [i8; 5];
+
+const DIMENSIONLESS: Dims = [0; 5];
+const LENGTH: Dims = [1, 0, 0, 0, 0];
+const AREA: Dims = [2, 0, 0, 0, 0];
+const VOLUME: Dims = [3, 0, 0, 0, 0];
+const MASS: Dims = [0, 1, 0, 0, 0];
+const TIME: Dims = [0, 0, 1, 0, 0];
+const FREQUENCY: Dims = [0, 0, -1, 0, 0];
+const SPEED: Dims = [1, 0, -1, 0, 0];
+const FORCE: Dims = [1, 1, -2, 0, 0];
+const PRESSURE: Dims = [-1, 1, -2, 0, 0];
+const ENERGY: Dims = [2, 1, -2, 0, 0];
+const POWER: Dims = [2, 1, -3, 0, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 1, 0];
+const INFORMATION: Dims = [0, 0, 0, 0, 1];
+
+const BASE_UNITS: [&str; 5] = ["m", "kg", "s", "K", "bit"];
+
+/// Longest expression worth reading.
+const MAX_LENGTH: usize = 200;
+
+/// What goes wrong with units raised too far, or multiplied together too often.
+const SMALL_POWERS: &str = "units can only be raised to small whole powers";
+
+struct Unit {
+    names: &'static [&'static str],
+    dims: Dims,
+    /// How many base units one of these is.
+    factor: f64,
+    /// Added before scaling, for temperatures that don't start at absolute zero.
+    offset: f64,
+}
+
+const fn unit(names: &'static [&'static str], dims: Dims, factor: f64) -> Unit {
+    Unit {
+        names,
+        dims,
+        factor,
+        offset: 0.0,
+    }
+}
+
+const MILE: f64 = 1609.344;
+const HOUR: f64 = 3600.0;
+const LITRE: f64 = 0.001;
+const GALLON: f64 = 3.785411784 * LITRE;
+
+#[rustfmt::skip]
+const UNITS: &[Unit] = &[
+    unit(&["m", "meter", "meters", "metre", "metres"], LENGTH, 1.0),
+    unit(&["km", "kilometer", "kilometers", "kilometre", "kilometres"], LENGTH, 1e3),
+    unit(&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], LENGTH, 1e-2),
+    unit(&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], LENGTH, 1e-3),
+    unit(&["um", "µm", "micrometer", "micrometers", "micron", "microns"], LENGTH, 1e-6),
+    unit(&["nm", "nanometer", "nanometers", "nanometre", "nanometres"], LENGTH, 1e-9),
+    unit(&["mi", "mile", "miles"], LENGTH, MILE),
+    unit(&["yd", "yard", "yards"], LENGTH, 0.9144),
+    unit(&["ft", "foot", "feet"], LENGTH, 0.3048),
+    unit(&["in", "inch", "inches"], LENGTH, 0.0254),
+    unit(&["nmi"], LENGTH, 1852.0),
+    unit(&["au"], LENGTH, 149_597_870_700.0),
+    unit(&["ly", "lightyear", "lightyears"], LENGTH, 9_460_730_472_580_800.0),
+    unit(&["ha", "hectare", "hectares"], AREA, 1e4),
+    unit(&["acre", "acres"], AREA, 4046.8564224),
+    unit(&["l", "L", "liter", "liters", "litre", "litres"], VOLUME, LITRE),
+    unit(&["ml", "mL", "milliliter", "milliliters", "millilitre", "millilitres"], VOLUME, 1e-3 * LITRE),
+    unit(&["gal", "gallon", "gallons"], VOLUME, GALLON),
+    unit(&["qt", "quart", "quarts"], VOLUME, GALLON / 4.0),
+    unit(&["pt", "pint", "pints"], VOLUME, GALLON / 8.0),
+    unit(&["cup", "cups"], VOLUME, GALLON / 16.0),
+    unit(&["floz"], VOLUME, GALLON / 128.0),
+    unit(&["tbsp"], VOLUME, GALLON / 256.0),
+    unit(&["tsp"], VOLUME, GALLON / 768.0),
+    unit(&["g", "gram", "grams"], MASS, 1e-3),
+    unit(&["kg", "kilogram", "kilograms", "kilo", "kilos"], MASS, 1.0),
+    unit(&["mg", "milligram", "milligrams"], MASS, 1e-6),
+    unit(&["t", "tonne", "tonnes"], MASS, 1e3),
+    unit(&["lb", "lbs", "pound", "pounds"], MASS, 0.45359237),
+    unit(&["oz", "ounce", "ounces"], MASS, 0.45359237 / 16.0),
+    unit(&["st", "stone"], MASS, 0.45359237 * 14.0),
+    unit(&["s", "sec", "secs", "second", "seconds"], TIME, 1.0),
+    unit(&["ms", "millisecond", "milliseconds"], TIME, 1e-3),
+    unit(&["min", "mins", "minute", "minutes"], TIME, 60.0),
+    unit(&["h", "hr", "hrs", "hour", "hours"], TIME, HOUR),
+    unit(&["d", "day", "days"], TIME, 24.0 * HOUR),
+    unit(&["wk", "week", "weeks"], TIME, 7.0 * 24.0 * HOUR),
+    unit(&["yr", "year", "years"], TIME, 365.25 * 24.0 * HOUR),
+    unit(&["Hz", "hertz"], FREQUENCY, 1.0),
+    unit(&["kHz"], FREQUENCY, 1e3),
+    unit(&["MHz"], FREQUENCY, 1e6),
+    unit(&["GHz"], FREQUENCY, 1e9),
+    unit(&["mph"], SPEED, MILE / HOUR),
+    unit(&["kph", "kmh"], SPEED, 1e3 / HOUR),
+    unit(&["kn", "kt", "knot", "knots"], SPEED, 1852.0 / HOUR),
+    unit(&["N", "newton", "newtons"], FORCE, 1.0),
+    unit(&["Pa", "pascal", "pascals"], PRESSURE, 1.0),
+    unit(&["kPa"], PRESSURE, 1e3),
+    unit(&["bar"], PRESSURE, 1e5),
+    unit(&["psi"], PRESSURE, 6894.757293168),
+    unit(&["atm"], PRESSURE, 101_325.0),
+    unit(&["J", "joule", "joules"], ENERGY, 1.0),
+    unit(&["kJ"], ENERGY, 1e3),
+    unit(&["cal", "calorie", "calories"], ENERGY, 4.184),
+    unit(&["kcal"], ENERGY, 4184.0),
+    unit(&["Wh"], ENERGY, HOUR),
+    unit(&["kWh"], ENERGY, 1e3 * HOUR),
+    unit(&["W", "watt", "watts"], POWER, 1.0),
+    unit(&["kW"], POWER, 1e3),
+    unit(&["hp", "horsepower"], POWER, 745.699871582270),
+    unit(&["rad", "radian", "radians"], DIMENSIONLESS, 1.0),
+    unit(&["deg", "degree", "degrees", "°"], DIMENSIONLESS, consts::PI / 180.0),
+    Unit { names: &["K", "kelvin"], dims: TEMPERATURE, factor: 1.0, offset: 0.0 },
+    Unit { names: &["C", "°C", "degC", "celsius"], dims: TEMPERATURE, factor: 1.0, offset: 273.15 },
+    Unit { names: &["F", "°F", "degF", "fahrenheit"], dims: TEMPERATURE, factor: 5.0 / 9.0, offset: 459.67 },
+    unit(&["bit", "bits", "b"], INFORMATION, 1.0),
+    unit(&["B", "byte", "bytes"], INFORMATION, 8.0),
+    unit(&["KB", "kB"], INFORMATION, 8e3),
+    unit(&["MB"], INFORMATION, 8e6),
+    unit(&["GB"], INFORMATION, 8e9),
+    unit(&["TB"], INFORMATION, 8e12),
+    unit(&["KiB"], INFORMATION, 8.0 * 1024.0),
+    unit(&["MiB"], INFORMATION, 8.0 * 1024.0 * 1024.0),
+    unit(&["GiB"], INFORMATION, 8.0 * 1024.0 * 1024.0 * 1024.0),
+    unit(&["TiB"], INFORMATION, 8.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+];
+
+/// The unit called `name`, exactly as written if there is one, so `B` isn't `b`, or else
+/// ignoring case.
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    UNITS
+        .iter()
+        .find(|unit| unit.names.contains(&name))
+        .or_else(|| {
+            UNITS
+                .iter()
+                .find(|unit| unit.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        })
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" | "π" => Some(consts::PI),
+        "tau" | "τ" => Some(consts::TAU),
+        "e" => Some(consts::E),
+        _ => None,
+    }
+}
+
+/// A base to write whole numbers in, as in `255 to hex`.
+fn radix(name: &str) -> Option<u32> {
+    match name.to_lowercase().as_str() {
+        "bin" | "binary" => Some(2),
+        "oct" | "octal" => Some(8),
+        "dec" | "decimal" => Some(10),
+        "hex" | "hexadecimal" => Some(16),
+        _ => None,
+    }
+}
+
+/// What a result is written in, as the units it was given in rather than base units.
+#[derive(Clone)]
+struct Shown {
+    name: String,
+    factor: f64,
+    offset: f64,
+}
+
+#[derive(Clone)]
+struct Quantity {
+    /// In base units.
+    value: f64,
+    dims: Dims,
+    shown: Option<Shown>,
+}
+
+impl Quantity {
+    fn number(value: f64) -> Self {
+        Self {
+            value,
+            dims: DIMENSIONLESS,
+            shown: None,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        self.dims == DIMENSIONLESS && self.shown.is_none()
+    }
+
+    /// The value when it has to be a plain number, as for functions.
+    fn plain(&self, what: &str) -> Result<f64, String> {
+        match self.dims == DIMENSIONLESS {
+            true => Ok(self.value),
+            false => Err(format!("{} needs a plain number", what)),
+        }
+    }
+
+    fn add(self, other: Self, sign: f64) -> Result<Self, String> {
+        if self.dims != other.dims {
+            return Err(String::from(
+                "can't add or subtract different kinds of units",
+            ));
+        }
+
+        Ok(Self {
+            value: self.value + sign * other.value,
+            dims: self.dims,
+            shown: self.shown.or(other.shown),
+        })
+    }
+
+    fn combine(self, other: Self, divide: bool) -> Result<Self, String> {
+        let mut dims = self.dims;
+        for (dim, other) in dims.iter_mut().zip(other.dims) {
+            let other = if divide {
+                other.checked_neg()
+            } else {
+                Some(other)
+            };
+            *dim = other
+                .and_then(|other| dim.checked_add(other))
+                .ok_or_else(|| String::from(SMALL_POWERS))?;
+        }
+        let shown = match (self.shown, other.shown) {
+            _ if dims == DIMENSIONLESS => None,
+            (Some(a), Some(b)) => Some(Shown {
+                name: format!("{}{}{}", a.name, if divide { "/" } else { "*" }, b.name),
+                factor: if divide {
+                    a.factor / b.factor
+                } else {
+                    a.factor * b.factor
+                },
+                offset: 0.0,
+            }),
+            (Some(a), None) => Some(a),
+            (None, Some(b)) if !divide => Some(b),
+            _ => None,
+        };
+
+        Ok(Self {
+            value: if divide {
+                self.value / other.value
+            } else {
+                self.value * other.value
+            },
+            dims,
+            shown,
+        })
+    }
+
+    fn pow(self, exponent: Self) -> Result<Self, String> {
+        let exponent = exponent.plain("a power")?;
+        if self.dims == DIMENSIONLESS {
+            return Ok(Self::number(self.value.powf(exponent)));
+        }
+        if exponent.fract() != 0.0 || exponent.abs() > 8.0 {
+            return Err(String::from(SMALL_POWERS));
+        }
+
+        let power = exponent as i32;
+        let mut dims = self.dims;
+        for dim in dims.iter_mut() {
+            *dim = dim
+                .checked_mul(power as i8)
+                .ok_or_else(|| String::from(SMALL_POWERS))?;
+        }
+        Ok(Self {
+            value: self.value.powi(power),
+            dims,
+            shown: self.shown.map(|shown| Shown {
+                name: format!("{}^{}", shown.name, power),
+                factor: shown.factor.powi(power),
+                offset: 0.0,
+            }),
+        })
+    }
+
+    /// Written in `shown`, the units it came in, or base units.
+    fn display(&self) -> String {
+        if let Some(shown) = &self.shown {
+            return format!(
+                "{} {}",
+                number(self.value / shown.factor - shown.offset),
+                shown.name
+            );
+        }
+
+        let power = |unit: &str, power: i8| match power {
+            1 => unit.to_string(),
+            power => format!("{}^{}", unit, power),
+        };
+        let mut above = Vec::new();
+        let mut below = Vec::new();
+        for (unit, &dim) in BASE_UNITS.iter().zip(&self.dims) {
+            match dim {
+                0 => {}
+                dim if dim > 0 => above.push(power(unit, dim)),
+                dim => below.push(power(unit, -dim)),
+            }
+        }
+        let mut units = match above.is_empty() {
+            true if !below.is_empty() => String::from("1"),
+            _ => above.join("*"),
+        };
+        if !below.is_empty() {
+            units.push('/');
+            units.push_str(&below.join("/"));
+        }
+
+        match units.is_empty() {
+            true => number(self.value),
+            false => format!("{} {}", number(self.value), units),
+        }
+    }
+}
+
+/// `value` to twelve significant figures, without trailing zeros.
+fn number(value: f64) -> String {
+    let magnitude = value.abs();
+    if value.fract() == 0.0 && magnitude < 1e16 {
+        return format!("{}", value as i64);
+    }
+    if !(1e-6..1e16).contains(&magnitude) {
+        let written = format!("{:.11e}", value);
+        let (mantissa, exponent) = written.split_once('e').unwrap_or((&written, "0"));
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        return format!("{}e{}", mantissa, exponent);
+    }
+
+    let decimals = (11 - magnitude.log10().floor() as i32).clamp(0, 17) as usize;
+    let written = format!("{:.*}", decimals, value);
+    let written = written.trim_end_matches('0').trim_end_matches('.');
+    match written {
+        "-0" => String::from("0"),
+        written => written.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Word(String),
+    Symbol(char),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Number(value) => number(*value),
+            Token::Word(word) => word.clone(),
+            Token::Symbol(symbol) => symbol.to_string(),
+        }
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(word))
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                let exponent =
+                    matches!(c, '+' | '-') && text.ends_with(['e', 'E']) && !text.starts_with("0x");
+                if !(c.is_ascii_alphanumeric() || c == '.' || c == '_' || exponent) {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            let (number, unit) = split_number(&text);
+            tokens.push(Token::Number(literal(number)?));
+            if !unit.is_empty() {
+                tokens.push(Token::Word(unit.to_string()));
+            }
+        } else if c.is_alphabetic() || matches!(c, '_' | '°' | 'µ') {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || matches!(c, '_' | '°' | 'µ')) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        } else {
+            chars.next();
+            let symbol = match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    '^'
+                }
+                '×' | '·' => '*',
+                '÷' => '/',
+                '−' => '-',
+                '+' | '-' | '*' | '/' | '%' | '^' | '(' | ')' | '!' => c,
+                c => return Err(format!("I don't know what {} means", c)),
+            };
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Where a number written right up against its unit, like `5km`, stops.
+fn split_number(text: &str) -> (&str, &str) {
+    let lower = text.to_lowercase();
+    if ["0x", "0b", "0o"]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix))
+    {
+        return (text, "");
+    }
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| {
+            let exponent = matches!(c, 'e' | 'E')
+                && text[i + 1..]
+                    .trim_start_matches(['+', '-'])
+                    .starts_with(|c: char| c.is_ascii_digit());
+            c.is_alphabetic() && !exponent
+        })
+        .map_or(text.len(), |(i, _)| i);
+
+    text.split_at(end)
+}
+
+fn literal(text: &str) -> Result<f64, String> {
+    let digits = text.replace('_', "");
+    let lower = digits.to_lowercase();
+    let based = [("0x", 16), ("0b", 2), ("0o", 8)]
+        .iter()
+        .find_map(|(prefix, radix)| Some((lower.strip_prefix(prefix)?, *radix)));
+    let value = match based {
+        Some((digits, radix)) => u64::from_str_radix(digits, radix)
+            .map(|value| value as f64)
+            .ok(),
+        None => digits.parse().ok(),
+    };
+
+    value.ok_or_else(|| format!("{} isn't a number", text))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// The number about to be read had a minus sign in front of a temperature.
+    below_zero: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(tokens: &'a [Token]) -> Result<Quantity, String> {
+        let mut parser = Self {
+            tokens,
+            position: 0,
+            below_zero: false,
+        };
+        let quantity = parser.expression()?;
+        match parser.peek() {
+            None => Ok(quantity),
+            Some(token) => Err(format!("I wasn't expecting {}", token.text())),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.position += 1;
+        }
+
+        found
+    }
+
+    fn expression(&mut self) -> Result<Quantity, String> {
+        let mut quantity = self.term()?;
+        loop {
+            if self.eat('+') {
+                quantity = quantity.add(self.term()?, 1.0)?;
+            } else if self.eat('-') {
+                quantity = quantity.add(self.term()?, -1.0)?;
+            } else if quantity.shown.is_some() && matches!(self.peek(), Some(Token::Number(_))) {
+                // 5 ft 11 in
+                quantity = quantity.add(self.term()?, 1.0)?;
+            } else {
+                return Ok(quantity);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Quantity, String> {
+        let mut quantity = self.unary()?;
+        loop {
+            if self.eat('*') {
+                quantity = quantity.combine(self.unary()?, false)?;
+            } else if self.eat('/') {
+                quantity = quantity.combine(self.unary()?, true)?;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if quantity.dims != divisor.dims {
+                    return Err(String::from("% needs the same kind of units on each side"));
+                }
+                quantity.value %= divisor.value;
+            } else {
+                return Ok(quantity);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Quantity, String> {
+        if self.eat('-') {
+            // -40 F is forty below zero, not the negative of forty above absolute zero
+            if let (Some(Token::Number(_)), Some(Token::Word(word))) =
+                (self.peek(), self.tokens.get(self.position + 1))
+            {
+                if find_unit(word).is_some_and(|unit| unit.offset != 0.0) {
+                    self.below_zero = true;
+                    return self.power();
+                }
+            }
+            let mut quantity = self.unary()?;
+            quantity.value = -quantity.value;
+            return Ok(quantity);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Quantity, String> {
+        let base = self.postfix()?;
+        match self.eat('^') {
+            true => base.pow(self.unary()?),
+            false => Ok(base),
+        }
+    }
+
+    /// A value with any factorials and the units written after it, as in `5 km` or `3 m^2`.
+    fn postfix(&mut self) -> Result<Quantity, String> {
+        let mut quantity = self.atom()?;
+        while self.eat('!') {
+            let n = quantity.plain("!")?;
+            if n < 0.0 || n.fract() != 0.0 || n > 170.0 {
+                return Err(String::from("! needs a whole number from 0 to 170"));
+            }
+            quantity.value = (1..=n as u32).map(f64::from).product();
+        }
+        while let Some(Token::Word(word)) = self.peek() {
+            let Some(unit) = find_unit(word) else {
+                break;
+            };
+            if self.tokens.get(self.position + 1) == Some(&Token::Symbol('(')) {
+                break;
+            }
+            self.position += 1;
+            let mut unit = self.unit(word, unit);
+            if let (Some(Token::Symbol('^')), Some(&Token::Number(power))) = (
+                self.tokens.get(self.position),
+                self.tokens.get(self.position + 1),
+            ) {
+                self.position += 2;
+                unit = unit.pow(Quantity::number(power))?;
+            }
+            quantity = match (&unit.shown, quantity.is_number()) {
+                // 20 C is 293.15 K, but C on its own is a size of degree
+                (Some(shown), true) if shown.offset != 0.0 => {
+                    let value = match std::mem::take(&mut self.below_zero) {
+                        true => -quantity.value,
+                        false => quantity.value,
+                    };
+                    Quantity {
+                        value: (value + shown.offset) * shown.factor,
+                        ..unit
+                    }
+                }
+                _ => quantity.combine(unit, false)?,
+            };
+        }
+
+        Ok(quantity)
+    }
+
+    fn unit(&self, name: &str, unit: &Unit) -> Quantity {
+        Quantity {
+            value: unit.factor,
+            dims: unit.dims,
+            shown: Some(Shown {
+                name: name.to_string(),
+                factor: unit.factor,
+                offset: unit.offset,
+            }),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Quantity, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Quantity::number(*value)),
+            Some(Token::Symbol('(')) => {
+                let quantity = self.expression()?;
+                match self.eat(')') {
+                    true => Ok(quantity),
+                    false => Err(String::from("there's a ( without a )")),
+                }
+            }
+            Some(Token::Word(word)) if self.eat('(') => {
+                let argument = self.expression()?;
+                if !self.eat(')') {
+                    return Err(String::from("there's a ( without a )"));
+                }
+                function(word, argument)
+            }
+            Some(Token::Word(word)) => {
+                if let Some(value) = constant(word) {
+                    return Ok(Quantity::number(value));
+                }
+                match find_unit(word) {
+                    Some(unit) => Ok(self.unit(word, unit)),
+                    None => Err(format!("I don't know what {} is", word)),
+                }
+            }
+            Some(token) => Err(format!("I wasn't expecting {}", token.text())),
+            None => Err(String::from("it stops too soon")),
+        }
+    }
+}
+
+fn function(name: &str, argument: Quantity) -> Result<Quantity, String> {
+    if name == "abs" {
+        return Ok(Quantity {
+            value: argument.value.abs(),
+            ..argument
+        });
+    }
+
+    let x = argument.plain(name)?;
+    let value = match name {
+        "sqrt" => x.sqrt(),
+        "cbrt" => x.cbrt(),
+        "round" => x.round(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        "ln" => x.ln(),
+        "log" | "log10" => x.log10(),
+        "log2" => x.log2(),
+        "exp" => x.exp(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "asin" => x.asin(),
+        "acos" => x.acos(),
+        "atan" => x.atan(),
+        _ => return Err(format!("I don't know how to {}", name)),
+    };
+
+    Ok(Quantity::number(value))
+}
+
+/// Works out `expression`, converting the result if it ends in `to <units>` or `to hex`.
+pub fn evaluate(expression: &str) -> Result<String, Error> {
+    if expression.len() > MAX_LENGTH {
+        return Err(Error::Calc(String::from("that's too long to read")));
+    }
+    let tokens = tokenize(expression).map_err(Error::Calc)?;
+    if tokens.is_empty() {
+        return Err(Error::Calc(String::from("there's nothing to work out")));
+    }
+
+    // `in` is also inches, so try each place the conversion might start from the end, and
+    // take the first where both sides make sense
+    let mut depth = 0;
+    let mut splits = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') => depth -= 1,
+            _ if depth == 0 && ["to", "in", "as"].iter().any(|word| token.is_word(word)) => {
+                splits.push(i)
+            }
+            _ => {}
+        }
+    }
+    for &i in splits.iter().rev() {
+        let (left, target) = (&tokens[..i], &tokens[i + 1..]);
+        if left.is_empty() || target.is_empty() {
+            continue;
+        }
+        let Ok(quantity) = Parser::parse(left) else {
+            continue;
+        };
+        if let [Token::Word(word)] = target {
+            if let Some(radix) = radix(word) {
+                return in_radix(&quantity, radix).map_err(Error::Calc);
+            }
+        }
+        if let Ok(unit) = Parser::parse(target) {
+            let name = target.iter().map(Token::text).collect::<String>();
+            return convert(&quantity, &unit, &name).map_err(Error::Calc);
+        }
+    }
+
+    let quantity = Parser::parse(&tokens).map_err(Error::Calc)?;
+    finite(&quantity).map_err(Error::Calc)?;
+    Ok(quantity.display())
+}
+
+fn finite(quantity: &Quantity) -> Result<(), String> {
+    match quantity.value {
+        value if value.is_nan() => Err(String::from("that isn't a number")),
+        value if value.is_infinite() => Err(String::from("that's too big")),
+        _ => Ok(()),
+    }
+}
+
+fn convert(quantity: &Quantity, target: &Quantity, name: &str) -> Result<String, String> {
+    finite(quantity)?;
+    if quantity.dims != target.dims || target.value == 0.0 {
+        return Err(format!("can't turn that into {}", name));
+    }
+    let offset = target.shown.as_ref().map_or(0.0, |shown| shown.offset);
+
+    Ok(format!(
+        "{} {}",
+        number(quantity.value / target.value - offset),
+        name
+    ))
+}
+
+fn in_radix(quantity: &Quantity, radix: u32) -> Result<String, String> {
+    finite(quantity)?;
+    let value = quantity.plain("changing base")?;
+    if value.fract() != 0.0 || value.abs() >= 2f64.powi(63) {
+        return Err(String::from("only whole numbers can change base"));
+    }
+
+    let n = value as i64;
+    let sign = if n < 0 { "-" } else { "" };
+    let n = n.unsigned_abs();
+    Ok(match radix {
+        2 => format!("{}0b{:b}", sign, n),
+        8 => format!("{}0o{:o}", sign, n),
+        16 => format!("{}0x{:x}", sign, n),
+        _ => format!("{}{}", sign, n),
+    })
+}
@@ -0,0 +1,52 @@
+/// How a network decides whether two nicks or channel names are the same, as given by
+/// `CASEMAPPING` in ISUPPORT. RFC 1459 treats `[]\~` as the uppercase of `{}|^`, because of
+/// Scandinavian character sets, and is what servers that don't say use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseMapping {
+    Ascii,
+    #[default]
+    Rfc1459,
+    /// RFC 1459 without `~` and `^`.
+    StrictRfc1459,
+}
+
+impl CaseMapping {
+    /// The mapping called `name` in ISUPPORT, if it's one we know.
+    pub fn from_isupport(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Self::Ascii),
+            "rfc1459" => Some(Self::Rfc1459),
+            "strict-rfc1459" => Some(Self::StrictRfc1459),
+            _ => None,
+        }
+    }
+
+    fn fold_char(self, c: char) -> char {
+        match (self, c) {
+            (Self::Rfc1459 | Self::StrictRfc1459, '[') => '{',
+            (Self::Rfc1459 | Self::StrictRfc1459, ']') => '}',
+            (Self::Rfc1459 | Self::StrictRfc1459, '\\') => '|',
+            (Self::Rfc1459, '~') => '^',
+            _ => c.to_ascii_lowercase(),
+        }
+    }
+
+    /// `name` in lowercase, for use as a key.
+    pub fn fold(self, name: &str) -> String {
+        name.chars().map(|c| self.fold_char(c)).collect()
+    }
+
+    pub fn eq(self, a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a.chars()
+                .zip(b.chars())
+                .all(|(a, b)| self.fold_char(a) == self.fold_char(b))
+    }
+
+    /// `text` without `prefix`, whatever case it's in.
+    pub fn strip_prefix<'a>(self, text: &'a str, prefix: &str) -> Option<&'a str> {
+        let head = text.get(..prefix.len())?;
+
+        self.eq(head, prefix).then(|| &text[prefix.len()..])
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::DateTime;
+use chrono::Utc;
+
+use irc::client::prelude::*;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::casemap::CaseMapping;
+use crate::config::ChannelConfig;
+use crate::config::NetworkConfig;
+use crate::ctcp;
+
+/// Lines of conversation kept per channel.
+const SCROLLBACK: usize = 100;
+
+/// Something said in a channel.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub nick: String,
+    /// A `/me` is written `*like this*`.
+    pub text: String,
+    pub at: DateTime<Utc>,
+}
+
+/// The channels pickles is in on one network. Starts out as the configured channels and
+/// changes with invites and `!join`/`!part`, outliving the connection so a reconnect rejoins
+/// wherever pickles was rather than wherever it started. Names are compared the way the
+/// network compares them, which the server tells us once we're connected.
+pub struct Channels {
+    joined: Mutex<Vec<ChannelConfig>>,
+    /// By folded channel name.
+    topics: Mutex<HashMap<String, String>>,
+    /// Who's in each channel, by folded channel name and then by folded nick.
+    members: Mutex<HashMap<String, BTreeMap<String, String>>>,
+    /// What's been said lately, by folded channel name.
+    scrollback: Mutex<HashMap<String, VecDeque<Line>>>,
+    casemapping: Mutex<CaseMapping>,
+}
+
+impl Channels {
+    pub fn new(network: &NetworkConfig) -> Self {
+        Self {
+            joined: Mutex::new(network.channels.clone()),
+            topics: Mutex::new(HashMap::new()),
+            members: Mutex::new(HashMap::new()),
+            scrollback: Mutex::new(HashMap::new()),
+            casemapping: Mutex::new(CaseMapping::default()),
+        }
+    }
+
+    pub fn casemapping(&self) -> CaseMapping {
+        *self.casemapping.lock().expect("casemapping lock poisoned")
+    }
+
+    pub fn set_casemapping(&self, casemapping: CaseMapping) {
+        *self.casemapping.lock().expect("casemapping lock poisoned") = casemapping;
+    }
+
+    pub fn get(&self, name: &str) -> Option<ChannelConfig> {
+        let casemapping = self.casemapping();
+        self.joined
+            .lock()
+            .expect("channels lock poisoned")
+            .iter()
+            .find(|channel| casemapping.eq(&channel.name, name))
+            .cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.joined
+            .lock()
+            .expect("channels lock poisoned")
+            .iter()
+            .map(|channel| channel.name.clone())
+            .collect()
+    }
+
+    /// Returns false if pickles was already in the channel.
+    pub fn join(&self, channel: ChannelConfig) -> bool {
+        let casemapping = self.casemapping();
+        let mut joined = self.joined.lock().expect("channels lock poisoned");
+        if joined
+            .iter()
+            .any(|c| casemapping.eq(&c.name, &channel.name))
+        {
+            return false;
+        }
+        joined.push(channel);
+
+        true
+    }
+
+    /// Returns false if pickles wasn't in the channel.
+    pub fn part(&self, name: &str) -> bool {
+        let casemapping = self.casemapping();
+        let mut joined = self.joined.lock().expect("channels lock poisoned");
+        let before = joined.len();
+        joined.retain(|channel| !casemapping.eq(&channel.name, name));
+
+        joined.len() < before
+    }
+
+    pub fn topic(&self, name: &str) -> Option<String> {
+        self.topics
+            .lock()
+            .expect("topics lock poisoned")
+            .get(&self.casemapping().fold(name))
+            .cloned()
+    }
+
+    pub fn set_topic(&self, name: &str, topic: &str) {
+        let name = self.casemapping().fold(name);
+        let mut topics = self.topics.lock().expect("topics lock poisoned");
+        if topic.is_empty() {
+            topics.remove(&name);
+        } else {
+            topics.insert(name, topic.to_string());
+        }
+    }
+
+    /// Everyone in `name` as far as we've seen, us included.
+    pub fn members(&self, name: &str) -> Vec<String> {
+        self.members
+            .lock()
+            .expect("members lock poisoned")
+            .get(&self.casemapping().fold(name))
+            .map(|members| members.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The last `n` lines said in `name`, oldest first.
+    pub fn scrollback(&self, name: &str, n: usize) -> Vec<Line> {
+        let scrollback = self.scrollback.lock().expect("scrollback lock poisoned");
+        let Some(lines) = scrollback.get(&self.casemapping().fold(name)) else {
+            return Vec::new();
+        };
+
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// Keeps track of who's where from NAMES replies and people coming and going, and what's
+    /// said in the channels we're in. `nickname` is ours and `prefixes` are the status symbols
+    /// the server puts in front of nicks in NAMES.
+    pub fn track(&self, message: &Message, nickname: &str, prefixes: &str) {
+        let casemapping = self.casemapping();
+        if let (Command::PRIVMSG(target, text), Some(nick)) =
+            (&message.command, message.source_nickname())
+        {
+            // Other CTCPs are queries, not conversation
+            let text = match ctcp::action(text) {
+                Some(action) => Some(format!("*{}*", action)),
+                None => ctcp::parse(text).is_none().then(|| text.clone()),
+            };
+            if let Some(text) = text.filter(|_| self.get(target).is_some()) {
+                let mut scrollback = self.scrollback.lock().expect("scrollback lock poisoned");
+                let lines = scrollback.entry(casemapping.fold(target)).or_default();
+                if lines.len() >= SCROLLBACK {
+                    lines.pop_front();
+                }
+                lines.push_back(Line {
+                    nick: nick.to_string(),
+                    text,
+                    at: Utc::now(),
+                });
+            }
+        }
+
+        let is_us = |nick: &str| casemapping.eq(nick, nickname);
+        let mut members = self.members.lock().expect("members lock poisoned");
+        let source = message.source_nickname();
+        match (&message.command, source) {
+            (Command::Response(Response::RPL_NAMREPLY, args), _) if args.len() >= 4 => {
+                let channel = members.entry(casemapping.fold(&args[2])).or_default();
+                for nick in args[3].split_whitespace() {
+                    let nick = nick.trim_start_matches(|c| prefixes.contains(c));
+                    channel.insert(casemapping.fold(nick), nick.to_string());
+                }
+            }
+            // NAMES is about to tell us who's there
+            (Command::JOIN(channel, ..), Some(nick)) if is_us(nick) => {
+                members.insert(casemapping.fold(channel), BTreeMap::new());
+            }
+            (Command::JOIN(channel, ..), Some(nick)) => {
+                members
+                    .entry(casemapping.fold(channel))
+                    .or_default()
+                    .insert(casemapping.fold(nick), nick.to_string());
+            }
+            (Command::PART(channel, _), Some(nick)) => {
+                leave(&mut members, casemapping, channel, nick, is_us(nick))
+            }
+            (Command::KICK(channel, nick, _), _) => {
+                leave(&mut members, casemapping, channel, nick, is_us(nick))
+            }
+            (Command::QUIT(_), Some(nick)) => {
+                let nick = casemapping.fold(nick);
+                for channel in members.values_mut() {
+                    channel.remove(&nick);
+                }
+            }
+            (Command::NICK(new), Some(old)) => {
+                let old = casemapping.fold(old);
+                for channel in members.values_mut() {
+                    if channel.remove(&old).is_some() {
+                        channel.insert(casemapping.fold(new), new.clone());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Forgets who was where, once we're no longer there to see.
+    pub fn clear_members(&self) {
+        self.members.lock().expect("members lock poisoned").clear();
+    }
+}
+
+fn leave(
+    members: &mut HashMap<String, BTreeMap<String, String>>,
+    casemapping: CaseMapping,
+    channel: &str,
+    nick: &str,
+    us: bool,
+) {
+    if us {
+        members.remove(&casemapping.fold(channel));
+    } else if let Some(channel) = members.get_mut(&casemapping.fold(channel)) {
+        channel.remove(&casemapping.fold(nick));
+    }
+}
@@ -0,0 +1,86 @@
+use clap::Parser;
+use clap::ValueEnum;
+
+use std::path::PathBuf;
+
+use crate::config::ChannelConfig;
+use crate::config::Config;
+
+/// Just a silly IRC bot.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Path to the TOML config file
+    #[arg(short, long, env = "PICKLES_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Log filter, e.g. `info` or `pickles=debug` (defaults to RUST_LOG)
+    #[arg(short, long)]
+    pub log_level: Option<String>,
+
+    /// How to write logs: readable, or one JSON object per line for log shippers
+    #[arg(long, value_enum, env = "PICKLES_LOG_FORMAT", default_value_t = LogFormat::Pretty)]
+    pub log_format: LogFormat,
+
+    /// Connect and respond as usual but log replies instead of sending them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Talk to pickles on stdin instead of connecting to IRC, as `nick: message` per line
+    #[arg(long)]
+    pub repl: bool,
+
+    /// Write every conversation in [storage] to this JSON file and exit
+    #[arg(long, value_name = "FILE", conflicts_with = "restore")]
+    pub dump: Option<PathBuf>,
+
+    /// Load conversations from a JSON file written by --dump into [storage] and exit
+    #[arg(long, value_name = "FILE")]
+    pub restore: Option<PathBuf>,
+
+    /// Train the [markov] chain on its logs and exit
+    #[arg(long, conflicts_with_all = ["dump", "restore"])]
+    pub train_markov: bool,
+
+    /// Override the nickname on every configured network
+    #[arg(long)]
+    pub nickname: Option<String>,
+
+    /// Override the server of the first configured network
+    #[arg(long)]
+    pub server: Option<String>,
+
+    /// Override the channels of the first configured network (may be given more than once)
+    #[arg(long = "channel")]
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl Args {
+    /// Applies command line overrides on top of the loaded config.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(nickname) = &self.nickname {
+            for network in config.networks.iter_mut() {
+                network.nickname = nickname.clone();
+            }
+        }
+        if let Some(network) = config.networks.first_mut() {
+            if let Some(server) = &self.server {
+                network.server = server.clone();
+            }
+            if !self.channels.is_empty() {
+                network.channels = self
+                    .channels
+                    .iter()
+                    .map(|name| ChannelConfig::new(name))
+                    .collect();
+            }
+        }
+        config.dry_run = self.dry_run;
+    }
+}
@@ -0,0 +1,278 @@
+use async_trait::async_trait;
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::acl::Privilege;
+use crate::ambient::Ambient;
+use crate::archive::Archive;
+use crate::channels::Channels;
+use crate::config::Config;
+use crate::config::NetworkConfig;
+use crate::filter::Filter;
+use crate::flood::Throttle;
+use crate::ignore::IgnoreList;
+use crate::isupport::ISupport;
+use crate::karma::Karma;
+use crate::llm::ChatBackend;
+use crate::markov::Markov;
+use crate::memory::Memory;
+use crate::memos::Memos;
+use crate::metrics::metrics;
+use crate::output::queue;
+use crate::output::say;
+use crate::output::send_privmsg;
+use crate::output::Outgoing;
+use crate::paste::Paste;
+use crate::persona::Personas;
+use crate::reminders::Reminders;
+use crate::schedule::Schedule;
+use crate::seen::Seen;
+use crate::trivia::Trivia;
+use crate::usage::Ledger;
+use crate::weather::Locations;
+use crate::word_games::WordGames;
+use crate::Error;
+
+mod ambient;
+mod calc;
+mod channels;
+mod define;
+mod export;
+mod forget;
+mod help;
+mod ignore;
+mod image;
+mod karma;
+mod optout;
+mod persona;
+mod price;
+mod remind;
+mod schedule;
+mod seen;
+mod stats;
+mod tell;
+mod tldr;
+mod trivia;
+mod usage;
+mod weather;
+mod wiki;
+mod word_games;
+
+/// Everything a command needs to know about the message that invoked it.
+pub struct Context<'a> {
+    pub config: &'a Config,
+    pub out: &'a Throttle,
+    /// Our own `nick!user@host`, as far as we know it.
+    pub source: &'a str,
+    pub isupport: &'a ISupport,
+    pub network: &'a NetworkConfig,
+    pub channels: &'a Channels,
+    pub ignores: &'a IgnoreList,
+    pub memory: &'a Memory,
+    pub ledger: &'a Arc<Ledger>,
+    pub seen: &'a Seen,
+    pub memos: &'a Memos,
+    pub reminders: &'a Reminders,
+    pub schedule: &'a Schedule,
+    pub karma: &'a Karma,
+    pub locations: &'a Locations,
+    pub trivia: &'a Arc<Trivia>,
+    pub word_games: &'a WordGames,
+    pub archive: Option<&'a Archive>,
+    pub markov: Option<&'a Arc<Markov>>,
+    pub ambient: &'a Ambient,
+    pub personas: &'a Personas,
+    pub commands: &'a Commands,
+    pub backend: &'a Arc<dyn ChatBackend>,
+    /// For replies from tasks that outlive the command, like anything that waits on the model.
+    pub outgoing: &'a mpsc::UnboundedSender<Outgoing>,
+    pub paste: Option<&'a Paste>,
+    /// Where replies go: the channel, or the sender for private messages.
+    pub target: &'a str,
+    pub nick: &'a str,
+    /// Who the sender's conversation is remembered as, see `memory::identity()`.
+    pub identity: &'a str,
+    pub privilege: Privilege,
+    pub dry_run: bool,
+    /// When pickles started serving, for `!stats`.
+    pub started: DateTime<Utc>,
+}
+
+impl Context<'_> {
+    pub fn reply(&self, msg: &str) -> Result<(), Error> {
+        send_privmsg(
+            self.out,
+            self.source,
+            self.isupport.line_length,
+            self.target,
+            msg,
+            self.dry_run,
+        )
+    }
+
+    /// Replies to the sender alone, wherever they asked.
+    pub fn reply_privately(&self, msg: &str) -> Result<(), Error> {
+        send_privmsg(
+            self.out,
+            self.source,
+            self.isupport.line_length,
+            self.nick,
+            msg,
+            self.dry_run,
+        )
+    }
+}
+
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// One line shown by `!help <name>`, starting with the usage.
+    fn help(&self) -> &'static str;
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Anyone
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error>;
+}
+
+/// The registry of `!commands`, checked before anything is sent to the model.
+pub struct Commands {
+    prefix: String,
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Commands {
+    /// `openai` is the HTTP client shared by everything that asks OpenAI.
+    pub fn new(config: &Config, openai: &reqwest::Client) -> Self {
+        let mut commands = Self {
+            prefix: config.command_prefix.clone(),
+            commands: Vec::new(),
+        };
+        commands.register(help::Help);
+        commands.register(forget::Forget);
+        commands.register(forget::ForgetAll);
+        commands.register(export::Export);
+        commands.register(optout::OptOut);
+        commands.register(optout::OptIn);
+        commands.register(channels::Join);
+        commands.register(channels::Part);
+        commands.register(ignore::Ignore);
+        commands.register(ignore::Unignore);
+        commands.register(persona::Persona);
+        commands.register(tldr::Tldr::new());
+        commands.register(usage::UsageCommand);
+        commands.register(seen::Seen);
+        commands.register(tell::Tell);
+        commands.register(remind::Remind);
+        commands.register(schedule::ScheduleCommand);
+        commands.register(karma::KarmaCommand);
+        commands.register(ambient::AmbientCommand);
+        commands.register(stats::Stats);
+        commands.register(calc::Calc);
+        commands.register(trivia::TriviaCommand);
+        commands.register(word_games::WordleCommand);
+        commands.register(word_games::HangmanCommand);
+        commands.register(define::Define::new());
+        commands.register(define::Urban::new());
+        commands.register(wiki::Wiki::new());
+        if let Some(images) = &config.images {
+            commands.register(image::Image::new(config, openai, images));
+        }
+        if let Some(weather) = &config.weather {
+            commands.register(weather::WeatherCommand::new(weather));
+        }
+        if !config.prices.is_empty() {
+            commands.register(price::Price::new(&config.prices));
+        }
+
+        commands
+    }
+
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Command> {
+        self.commands.iter().map(|command| command.as_ref())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Command> {
+        self.iter()
+            .find(|command| command.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Runs the command in `msg` if there is one. Returns whether `msg` was a command, so the
+    /// caller knows not to treat it as a normal message.
+    pub async fn dispatch(&self, ctx: &Context<'_>, msg: &str) -> Result<bool, Error> {
+        let Some(line) = msg.strip_prefix(&self.prefix) else {
+            return Ok(false);
+        };
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let Some(command) = self.get(name) else {
+            return Ok(false);
+        };
+
+        if ctx.privilege < command.privilege() {
+            warn!("{} isn't allowed to {}{}", ctx.nick, self.prefix, name);
+            ctx.reply(&format!("{}: nice try", ctx.nick))?;
+        } else {
+            command.run(ctx, args.trim()).await?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// Waits on `lookup` without holding up everything else, then says what it found, which can
+/// run to a few lines. Failures are counted as `kind` errors.
+fn look_up(
+    ctx: &Context<'_>,
+    kind: &'static str,
+    lookup: impl Future<Output = Result<Option<Vec<String>>, Error>> + Send + 'static,
+) {
+    let outgoing = ctx.outgoing.clone();
+    let target = ctx.target.to_string();
+    let nick = ctx.nick.to_string();
+    let paste = ctx.paste.cloned();
+    let filter = Filter::new(&ctx.config.filter, ctx.backend.clone());
+    tokio::spawn(
+        async move {
+            let lines = match lookup.await {
+                Ok(Some(lines)) => lines,
+                Ok(None) => {
+                    return queue(&outgoing, &target, format!("{}: never heard of it", nick))
+                }
+                Err(e) => {
+                    warn!("Unable to look that up for {}: {}", kind, e);
+                    metrics().errors.with_label_values(&[kind]).inc();
+                    return queue(
+                        &outgoing,
+                        &target,
+                        format!("{}: I can't look that up right now", nick),
+                    );
+                }
+            };
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            for line in lines {
+                let _ = tx.send(line);
+            }
+            drop(tx);
+            say(&outgoing, &target, rx, &nick, false, paste, filter).await;
+        }
+        .in_current_span(),
+    );
+}
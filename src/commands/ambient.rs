@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::Error;
+
+/// The kill switch for joining in on conversations uninvited.
+pub struct AmbientCommand;
+
+#[async_trait]
+impl Command for AmbientCommand {
+    fn name(&self) -> &'static str {
+        "ambient"
+    }
+
+    fn help(&self) -> &'static str {
+        "ambient [on|off] [#channel] - hush pickles, or let it join in on conversations again"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Trusted
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let mut args = args.split_whitespace();
+        let (switch, name) = (args.next(), args.next().unwrap_or(ctx.target));
+        let Some(channel) = ctx.channels.get(name) else {
+            return ctx.reply(&format!("{}: I'm not in {}", ctx.nick, name));
+        };
+        if !channel.ambient {
+            return ctx.reply(&format!(
+                "{}: I only speak when spoken to in {}",
+                ctx.nick, name
+            ));
+        }
+
+        let folded = ctx.channels.casemapping().fold(name);
+        match switch {
+            Some("off") => {
+                info!("Hushed in {} by {}", name, ctx.nick);
+                ctx.ambient.hush(&folded, true);
+                ctx.reply(&format!("{}: ok, I'll keep quiet in {}", ctx.nick, name))
+            }
+            Some("on") => {
+                info!("Unhushed in {} by {}", name, ctx.nick);
+                ctx.ambient.hush(&folded, false);
+                ctx.reply(&format!(
+                    "{}: ok, I might chime in in {} now and then",
+                    ctx.nick, name
+                ))
+            }
+            Some(_) => ctx.reply(&format!("{}: usage: {}", ctx.nick, self.help())),
+            None => match ctx.ambient.is_hushed(&folded) {
+                true => ctx.reply(&format!(
+                    "{}: I've been told to keep quiet in {}",
+                    ctx.nick, name
+                )),
+                false => ctx.reply(&format!(
+                    "{}: I chime in in {} now and then",
+                    ctx.nick, name
+                )),
+            },
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::calc;
+use crate::Error;
+
+/// Arithmetic, units and bases, worked out rather than guessed at by the model.
+pub struct Calc;
+
+#[async_trait]
+impl Command for Calc {
+    fn name(&self) -> &'static str {
+        "calc"
+    }
+
+    fn help(&self) -> &'static str {
+        "calc <expression> - maths, units and bases, e.g. 2^10, 5 ft 11 in to cm or 255 to hex"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            return ctx.reply(&format!("{}: calculate what?", ctx.nick));
+        }
+
+        match calc::evaluate(args) {
+            Ok(result) => ctx.reply(&format!("{}: {} = {}", ctx.nick, args, result)),
+            Err(Error::Calc(e)) => ctx.reply(&format!("{}: {}", ctx.nick, e)),
+            Err(e) => Err(e),
+        }
+    }
+}
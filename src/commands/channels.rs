@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::config::ChannelConfig;
+use crate::Error;
+
+pub struct Join;
+
+#[async_trait]
+impl Command for Join {
+    fn name(&self) -> &'static str {
+        "join"
+    }
+
+    fn help(&self) -> &'static str {
+        "join <#channel> - start hanging out in another channel"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Admin
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some(name) = args.split_whitespace().next() else {
+            return ctx.reply(&format!("{}: join where?", ctx.nick));
+        };
+
+        // Keep any trigger or prompt the config has for it
+        let channel = ctx
+            .network
+            .channel(name, ctx.channels.casemapping())
+            .cloned()
+            .unwrap_or_else(|| ChannelConfig::new(name));
+        if !ctx.channels.join(channel) {
+            return ctx.reply(&format!("{}: I'm already in {}", ctx.nick, name));
+        }
+
+        info!("Joining {} at {}'s request", name, ctx.nick);
+        ctx.out.send_join(name)?;
+
+        Ok(())
+    }
+}
+
+pub struct Part;
+
+#[async_trait]
+impl Command for Part {
+    fn name(&self) -> &'static str {
+        "part"
+    }
+
+    fn help(&self) -> &'static str {
+        "part [#channel] - leave a channel, this one by default"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Admin
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let name = args.split_whitespace().next().unwrap_or(ctx.target);
+        if !ctx.channels.part(name) {
+            return ctx.reply(&format!("{}: I'm not in {}", ctx.nick, name));
+        }
+
+        info!("Leaving {} at {}'s request", name, ctx.nick);
+        ctx.out.send(irc::proto::Command::PART(
+            name.to_string(),
+            Some(format!("{} told me to leave", ctx.nick)),
+        ))?;
+
+        Ok(())
+    }
+}
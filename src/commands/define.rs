@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+
+use super::look_up;
+use super::Command;
+use super::Context;
+use crate::dictionary::Dictionary;
+use crate::Error;
+
+/// Looks a word up in the Free Dictionary.
+pub struct Define {
+    dictionary: Dictionary,
+}
+
+impl Define {
+    pub fn new() -> Self {
+        Self {
+            dictionary: Dictionary::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Define {
+    fn name(&self) -> &'static str {
+        "define"
+    }
+
+    fn help(&self) -> &'static str {
+        "define <word> - what a word means, from Wiktionary"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            return ctx.reply(&format!("{}: define what?", ctx.nick));
+        }
+
+        let dictionary = self.dictionary.clone();
+        let word = args.to_string();
+        look_up(ctx, "define", async move { dictionary.define(&word).await });
+        Ok(())
+    }
+}
+
+/// Looks a term up in Urban Dictionary, which is rarely safe for work, so only in private
+/// messages and channels with `nsfw = true`.
+pub struct Urban {
+    dictionary: Dictionary,
+}
+
+impl Urban {
+    pub fn new() -> Self {
+        Self {
+            dictionary: Dictionary::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Urban {
+    fn name(&self) -> &'static str {
+        "ud"
+    }
+
+    fn help(&self) -> &'static str {
+        "ud <term> - what Urban Dictionary says a term means, in NSFW channels or privately"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            return ctx.reply(&format!("{}: look up what?", ctx.nick));
+        }
+        let private = ctx.target == ctx.nick;
+        if !private
+            && !ctx
+                .channels
+                .get(ctx.target)
+                .is_some_and(|channel| channel.nsfw)
+        {
+            return ctx.reply(&format!(
+                "{}: not in here, ask me in a private message",
+                ctx.nick
+            ));
+        }
+
+        let dictionary = self.dictionary.clone();
+        let term = args.to_string();
+        look_up(ctx, "ud", async move { dictionary.urban(&term).await });
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::output::queue;
+use crate::Error;
+
+/// Hands people everything pickles remembers about them, in a private message.
+pub struct Export;
+
+#[async_trait]
+impl Command for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn help(&self) -> &'static str {
+        "export - get what I remember about our conversation as JSON, in a private message"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        let Some(conversation) = ctx.memory.export(ctx.identity) else {
+            return ctx.reply(&format!("{}: I don't remember you anyway", ctx.nick));
+        };
+        if ctx.target != ctx.nick {
+            ctx.reply(&format!("{}: check your private messages", ctx.nick))?;
+        }
+
+        let Some(paste) = ctx.paste.cloned() else {
+            let json = serde_json::to_string(&conversation).expect("conversations serialize");
+            return ctx.reply_privately(&json);
+        };
+        let json = serde_json::to_string_pretty(&conversation).expect("conversations serialize");
+        let outgoing = ctx.outgoing.clone();
+        let nick = ctx.nick.to_string();
+        tokio::spawn(
+            async move {
+                let reply = match paste.upload(&json).await {
+                    Ok(url) => format!("here's what I remember about you: {}", url),
+                    Err(e) => {
+                        warn!("Unable to paste export for {}: {}", nick, e);
+                        "I couldn't upload it, try again in a bit".to_string()
+                    }
+                };
+                queue(&outgoing, &nick, reply);
+            }
+            .in_current_span(),
+        );
+
+        Ok(())
+    }
+}
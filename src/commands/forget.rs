@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::Error;
+
+/// Lets someone start over when their conversation has gone off the rails.
+pub struct Forget;
+
+#[async_trait]
+impl Command for Forget {
+    fn name(&self) -> &'static str {
+        "forget"
+    }
+
+    fn help(&self) -> &'static str {
+        "forget - wipe what I remember about our conversation"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        if ctx.memory.forget(ctx.identity).await {
+            info!("Forgot {} at their request", ctx.nick);
+            ctx.reply(&format!("{}: who are you again?", ctx.nick))
+        } else {
+            ctx.reply(&format!("{}: I don't remember you anyway", ctx.nick))
+        }
+    }
+}
+
+pub struct ForgetAll;
+
+#[async_trait]
+impl Command for ForgetAll {
+    fn name(&self) -> &'static str {
+        "forgetall"
+    }
+
+    fn help(&self) -> &'static str {
+        "forgetall - wipe every conversation I remember"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Admin
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        let forgotten = ctx.memory.forget_all().await;
+        info!(
+            "Forgot {} conversations at {}'s request",
+            forgotten, ctx.nick
+        );
+        ctx.reply(&format!("{}: my mind is a blank slate", ctx.nick))
+    }
+}
@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::Error;
+
+pub struct Help;
+
+#[async_trait]
+impl Command for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn help(&self) -> &'static str {
+        "help [command] - list commands, or explain one"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let prefix = ctx.commands.prefix();
+        if args.is_empty() {
+            let names = ctx
+                .commands
+                .iter()
+                .filter(|command| ctx.privilege >= command.privilege())
+                .map(|command| format!("{}{}", prefix, command.name()))
+                .collect::<Vec<_>>();
+            return ctx.reply(&format!("commands: {}", names.join(" ")));
+        }
+
+        let name = args.trim_start_matches(prefix);
+        match ctx.commands.get(name) {
+            Some(command) => ctx.reply(&format!("{}{}", prefix, command.help())),
+            None => ctx.reply(&format!("{}: never heard of {}{}", ctx.nick, prefix, name)),
+        }
+    }
+}
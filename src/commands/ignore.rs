@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::ignore::IgnoreList;
+use crate::Error;
+
+pub struct Ignore;
+
+#[async_trait]
+impl Command for Ignore {
+    fn name(&self) -> &'static str {
+        "ignore"
+    }
+
+    fn help(&self) -> &'static str {
+        "ignore [nick|hostmask] - stop listening to someone, or list who I'm ignoring"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Admin
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some(target) = args.split_whitespace().next() else {
+            let masks = ctx.ignores.masks();
+            return match masks.is_empty() {
+                true => ctx.reply(&format!("{}: I'm listening to everyone", ctx.nick)),
+                false => ctx.reply(&format!("ignoring: {}", masks.join(" "))),
+            };
+        };
+
+        let mask = IgnoreList::mask(target);
+        if ctx.ignores.ignore(&mask).await {
+            info!("Ignoring {} at {}'s request", mask, ctx.nick);
+            ctx.reply(&format!("{}: la la la I can't hear {}", ctx.nick, mask))
+        } else {
+            ctx.reply(&format!("{}: already ignoring {}", ctx.nick, mask))
+        }
+    }
+}
+
+pub struct Unignore;
+
+#[async_trait]
+impl Command for Unignore {
+    fn name(&self) -> &'static str {
+        "unignore"
+    }
+
+    fn help(&self) -> &'static str {
+        "unignore <nick|hostmask> - start listening to someone again"
+    }
+
+    fn privilege(&self) -> Privilege {
+        Privilege::Admin
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some(target) = args.split_whitespace().next() else {
+            return ctx.reply(&format!("{}: unignore who?", ctx.nick));
+        };
+
+        let mask = IgnoreList::mask(target);
+        if ctx.ignores.unignore(&mask).await {
+            info!("No longer ignoring {} at {}'s request", mask, ctx.nick);
+            ctx.reply(&format!(
+                "{}: fine, I'll listen to {} again",
+                ctx.nick, mask
+            ))
+        } else {
+            ctx.reply(&format!("{}: I wasn't ignoring {}", ctx.nick, mask))
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use async_openai::error::OpenAIError;
+
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::config::Config;
+use crate::config::ImagesConfig;
+use crate::images::Images;
+use crate::metrics::metrics;
+use crate::output::queue;
+use crate::ratelimit::RateLimiter;
+use crate::Error;
+
+pub struct Image {
+    images: Images,
+    limiter: RateLimiter,
+}
+
+impl Image {
+    pub fn new(config: &Config, openai: &reqwest::Client, images: &ImagesConfig) -> Self {
+        Self {
+            images: Images::new(&config.openai, openai, images),
+            limiter: RateLimiter::new(&images.rate_limit),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Image {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn help(&self) -> &'static str {
+        "image <prompt> - draw a picture"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            return ctx.reply(&format!("{}: a picture of what?", ctx.nick));
+        }
+        if ctx.privilege < Privilege::Admin {
+            if let Err(wait) = self.limiter.check(ctx.nick) {
+                return ctx.reply(&format!(
+                    "{}: my crayons need sharpening, try again in {}s",
+                    ctx.nick,
+                    wait.as_secs() + 1
+                ));
+            }
+        }
+
+        let images = self.images.clone();
+        let outgoing = ctx.outgoing.clone();
+        let target = ctx.target.to_string();
+        let nick = ctx.nick.to_string();
+        let prompt = args.to_string();
+        tokio::spawn(
+            async move {
+                let reply = match images.generate(&prompt, &nick).await {
+                    Ok(url) => format!("{}: {}", nick, url),
+                    Err(e) => {
+                        warn!("Unable to draw {:?}: {}", prompt, e);
+                        metrics().errors.with_label_values(&["image"]).inc();
+                        match e {
+                            // Usually the safety system, which is worth passing on
+                            Error::OpenAI(OpenAIError::ApiError(e)) => {
+                                format!("{}: DALL-E says no: {}", nick, e.message)
+                            }
+                            _ => format!("{}: I dropped my crayons, try again in a bit", nick),
+                        }
+                    }
+                };
+                queue(&outgoing, &target, reply);
+            }
+            .in_current_span(),
+        );
+
+        Ok(())
+    }
+}
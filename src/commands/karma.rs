@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::Error;
+
+/// How many names `!karma top` lists.
+const TOP: usize = 5;
+
+/// Shows the points handed out with `nick++` and `nick--`.
+pub struct KarmaCommand;
+
+#[async_trait]
+impl Command for KarmaCommand {
+    fn name(&self) -> &'static str {
+        "karma"
+    }
+
+    fn help(&self) -> &'static str {
+        "karma [nick|top] - points from nick++ and nick-- in channels"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.eq_ignore_ascii_case("top") {
+            let top = ctx.karma.top(TOP);
+            if top.is_empty() {
+                return ctx.reply(&format!("{}: nobody has any karma yet", ctx.nick));
+            }
+            let top = top
+                .into_iter()
+                .map(|(name, score)| format!("{} ({})", name, score))
+                .collect::<Vec<_>>();
+            return ctx.reply(&format!("top karma: {}", top.join(", ")));
+        }
+
+        let who = args.split_whitespace().next().unwrap_or(ctx.nick);
+        ctx.reply(&format!("{} has {} karma", who, ctx.karma.get(who)))
+    }
+}
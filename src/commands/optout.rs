@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use tokio::task;
+
+use super::Command;
+use super::Context;
+use crate::Error;
+
+/// For people who'd rather nothing they say to pickles is kept.
+pub struct OptOut;
+
+#[async_trait]
+impl Command for OptOut {
+    fn name(&self) -> &'static str {
+        "optout"
+    }
+
+    fn help(&self) -> &'static str {
+        "optout - forget our conversation and never remember anything you say to me again"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        ctx.seen.forget_words(ctx.nick).await;
+        if let Some(archive) = ctx.archive {
+            archive.forget(ctx.nick, ctx.channels.casemapping()).await;
+        }
+        if let Some(markov) = ctx.markov {
+            let (markov, config, nick) = (markov.clone(), ctx.config.clone(), ctx.nick.to_string());
+            let result = task::spawn_blocking(move || markov.forget(&config, &nick))
+                .await
+                .unwrap_or_else(|e| Err(Error::Markov(e.to_string())));
+            if let Err(e) = result {
+                warn!(
+                    "Unable to drop what {} said from the Markov chain: {}",
+                    ctx.nick, e
+                );
+            }
+        }
+        if ctx.memory.opt_out(ctx.identity).await {
+            info!("Opted {} out of memory at their request", ctx.nick);
+            ctx.reply(&format!(
+                "{}: ok, I've forgotten you and won't remember anything you say from now on",
+                ctx.nick
+            ))
+        } else {
+            ctx.reply(&format!("{}: I already don't remember you", ctx.nick))
+        }
+    }
+}
+
+pub struct OptIn;
+
+#[async_trait]
+impl Command for OptIn {
+    fn name(&self) -> &'static str {
+        "optin"
+    }
+
+    fn help(&self) -> &'static str {
+        "optin - let me remember our conversation again"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        if let Some(Err(e)) = ctx.markov.map(|markov| markov.remember(ctx.nick)) {
+            warn!(
+                "Unable to let the Markov chain learn from {}: {}",
+                ctx.nick, e
+            );
+        }
+        if ctx.memory.opt_in(ctx.identity).await {
+            info!("Opted {} back in to memory at their request", ctx.nick);
+            ctx.reply(&format!("{}: ok, I'll remember you again", ctx.nick))
+        } else {
+            ctx.reply(&format!("{}: I'm already remembering you", ctx.nick))
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::Error;
+
+/// Switches the system prompt for a channel, or for one person when used in private.
+pub struct Persona;
+
+#[async_trait]
+impl Command for Persona {
+    fn name(&self) -> &'static str {
+        "persona"
+    }
+
+    fn help(&self) -> &'static str {
+        "persona list|set <name>|reset - change who I am, for this channel or in private just for you"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let mut args = args.split_whitespace();
+        let subcommand = args.next().unwrap_or("list");
+        let is_private = ctx.target == ctx.nick;
+        if subcommand != "list" && !is_private && ctx.privilege < Privilege::Trusted {
+            return ctx.reply(&format!(
+                "{}: only trusted folks can change me here, try it in private",
+                ctx.nick
+            ));
+        }
+
+        match (subcommand, args.next()) {
+            ("list", _) => {
+                let names = ctx.personas.names();
+                match names.is_empty() {
+                    true => ctx.reply(&format!("{}: I'm only ever myself", ctx.nick)),
+                    false => ctx.reply(&format!("personas: {}", names.join(" "))),
+                }
+            }
+            ("set", Some(name)) if ctx.personas.exists(name) => {
+                info!("{} switched {} to {}", ctx.nick, ctx.target, name);
+                ctx.personas.set(ctx.target, Some(name));
+                ctx.reply(&format!("{}: I'm {} now", ctx.nick, name))
+            }
+            ("set", Some(name)) => ctx.reply(&format!(
+                "{}: I don't know how to be {}, try {}persona list",
+                ctx.nick,
+                name,
+                ctx.commands.prefix()
+            )),
+            ("reset", _) => {
+                info!("{} reset the persona for {}", ctx.nick, ctx.target);
+                ctx.personas.set(ctx.target, None);
+                ctx.reply(&format!("{}: back to my old self", ctx.nick))
+            }
+            _ => ctx.reply(&format!("{}{}", ctx.commands.prefix(), self.help())),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+
+use super::look_up;
+use super::Command;
+use super::Context;
+use crate::config::PriceConfig;
+use crate::prices::Prices;
+use crate::Error;
+
+/// What a stock or coin is at, from the configured quote APIs.
+pub struct Price {
+    prices: Prices,
+}
+
+impl Price {
+    pub fn new(sources: &[PriceConfig]) -> Self {
+        Self {
+            prices: Prices::new(sources),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Price {
+    fn name(&self) -> &'static str {
+        "price"
+    }
+
+    fn help(&self) -> &'static str {
+        "price <ticker> - what a stock or coin is at, e.g. AAPL or BTC"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some(symbol) = args.split_whitespace().next() else {
+            return ctx.reply(&format!("{}: the price of what?", ctx.nick));
+        };
+
+        let prices = self.prices.clone();
+        let symbol = symbol.trim_start_matches('$').to_string();
+        look_up(ctx, "price", async move {
+            Ok(prices.quote(&symbol).await?.map(|quote| vec![quote]))
+        });
+        Ok(())
+    }
+}
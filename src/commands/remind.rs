@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+
+use chrono::TimeDelta;
+use chrono::Utc;
+
+use super::Command;
+use super::Context;
+use crate::reminders::parse_delay;
+use crate::reminders::Reminder;
+use crate::Error;
+
+/// The furthest ahead anyone can be reminded of anything.
+const MAX_DELAY: TimeDelta = TimeDelta::days(365);
+
+/// Sends someone's message back to them later.
+pub struct Remind;
+
+#[async_trait]
+impl Command for Remind {
+    fn name(&self) -> &'static str {
+        "remind"
+    }
+
+    fn help(&self) -> &'static str {
+        "remind me in <20m|1h30m|2 days> to <something> - get pinged about it later"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let args = args.strip_prefix("me ").unwrap_or(args).trim_start();
+        let parsed = args
+            .strip_prefix("in ")
+            .and_then(parse_delay)
+            .map(|(delay, text)| (delay, text.strip_prefix("to ").unwrap_or(text).trim()))
+            .filter(|(_, text)| !text.is_empty());
+        let Some((delay, text)) = parsed else {
+            return ctx.reply(&format!("{}{}", ctx.commands.prefix(), self.help()));
+        };
+        if delay > MAX_DELAY {
+            return ctx.reply(&format!(
+                "{}: I can't promise I'll still be here then",
+                ctx.nick
+            ));
+        }
+
+        let reminder = Reminder {
+            id: None,
+            nick: ctx.nick.to_string(),
+            target: ctx.target.to_string(),
+            due: Utc::now() + delay,
+            text: text.to_string(),
+        };
+        match ctx.reminders.add(reminder).await {
+            true => ctx.reply(&format!("{}: ok, I'll remind you", ctx.nick)),
+            false => ctx.reply(&format!(
+                "{}: you've got enough to remember already",
+                ctx.nick
+            )),
+        }
+    }
+}
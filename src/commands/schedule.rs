@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::Error;
+
+/// Shows what's scheduled to be said and when.
+pub struct ScheduleCommand;
+
+#[async_trait]
+impl Command for ScheduleCommand {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn help(&self) -> &'static str {
+        "schedule list - what I've been told to say when, and where"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if !matches!(args, "" | "list") {
+            return ctx.reply(&format!("{}{}", ctx.commands.prefix(), self.help()));
+        }
+
+        let jobs = ctx.schedule.list();
+        if jobs.is_empty() {
+            return ctx.reply(&format!("{}: nothing's scheduled", ctx.nick));
+        }
+        for (job, next) in jobs {
+            ctx.reply(&format!(
+                "{} ({}) in {}: {}",
+                job.name,
+                job.cron,
+                job.channels.join(", "),
+                match next {
+                    Some(next) => format!("next {}", next),
+                    None => String::from("never again"),
+                }
+            ))?;
+        }
+
+        Ok(())
+    }
+}
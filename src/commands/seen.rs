@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+
+use chrono::Utc;
+
+use super::Command;
+use super::Context;
+use crate::seen::ago;
+use crate::Error;
+
+/// Says when somebody was last around and what they were doing.
+pub struct Seen;
+
+#[async_trait]
+impl Command for Seen {
+    fn name(&self) -> &'static str {
+        "seen"
+    }
+
+    fn help(&self) -> &'static str {
+        "seen <nick> - when and where somebody was last active"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some(who) = args.split_whitespace().next() else {
+            return ctx.reply(&format!("{}{}", ctx.commands.prefix(), self.help()));
+        };
+        if who.eq_ignore_ascii_case(ctx.nick) {
+            return ctx.reply(&format!("{}: I'm looking right at you", ctx.nick));
+        }
+
+        let reply = match ctx.seen.get(who) {
+            Some(sighting) => format!(
+                "{}: {} was last seen {} {}",
+                ctx.nick,
+                sighting.nick,
+                ago(Utc::now() - sighting.at),
+                sighting.describe()
+            ),
+            None => format!("{}: I haven't seen {}", ctx.nick, who),
+        };
+        ctx.reply(&reply)
+    }
+}
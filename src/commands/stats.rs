@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+
+use chrono::Utc;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::metrics::by_label;
+use crate::metrics::metrics;
+use crate::Error;
+
+/// How pickles has been doing since it started, in more detail for admins.
+pub struct Stats;
+
+#[async_trait]
+impl Command for Stats {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn help(&self) -> &'static str {
+        "stats - how long I've been up and how busy I've been"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, _args: &str) -> Result<(), Error> {
+        let metrics = metrics();
+        let uptime = (Utc::now() - ctx.started).num_minutes();
+        let received = by_label(&metrics.messages_received, "network");
+        let requests = by_label(&metrics.openai_requests, "outcome");
+        let errors = by_label(&metrics.errors, "kind");
+        let latency = match metrics.average_completion_seconds() {
+            Some(seconds) => format!(" in {:.1}s on average", seconds),
+            None => String::new(),
+        };
+        ctx.reply(&format!(
+            "{}: up {}d {}h {}m, seen {} messages, answered {} times{}, {} errors",
+            ctx.nick,
+            uptime / (24 * 60),
+            uptime / 60 % 24,
+            uptime % 60,
+            received.values().sum::<u64>(),
+            requests.get("ok").copied().unwrap_or_default(),
+            latency,
+            errors.values().sum::<u64>()
+        ))?;
+        if ctx.privilege < Privilege::Admin {
+            return Ok(());
+        }
+
+        let sent = by_label(&metrics.messages_sent, "network");
+        let reconnects = by_label(&metrics.reconnects, "network");
+        let networks = received
+            .iter()
+            .map(|(network, received)| {
+                format!(
+                    "{} {} in, {} out, {} reconnects",
+                    network,
+                    received,
+                    sent.get(network).copied().unwrap_or_default(),
+                    reconnects.get(network).copied().unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>();
+        let errors = errors
+            .iter()
+            .map(|(kind, count)| format!("{} {}", kind, count))
+            .collect::<Vec<_>>();
+        let tokens = by_label(&metrics.usage_tokens, "kind");
+        ctx.reply(&format!(
+            "{}: {}; failed requests {}; errors: {}; tokens {} prompt, {} completion",
+            ctx.nick,
+            networks.join(", "),
+            requests.get("error").copied().unwrap_or_default(),
+            match errors.is_empty() {
+                true => String::from("none"),
+                false => errors.join(", "),
+            },
+            tokens.get("prompt").copied().unwrap_or_default(),
+            tokens.get("completion").copied().unwrap_or_default()
+        ))
+    }
+}
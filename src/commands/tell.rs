@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+
+use chrono::Utc;
+
+use super::Command;
+use super::Context;
+use crate::memos::Memo;
+use crate::Error;
+
+/// Leaves a message for somebody who isn't around.
+pub struct Tell;
+
+#[async_trait]
+impl Command for Tell {
+    fn name(&self) -> &'static str {
+        "tell"
+    }
+
+    fn help(&self) -> &'static str {
+        "tell <nick> <message> - pass a message on the next time they speak or join"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let Some((to, text)) = args
+            .split_once(' ')
+            .map(|(to, text)| (to, text.trim()))
+            .filter(|(_, text)| !text.is_empty())
+        else {
+            return ctx.reply(&format!("{}{}", ctx.commands.prefix(), self.help()));
+        };
+        if to.eq_ignore_ascii_case(ctx.nick) {
+            return ctx.reply(&format!("{}: tell yourself", ctx.nick));
+        }
+
+        let memo = Memo {
+            from: ctx.nick.to_string(),
+            to: to.to_string(),
+            at: Utc::now(),
+            text: text.to_string(),
+        };
+        match ctx.memos.leave(memo).await {
+            true => ctx.reply(&format!(
+                "{}: ok, I'll tell {} when I see them",
+                ctx.nick, to
+            )),
+            false => ctx.reply(&format!(
+                "{}: {} already has a pile of messages waiting",
+                ctx.nick, to
+            )),
+        }
+    }
+}
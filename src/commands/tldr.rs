@@ -0,0 +1,187 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use std::future::Future;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::fetch::Fetch;
+use crate::llm::ChatMessage;
+use crate::markov::parse_line;
+use crate::output::queue;
+use crate::Error;
+
+const PROMPT: &str = "Summarize this web page in two or three short sentences for an IRC \
+                      channel. Plain text only, no markdown.";
+
+const CHANNEL_PROMPT: &str = "Summarize this IRC conversation in two or three short sentences \
+                              for someone who just got back: who talked about what, and \
+                              anything that was decided. Plain text only, no markdown.";
+
+/// Lines of the channel summarized when `!tldr` isn't told how many.
+const DEFAULT_LINES: usize = 50;
+
+/// Most lines of the channel summarized at once. Without an `[archive]` there are only as
+/// many as `Channels` keeps.
+const MAX_LINES: usize = 500;
+
+/// Reads a link, or catches up on the channel, so nobody else has to.
+pub struct Tldr {
+    fetch: Fetch,
+}
+
+impl Tldr {
+    pub fn new() -> Self {
+        Self {
+            fetch: Fetch::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Tldr {
+    fn name(&self) -> &'static str {
+        "tldr"
+    }
+
+    fn help(&self) -> &'static str {
+        "tldr [url|lines] - summarize a web page, or what's been said here lately"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let in_channel = ctx.channels.get(ctx.target).is_some();
+        if args.is_empty() && !in_channel {
+            return ctx.reply(&format!("{}: tldr of what?", ctx.nick));
+        }
+        if ctx.privilege < Privilege::Trusted {
+            let quota = &ctx.config.quota;
+            let casemapping = ctx.isupport.casemapping;
+            let exhausted =
+                ctx.ledger
+                    .exhausted(quota, ctx.nick, ctx.identity, ctx.target, casemapping);
+            if let Some(exhausted) = exhausted {
+                return ctx.reply(&exhausted.apology(ctx.nick));
+            }
+        }
+
+        // Any number at all is a count of lines, however many more than we'd summarize
+        if args.bytes().all(|b| b.is_ascii_digit()) {
+            if !in_channel {
+                return ctx.reply(&format!("{}: that only works in a channel", ctx.nick));
+            }
+            let n = match args {
+                "" => DEFAULT_LINES,
+                n => n.parse().unwrap_or(MAX_LINES),
+            };
+            return catch_up(ctx, n.clamp(1, MAX_LINES)).await;
+        }
+
+        let fetch = self.fetch.clone();
+        let url = args.to_string();
+        let page = async move { Ok(fetch.page(&url).await?.excerpt()) };
+        summarize(ctx, PROMPT, page, args, "couldn't read that one");
+
+        Ok(())
+    }
+}
+
+/// Asks the model to sum up the last `n` lines said in the channel, from the `[archive]` if
+/// there is one or else what pickles remembers of it. Commands, this one included, are left
+/// out.
+async fn catch_up(ctx: &Context<'_>, n: usize) -> Result<(), Error> {
+    let prefix = &ctx.config.command_prefix;
+    let is_command = |text: &str| text.starts_with(prefix.as_str());
+    // One more, since this `!tldr` is already in there
+    let lines = match ctx.archive {
+        Some(archive) => archive
+            .recent(&ctx.channels.casemapping().fold(ctx.target), n + 1)
+            .await
+            .into_iter()
+            .map(|line| line.to_string())
+            .filter(|line| !parse_line(line).is_some_and(|(_, text)| is_command(text)))
+            .collect::<Vec<_>>(),
+        None => ctx
+            .channels
+            .scrollback(ctx.target, n + 1)
+            .into_iter()
+            .filter(|line| !is_command(&line.text))
+            .map(|line| {
+                format!(
+                    "[{}] <{}> {}",
+                    line.at.format("%H:%M"),
+                    line.nick,
+                    line.text
+                )
+            })
+            .collect(),
+    };
+    let lines = lines[lines.len().saturating_sub(n)..].to_vec();
+    if lines.is_empty() {
+        return ctx.reply(&format!("{}: nothing's been said here lately", ctx.nick));
+    }
+
+    let text = async move { Ok(lines.join("\n")) };
+    summarize(
+        ctx,
+        CHANNEL_PROMPT,
+        text,
+        ctx.target,
+        "couldn't make sense of it",
+    );
+
+    Ok(())
+}
+
+/// Answers with the model's summary of whatever `text` turns out to be, on one line. Reading
+/// and summarizing takes a while, so it's done without holding up everything else. `what` is
+/// for the log and `apology` for whoever asked, if it can't be done.
+fn summarize(
+    ctx: &Context<'_>,
+    prompt: &'static str,
+    text: impl Future<Output = Result<String, Error>> + Send + 'static,
+    what: &str,
+    apology: &'static str,
+) {
+    let backend = ctx.backend.clone();
+    let ledger = ctx.ledger.clone();
+    let outgoing = ctx.outgoing.clone();
+    let target = ctx.target.to_string();
+    let nick = ctx.nick.to_string();
+    let identity = ctx.identity.to_string();
+    let what = what.to_string();
+    tokio::spawn(
+        async move {
+            let summary = async {
+                let text = text.await?;
+                backend
+                    .complete(&[ChatMessage::system(prompt), ChatMessage::user(text)])
+                    .await
+            };
+
+            let reply = match summary.await {
+                Ok(summary) => {
+                    ledger
+                        .record(&nick, &identity, &target, summary.usage)
+                        .await;
+                    format!(
+                        "{}: {}",
+                        nick,
+                        summary
+                            .content
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    )
+                }
+                Err(e) => {
+                    warn!("Unable to summarize {}: {}", what, e);
+                    format!("{}: {}, sorry", nick, apology)
+                }
+            };
+            queue(&outgoing, &target, reply);
+        }
+        .in_current_span(),
+    );
+}
@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::acl::Privilege;
+use crate::config::TriviaSource;
+use crate::Error;
+
+/// How many names `!trivia top` lists.
+const TOP: usize = 5;
+
+/// Runs trivia games in channels. Answers are picked up by the trivia handler.
+pub struct TriviaCommand;
+
+#[async_trait]
+impl Command for TriviaCommand {
+    fn name(&self) -> &'static str {
+        "trivia"
+    }
+
+    fn help(&self) -> &'static str {
+        "trivia [topic|stop|top|score [nick]] - play a game of trivia in the channel"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let key = ctx.isupport.casemapping.fold(ctx.target);
+        match subcommand.to_lowercase().as_str() {
+            "top" => {
+                let top = ctx.trivia.top(TOP);
+                if top.is_empty() {
+                    return ctx.reply(&format!("{}: nobody has scored yet", ctx.nick));
+                }
+                let top = top
+                    .into_iter()
+                    .map(|(nick, score)| format!("{} ({})", nick, score))
+                    .collect::<Vec<_>>();
+                return ctx.reply(&format!("trivia champions: {}", top.join(", ")));
+            }
+            "score" => {
+                let who = rest.split_whitespace().next().unwrap_or(ctx.nick);
+                return ctx.reply(&format!(
+                    "{} has {} trivia points",
+                    who,
+                    ctx.trivia.score(who)
+                ));
+            }
+            "stop" => {
+                let Some(started_by) = ctx.trivia.started_by(&key) else {
+                    return ctx.reply(&format!("{}: there's no game going", ctx.nick));
+                };
+                if ctx.privilege < Privilege::Trusted
+                    && !ctx.isupport.casemapping.eq(&started_by, ctx.nick)
+                {
+                    return ctx.reply(&format!("{}: it's {}'s game", ctx.nick, started_by));
+                }
+                return match ctx.trivia.stop(&key) {
+                    Some(stopped) => ctx.reply(&stopped),
+                    None => Ok(()),
+                };
+            }
+            _ => {}
+        }
+
+        if ctx.target == ctx.nick {
+            return ctx.reply(&format!("{}: trivia needs a channel", ctx.nick));
+        }
+        let topic = (!args.is_empty()).then(|| args.to_string());
+        if topic.is_some() && ctx.trivia.source() == TriviaSource::OpenTdb {
+            return ctx.reply(&format!(
+                "{}: I only have questions about everything",
+                ctx.nick
+            ));
+        }
+        if ctx.trivia.source() == TriviaSource::Model && ctx.privilege < Privilege::Trusted {
+            let quota = &ctx.config.quota;
+            let casemapping = ctx.isupport.casemapping;
+            let exhausted =
+                ctx.ledger
+                    .exhausted(quota, ctx.nick, ctx.identity, ctx.target, casemapping);
+            if let Some(exhausted) = exhausted {
+                return ctx.reply(&exhausted.apology(ctx.nick));
+            }
+        }
+
+        let trivia = ctx.trivia.clone();
+        let backend = ctx.backend.clone();
+        let ledger = ctx.ledger.clone();
+        let nick = ctx.nick.to_string();
+        let identity = ctx.identity.to_string();
+        let target = ctx.target.to_string();
+        let questions = async move {
+            let (questions, usage) = trivia.questions(topic.as_deref(), backend.as_ref()).await?;
+            if let Some(usage) = usage {
+                ledger.record(&nick, &identity, &target, usage).await;
+            }
+            Ok(questions)
+        };
+        match ctx
+            .trivia
+            .start(ctx.target, &key, ctx.nick, ctx.outgoing, questions)
+        {
+            true => ctx.reply("Trivia time! Answer in the channel, the sooner the better."),
+            false => ctx.reply(&format!("{}: there's a game going already", ctx.nick)),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+
+use chrono::Days;
+use chrono::Utc;
+
+use super::Command;
+use super::Context;
+use crate::memory;
+use crate::usage::Spent;
+use crate::Error;
+
+/// How many big spenders `!usage top` names.
+const TOP: usize = 5;
+
+/// Shows who is running up the OpenAI bill.
+pub struct UsageCommand;
+
+#[async_trait]
+impl Command for UsageCommand {
+    fn name(&self) -> &'static str {
+        "usage"
+    }
+
+    fn help(&self) -> &'static str {
+        "usage [nick|#channel|top] - tokens spent today and over the last 30 days"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        let today = Utc::now().date_naive();
+        let month = today - Days::new(29);
+        let describe = |spent: Spent| {
+            let tokens = format!(
+                "{} requests, {} tokens",
+                spent.requests,
+                spent.usage.total()
+            );
+            match ctx.config.openai.cost(spent.usage) {
+                Some(cost) => format!("{} (${:.4})", tokens, cost),
+                None => tokens,
+            }
+        };
+
+        if args.eq_ignore_ascii_case("top") {
+            let top = ctx.ledger.top(month, TOP);
+            if top.is_empty() {
+                return ctx.reply(&format!("{}: nobody has cost anything yet", ctx.nick));
+            }
+            let top = top
+                .into_iter()
+                .map(|(identity, spent)| format!("{} {}", memory::name(&identity), describe(spent)))
+                .collect::<Vec<_>>();
+            return ctx.reply(&format!("last 30 days: {}", top.join(", ")));
+        }
+
+        // Spending is filed under `memory::identity()`, so our own follows our account
+        let who = if args.is_empty() { ctx.nick } else { args };
+        let matches = |identity: &str, channel: &str| {
+            if ctx.isupport.is_channel(who) {
+                ctx.isupport.casemapping.eq(channel, who)
+            } else if args.is_empty() {
+                ctx.isupport.casemapping.eq(identity, ctx.identity)
+            } else {
+                ctx.isupport.casemapping.eq(memory::name(identity), who)
+            }
+        };
+        let today_spent = ctx.ledger.spent(today, matches);
+        let month_spent = ctx.ledger.spent(month, matches);
+        ctx.reply(&format!(
+            "{}: today {}, last 30 days {}",
+            who,
+            describe(today_spent),
+            describe(month_spent)
+        ))
+    }
+}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use super::Command;
+use super::Context;
+use crate::config::WeatherConfig;
+use crate::metrics::metrics;
+use crate::output::queue;
+use crate::weather::Weather;
+use crate::Error;
+
+pub struct WeatherCommand {
+    weather: Weather,
+}
+
+impl WeatherCommand {
+    pub fn new(config: &WeatherConfig) -> Self {
+        Self {
+            weather: Weather::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for WeatherCommand {
+    fn name(&self) -> &'static str {
+        "weather"
+    }
+
+    fn help(&self) -> &'static str {
+        "weather [location] | weather set <location> - the weather where you say, or where you said before"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if let Some(location) = args
+            .strip_prefix("set")
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+        {
+            let location = location.trim();
+            if location.is_empty() {
+                return ctx.reply(&format!("{}: set it to where?", ctx.nick));
+            }
+            ctx.locations.set(ctx.identity, location).await;
+            return ctx.reply(&format!(
+                "{}: I'll remember you're in {}",
+                ctx.nick, location
+            ));
+        }
+        let location = match args {
+            "" => match ctx.locations.get(ctx.identity) {
+                Some(location) => location,
+                None => {
+                    return ctx.reply(&format!(
+                        "{}: where? Tell me once with {}weather set <location>",
+                        ctx.nick,
+                        ctx.commands.prefix()
+                    ))
+                }
+            },
+            location => location.to_string(),
+        };
+
+        let weather = self.weather.clone();
+        let outgoing = ctx.outgoing.clone();
+        let target = ctx.target.to_string();
+        let nick = ctx.nick.to_string();
+        tokio::spawn(
+            async move {
+                let reply = match weather.current(&location).await {
+                    Ok(weather) => format!("{}: {}", nick, weather),
+                    Err(Error::Weather(e)) => format!("{}: {}", nick, e),
+                    Err(e) => {
+                        warn!("Unable to look up the weather in {:?}: {}", location, e);
+                        metrics().errors.with_label_values(&["weather"]).inc();
+                        format!("{}: I can't see out of the window right now", nick)
+                    }
+                };
+                queue(&outgoing, &target, reply);
+            }
+            .in_current_span(),
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+
+use super::look_up;
+use super::Command;
+use super::Context;
+use crate::wiki::Wikipedia;
+use crate::Error;
+
+/// The start of a Wikipedia article, for facts the model would only guess at.
+pub struct Wiki {
+    wikipedia: Wikipedia,
+}
+
+impl Wiki {
+    pub fn new() -> Self {
+        Self {
+            wikipedia: Wikipedia::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Wiki {
+    fn name(&self) -> &'static str {
+        "wiki"
+    }
+
+    fn help(&self) -> &'static str {
+        "wiki <query> - the start of the Wikipedia article about something, and a link"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            return ctx.reply(&format!("{}: look up what?", ctx.nick));
+        }
+
+        let wikipedia = self.wikipedia.clone();
+        let query = args.to_string();
+        look_up(ctx, "wiki", async move {
+            Ok(wikipedia
+                .summary(&query)
+                .await?
+                .map(|summary| vec![summary]))
+        });
+        Ok(())
+    }
+}
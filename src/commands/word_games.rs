@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+
+use super::Command;
+use super::Context;
+use crate::word_games::HANGMAN;
+use crate::word_games::WORDLE;
+use crate::Error;
+
+/// How many names `top` lists.
+const TOP: usize = 5;
+
+/// Guessing at the day's five letter word, everyone in a channel together.
+pub struct WordleCommand;
+
+#[async_trait]
+impl Command for WordleCommand {
+    fn name(&self) -> &'static str {
+        "wordle"
+    }
+
+    fn help(&self) -> &'static str {
+        "wordle [word|top|score [nick]] - guess today's five letter word, six tries a channel"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if let Some(reply) = scores(ctx, WORDLE, args) {
+            return ctx.reply(&reply);
+        }
+
+        let key = ctx.isupport.casemapping.fold(ctx.target);
+        let reply = match args {
+            "" => ctx.word_games.wordle(&key),
+            guess => ctx.word_games.guess_wordle(&key, ctx.nick, guess).await,
+        };
+        ctx.reply(&reply)
+    }
+}
+
+/// Hangman, everyone in a channel against the same word.
+pub struct HangmanCommand;
+
+#[async_trait]
+impl Command for HangmanCommand {
+    fn name(&self) -> &'static str {
+        "hangman"
+    }
+
+    fn help(&self) -> &'static str {
+        "hangman [letter|word|stop|top|score [nick]] - guess a word a letter at a time"
+    }
+
+    async fn run(&self, ctx: &Context<'_>, args: &str) -> Result<(), Error> {
+        if let Some(reply) = scores(ctx, HANGMAN, args) {
+            return ctx.reply(&reply);
+        }
+
+        let key = ctx.isupport.casemapping.fold(ctx.target);
+        let reply = match args {
+            "" => ctx.word_games.hangman(&key),
+            "stop" => ctx
+                .word_games
+                .stop_hangman(&key)
+                .unwrap_or_else(|| format!("{}: there's no game going", ctx.nick)),
+            guess => ctx.word_games.guess_hangman(&key, ctx.nick, guess).await,
+        };
+        ctx.reply(&reply)
+    }
+}
+
+/// What to say to `top` or `score [nick]` about `game`, if that's what was asked.
+fn scores(ctx: &Context<'_>, game: &str, args: &str) -> Option<String> {
+    let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+    match subcommand.to_lowercase().as_str() {
+        "top" => {
+            let top = ctx.word_games.top(game, TOP);
+            if top.is_empty() {
+                return Some(format!("{}: nobody has scored yet", ctx.nick));
+            }
+            let top = top
+                .into_iter()
+                .map(|(nick, score)| format!("{} ({})", nick, score))
+                .collect::<Vec<_>>();
+            Some(format!("{} champions: {}", game, top.join(", ")))
+        }
+        "score" => {
+            let who = rest.split_whitespace().next().unwrap_or(ctx.nick);
+            Some(format!(
+                "{} has {} {} points",
+                who,
+                ctx.word_games.score(game, who),
+                game
+            ))
+        }
+        _ => None,
+    }
+}
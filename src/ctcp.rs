@@ -0,0 +1,50 @@
+//! The client-to-client protocol: queries like VERSION and `/me` actions, wrapped in `\x01`
+//! inside ordinary PRIVMSGs.
+
+use chrono::Local;
+
+use crate::config::CtcpConfig;
+
+const DELIMITER: char = '\x01';
+
+/// Splits a CTCP message into its command and whatever follows, e.g. `("PING", "12345")`.
+pub fn parse(msg: &str) -> Option<(&str, &str)> {
+    let body = msg.strip_prefix(DELIMITER)?;
+    let body = body.strip_suffix(DELIMITER).unwrap_or(body);
+
+    Some(body.split_once(' ').unwrap_or((body, "")))
+}
+
+/// What someone did with `/me`, if that's what `msg` is.
+pub fn action(msg: &str) -> Option<&str> {
+    parse(msg)
+        .filter(|(command, _)| command.eq_ignore_ascii_case("ACTION"))
+        .map(|(_, text)| text)
+}
+
+/// `text` as a `/me`.
+pub fn to_action(text: &str) -> String {
+    format!("{DELIMITER}ACTION {text}{DELIMITER}")
+}
+
+/// What the model is doing if it wrote the whole line as an action, like `*wags tail*`. Bold
+/// and lines with more than one bit of emphasis don't count.
+pub fn as_action(line: &str) -> Option<&str> {
+    let text = line.trim().strip_prefix('*')?.strip_suffix('*')?;
+    let hugged = !text.starts_with(char::is_whitespace) && !text.ends_with(char::is_whitespace);
+
+    (!text.is_empty() && hugged && !text.contains('*')).then_some(text)
+}
+
+/// The answer to a CTCP query, ready to be sent back in a NOTICE, if it's one we answer.
+pub fn reply(config: &CtcpConfig, command: &str, params: &str) -> Option<String> {
+    let command = command.to_ascii_uppercase();
+    let answer = match command.as_str() {
+        "VERSION" => config.version.clone(),
+        "PING" => params.to_string(),
+        "TIME" => Local::now().to_rfc2822(),
+        _ => config.replies.get(&command)?.clone(),
+    };
+
+    Some(format!("{DELIMITER}{command} {answer}{DELIMITER}"))
+}
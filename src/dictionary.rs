@@ -0,0 +1,184 @@
+//! Looking words up in the Free Dictionary, which is built from Wiktionary, and in Urban
+//! Dictionary.
+
+use regex::Regex;
+
+use reqwest::StatusCode;
+use reqwest::Url;
+
+use serde::Deserialize;
+
+use tokio::time::Duration;
+
+use std::sync::LazyLock;
+
+use crate::Error;
+
+const FREE_DICTIONARY: &str = "https://api.dictionaryapi.dev/api/v2/entries/en";
+const URBAN_DICTIONARY: &str = "https://api.urbandictionary.com/v0/define";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Senses given for a word, so the answer stays inside the lines `say()` keeps in the channel.
+const MAX_SENSES: usize = 3;
+
+/// Longest a definition or example gets before it's cut short.
+const MAX_CHARS: usize = 300;
+
+/// Urban Dictionary links other entries as `[word]`.
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\]").expect("Invalid link regex"));
+
+#[derive(Deserialize)]
+struct Entry {
+    word: String,
+    #[serde(default)]
+    phonetic: Option<String>,
+    #[serde(default)]
+    meanings: Vec<Meaning>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meaning {
+    part_of_speech: String,
+    #[serde(default)]
+    definitions: Vec<Sense>,
+}
+
+#[derive(Deserialize)]
+struct Sense {
+    definition: String,
+}
+
+#[derive(Deserialize)]
+struct UrbanResults {
+    #[serde(default)]
+    list: Vec<UrbanEntry>,
+}
+
+#[derive(Deserialize)]
+struct UrbanEntry {
+    word: String,
+    definition: String,
+    #[serde(default)]
+    example: String,
+    #[serde(default)]
+    thumbs_up: i64,
+    #[serde(default)]
+    thumbs_down: i64,
+}
+
+#[derive(Clone)]
+pub struct Dictionary {
+    http: reqwest::Client,
+}
+
+impl Default for Dictionary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+        }
+    }
+
+    /// A few senses of `word`, a line each, or `None` if it isn't in the dictionary.
+    pub async fn define(&self, word: &str) -> Result<Option<Vec<String>>, Error> {
+        let mut url = Url::parse(FREE_DICTIONARY).expect("Invalid dictionary URL");
+        url.path_segments_mut()
+            .expect("Dictionary URL has a path")
+            .push(word);
+        let response = self.http.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let entries: Vec<Entry> = response.error_for_status()?.json().await?;
+        let Some(first) = entries.first() else {
+            return Ok(None);
+        };
+
+        let senses = entries
+            .iter()
+            .flat_map(|entry| &entry.meanings)
+            .flat_map(|meaning| {
+                meaning
+                    .definitions
+                    .iter()
+                    .map(move |sense| (&meaning.part_of_speech, &sense.definition))
+            })
+            .take(MAX_SENSES)
+            .collect::<Vec<_>>();
+        if senses.is_empty() {
+            return Ok(None);
+        }
+        let word = match entries.iter().find_map(|entry| entry.phonetic.as_ref()) {
+            Some(phonetic) => format!("{} {}", first.word, phonetic),
+            None => first.word.clone(),
+        };
+
+        Ok(Some(
+            senses
+                .into_iter()
+                .enumerate()
+                .map(|(i, (part_of_speech, definition))| {
+                    let line = format!("{}. ({}) {}", i + 1, part_of_speech, tidy(definition));
+                    match i {
+                        0 => format!("{}: {}", word, line),
+                        _ => line,
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    /// The best liked Urban Dictionary entry for `term` and its example, or `None` if
+    /// there isn't one.
+    pub async fn urban(&self, term: &str) -> Result<Option<Vec<String>>, Error> {
+        let results: UrbanResults = self
+            .http
+            .get(URBAN_DICTIONARY)
+            .query(&[("term", term)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let Some(entry) = results
+            .list
+            .into_iter()
+            .max_by_key(|entry| entry.thumbs_up - entry.thumbs_down)
+        else {
+            return Ok(None);
+        };
+
+        let mut lines = vec![format!(
+            "{}: {}",
+            entry.word,
+            tidy(&LINK.replace_all(&entry.definition, "$1"))
+        )];
+        let example = tidy(&LINK.replace_all(&entry.example, "$1"));
+        if !example.is_empty() {
+            lines.push(format!("e.g. {}", example));
+        }
+
+        Ok(Some(lines))
+    }
+}
+
+/// `text` on one line, cut short if it goes on.
+fn tidy(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", text[..end].trim_end()),
+        None => text,
+    }
+}
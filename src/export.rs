@@ -0,0 +1,182 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use tracing::*;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::llm::ChatMessage;
+use crate::llm::Role;
+use crate::memory::SHARED_SCOPE;
+use crate::recall::Recollection;
+use crate::storage;
+use crate::storage::MemoryStore;
+use crate::Error;
+
+/// Everything remembered about one conversation, the way it's written out as JSON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Conversation {
+    /// Recent messages, oldest first.
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recollections: Vec<Remembered>,
+    /// Asked for nothing to be kept, so there's nothing else.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub opted_out: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Remembered {
+    pub content: String,
+    /// Left out of what people export for themselves, they're no use to anyone but us.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embedding: Vec<f32>,
+}
+
+impl From<&ChatMessage> for Message {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            role: message.role.as_str().to_string(),
+            content: message.content.clone(),
+        }
+    }
+}
+
+impl From<&Recollection> for Remembered {
+    fn from(recollection: &Recollection) -> Self {
+        Self {
+            content: recollection.content.clone(),
+            embedding: recollection.embedding.clone(),
+        }
+    }
+}
+
+/// Every conversation in a store, by scope and then by who it's remembered as.
+pub type Dump = BTreeMap<String, BTreeMap<String, Conversation>>;
+
+/// Reads every conversation in `scopes` out of `store`.
+pub async fn dump(store: &dyn MemoryStore, scopes: &[&str]) -> Result<Dump, Error> {
+    let mut dump = Dump::new();
+    for scope in scopes {
+        let mut conversations: BTreeMap<String, Conversation> = BTreeMap::new();
+        for (nick, messages) in store.load(scope).await? {
+            conversations.entry(nick).or_default().messages =
+                messages.iter().map(Message::from).collect();
+        }
+        for (nick, summary) in store.summaries(scope).await? {
+            conversations.entry(nick).or_default().summary = Some(summary);
+        }
+        for (nick, recollections) in store.recollections(scope).await? {
+            conversations.entry(nick).or_default().recollections =
+                recollections.iter().map(Remembered::from).collect();
+        }
+        for nick in store.opt_outs(scope).await? {
+            conversations.entry(nick).or_default().opted_out = true;
+        }
+        if !conversations.is_empty() {
+            dump.insert(scope.to_string(), conversations);
+        }
+    }
+
+    Ok(dump)
+}
+
+/// Writes every conversation in `dump` to `store`, replacing whatever it held for the same
+/// people. Returns how many conversations were restored.
+pub async fn restore(store: &dyn MemoryStore, dump: &Dump) -> Result<usize, Error> {
+    let mut restored = 0;
+    for (scope, conversations) in dump {
+        for (nick, conversation) in conversations {
+            store.forget(scope, nick).await?;
+            match conversation.opted_out {
+                true => store.opt_out(scope, nick).await?,
+                false => store.opt_in(scope, nick).await?,
+            }
+            for message in &conversation.messages {
+                let Ok(role) = message.role.parse::<Role>() else {
+                    warn!("Skipping message with unknown role {}", message.role);
+                    continue;
+                };
+                let message = ChatMessage {
+                    role,
+                    content: message.content.clone(),
+                };
+                store.push(scope, nick, &message).await?;
+            }
+            if let Some(summary) = &conversation.summary {
+                store.set_summary(scope, nick, summary).await?;
+            }
+            for remembered in &conversation.recollections {
+                let recollection = Recollection {
+                    content: remembered.content.clone(),
+                    embedding: remembered.embedding.clone(),
+                };
+                store.add_recollection(scope, nick, &recollection).await?;
+            }
+            restored += 1;
+        }
+    }
+
+    Ok(restored)
+}
+
+/// Dumps the memory of every configured network, and the shared memory, from `[storage]` to
+/// the JSON file at `path`.
+pub async fn dump_to(config: &Config, path: &Path) -> Result<(), Error> {
+    let store = open(config).await?;
+    let mut scopes = vec![SHARED_SCOPE];
+    scopes.extend(config.networks.iter().map(|network| network.name.as_str()));
+    let dump = dump(store.as_ref(), &scopes).await;
+    store.close().await;
+    let dump = dump?;
+
+    let json = serde_json::to_string_pretty(&dump).expect("conversations serialize");
+    std::fs::write(path, json)
+        .map_err(|e| Error::Export(format!("unable to write {}: {}", path.display(), e)))?;
+    info!(
+        "Dumped {} conversations to {}",
+        dump.values().map(BTreeMap::len).sum::<usize>(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Restores a dump written by `dump_to()` into `[storage]`, which needn't be the same kind of
+/// store it was dumped from.
+pub async fn restore_from(config: &Config, path: &Path) -> Result<(), Error> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| Error::Export(format!("unable to read {}: {}", path.display(), e)))?;
+    let dump: Dump = serde_json::from_str(&json)
+        .map_err(|e| Error::Export(format!("{} isn't a dump: {}", path.display(), e)))?;
+
+    let store = open(config).await?;
+    let restored = restore(store.as_ref(), &dump).await;
+    store.close().await;
+    info!(
+        "Restored {} conversations from {}",
+        restored?,
+        path.display()
+    );
+
+    Ok(())
+}
+
+async fn open(config: &Config) -> Result<Arc<dyn storage::Store>, Error> {
+    match &config.storage {
+        Some(storage) => storage::connect(storage).await,
+        None => Err(Error::Export("there's no [storage] configured".to_string())),
+    }
+}
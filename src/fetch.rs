@@ -0,0 +1,386 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use hyper::client::connect::dns::Name;
+
+use reqwest::dns::Addrs;
+use reqwest::dns::Resolve;
+use reqwest::dns::Resolving;
+use reqwest::header;
+use reqwest::redirect;
+use reqwest::Url;
+
+use tokio::time::Duration;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::Error;
+
+/// Stop reading pages after this much; whatever matters is near the top anyway.
+const MAX_BYTES: usize = 512 * 1024;
+
+/// How much of a page's text is worth handing to the model.
+const MAX_TEXT_CHARS: usize = 6000;
+
+/// Bigger images are refused rather than cut off.
+const MAX_IMAGE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Links ending in these are worth checking for an image.
+const IMAGE_EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".webp"];
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Elements whose contents are never worth reading.
+const SKIPPED: &[&str] = &["script", "style", "noscript", "svg", "template"];
+
+/// Elements that start a new line of text.
+const BLOCKS: &[&str] = &[
+    "p",
+    "br",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "blockquote",
+    "article",
+    "section",
+    "header",
+    "footer",
+];
+
+/// The readable parts of a web page.
+pub struct Page {
+    pub title: Option<String>,
+    pub text: String,
+}
+
+impl Page {
+    /// The title and as much of the text as is worth handing to the model.
+    pub fn excerpt(&self) -> String {
+        let text = truncate(&self.text);
+        match &self.title {
+            Some(title) => format!("{}\n\n{}", title, text),
+            None => text.to_string(),
+        }
+    }
+}
+
+/// Downloads web pages for the model to read, within reason.
+#[derive(Clone)]
+pub struct Fetch {
+    http: reqwest::Client,
+}
+
+impl Default for Fetch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fetch {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .redirect(redirect::Policy::custom(|attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        attempt.error("too many redirects")
+                    } else if let Err(e) = check(attempt.url()) {
+                        attempt.error(e)
+                    } else {
+                        attempt.follow()
+                    }
+                }))
+                .dns_resolver(Arc::new(PublicOnly))
+                .build()
+                .expect("HTTP client should build"),
+        }
+    }
+
+    /// Fetches with `http` instead, which had better keep off the local network itself.
+    pub fn with_http(http: reqwest::Client) -> Self {
+        Self { http }
+    }
+
+    pub async fn page(&self, url: &str) -> Result<Page, Error> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Error::Fetch(format!("{} isn't a web page", url)));
+        }
+
+        let mut response = self.get(url).await?;
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/html")
+            .to_ascii_lowercase();
+        if !content_type.starts_with("text/") && !content_type.contains("xhtml") {
+            return Err(Error::Fetch(format!("can't read {}", content_type)));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BYTES {
+                body.truncate(MAX_BYTES);
+                break;
+            }
+        }
+        let body = String::from_utf8_lossy(&body);
+
+        Ok(match content_type.contains("html") {
+            true => from_html(&body),
+            false => Page {
+                title: None,
+                text: collapse(&body),
+            },
+        })
+    }
+
+    /// Downloads the image at `url` as a `data:` URL, ready to show the model.
+    pub async fn image(&self, url: &str) -> Result<String, Error> {
+        let mut response = self.get(url).await?;
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !content_type.starts_with("image/") {
+            return Err(Error::Fetch(format!("{} isn't an image", url)));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > MAX_IMAGE_BYTES {
+                return Err(Error::Fetch(format!("{} is too big", url)));
+            }
+        }
+
+        Ok(format!(
+            "data:{};base64,{}",
+            content_type,
+            BASE64.encode(body)
+        ))
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response, Error> {
+        let url = Url::parse(url).map_err(|_| Error::Fetch(format!("{} isn't a web page", url)))?;
+        check(&url).map_err(Error::Fetch)?;
+
+        Ok(self.http.get(url).send().await?.error_for_status()?)
+    }
+}
+
+/// Looks names up as usual, but only ever connects to addresses on the internet. Otherwise
+/// anyone could have pickles read out the cloud metadata service or whatever else is only
+/// meant to be reachable from the machine it's on, redirects and DNS tricks included.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            if addrs.iter().any(|addr| !is_public(addr.ip())) {
+                return Err(format!("{} is on a private network", name).into());
+            }
+
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Refuses `url` if it names a private address outright, which never gets as far as the
+/// resolver.
+fn check(url: &Url) -> Result<(), String> {
+    let Some(host) = url.host_str() else {
+        return Err(format!("{} isn't a web page", url));
+    };
+    let ip = host.trim_start_matches('[').trim_end_matches(']').parse();
+    match ip {
+        Ok(ip) if !is_public(ip) => Err(format!("{} is on a private network", url)),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `ip` is out on the internet rather than this machine or its network.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // Carrier-grade NAT, which some clouds use internally
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local and link-local
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// The web links in `text`.
+pub fn urls(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+}
+
+/// The links in `text` that look like they point at images.
+pub fn image_urls(text: &str) -> impl Iterator<Item = &str> {
+    urls(text).filter(|url| {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|extension| path.to_ascii_lowercase().ends_with(extension))
+    })
+}
+
+/// Cuts `text` down to `MAX_TEXT_CHARS`, on a character boundary.
+fn truncate(text: &str) -> &str {
+    match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Pulls the title and visible text out of an HTML document. Not a real parser, but close
+/// enough for reading articles.
+fn from_html(html: &str) -> Page {
+    let mut title = None;
+    let mut text = String::new();
+    let mut rest = html;
+    let mut skipping: Option<String> = None;
+    let mut in_title = false;
+
+    while let Some(start) = rest.find('<') {
+        let content = &rest[..start];
+        if skipping.is_none() {
+            if in_title {
+                title = Some(collapse(&decode(content)));
+            } else {
+                text.push_str(&decode(content));
+            }
+        }
+
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if let Some(skipped) = &skipping {
+            if closing && &name == skipped {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED.contains(&name.as_str()) && !closing {
+            skipping = Some(name);
+        } else if name == "title" {
+            in_title = !closing;
+        } else if BLOCKS.contains(&name.as_str()) {
+            text.push('\n');
+        }
+    }
+    if skipping.is_none() {
+        text.push_str(&decode(rest));
+    }
+
+    Page {
+        title: title.filter(|title| !title.is_empty()),
+        text: collapse(&text),
+    }
+}
+
+/// Squeezes runs of spaces and blank lines out of `text`.
+fn collapse(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Replaces the HTML entities that turn up in ordinary text.
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let replacement = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#')?.parse().ok())
+                .and_then(char::from_u32),
+        });
+
+        match (entity, replacement) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
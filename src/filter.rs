@@ -0,0 +1,76 @@
+use tracing::*;
+
+use std::sync::Arc;
+
+use crate::config::FilterAction;
+use crate::config::FilterConfig;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+
+/// What banned words are replaced with.
+const REDACTED: &str = "[redacted]";
+
+const REPHRASE_PROMPT: &str = "Rephrase the line you're given so that it means the same but \
+    avoids any rude, offensive or banned words. Reply with nothing but the rephrased line.";
+
+/// Keeps lines matching any of `[filter].patterns` from being said, whatever the model thinks
+/// of them.
+#[derive(Clone)]
+pub struct Filter {
+    config: FilterConfig,
+    backend: Arc<dyn ChatBackend>,
+}
+
+impl Filter {
+    /// None unless there's something to filter.
+    pub fn new(config: &FilterConfig, backend: Arc<dyn ChatBackend>) -> Option<Self> {
+        (!config.patterns.is_empty()).then(|| Self {
+            config: config.clone(),
+            backend,
+        })
+    }
+
+    fn is_banned(&self, line: &str) -> bool {
+        self.config
+            .patterns
+            .iter()
+            .any(|pattern| pattern.is_match(line))
+    }
+
+    fn redact(&self, line: &str) -> String {
+        self.config
+            .patterns
+            .iter()
+            .fold(line.to_string(), |line, pattern| {
+                pattern.replace_all(&line, REDACTED).into_owned()
+            })
+    }
+
+    /// `line` as it may be said, or None if the whole response should be refused instead.
+    pub async fn check(&self, line: String) -> Option<String> {
+        if !self.is_banned(&line) {
+            return Some(line);
+        }
+
+        debug!("Filtering {:?}", line);
+        match self.config.action {
+            FilterAction::Redact => Some(self.redact(&line)),
+            FilterAction::Refuse => None,
+            FilterAction::Rephrase => {
+                let request = [
+                    ChatMessage::system(REPHRASE_PROMPT),
+                    ChatMessage::user(line.clone()),
+                ];
+                let rephrased = match self.backend.complete(&request).await {
+                    Ok(completion) => completion.content,
+                    Err(e) => {
+                        warn!("Unable to rephrase a filtered line: {}", e);
+                        line
+                    }
+                };
+                // Redacted after all if it's still no good
+                Some(self.redact(rephrased.trim()))
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use irc::client::prelude::*;
+
+use prometheus::IntCounter;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use crate::config::FloodConfig;
+use crate::config::NetworkConfig;
+use crate::metrics::metrics;
+use crate::Error;
+
+/// Everything pickles sends after registering goes through here, so no matter how many
+/// responses and commands are talking at once the server sees a steady trickle instead of a
+/// flood it'll kick us for.
+#[derive(Clone)]
+pub struct Throttle {
+    queue: mpsc::UnboundedSender<Message>,
+}
+
+impl Throttle {
+    pub fn new(sender: Sender, network: &NetworkConfig) -> (Self, JoinHandle<()>) {
+        let (queue, rx) = mpsc::unbounded_channel();
+        let bucket = TokenBucket::new(&network.flood);
+        let sent = metrics().messages_sent.with_label_values(&[&network.name]);
+        let task = tokio::spawn(drain(sender, rx, bucket, sent).in_current_span());
+
+        (Self { queue }, task)
+    }
+
+    pub fn send(&self, message: impl Into<Message>) -> Result<(), Error> {
+        self.queue
+            .send(message.into())
+            .map_err(|_| Error::Disconnected)
+    }
+
+    pub fn send_privmsg(&self, target: &str, msg: &str) -> Result<(), Error> {
+        self.send(Command::PRIVMSG(target.to_string(), msg.to_string()))
+    }
+
+    pub fn send_join(&self, channel: &str) -> Result<(), Error> {
+        self.send(Command::JOIN(channel.to_string(), None, None))
+    }
+}
+
+async fn drain(
+    sender: Sender,
+    mut queue: mpsc::UnboundedReceiver<Message>,
+    mut bucket: TokenBucket,
+    sent: IntCounter,
+) {
+    while let Some(message) = queue.recv().await {
+        bucket.take().await;
+        if let Err(e) = sender.send(message) {
+            debug!("Dropping outgoing messages: {}", e);
+            return;
+        }
+        sent.inc();
+    }
+}
+
+/// Allows `burst` messages straight away, then one more each `refill` interval.
+struct TokenBucket {
+    burst: f64,
+    tokens: f64,
+    refill: Duration,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &FloodConfig) -> Self {
+        let burst = f64::from(config.burst.max(1));
+        Self {
+            burst,
+            tokens: burst,
+            refill: Duration::from_millis(config.refill_ms.max(1)),
+            updated: Instant::now(),
+        }
+    }
+
+    async fn take(&mut self) {
+        self.refill();
+        if self.tokens < 1.0 {
+            time::sleep(self.refill.mul_f64(1.0 - self.tokens)).await;
+            self.refill();
+        }
+        self.tokens -= 1.0;
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = (now - self.updated).as_secs_f64() / self.refill.as_secs_f64();
+        self.tokens = (self.tokens + earned).min(self.burst);
+        self.updated = now;
+    }
+}
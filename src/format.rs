@@ -0,0 +1,267 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// mIRC control codes.
+const BOLD: char = '\x02';
+const ITALIC: char = '\x1d';
+const MONOSPACE: char = '\x11';
+const COLOR: char = '\x03';
+const UNDERLINE: char = '\x1f';
+const STRIKETHROUGH: char = '\x1e';
+const REVERSE: char = '\x16';
+const RESET: char = '\x0f';
+
+/// Turns the Markdown models like to answer in into something that looks right on IRC. Works a
+/// line at a time so it can keep up with a streamed response, remembering whether it's inside
+/// a fenced code block.
+#[derive(Default)]
+pub struct Formatter {
+    in_code_block: bool,
+}
+
+impl Formatter {
+    pub fn in_code_block(&self) -> bool {
+        self.in_code_block
+    }
+
+    /// Returns the line as it should be said, or `None` if it's only Markdown markup.
+    pub fn line(&mut self, line: &str) -> Option<String> {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            self.in_code_block = !self.in_code_block;
+            return None;
+        }
+        if self.in_code_block {
+            if line.trim().is_empty() {
+                return None;
+            }
+            return Some(format!("{MONOSPACE}{line}{MONOSPACE}"));
+        }
+
+        // Headings become bold, lists lose their indentation and get real bullets
+        if let Some(heading) = heading(trimmed) {
+            return Some(format!("{BOLD}{}{BOLD}", inline(heading)));
+        }
+        if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| trimmed.strip_prefix(bullet))
+        {
+            return Some(format!("• {}", inline(item)));
+        }
+
+        Some(inline(trimmed))
+    }
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    ((1..=6).contains(&level) && text.starts_with(' ')).then(|| text.trim())
+}
+
+/// Converts bold, italics, inline code and links within a line.
+fn inline(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut bold = false;
+    let mut italic = false;
+
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix('`') {
+            if let Some(end) = after.find('`') {
+                out.push(MONOSPACE);
+                out.push_str(&after[..end]);
+                out.push(MONOSPACE);
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+        if let Some(after) = rest.strip_prefix("**").or_else(|| rest.strip_prefix("__")) {
+            bold = !bold;
+            out.push(BOLD);
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix('*') {
+            // Only a marker when it hugs a word, so "2 * 3" stays arithmetic
+            let opens = !italic && after.starts_with(|c: char| !c.is_whitespace());
+            let closes = italic && out.ends_with(|c: char| !c.is_whitespace());
+            if opens || closes {
+                italic = !italic;
+                out.push(ITALIC);
+                rest = after;
+                continue;
+            }
+        }
+        if let Some((text, url, after)) = link(rest) {
+            out.push_str(&format!("{} ({})", text, url));
+            rest = after;
+            continue;
+        }
+
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    // Don't let an unclosed marker bleed into the next line
+    if bold {
+        out.push(BOLD);
+    }
+    if italic {
+        out.push(ITALIC);
+    }
+
+    out
+}
+
+/// Parses `[text](url)` at the start of `s`, returning the text, url and whatever follows.
+fn link(s: &str) -> Option<(&str, &str, &str)> {
+    let s = s.strip_prefix('[')?;
+    let (text, s) = s.split_once("](")?;
+    let (url, rest) = s.split_once(')')?;
+    (!text.contains(']') && !url.contains(char::is_whitespace)).then_some((text, url, rest))
+}
+
+/// The code in `line` if it's from a code block, which `Formatter` makes all monospace.
+pub fn code(line: &str) -> Option<&str> {
+    let code = line.strip_prefix(MONOSPACE)?.strip_suffix(MONOSPACE)?;
+    (!code.contains(MONOSPACE)).then_some(code)
+}
+
+/// `line` without any mIRC formatting, for networks that can't show it.
+pub fn strip(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            COLOR => skip_color(&mut chars),
+            BOLD | ITALIC | MONOSPACE | UNDERLINE | STRIKETHROUGH | REVERSE | RESET => (),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// `line` as HTML, with bold, italics and monospace as tags and any other formatting dropped.
+pub fn html(line: &str) -> String {
+    convert(line, &HTML)
+}
+
+/// `line` as Markdown the way Discord takes it, with bold, italics and monospace kept, any
+/// other formatting dropped, and anything that would otherwise be taken as Markdown escaped.
+pub fn markdown(line: &str) -> String {
+    convert(line, &MARKDOWN)
+}
+
+/// `line` as Slack's mrkdwn, with bold, italics and monospace kept and any other formatting
+/// dropped.
+pub fn mrkdwn(line: &str) -> String {
+    convert(line, &MRKDWN)
+}
+
+/// How some markup opens and closes bold, italics and monospace, and escapes the rest.
+struct Markup {
+    bold: [&'static str; 2],
+    italic: [&'static str; 2],
+    monospace: [&'static str; 2],
+    /// Adds a character to what's been written so far, escaped if it needs to be, given
+    /// whether it's inside monospace.
+    escape: fn(&mut String, char, bool),
+}
+
+const HTML: Markup = Markup {
+    bold: ["<b>", "</b>"],
+    italic: ["<i>", "</i>"],
+    monospace: ["<code>", "</code>"],
+    escape: escape_html,
+};
+
+const MARKDOWN: Markup = Markup {
+    bold: ["**", "**"],
+    italic: ["*", "*"],
+    monospace: ["`", "`"],
+    escape: escape_markdown,
+};
+
+const MRKDWN: Markup = Markup {
+    bold: ["*", "*"],
+    italic: ["_", "_"],
+    monospace: ["`", "`"],
+    // Slack has no way to escape its own markers, only these
+    escape: escape_html,
+};
+
+fn convert(line: &str, markup: &Markup) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut open: Vec<[&str; 2]> = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let marker = match c {
+            BOLD => markup.bold,
+            ITALIC => markup.italic,
+            MONOSPACE => markup.monospace,
+            COLOR => {
+                skip_color(&mut chars);
+                continue;
+            }
+            UNDERLINE | STRIKETHROUGH | REVERSE | RESET => continue,
+            c => {
+                (markup.escape)(&mut out, c, open.last() == Some(&markup.monospace));
+                continue;
+            }
+        };
+        match open.iter().rposition(|&open| open == marker) {
+            // Closing a marker closes whatever was opened inside it too
+            Some(index) => {
+                for [_, close] in open.drain(index..).rev() {
+                    out.push_str(close);
+                }
+            }
+            None => {
+                out.push_str(marker[0]);
+                open.push(marker);
+            }
+        }
+    }
+    for [_, close] in open.into_iter().rev() {
+        out.push_str(close);
+    }
+
+    out
+}
+
+fn escape_html(out: &mut String, c: char, _: bool) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        c => out.push(c),
+    }
+}
+
+fn escape_markdown(out: &mut String, c: char, in_monospace: bool) {
+    // Nothing is special inside monospace
+    let escape = matches!(c, '\\' | '*' | '_' | '~' | '`' | '|')
+        || (out.is_empty() && matches!(c, '>' | '#' | '-'));
+    if escape && !in_monospace {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+/// Skips the foreground and background, a digit or two each, that follow a color code.
+fn skip_color(chars: &mut Peekable<Chars>) {
+    let digits = |chars: &mut Peekable<Chars>| {
+        for _ in 0..2 {
+            if chars.next_if(char::is_ascii_digit).is_none() {
+                break;
+            }
+        }
+    };
+    digits(chars);
+    if chars.peek() == Some(&',') {
+        chars.next();
+        digits(chars);
+    }
+}
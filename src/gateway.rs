@@ -0,0 +1,504 @@
+//! Talking somewhere other than IRC. Each network that does gets a gateway, an IRC server of
+//! its own on localhost that pickles connects to like any other, passing messages between it
+//! and a [`ChatNetwork`]. Memory, commands and answers all work the same as on IRC.
+
+pub mod discord;
+pub mod matrix;
+pub mod slack;
+pub mod telegram;
+mod websocket;
+pub mod xml;
+pub mod xmpp;
+
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use crate::casemap::CaseMapping;
+use crate::config::FloodConfig;
+use crate::config::NetworkConfig;
+use crate::config::TlsConfig;
+use crate::config::TransportConfig;
+use crate::ctcp;
+use crate::split;
+use crate::Error;
+
+use discord::Discord;
+use matrix::Matrix;
+use slack::Slack;
+use telegram::Telegram;
+use xmpp::Xmpp;
+
+/// Lines pickles says to the same place within this long of each other go out as one message.
+const COALESCE: Duration = Duration::from_millis(500);
+
+/// How long to wait before trying again when the network fails us.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How long a connection to a relay gets to give the token, and how long a line it gets to do
+/// it in.
+const PASS_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_PASS_LINE: u64 = 512;
+
+/// Somewhere other than IRC that pickles can talk.
+#[async_trait]
+pub trait ChatNetwork: Send + Sync {
+    /// Waits for something to happen. It's called again as soon as it returns, so this is
+    /// where to long poll.
+    async fn receive(&self) -> Result<Vec<Event>, Error>;
+
+    /// Starts listening in `channel`, named as it is in the config.
+    async fn join(&self, channel: &str) -> Result<(), Error>;
+
+    /// Says `text` in `target`, a channel or the nick of someone. It can run to several lines
+    /// and have mIRC formatting in it.
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error>;
+
+    /// The most text, in bytes, that fits in one message.
+    fn max_length(&self) -> usize;
+}
+
+/// What kind of message something is, in IRC terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Message,
+    /// `/me`
+    Action,
+    /// Something automated, that bots shouldn't answer.
+    Notice,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// `from` said `text` in `channel`, or to pickles alone if there isn't one. `mentioned` is
+    /// for networks where addressing someone is done with a link or a tag rather than by
+    /// starting with their name.
+    Message {
+        from: User,
+        channel: Option<String>,
+        text: String,
+        kind: Kind,
+        mentioned: bool,
+    },
+    /// `from` would like pickles in `channel`.
+    Invite { from: User, channel: String },
+}
+
+/// Someone on the network, the way IRC would see them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub nick: String,
+    pub user: String,
+    pub host: String,
+}
+
+/// Starts a gateway for `network` if it has a `transport`, and points it there.
+pub async fn open(network: &mut NetworkConfig) -> Result<(), Error> {
+    let transport: Arc<dyn ChatNetwork> = match &network.transport {
+        Some(TransportConfig::Matrix(matrix)) => Arc::new(Matrix::new(matrix)?),
+        Some(TransportConfig::Discord(discord)) => Arc::new(Discord::new(discord)?),
+        Some(TransportConfig::Telegram(telegram)) => Arc::new(Telegram::new(telegram)?),
+        Some(TransportConfig::Slack(slack)) => Arc::new(Slack::new(slack)?),
+        Some(TransportConfig::Xmpp(xmpp)) => Arc::new(Xmpp::new(xmpp, &network.nickname)?),
+        None => return Ok(()),
+    };
+
+    attach(network, transport).await
+}
+
+/// Starts a gateway to `transport`, and points `network` at it.
+pub async fn attach(
+    network: &mut NetworkConfig,
+    transport: Arc<dyn ChatNetwork>,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .map_err(|e| Error::Gateway(format!("unable to listen: {}", e)))?;
+    network.server = Ipv4Addr::LOCALHOST.to_string();
+    network.port = listener
+        .local_addr()
+        .map_err(|e| Error::Gateway(format!("unable to listen: {}", e)))?
+        .port();
+    network.tls = TlsConfig::default();
+    let token = relay_token();
+    network.relay_token = Some(token.clone());
+    network.sasl = None;
+    network.nickserv = None;
+    // Keeping to the network's own limits is up to the transport
+    network.flood = FloodConfig {
+        burst: u32::MAX,
+        refill_ms: 1,
+    };
+
+    let (events_tx, events) = mpsc::unbounded_channel();
+    tokio::spawn(receive(transport.clone(), events_tx).in_current_span());
+    tokio::spawn(serve(listener, transport, events, network.clone(), token).in_current_span());
+
+    Ok(())
+}
+
+/// A secret for pickles to give a relay on localhost, so nobody else on the machine can use it.
+pub(crate) fn relay_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Whether whoever's on the other end of `reader` starts by giving `token`. Anyone else on the
+/// machine can connect too, and would otherwise get to speak as pickles.
+pub(crate) async fn authenticate<R: AsyncBufRead + Unpin>(reader: &mut R, token: &str) -> bool {
+    let mut line = String::new();
+    let mut limited = (&mut *reader).take(MAX_PASS_LINE);
+    let read = limited.read_line(&mut line);
+    let Ok(Ok(_)) = time::timeout(PASS_TIMEOUT, read).await else {
+        return false;
+    };
+    let given = line.trim_end().strip_prefix("PASS ").unwrap_or_default();
+
+    same(given, token)
+}
+
+/// Takes the same time whatever's given, so a token can't be guessed a character at a time.
+pub(crate) fn same(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |differ, (a, b)| differ | (a ^ b))
+            == 0
+}
+
+/// Passes along whatever `transport` has to say for as long as anyone's listening.
+async fn receive(transport: Arc<dyn ChatNetwork>, events: mpsc::UnboundedSender<Event>) {
+    loop {
+        match transport.receive().await {
+            Ok(received) => {
+                for event in received {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("{}, trying again in {:?}", e, RETRY_DELAY);
+                time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Lets pickles connect, and reconnect, for as long as it likes.
+async fn serve(
+    listener: TcpListener,
+    transport: Arc<dyn ChatNetwork>,
+    mut events: mpsc::UnboundedReceiver<Event>,
+    network: NetworkConfig,
+    token: String,
+) {
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                error!("Gateway unable to accept: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = relay(socket, transport.as_ref(), &mut events, &network, &token).await {
+            warn!("Gateway connection failed: {}", e);
+        }
+    }
+}
+
+/// Lines waiting to be said as one message.
+struct Pending {
+    target: String,
+    kind: Kind,
+    text: String,
+    since: Instant,
+}
+
+/// Plays IRC server to pickles over `socket` until it hangs up.
+async fn relay(
+    socket: TcpStream,
+    transport: &dyn ChatNetwork,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+    network: &NetworkConfig,
+    token: &str,
+) -> Result<(), Error> {
+    let (reader, mut writer) = socket.into_split();
+    let mut reader = BufReader::new(reader);
+    if !authenticate(&mut reader, token).await {
+        return Err(Error::Gateway(String::from(
+            "turned away a connection that wasn't pickles",
+        )));
+    }
+    let mut lines = reader.lines();
+    let mut nickname = network.nickname.clone();
+    let mut joined = HashSet::new();
+    let mut pending: Option<Pending> = None;
+
+    loop {
+        let flush_at = pending.as_ref().map(|pending| pending.since + COALESCE);
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line.map_err(io_error)? else {
+                    break;
+                };
+                let message = match line.parse::<Message>() {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Unable to parse {:?}: {}", line, e);
+                        continue;
+                    }
+                };
+                match message.command {
+                    Command::NICK(nick) => nickname = nick,
+                    Command::USER(..) => {
+                        let isupport = format!(
+                            "CHANTYPES=#!&+ CASEMAPPING=ascii LINELEN={}",
+                            transport.max_length()
+                        );
+                        send(&mut writer, &format!(":gateway 001 {} :Welcome", nickname)).await?;
+                        send(&mut writer, &format!(":gateway 005 {} {} :are supported", nickname, isupport)).await?;
+                        send(&mut writer, &format!(":gateway 422 {} :No MOTD", nickname)).await?;
+                    }
+                    Command::JOIN(channels, ..) => {
+                        for channel in channels.split(',') {
+                            if let Err(e) = transport.join(channel).await {
+                                warn!("Unable to join {}: {}", channel, e);
+                                let reply = format!(":gateway 403 {} {} :{}", nickname, channel, e);
+                                send(&mut writer, &reply).await?;
+                                continue;
+                            }
+                            joined.insert(CaseMapping::Ascii.fold(channel));
+                            let join = format!(":{0}!{0}@gateway JOIN {1}", nickname, channel);
+                            send(&mut writer, &join).await?;
+                        }
+                    }
+                    Command::PART(channels, _) => {
+                        for channel in channels.split(',') {
+                            joined.remove(&CaseMapping::Ascii.fold(channel));
+                            let part = format!(":{0}!{0}@gateway PART {1}", nickname, channel);
+                            send(&mut writer, &part).await?;
+                        }
+                    }
+                    Command::PING(server, _) => {
+                        send(&mut writer, &format!(":gateway PONG gateway :{}", server)).await?;
+                    }
+                    Command::PRIVMSG(target, text) => {
+                        let (kind, text) = match ctcp::action(&text) {
+                            Some(action) => (Kind::Action, action.to_string()),
+                            None if text.starts_with('\x01') => continue,
+                            None => (Kind::Message, text),
+                        };
+                        pending = coalesce(transport, pending, target, kind, text).await;
+                    }
+                    // CTCP replies mean nothing anywhere else
+                    Command::NOTICE(target, text) if !text.starts_with('\x01') => {
+                        pending = coalesce(transport, pending, target, Kind::Notice, text).await;
+                    }
+                    Command::QUIT(_) => break,
+                    _ => (),
+                }
+            }
+            Some(event) = events.recv() => {
+                if let Some(line) = irc_line(event, &nickname, &joined, network) {
+                    send(&mut writer, &line).await?;
+                }
+            }
+            _ = time::sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                flush(transport, pending.take()).await;
+            }
+        }
+    }
+    flush(transport, pending.take()).await;
+
+    Ok(())
+}
+
+/// Adds a line to what's waiting to be said, first saying what's there if the line can't go
+/// with it.
+async fn coalesce(
+    transport: &dyn ChatNetwork,
+    pending: Option<Pending>,
+    target: String,
+    kind: Kind,
+    text: String,
+) -> Option<Pending> {
+    match pending {
+        Some(mut pending)
+            if pending.target == target
+                && pending.kind == kind
+                && pending.text.len() + 1 + text.len() <= transport.max_length() =>
+        {
+            pending.text.push('\n');
+            pending.text.push_str(&text);
+            pending.since = Instant::now();
+            Some(pending)
+        }
+        pending => {
+            flush(transport, pending).await;
+            Some(Pending {
+                target,
+                kind,
+                text,
+                since: Instant::now(),
+            })
+        }
+    }
+}
+
+async fn flush(transport: &dyn ChatNetwork, pending: Option<Pending>) {
+    let Some(pending) = pending else {
+        return;
+    };
+    for text in split::split(&pending.text, transport.max_length()) {
+        if let Err(e) = transport.send(&pending.target, text, pending.kind).await {
+            warn!("Unable to say something to {}: {}", pending.target, e);
+        }
+    }
+}
+
+/// `event` the way an IRC server would have sent it to pickles, if it's somewhere pickles is.
+fn irc_line(
+    event: Event,
+    nickname: &str,
+    joined: &HashSet<String>,
+    network: &NetworkConfig,
+) -> Option<String> {
+    match event {
+        Event::Message {
+            from,
+            channel,
+            text,
+            kind,
+            mentioned,
+        } => {
+            // IRC has no room for more than one line in a message
+            let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                return None;
+            }
+            let target = match &channel {
+                Some(channel) if !joined.contains(&CaseMapping::Ascii.fold(channel)) => {
+                    return None;
+                }
+                Some(channel) => {
+                    let trigger = match network.channel(channel, CaseMapping::Ascii) {
+                        Some(config) => config.trigger(nickname),
+                        None => format!("{}: ", nickname),
+                    };
+                    if mentioned && !text.starts_with(&trigger) {
+                        text.insert_str(0, &trigger);
+                    }
+                    channel.as_str()
+                }
+                None => nickname,
+            };
+            let (command, text) = match kind {
+                Kind::Message => ("PRIVMSG", text),
+                Kind::Action => ("PRIVMSG", format!("\x01ACTION {}\x01", text)),
+                Kind::Notice => ("NOTICE", text),
+            };
+
+            Some(format!(
+                ":{} {} {} :{}",
+                prefix(&from),
+                command,
+                param(target),
+                text
+            ))
+        }
+        Event::Invite { from, channel } => Some(format!(
+            ":{} INVITE {} {}",
+            prefix(&from),
+            nickname,
+            param(&channel)
+        )),
+    }
+}
+
+/// `from` as an IRC prefix. Names on other networks can hold anything, and one that passed
+/// through as it is could pass for someone else's hostmask, or a different line altogether.
+fn prefix(from: &User) -> String {
+    let part = |name: &str| {
+        let name = name
+            .chars()
+            .map(|c| match c {
+                '!' | '@' | ':' => '_',
+                c if c.is_whitespace() || c.is_control() => '_',
+                c => c,
+            })
+            .collect::<String>();
+        match name.is_empty() {
+            true => String::from("_"),
+            false => name,
+        }
+    };
+
+    format!(
+        "{}!{}@{}",
+        part(&from.nick),
+        part(&from.user),
+        part(&from.host)
+    )
+}
+
+/// `name` safe to use as a middle parameter. Room IDs and aliases need their `!` and `:`, and
+/// only whitespace, control characters or a leading `:` could change what the line means.
+fn param(name: &str) -> String {
+    let name = name
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>();
+    match name.strip_prefix(':') {
+        Some(rest) => format!("_{}", rest),
+        None if name.is_empty() => String::from("_"),
+        None => name,
+    }
+}
+
+async fn send(writer: &mut OwnedWriteHalf, line: &str) -> Result<(), Error> {
+    writer
+        .write_all(format!("{}\r\n", line).as_bytes())
+        .await
+        .map_err(io_error)
+}
+
+/// A connection to a network, with or without TLS.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// Starts TLS over `socket`, making sure whoever's on the other end is `host`.
+async fn tls(host: &str, socket: impl Stream + 'static) -> Result<Box<dyn Stream>, Error> {
+    let connector = native_tls::TlsConnector::new().map_err(|e| Error::Gateway(e.to_string()))?;
+    let stream = tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, socket)
+        .await
+        .map_err(|e| Error::Gateway(format!("TLS with {} failed: {}", host, e)))?;
+
+    Ok(Box::new(stream))
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Gateway(e.to_string())
+}
@@ -0,0 +1,527 @@
+use async_trait::async_trait;
+
+use regex::Captures;
+use regex::Regex;
+
+use reqwest::Method;
+use reqwest::StatusCode;
+use reqwest::Url;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tokio::time::Interval;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use super::websocket::WebSocket;
+use super::ChatNetwork;
+use super::Event;
+use super::Kind;
+use super::User;
+use crate::casemap::CaseMapping;
+use crate::config::DiscordConfig;
+use crate::format;
+use crate::Error;
+
+const API: &str = "https://discord.com/api/v10";
+
+/// Discord counts characters rather than bytes, so this leaves room to spare.
+const MAX_LENGTH: usize = 2000;
+
+/// Times to wait out Discord's rate limit before giving up on a request.
+const MAX_RATE_LIMITED: u32 = 3;
+
+/// Guilds and their channels, guild messages, direct messages, and what's in the messages.
+/// The last needs the Message Content intent turned on for the bot.
+const INTENTS: u64 = 1 << 0 | 1 << 9 | 1 << 12 | 1 << 15;
+
+/// Closing with anything but 1000 or 1001 leaves the session open to resume.
+const RESUMABLE: u16 = 4000;
+
+/// Mentions of people, channels and emoji, like `<@123>`, `<#123>` and `<:wave:123>`.
+static MARKUP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(@!?|#|a?:\w+:)(\d+)>").expect("Invalid markup regex"));
+
+/// A Discord bot, over the gateway for events and the HTTP API for everything else. Text
+/// channels are channels, named `#name` or `#<channel ID>` where names clash between
+/// servers, and people go by their username. Direct messages are private messages.
+pub struct Discord {
+    config: DiscordConfig,
+    http: reqwest::Client,
+    connection: AsyncMutex<Option<Connection>>,
+    /// Where to pick up after reconnecting, if Discord will let us.
+    session: Mutex<Option<Session>>,
+    /// Our own user ID, once Discord has told us.
+    user_id: Mutex<Option<String>>,
+    channels: Mutex<Channels>,
+}
+
+struct Connection {
+    socket: WebSocket,
+    heartbeat: Interval,
+    /// Whether Discord answered the last heartbeat. If it doesn't by the next, the
+    /// connection is dead.
+    acked: bool,
+}
+
+struct Session {
+    id: String,
+    resume_url: String,
+    sequence: Option<u64>,
+}
+
+#[derive(Default)]
+struct Channels {
+    /// Channel names, by channel ID.
+    names: HashMap<String, String>,
+    /// Channel IDs, by folded channel name.
+    ids: HashMap<String, String>,
+    /// Direct message channel IDs, by nick.
+    direct: HashMap<String, String>,
+    /// User IDs, by nick.
+    users: HashMap<String, String>,
+}
+
+impl Discord {
+    pub fn new(config: &DiscordConfig) -> Result<Self, Error> {
+        if config.token.is_empty() {
+            return Err(Error::Gateway(String::from("Discord needs a token")));
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            connection: AsyncMutex::new(None),
+            session: Mutex::new(None),
+            user_id: Mutex::new(None),
+            channels: Mutex::new(Channels::default()),
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, Error> {
+        let api = self.config.api_base.as_deref().unwrap_or(API);
+        let url = format!("{}{}", api.trim_end_matches('/'), path);
+        let mut rate_limited = 0;
+        loop {
+            let mut request = self
+                .http
+                .request(method.clone(), &url)
+                .header("Authorization", format!("Bot {}", self.config.token));
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let response = request.send().await?;
+            let status = response.status();
+            let response: Value = response.json().await?;
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS && rate_limited < MAX_RATE_LIMITED {
+                rate_limited += 1;
+                let wait = response["retry_after"].as_f64().unwrap_or(1.0);
+                time::sleep(Duration::from_secs_f64(wait)).await;
+                continue;
+            }
+            return Err(Error::Gateway(format!(
+                "{}: {}",
+                status,
+                response["message"].as_str().unwrap_or_default()
+            )));
+        }
+    }
+
+    /// Connects to the gateway, resuming the last session if there is one.
+    async fn connect(&self) -> Result<Connection, Error> {
+        let resume = self
+            .session
+            .lock()
+            .expect("session lock poisoned")
+            .as_ref()
+            .map(|session| {
+                (
+                    session.id.clone(),
+                    session.resume_url.clone(),
+                    session.sequence,
+                )
+            });
+        let url = match &resume {
+            Some((_, url, _)) => url.clone(),
+            None => {
+                let gateway = self.request(Method::GET, "/gateway/bot", None).await?;
+                gateway["url"]
+                    .as_str()
+                    .ok_or_else(|| Error::Gateway(String::from("no gateway URL")))?
+                    .to_string()
+            }
+        };
+        let url = Url::parse(&format!(
+            "{}/?v=10&encoding=json",
+            url.trim_end_matches('/')
+        ))
+        .map_err(|e| Error::Gateway(format!("invalid gateway URL {}: {}", url, e)))?;
+        let mut socket = WebSocket::connect(&url).await?;
+
+        let hello = match socket.receive().await? {
+            Some(hello) => payload(&hello)?,
+            None => return Err(Error::Gateway(String::from("Discord hung up"))),
+        };
+        let interval = hello["d"]["heartbeat_interval"]
+            .as_u64()
+            .ok_or_else(|| Error::Gateway(String::from("no heartbeat interval")))?;
+        let interval = Duration::from_millis(interval);
+        // The first heartbeat is sent at a random point in the first interval, so everyone
+        // reconnecting at once doesn't heartbeat all at once too
+        let first = Instant::now() + interval.mul_f64(rand::random());
+
+        let hello = match resume {
+            Some((session_id, _, sequence)) => json!({
+                "op": 6,
+                "d": { "token": self.config.token, "session_id": session_id, "seq": sequence },
+            }),
+            None => json!({
+                "op": 2,
+                "d": {
+                    "token": self.config.token,
+                    "intents": INTENTS,
+                    "properties": { "os": std::env::consts::OS, "browser": "pickles", "device": "pickles" },
+                },
+            }),
+        };
+        socket.send(&hello.to_string()).await?;
+
+        Ok(Connection {
+            socket,
+            heartbeat: time::interval_at(first, interval),
+            acked: true,
+        })
+    }
+
+    /// Waits for something pickles cares about, or `None` if it's time to reconnect.
+    async fn next(&self, connection: &mut Connection) -> Result<Option<Vec<Event>>, Error> {
+        loop {
+            let message = tokio::select! {
+                message = connection.socket.receive() => message?,
+                _ = connection.heartbeat.tick() => {
+                    if !connection.acked {
+                        warn!("Discord stopped answering heartbeats");
+                        return Ok(None);
+                    }
+                    connection.acked = false;
+                    self.heartbeat(connection).await?;
+                    continue;
+                }
+            };
+            let Some(message) = message else {
+                return Err(Error::Gateway(String::from(
+                    "Discord closed the connection",
+                )));
+            };
+
+            let payload = payload(&message)?;
+            if let Some(sequence) = payload["s"].as_u64() {
+                if let Some(session) = &mut *self.session.lock().expect("session lock poisoned") {
+                    session.sequence = Some(sequence);
+                }
+            }
+            match payload["op"].as_u64() {
+                Some(0) => {
+                    let events = self.dispatch(&payload).await;
+                    if !events.is_empty() {
+                        return Ok(Some(events));
+                    }
+                }
+                Some(1) => self.heartbeat(connection).await?,
+                Some(7) => {
+                    debug!("Discord asked us to reconnect");
+                    return Ok(None);
+                }
+                Some(9) => {
+                    if payload["d"] != true {
+                        *self.session.lock().expect("session lock poisoned") = None;
+                    }
+                    debug!("Discord invalidated the session");
+                    return Ok(None);
+                }
+                Some(11) => connection.acked = true,
+                _ => (),
+            }
+        }
+    }
+
+    async fn heartbeat(&self, connection: &Connection) -> Result<(), Error> {
+        let sequence = self
+            .session
+            .lock()
+            .expect("session lock poisoned")
+            .as_ref()
+            .and_then(|session| session.sequence);
+        connection
+            .socket
+            .send(&json!({ "op": 1, "d": sequence }).to_string())
+            .await
+    }
+
+    async fn dispatch(&self, payload: &Value) -> Vec<Event> {
+        let data = &payload["d"];
+        match payload["t"].as_str() {
+            Some("READY") => {
+                let user = &data["user"];
+                info!(
+                    "Connected to Discord as {}",
+                    user["username"].as_str().unwrap_or_default()
+                );
+                *self.user_id.lock().expect("user_id lock poisoned") =
+                    user["id"].as_str().map(str::to_string);
+                if let (Some(id), Some(resume_url)) = (
+                    data["session_id"].as_str(),
+                    data["resume_gateway_url"].as_str(),
+                ) {
+                    *self.session.lock().expect("session lock poisoned") = Some(Session {
+                        id: id.to_string(),
+                        resume_url: resume_url.to_string(),
+                        sequence: payload["s"].as_u64(),
+                    });
+                }
+                Vec::new()
+            }
+            Some("RESUMED") => {
+                debug!("Resumed the Discord session");
+                Vec::new()
+            }
+            Some("MESSAGE_CREATE") => self.message(data).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn message(&self, message: &Value) -> Option<Event> {
+        let ours = self
+            .user_id
+            .lock()
+            .expect("user_id lock poisoned")
+            .clone()?;
+        let author = &message["author"];
+        let author_id = author["id"].as_str()?;
+        // Type 0 is a plain message, 19 a reply. The rest are joins, pins and the like.
+        if author_id == ours || !matches!(message["type"].as_u64(), Some(0 | 19)) {
+            return None;
+        }
+        let from = User {
+            nick: author["username"].as_str()?.to_string(),
+            user: author_id.to_string(),
+            host: String::from("discord"),
+        };
+
+        let channel_id = message["channel_id"].as_str()?;
+        let mut channels = self.channels.lock().expect("channels lock poisoned");
+        channels
+            .users
+            .insert(from.nick.clone(), author_id.to_string());
+        let channel = match message["guild_id"].is_string() {
+            true => Some(channels.names.get(channel_id)?.clone()),
+            false => {
+                channels
+                    .direct
+                    .insert(from.nick.clone(), channel_id.to_string());
+                None
+            }
+        };
+
+        let mentions = message["mentions"].as_array();
+        let mentioned = mentions
+            .into_iter()
+            .flatten()
+            .any(|user| user["id"] == ours.as_str());
+        let content = message["content"].as_str().unwrap_or_default();
+        let text = MARKUP.replace_all(content, |captures: &Captures| {
+            let id = &captures[2];
+            match &captures[1] {
+                "@" | "@!" if id == ours => String::new(),
+                "@" | "@!" => mentions
+                    .into_iter()
+                    .flatten()
+                    .find(|user| user["id"] == id)
+                    .and_then(|user| user["username"].as_str())
+                    .map(|name| format!("@{}", name))
+                    .unwrap_or_else(|| captures[0].to_string()),
+                "#" => channels
+                    .names
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| captures[0].to_string()),
+                emoji => format!(":{}:", emoji.rsplit(':').nth(1).unwrap_or_default()),
+            }
+        });
+
+        Some(Event::Message {
+            from,
+            channel,
+            text: text.into_owned(),
+            kind: Kind::Message,
+            mentioned,
+        })
+    }
+
+    /// The ID of the text channel called `channel`, in any server we're in.
+    async fn find_channel(&self, channel: &str) -> Result<String, Error> {
+        let name = channel.trim_start_matches('#');
+        if name.bytes().all(|b| b.is_ascii_digit()) {
+            let path = format!("/channels/{}", name);
+            let found = self.request(Method::GET, &path, None).await?;
+            return Ok(found["id"].as_str().unwrap_or(name).to_string());
+        }
+
+        let guilds = self.request(Method::GET, "/users/@me/guilds", None).await?;
+        for guild in guilds.as_array().into_iter().flatten() {
+            let Some(guild_id) = guild["id"].as_str() else {
+                continue;
+            };
+            let path = format!("/guilds/{}/channels", guild_id);
+            let found = self.request(Method::GET, &path, None).await?;
+            let found = found.as_array().into_iter().flatten().find(|found| {
+                // Text and announcement channels
+                matches!(found["type"].as_u64(), Some(0 | 5))
+                    && found["name"]
+                        .as_str()
+                        .is_some_and(|found| found.eq_ignore_ascii_case(name))
+            });
+            if let Some(id) = found.and_then(|found| found["id"].as_str()) {
+                return Ok(id.to_string());
+            }
+        }
+
+        Err(Error::Gateway(format!("no channel called {}", channel)))
+    }
+
+    /// The direct message channel with `nick`, opening one if there isn't one already.
+    async fn direct_channel(&self, nick: &str) -> Result<String, Error> {
+        let user_id = {
+            let channels = self.channels.lock().expect("channels lock poisoned");
+            if let Some(channel) = channels.direct.get(nick) {
+                return Ok(channel.clone());
+            }
+            channels
+                .users
+                .get(nick)
+                .cloned()
+                .ok_or_else(|| Error::Gateway(format!("no idea who {} is", nick)))?
+        };
+
+        let body = json!({ "recipient_id": user_id });
+        let response = self.request(Method::POST, "/users/@me/channels", Some(&body));
+        let channel = response.await?["id"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(String::from("no ID for the new channel")))?
+            .to_string();
+        self.channels
+            .lock()
+            .expect("channels lock poisoned")
+            .direct
+            .insert(nick.to_string(), channel.clone());
+
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl ChatNetwork for Discord {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        let mut connection = self.connection.lock().await;
+        let current = match &mut *connection {
+            Some(current) => current,
+            None => connection.insert(self.connect().await?),
+        };
+
+        match self.next(current).await {
+            Ok(Some(events)) => Ok(events),
+            Ok(None) => {
+                current.socket.close(RESUMABLE).await;
+                *connection = None;
+                Ok(Vec::new())
+            }
+            Err(e) => {
+                *connection = None;
+                Err(e)
+            }
+        }
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let folded = CaseMapping::Ascii.fold(channel);
+        if self
+            .channels
+            .lock()
+            .expect("channels lock poisoned")
+            .ids
+            .contains_key(&folded)
+        {
+            return Ok(());
+        }
+
+        let id = self.find_channel(channel).await?;
+        let mut channels = self.channels.lock().expect("channels lock poisoned");
+        channels.names.insert(id.clone(), channel.to_string());
+        channels.ids.insert(folded, id);
+
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let channel = self
+            .channels
+            .lock()
+            .expect("channels lock poisoned")
+            .ids
+            .get(&CaseMapping::Ascii.fold(target))
+            .cloned();
+        let channel = match channel {
+            Some(channel) => channel,
+            None => self.direct_channel(target).await?,
+        };
+
+        let mut content = text
+            .lines()
+            .map(format::markdown)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if kind == Kind::Action {
+            content = format!("_{}_", content);
+        }
+        // Formatting takes more room as Markdown, and it has to fit
+        if content.chars().count() > MAX_LENGTH {
+            content = format::strip(text);
+        }
+        let body = json!({
+            "content": content,
+            "allowed_mentions": { "parse": [] },
+        });
+        let path = format!("/channels/{}/messages", channel);
+        self.request(Method::POST, &path, Some(&body)).await?;
+
+        Ok(())
+    }
+
+    fn max_length(&self) -> usize {
+        MAX_LENGTH
+    }
+}
+
+fn payload(message: &str) -> Result<Value, Error> {
+    serde_json::from_str(message)
+        .map_err(|e| Error::Gateway(format!("unable to parse {:?}: {}", message, e)))
+}
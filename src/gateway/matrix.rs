@@ -0,0 +1,491 @@
+use async_trait::async_trait;
+
+use reqwest::Method;
+use reqwest::StatusCode;
+use reqwest::Url;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use super::ChatNetwork;
+use super::Event;
+use super::Kind;
+use super::User;
+use crate::casemap::CaseMapping;
+use crate::config::MatrixConfig;
+use crate::format;
+use crate::Error;
+
+/// How long the server may hold on to a sync when there's nothing new.
+const SYNC_TIMEOUT_MS: u64 = 30_000;
+
+/// Events can't be bigger than 64 KiB, and nobody wants to read one that big anyway.
+const MAX_LENGTH: usize = 16 * 1024;
+
+/// Times to wait out the server's rate limit before giving up on a request.
+const MAX_RATE_LIMITED: u32 = 3;
+
+/// Only messages, and none of the typing notices, read receipts and presence that come with
+/// them by default.
+const SYNC_FILTER: &str = r#"{"room":{"timeline":{"types":["m.room.message"]},"state":{"lazy_load_members":true},"ephemeral":{"not_types":["*"]},"account_data":{"not_types":["*"]}},"presence":{"not_types":["*"]},"account_data":{"not_types":["*"]}}"#;
+
+/// A Matrix account, over the client-server API. Rooms are channels, named by their alias
+/// or ID, and people go by the localpart of their ID, plus their server when it isn't ours.
+/// Rooms with just pickles and one other person in them are private messages.
+pub struct Matrix {
+    config: MatrixConfig,
+    http: reqwest::Client,
+    session: AsyncMutex<Option<Session>>,
+    /// Where the last sync left off. Until the first, everything is history and isn't
+    /// answered.
+    since: Mutex<Option<String>>,
+    rooms: Mutex<Rooms>,
+    /// For telling our messages apart, so retries aren't sent twice.
+    transactions: AtomicU64,
+}
+
+#[derive(Clone)]
+struct Session {
+    access_token: String,
+    user_id: String,
+}
+
+#[derive(Default)]
+struct Rooms {
+    /// Channel names, by room ID.
+    channels: HashMap<String, String>,
+    /// Room IDs, by folded channel name.
+    ids: HashMap<String, String>,
+    /// Private rooms, by who's in them with us.
+    direct: HashMap<String, String>,
+    /// Matrix IDs, by nick.
+    users: HashMap<String, String>,
+    /// How many people are in each room.
+    members: HashMap<String, u64>,
+}
+
+impl Matrix {
+    pub fn new(config: &MatrixConfig) -> Result<Self, Error> {
+        if config.access_token.is_none() && (config.user.is_none() || config.password.is_none()) {
+            return Err(Error::Gateway(String::from(
+                "Matrix needs an access_token, or a user and password",
+            )));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(SYNC_TIMEOUT_MS * 2))
+            .build()?;
+
+        Ok(Self {
+            config: config.clone(),
+            http,
+            session: AsyncMutex::new(None),
+            since: Mutex::new(None),
+            rooms: Mutex::new(Rooms::default()),
+            transactions: AtomicU64::new(0),
+        })
+    }
+
+    /// `/_matrix/client/v3/<segments>` on our homeserver.
+    fn url(&self, segments: &[&str]) -> Result<Url, Error> {
+        let invalid = || Error::Gateway(format!("invalid homeserver {}", self.config.homeserver));
+        let mut url = Url::parse(&self.config.homeserver).map_err(|_| invalid())?;
+        url.path_segments_mut()
+            .map_err(|_| invalid())?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(segments);
+
+        Ok(url)
+    }
+
+    /// Logs in if we haven't yet, and finds out who we are.
+    async fn session(&self) -> Result<Session, Error> {
+        let mut session = self.session.lock().await;
+        if let Some(session) = &*session {
+            return Ok(session.clone());
+        }
+
+        let access_token = match &self.config.access_token {
+            Some(token) => token.clone(),
+            None => {
+                let body = json!({
+                    "type": "m.login.password",
+                    "identifier": { "type": "m.id.user", "user": self.config.user },
+                    "password": self.config.password,
+                    "initial_device_display_name": "pickles",
+                });
+                let response = self.request(Method::POST, &["login"], None, &[], Some(&body));
+                let response = response.await?;
+                response["access_token"]
+                    .as_str()
+                    .ok_or_else(|| Error::Gateway(String::from("no access token after login")))?
+                    .to_string()
+            }
+        };
+        let whoami = self
+            .request(
+                Method::GET,
+                &["account", "whoami"],
+                Some(&access_token),
+                &[],
+                None,
+            )
+            .await?;
+        let user_id = whoami["user_id"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(String::from("unable to tell who we are")))?
+            .to_string();
+        info!("Logged in to Matrix as {}", user_id);
+
+        Ok(session
+            .insert(Session {
+                access_token,
+                user_id,
+            })
+            .clone())
+    }
+
+    async fn call(
+        &self,
+        method: Method,
+        segments: &[&str],
+        query: &[(&str, &str)],
+        body: Option<&Value>,
+    ) -> Result<Value, Error> {
+        let session = self.session().await?;
+        let result = self
+            .request(method, segments, Some(&session.access_token), query, body)
+            .await;
+        // Log in again next time if the token stopped working
+        if let Err(Error::Gateway(e)) = &result {
+            if e.starts_with("M_UNKNOWN_TOKEN") {
+                *self.session.lock().await = None;
+            }
+        }
+
+        result
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        segments: &[&str],
+        access_token: Option<&str>,
+        query: &[(&str, &str)],
+        body: Option<&Value>,
+    ) -> Result<Value, Error> {
+        let url = self.url(segments)?;
+        let mut rate_limited = 0;
+        loop {
+            let mut request = self.http.request(method.clone(), url.clone()).query(query);
+            if let Some(token) = access_token {
+                request = request.bearer_auth(token);
+            }
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let response = request.send().await?;
+            let status = response.status();
+            let response: Value = response.json().await?;
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS && rate_limited < MAX_RATE_LIMITED {
+                rate_limited += 1;
+                let wait = response["retry_after_ms"].as_u64().unwrap_or(1000);
+                time::sleep(Duration::from_millis(wait)).await;
+                continue;
+            }
+            return Err(Error::Gateway(format!(
+                "{}: {}",
+                response["errcode"].as_str().unwrap_or(status.as_str()),
+                response["error"].as_str().unwrap_or_default()
+            )));
+        }
+    }
+
+    /// Joins `room`, an alias or ID, returning its ID.
+    async fn join_room(&self, room: &str) -> Result<String, Error> {
+        let response = self
+            .call(Method::POST, &["join", room], &[], Some(&json!({})))
+            .await?;
+
+        Ok(response["room_id"].as_str().unwrap_or(room).to_string())
+    }
+
+    /// A private room with `nick`, making one if there isn't one already.
+    async fn direct_room(&self, nick: &str) -> Result<String, Error> {
+        let user_id = {
+            let rooms = self.rooms.lock().expect("rooms lock poisoned");
+            if let Some(room) = rooms.direct.get(nick) {
+                return Ok(room.clone());
+            }
+            rooms
+                .users
+                .get(nick)
+                .cloned()
+                .ok_or_else(|| Error::Gateway(format!("no idea who {} is", nick)))?
+        };
+
+        let body = json!({
+            "is_direct": true,
+            "invite": [user_id],
+            "preset": "trusted_private_chat",
+        });
+        let response = self.call(Method::POST, &["createRoom"], &[], Some(&body));
+        let room = response.await?["room_id"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(String::from("no room ID for the new room")))?
+            .to_string();
+        let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+        rooms.direct.insert(nick.to_string(), room.clone());
+        rooms.members.insert(room.clone(), 2);
+
+        Ok(room)
+    }
+
+    /// How `user_id` is known on IRC.
+    fn user(&self, user_id: &str, ours: &str) -> User {
+        let (localpart, server) = split_id(user_id);
+        let nick = match server == split_id(ours).1 {
+            true => localpart.to_string(),
+            false => format!("{}:{}", localpart, server),
+        };
+
+        User {
+            nick,
+            user: localpart.to_string(),
+            host: server.to_string(),
+        }
+    }
+
+    /// What happened in `room`, as far as pickles is concerned.
+    fn messages(&self, room_id: &str, room: &Value, session: &Session) -> Vec<Event> {
+        let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+        if let Some(count) = room["summary"]["m.joined_member_count"].as_u64() {
+            rooms.members.insert(room_id.to_string(), count);
+        }
+
+        let mut events = Vec::new();
+        for event in room["timeline"]["events"].as_array().into_iter().flatten() {
+            let Some(sender) = event["sender"].as_str() else {
+                continue;
+            };
+            if event["type"] != "m.room.message" || sender == session.user_id {
+                continue;
+            }
+            let content = &event["content"];
+            let kind = match content["msgtype"].as_str() {
+                Some("m.text") => Kind::Message,
+                Some("m.emote") => Kind::Action,
+                Some("m.notice") => Kind::Notice,
+                _ => continue,
+            };
+            let from = self.user(sender, &session.user_id);
+            rooms.users.insert(from.nick.clone(), sender.to_string());
+            let channel = match rooms.channels.get(room_id) {
+                Some(channel) => Some(channel.clone()),
+                None if rooms.members.get(room_id).is_some_and(|&count| count <= 2) => {
+                    rooms.direct.insert(from.nick.clone(), room_id.to_string());
+                    None
+                }
+                // Somewhere we were once asked to be but aren't any more
+                None => continue,
+            };
+
+            let mentioned = content["m.mentions"]["user_ids"]
+                .as_array()
+                .is_some_and(|ids| ids.iter().any(|id| id == session.user_id.as_str()));
+            let text = content["body"].as_str().unwrap_or_default();
+            let text = match mentioned {
+                true => without_pill(text, content, &session.user_id),
+                false => without_reply_fallback(text),
+            };
+            events.push(Event::Message {
+                from,
+                channel,
+                text: text.to_string(),
+                kind,
+                mentioned,
+            });
+        }
+
+        events
+    }
+
+    /// Invitations to private rooms are accepted straight away, anything else is up to
+    /// `invite_channels`.
+    async fn invite(&self, room_id: &str, room: &Value, session: &Session) -> Option<Event> {
+        let state = room["invite_state"]["events"].as_array()?;
+        let invite = state.iter().find(|event| {
+            event["type"] == "m.room.member" && event["state_key"] == session.user_id.as_str()
+        })?;
+        let from = self.user(invite["sender"].as_str()?, &session.user_id);
+        if invite["content"]["is_direct"] == true {
+            info!("Accepting a private chat with {}", from.nick);
+            match self.join_room(room_id).await {
+                Ok(room) => {
+                    let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+                    rooms.direct.insert(from.nick.clone(), room.clone());
+                    let user_id = invite["sender"].as_str().unwrap_or_default();
+                    rooms.users.insert(from.nick.clone(), user_id.to_string());
+                    rooms.members.insert(room, 2);
+                }
+                Err(e) => warn!("Unable to join {}: {}", room_id, e),
+            }
+            return None;
+        }
+
+        let alias = state
+            .iter()
+            .find(|event| event["type"] == "m.room.canonical_alias")
+            .and_then(|event| event["content"]["alias"].as_str());
+        Some(Event::Invite {
+            from,
+            channel: alias.unwrap_or(room_id).to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl ChatNetwork for Matrix {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        let session = self.session().await?;
+        let since = self.since.lock().expect("since lock poisoned").clone();
+        let timeout = SYNC_TIMEOUT_MS.to_string();
+        let mut query = vec![("filter", SYNC_FILTER)];
+        match &since {
+            Some(since) => query.extend([("since", since.as_str()), ("timeout", timeout.as_str())]),
+            None => query.push(("timeout", "0")),
+        }
+        let response = self.call(Method::GET, &["sync"], &query, None).await?;
+        if let Some(next) = response["next_batch"].as_str() {
+            *self.since.lock().expect("since lock poisoned") = Some(next.to_string());
+        }
+
+        let mut events = Vec::new();
+        if let Some(joined) = response["rooms"]["join"].as_object() {
+            for (room_id, room) in joined {
+                let messages = self.messages(room_id, room, &session);
+                if since.is_some() {
+                    events.extend(messages);
+                }
+            }
+        }
+        if let Some(invited) = response["rooms"]["invite"].as_object() {
+            for (room_id, room) in invited {
+                events.extend(self.invite(room_id, room, &session).await);
+            }
+        }
+
+        Ok(events)
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let folded = CaseMapping::Ascii.fold(channel);
+        if self
+            .rooms
+            .lock()
+            .expect("rooms lock poisoned")
+            .ids
+            .contains_key(&folded)
+        {
+            return Ok(());
+        }
+
+        let room = self.join_room(channel).await?;
+        let mut rooms = self.rooms.lock().expect("rooms lock poisoned");
+        rooms.channels.insert(room.clone(), channel.to_string());
+        rooms.ids.insert(folded, room);
+
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let room = self
+            .rooms
+            .lock()
+            .expect("rooms lock poisoned")
+            .ids
+            .get(&CaseMapping::Ascii.fold(target))
+            .cloned();
+        let room = match room {
+            Some(room) => room,
+            None => self.direct_room(target).await?,
+        };
+
+        let lines: Vec<&str> = text.lines().collect();
+        let body = json!({
+            "msgtype": match kind {
+                Kind::Message => "m.text",
+                Kind::Action => "m.emote",
+                Kind::Notice => "m.notice",
+            },
+            "body": lines.iter().map(|line| format::strip(line)).collect::<Vec<_>>().join("\n"),
+            "format": "org.matrix.custom.html",
+            "formatted_body": lines.iter().map(|line| format::html(line)).collect::<Vec<_>>().join("<br>"),
+        });
+        let transaction = format!(
+            "pickles-{}-{}",
+            std::process::id(),
+            self.transactions.fetch_add(1, Ordering::Relaxed)
+        );
+        self.call(
+            Method::PUT,
+            &["rooms", &room, "send", "m.room.message", &transaction],
+            &[],
+            Some(&body),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    fn max_length(&self) -> usize {
+        MAX_LENGTH
+    }
+}
+
+/// `@alice:example.org` as `("alice", "example.org")`.
+fn split_id(user_id: &str) -> (&str, &str) {
+    let user_id = user_id.strip_prefix('@').unwrap_or(user_id);
+    user_id.split_once(':').unwrap_or((user_id, ""))
+}
+
+/// Clients put the name of whoever's mentioned with a pill at the start, followed by a colon.
+fn without_pill<'a>(text: &'a str, content: &Value, user_id: &str) -> &'a str {
+    let text = without_reply_fallback(text);
+    let pill = format!("<a href=\"https://matrix.to/#/{}\">", user_id);
+    let formatted = content["formatted_body"].as_str().unwrap_or_default();
+    // The HTML has the message being replied to in an <mx-reply> instead
+    let formatted = formatted
+        .split_once("</mx-reply>")
+        .map(|(_, formatted)| formatted)
+        .unwrap_or(formatted);
+    match formatted.starts_with(&pill) {
+        true => text.split_once(": ").map(|(_, rest)| rest).unwrap_or(text),
+        false => text,
+    }
+}
+
+/// Replies start with the message they're replying to, quoted, for clients that don't know
+/// about replies.
+fn without_reply_fallback(text: &str) -> &str {
+    if !text.starts_with("> ") {
+        return text;
+    }
+    text.split_once("\n\n")
+        .map(|(_, body)| body)
+        .unwrap_or(text)
+}
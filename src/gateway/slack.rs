@@ -0,0 +1,528 @@
+use async_trait::async_trait;
+
+use regex::Captures;
+use regex::Regex;
+
+use reqwest::StatusCode;
+use reqwest::Url;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use super::websocket::WebSocket;
+use super::ChatNetwork;
+use super::Event;
+use super::Kind;
+use super::User;
+use crate::casemap::CaseMapping;
+use crate::config::SlackConfig;
+use crate::format;
+use crate::Error;
+
+const API: &str = "https://slack.com/api";
+
+/// Slack takes a lot more, but cuts long messages up itself past this.
+const MAX_LENGTH: usize = 4000;
+
+/// Times to wait out Slack's rate limit before giving up on a request.
+const MAX_RATE_LIMITED: u32 = 3;
+
+/// Mentions of people and channels, special mentions like `<!here>`, and links, each with an
+/// optional label after a `|`.
+static MARKUP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<([@#!]?)([^>|]+)(?:\|([^>]*))?>").expect("Invalid markup regex")
+});
+
+/// A Slack app in Socket Mode, with events over a WebSocket and the Web API for everything
+/// else. Channels are channels, named `#name` or `#<channel ID>`, and people go by their
+/// username. Direct messages are private messages. Answers go to the thread, if any, that
+/// whoever's being answered last spoke in.
+pub struct Slack {
+    config: SlackConfig,
+    http: reqwest::Client,
+    socket: AsyncMutex<Option<WebSocket>>,
+    /// Our own user ID, once Slack has told us.
+    user_id: AsyncMutex<Option<String>>,
+    conversations: Mutex<Conversations>,
+}
+
+#[derive(Default)]
+struct Conversations {
+    /// Channel names, by channel ID.
+    names: HashMap<String, String>,
+    /// Channel IDs, by folded channel name.
+    ids: HashMap<String, String>,
+    /// Direct message channel IDs, by nick.
+    direct: HashMap<String, String>,
+    /// Nicks, by user ID.
+    nicks: HashMap<String, String>,
+    /// User IDs, by nick.
+    users: HashMap<String, String>,
+    /// The thread each person last spoke in, by channel ID and nick, if it was in one.
+    threads: HashMap<(String, String), String>,
+    /// The thread the last message in each channel was in, by channel ID.
+    last_thread: HashMap<String, String>,
+}
+
+impl Slack {
+    pub fn new(config: &SlackConfig) -> Result<Self, Error> {
+        if config.app_token.is_empty() || config.bot_token.is_empty() {
+            return Err(Error::Gateway(String::from(
+                "Slack needs an app_token and a bot_token",
+            )));
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            socket: AsyncMutex::new(None),
+            user_id: AsyncMutex::new(None),
+            conversations: Mutex::new(Conversations::default()),
+        })
+    }
+
+    /// Calls a Web API `method` with the bot token.
+    async fn call(&self, method: &str, params: &[(&str, &str)]) -> Result<Value, Error> {
+        self.request(method, &self.config.bot_token, params).await
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        token: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Value, Error> {
+        let api = self.config.api_base.as_deref().unwrap_or(API);
+        let url = format!("{}/{}", api.trim_end_matches('/'), method);
+        let mut rate_limited = 0;
+        loop {
+            let response = self
+                .http
+                .post(&url)
+                .bearer_auth(token)
+                .form(params)
+                .send()
+                .await?;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS && rate_limited < MAX_RATE_LIMITED
+            {
+                rate_limited += 1;
+                let wait = response
+                    .headers()
+                    .get("Retry-After")
+                    .and_then(|wait| wait.to_str().ok()?.parse().ok())
+                    .unwrap_or(1);
+                time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            let response: Value = response.error_for_status()?.json().await?;
+            if response["ok"] != true {
+                return Err(Error::Gateway(format!(
+                    "{} failed: {}",
+                    method,
+                    response["error"].as_str().unwrap_or("unknown error")
+                )));
+            }
+            return Ok(response);
+        }
+    }
+
+    async fn user_id(&self) -> Result<String, Error> {
+        let mut user_id = self.user_id.lock().await;
+        if let Some(user_id) = &*user_id {
+            return Ok(user_id.clone());
+        }
+
+        let auth = self.call("auth.test", &[]).await?;
+        let (Some(id), Some(name)) = (auth["user_id"].as_str(), auth["user"].as_str()) else {
+            return Err(Error::Gateway(String::from("unable to tell who we are")));
+        };
+        info!(
+            "Connected to Slack as {} in {}",
+            name,
+            auth["team"].as_str().unwrap_or_default()
+        );
+
+        Ok(user_id.insert(id.to_string()).clone())
+    }
+
+    async fn connect(&self) -> Result<WebSocket, Error> {
+        let response = self
+            .request("apps.connections.open", &self.config.app_token, &[])
+            .await?;
+        let url = response["url"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(String::from("no Socket Mode URL")))?;
+        let url =
+            Url::parse(url).map_err(|e| Error::Gateway(format!("invalid URL {}: {}", url, e)))?;
+
+        WebSocket::connect(&url).await
+    }
+
+    /// Waits for something pickles cares about, or `None` if it's time to reconnect.
+    async fn next(&self, socket: &mut WebSocket, ours: &str) -> Result<Option<Vec<Event>>, Error> {
+        loop {
+            let Some(message) = socket.receive().await? else {
+                return Err(Error::Gateway(String::from("Slack closed the connection")));
+            };
+            let envelope: Value = serde_json::from_str(&message)
+                .map_err(|e| Error::Gateway(format!("unable to parse {:?}: {}", message, e)))?;
+
+            // Everything has to be acknowledged, or Slack sends it again
+            if let Some(id) = envelope["envelope_id"].as_str() {
+                socket
+                    .send(&json!({ "envelope_id": id }).to_string())
+                    .await?;
+            }
+            match envelope["type"].as_str() {
+                Some("events_api") => {
+                    if let Some(event) = self.message(&envelope["payload"]["event"], ours).await {
+                        return Ok(Some(vec![event]));
+                    }
+                }
+                Some("disconnect") => {
+                    debug!("Slack asked us to reconnect");
+                    return Ok(None);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    async fn message(&self, event: &Value, ours: &str) -> Option<Event> {
+        // Edits, deletions, joins and the like have a subtype
+        let subtype = event["subtype"].as_str();
+        if event["type"] != "message" || !matches!(subtype, None | Some("thread_broadcast")) {
+            return None;
+        }
+        let user_id = event["user"].as_str()?;
+        let conversation = event["channel"].as_str()?;
+        if user_id == ours {
+            return None;
+        }
+        let nick = self.nick(user_id).await;
+        let (text, mentioned) = self.text(event["text"].as_str()?, ours).await;
+
+        let mut conversations = self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned");
+        let channel = match event["channel_type"].as_str() {
+            Some("im") => {
+                conversations
+                    .direct
+                    .insert(nick.clone(), conversation.to_string());
+                None
+            }
+            _ => Some(conversations.names.get(conversation)?.clone()),
+        };
+        let key = (conversation.to_string(), nick.clone());
+        match event["thread_ts"].as_str() {
+            Some(thread) => {
+                conversations.threads.insert(key, thread.to_string());
+                conversations
+                    .last_thread
+                    .insert(conversation.to_string(), thread.to_string());
+            }
+            None => {
+                conversations.threads.remove(&key);
+                conversations.last_thread.remove(conversation);
+            }
+        }
+
+        Some(Event::Message {
+            from: User {
+                nick,
+                user: user_id.to_string(),
+                host: String::from("slack"),
+            },
+            channel,
+            text,
+            kind: Kind::Message,
+            mentioned,
+        })
+    }
+
+    /// What `user_id` goes by, looking them up if we haven't yet.
+    async fn nick(&self, user_id: &str) -> String {
+        if let Some(nick) = self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned")
+            .nicks
+            .get(user_id)
+        {
+            return nick.clone();
+        }
+
+        let nick = match self.call("users.info", &[("user", user_id)]).await {
+            Ok(info) => info["user"]["name"].as_str().unwrap_or(user_id).to_string(),
+            Err(e) => {
+                warn!("Unable to look up {}: {}", user_id, e);
+                return user_id.to_string();
+            }
+        };
+        let mut conversations = self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned");
+        conversations
+            .nicks
+            .insert(user_id.to_string(), nick.clone());
+        conversations
+            .users
+            .insert(nick.clone(), user_id.to_string());
+
+        nick
+    }
+
+    /// `text` as plain text, and whether it mentions us.
+    async fn text(&self, text: &str, ours: &str) -> (String, bool) {
+        let mut nicks = HashMap::new();
+        for captures in MARKUP.captures_iter(text) {
+            if &captures[1] == "@" && &captures[2] != ours {
+                nicks.insert(captures[2].to_string(), self.nick(&captures[2]).await);
+            }
+        }
+
+        let mut mentioned = false;
+        let text = MARKUP.replace_all(text, |captures: &Captures| {
+            let id = &captures[2];
+            let label = captures.get(3).map(|label| label.as_str());
+            match &captures[1] {
+                "@" if id == ours => {
+                    mentioned = true;
+                    String::new()
+                }
+                "@" => format!("@{}", nicks.get(id).map(String::as_str).unwrap_or(id)),
+                "#" => format!("#{}", label.unwrap_or(id)),
+                // @here, @channel and user groups
+                "!" => label
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("@{}", id)),
+                // Links, as the URL so titles and the like still work
+                _ => id.to_string(),
+            }
+        });
+        let text = text
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&");
+
+        (text, mentioned)
+    }
+
+    /// The ID of the channel called `channel`, joining it if it's public and we haven't yet.
+    async fn find_channel(&self, channel: &str) -> Result<String, Error> {
+        let name = channel.trim_start_matches('#');
+        let mut cursor = String::new();
+        // IDs are upper case, names never are
+        let found = if name
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        {
+            let info = self
+                .call("conversations.info", &[("channel", name)])
+                .await?;
+            info["channel"].clone()
+        } else {
+            loop {
+                let page = self
+                    .call(
+                        "conversations.list",
+                        &[
+                            ("types", "public_channel,private_channel"),
+                            ("exclude_archived", "true"),
+                            ("limit", "1000"),
+                            ("cursor", &cursor),
+                        ],
+                    )
+                    .await?;
+                let found = page["channels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|found| {
+                        found["name"]
+                            .as_str()
+                            .is_some_and(|found| found.eq_ignore_ascii_case(name))
+                    });
+                if let Some(found) = found {
+                    break found.clone();
+                }
+                match page["response_metadata"]["next_cursor"].as_str() {
+                    Some(next) if !next.is_empty() => cursor = next.to_string(),
+                    _ => return Err(Error::Gateway(format!("no channel called {}", channel))),
+                }
+            }
+        };
+        let id = found["id"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(format!("no channel called {}", channel)))?;
+
+        // Private channels we have to be invited to
+        if found["is_member"] != true && found["is_private"] != true {
+            self.call("conversations.join", &[("channel", id)]).await?;
+        }
+
+        Ok(id.to_string())
+    }
+
+    /// The direct message channel with `nick`, opening one if there isn't one already.
+    async fn direct_channel(&self, nick: &str) -> Result<String, Error> {
+        let user_id = {
+            let conversations = self
+                .conversations
+                .lock()
+                .expect("conversations lock poisoned");
+            if let Some(channel) = conversations.direct.get(nick) {
+                return Ok(channel.clone());
+            }
+            conversations
+                .users
+                .get(nick)
+                .cloned()
+                .ok_or_else(|| Error::Gateway(format!("no idea who {} is", nick)))?
+        };
+
+        let response = self
+            .call("conversations.open", &[("users", &user_id)])
+            .await?;
+        let channel = response["channel"]["id"]
+            .as_str()
+            .ok_or_else(|| Error::Gateway(String::from("no ID for the new channel")))?
+            .to_string();
+        self.conversations
+            .lock()
+            .expect("conversations lock poisoned")
+            .direct
+            .insert(nick.to_string(), channel.clone());
+
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl ChatNetwork for Slack {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        let ours = self.user_id().await?;
+        let mut socket = self.socket.lock().await;
+        let current = match &mut *socket {
+            Some(current) => current,
+            None => socket.insert(self.connect().await?),
+        };
+
+        match self.next(current, &ours).await {
+            Ok(Some(events)) => Ok(events),
+            Ok(None) => {
+                current.close(1000).await;
+                *socket = None;
+                Ok(Vec::new())
+            }
+            Err(e) => {
+                *socket = None;
+                Err(e)
+            }
+        }
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let folded = CaseMapping::Ascii.fold(channel);
+        if self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned")
+            .ids
+            .contains_key(&folded)
+        {
+            return Ok(());
+        }
+
+        let id = self.find_channel(channel).await?;
+        let mut conversations = self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned");
+        conversations.names.insert(id.clone(), channel.to_string());
+        conversations.ids.insert(folded, id);
+
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let channel = self
+            .conversations
+            .lock()
+            .expect("conversations lock poisoned")
+            .ids
+            .get(&CaseMapping::Ascii.fold(target))
+            .cloned();
+        let (conversation, nick) = match channel {
+            // Answers in channels start with who they're for, when they're for someone
+            Some(channel) => (channel, text.split_once(": ").map(|(nick, _)| nick)),
+            None => (self.direct_channel(target).await?, Some(target)),
+        };
+        let thread = {
+            let conversations = self
+                .conversations
+                .lock()
+                .expect("conversations lock poisoned");
+            nick.and_then(|nick| {
+                conversations
+                    .threads
+                    .get(&(conversation.clone(), nick.to_string()))
+            })
+            .or_else(|| conversations.last_thread.get(&conversation))
+            .cloned()
+        };
+
+        let mut text = mrkdwn(text);
+        if kind == Kind::Action {
+            text = format!("_{}_", text);
+        }
+        let mut params = vec![("channel", conversation.as_str()), ("text", text.as_str())];
+        if let Some(thread) = &thread {
+            params.push(("thread_ts", thread));
+        }
+        self.call("chat.postMessage", &params).await?;
+
+        Ok(())
+    }
+
+    fn max_length(&self) -> usize {
+        MAX_LENGTH
+    }
+}
+
+/// `text` as mrkdwn, with code blocks as code blocks rather than a line of code at a time.
+fn mrkdwn(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut block: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if let Some(code) = format::code(line) {
+            // Even in code, &, < and > have to be escaped as they would be in HTML
+            block.push(format::html(code));
+            continue;
+        }
+        if !block.is_empty() {
+            lines.push(format!("```{}```", block.join("\n")));
+            block.clear();
+        }
+        lines.push(format::mrkdwn(line));
+    }
+    if !block.is_empty() {
+        lines.push(format!("```{}```", block.join("\n")));
+    }
+
+    lines.join("\n")
+}
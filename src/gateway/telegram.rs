@@ -0,0 +1,306 @@
+use async_trait::async_trait;
+
+use regex::Regex;
+
+use reqwest::StatusCode;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::ChatNetwork;
+use super::Event;
+use super::Kind;
+use super::User;
+use crate::casemap::CaseMapping;
+use crate::config::TelegramConfig;
+use crate::format;
+use crate::Error;
+
+const API: &str = "https://api.telegram.org";
+
+/// How long Telegram may hold on to a request for updates when there's nothing new.
+const POLL_TIMEOUT_SECS: u64 = 30;
+
+/// Telegram counts UTF-16 code units after the HTML is taken out, which there are never more
+/// of than bytes.
+const MAX_LENGTH: usize = 4096;
+
+/// Times to wait out Telegram's rate limit before giving up on a request.
+const MAX_RATE_LIMITED: u32 = 3;
+
+/// A Telegram bot, over the Bot API with long polling. Groups are channels, named `#<chat ID>`
+/// or `#<username>` for public ones, and people go by their username. Private chats are
+/// private messages.
+pub struct Telegram {
+    config: TelegramConfig,
+    http: reqwest::Client,
+    /// Who we are, once Telegram has said.
+    me: AsyncMutex<Option<Me>>,
+    /// The first update we haven't seen. Until the first poll, everything is history and
+    /// isn't answered.
+    offset: Mutex<Option<i64>>,
+    chats: Mutex<Chats>,
+}
+
+#[derive(Clone)]
+struct Me {
+    id: i64,
+    /// `@username`, for finding where we're mentioned.
+    mention: Regex,
+}
+
+#[derive(Default)]
+struct Chats {
+    /// Channel names, by chat ID.
+    names: HashMap<i64, String>,
+    /// Chat IDs, by folded channel name.
+    ids: HashMap<String, i64>,
+    /// Private chat IDs, by nick.
+    direct: HashMap<String, i64>,
+}
+
+impl Telegram {
+    pub fn new(config: &TelegramConfig) -> Result<Self, Error> {
+        if config.token.is_empty() {
+            return Err(Error::Gateway(String::from("Telegram needs a token")));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS * 2))
+            .build()?;
+
+        Ok(Self {
+            config: config.clone(),
+            http,
+            me: AsyncMutex::new(None),
+            offset: Mutex::new(None),
+            chats: Mutex::new(Chats::default()),
+        })
+    }
+
+    async fn request(&self, method: &str, body: &Value) -> Result<Value, Error> {
+        let api = self.config.api_base.as_deref().unwrap_or(API);
+        let url = format!(
+            "{}/bot{}/{}",
+            api.trim_end_matches('/'),
+            self.config.token,
+            method
+        );
+        let mut rate_limited = 0;
+        loop {
+            let response = self.http.post(&url).json(body).send().await?;
+            let status = response.status();
+            let mut response: Value = response.json().await?;
+            if response["ok"] == true {
+                return Ok(response["result"].take());
+            }
+
+            if status == StatusCode::TOO_MANY_REQUESTS && rate_limited < MAX_RATE_LIMITED {
+                rate_limited += 1;
+                let wait = response["parameters"]["retry_after"].as_u64().unwrap_or(1);
+                time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            return Err(Error::Gateway(format!(
+                "{} failed: {}",
+                method,
+                response["description"].as_str().unwrap_or(status.as_str())
+            )));
+        }
+    }
+
+    async fn me(&self) -> Result<Me, Error> {
+        let mut me = self.me.lock().await;
+        if let Some(me) = &*me {
+            return Ok(me.clone());
+        }
+
+        let bot = self.request("getMe", &json!({})).await?;
+        let (Some(id), Some(username)) = (bot["id"].as_i64(), bot["username"].as_str()) else {
+            return Err(Error::Gateway(String::from("unable to tell who we are")));
+        };
+        info!("Connected to Telegram as @{}", username);
+        let mention = Regex::new(&format!(r"(?i)@{}\b", regex::escape(username)))
+            .map_err(|e| Error::Gateway(e.to_string()))?;
+
+        Ok(me.insert(Me { id, mention }).clone())
+    }
+
+    fn message(&self, message: &Value, me: &Me) -> Option<Event> {
+        let from = &message["from"];
+        let user_id = from["id"].as_i64()?;
+        let nick = match from["username"].as_str() {
+            Some(username) => username.to_string(),
+            // Not everyone has a username, but everyone has a first name
+            None => from["first_name"].as_str()?.split_whitespace().collect(),
+        };
+        let from = User {
+            nick,
+            user: user_id.to_string(),
+            host: String::from("telegram"),
+        };
+
+        let chat = &message["chat"];
+        let chat_id = chat["id"].as_i64()?;
+        let mut chats = self.chats.lock().expect("chats lock poisoned");
+        let channel = match chat["type"].as_str()? {
+            "private" => {
+                chats.direct.insert(from.nick.clone(), chat_id);
+                None
+            }
+            "group" | "supergroup" => match chats.names.get(&chat_id) {
+                Some(channel) => Some(channel.clone()),
+                None => {
+                    // Being added to a group is as good as an invite
+                    let added = message["new_chat_members"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .any(|member| member["id"] == me.id);
+                    let channel = match chat["username"].as_str() {
+                        Some(username) => format!("#{}", username),
+                        None => format!("#{}", chat_id),
+                    };
+                    return added.then_some(Event::Invite { from, channel });
+                }
+            },
+            _ => return None,
+        };
+
+        let text = message["text"].as_str().or(message["caption"].as_str())?;
+        let replying = message["reply_to_message"]["from"]["id"] == me.id;
+        let mentioned = replying || me.mention.is_match(text);
+
+        Some(Event::Message {
+            from,
+            channel,
+            text: me.mention.replace_all(text, "").into_owned(),
+            kind: Kind::Message,
+            mentioned,
+        })
+    }
+}
+
+#[async_trait]
+impl ChatNetwork for Telegram {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        let me = self.me().await?;
+        let offset = *self.offset.lock().expect("offset lock poisoned");
+        let body = match offset {
+            Some(offset) => json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT_SECS,
+                "allowed_updates": ["message"],
+            }),
+            // Only the latest, to know where to start from
+            None => json!({ "offset": -1, "timeout": 0 }),
+        };
+        let updates = self.request("getUpdates", &body).await?;
+
+        let mut next = offset.unwrap_or_default();
+        let mut events = Vec::new();
+        for update in updates.as_array().into_iter().flatten() {
+            if let Some(id) = update["update_id"].as_i64() {
+                next = next.max(id + 1);
+            }
+            if offset.is_some() && update["message"].is_object() {
+                events.extend(self.message(&update["message"], &me));
+            }
+        }
+        *self.offset.lock().expect("offset lock poisoned") = Some(next);
+
+        Ok(events)
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let folded = CaseMapping::Ascii.fold(channel);
+        if self
+            .chats
+            .lock()
+            .expect("chats lock poisoned")
+            .ids
+            .contains_key(&folded)
+        {
+            return Ok(());
+        }
+
+        let name = channel.trim_start_matches('#');
+        let chat_id = match name.parse::<i64>() {
+            Ok(chat_id) => json!(chat_id),
+            Err(_) => json!(format!("@{}", name)),
+        };
+        let chat = self
+            .request("getChat", &json!({ "chat_id": chat_id }))
+            .await?;
+        let chat_id = chat["id"]
+            .as_i64()
+            .ok_or_else(|| Error::Gateway(format!("no chat called {}", channel)))?;
+        let mut chats = self.chats.lock().expect("chats lock poisoned");
+        chats.names.insert(chat_id, channel.to_string());
+        chats.ids.insert(folded, chat_id);
+
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let chat_id = {
+            let chats = self.chats.lock().expect("chats lock poisoned");
+            chats
+                .ids
+                .get(&CaseMapping::Ascii.fold(target))
+                .or_else(|| chats.direct.get(target))
+                .copied()
+                // Bots can't start private chats, only answer them
+                .ok_or_else(|| Error::Gateway(format!("no chat with {}", target)))?
+        };
+
+        let mut html = html(text);
+        if kind == Kind::Action {
+            html = format!("<i>{}</i>", html);
+        }
+        let body = json!({
+            "chat_id": chat_id,
+            "text": html,
+            "parse_mode": "HTML",
+            // Notices are for things that don't need anyone's attention
+            "disable_notification": kind == Kind::Notice,
+        });
+        self.request("sendMessage", &body).await?;
+
+        Ok(())
+    }
+
+    fn max_length(&self) -> usize {
+        MAX_LENGTH
+    }
+}
+
+/// `text` as the HTML Telegram takes, with code blocks as code blocks rather than a line of
+/// code at a time.
+fn html(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut block: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if let Some(code) = format::code(line) {
+            block.push(format::html(code));
+            continue;
+        }
+        if !block.is_empty() {
+            lines.push(format!("<pre>{}</pre>", block.join("\n")));
+            block.clear();
+        }
+        lines.push(format::html(line));
+    }
+    if !block.is_empty() {
+        lines.push(format!("<pre>{}</pre>", block.join("\n")));
+    }
+
+    lines.join("\n")
+}
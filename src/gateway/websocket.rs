@@ -0,0 +1,256 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use reqwest::Url;
+
+use sha1::Digest;
+use sha1::Sha1;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::io::ReadHalf;
+use tokio::io::WriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::sync::Mutex;
+use tracing::*;
+
+use std::sync::Arc;
+
+use super::io_error;
+use super::tls;
+use super::Stream;
+use crate::Error;
+
+/// Messages bigger than this are taken as the connection having gone wrong.
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+/// Appended to the key to prove the server understood the handshake, RFC 6455 section 1.3.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+type Writer = Arc<Mutex<WriteHalf<Box<dyn Stream>>>>;
+
+/// Just enough of a WebSocket client for the chat networks that push events over one. Only
+/// text messages are passed along, pings are answered as they arrive.
+pub struct WebSocket {
+    messages: mpsc::UnboundedReceiver<Result<String, Error>>,
+    writer: Writer,
+}
+
+impl WebSocket {
+    /// Connects to a `ws://` or `wss://` URL.
+    pub async fn connect(url: &Url) -> Result<Self, Error> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::Gateway(format!("no host in {}", url)))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| Error::Gateway(format!("no port for {}", url)))?;
+        let socket = TcpStream::connect((host, port)).await.map_err(io_error)?;
+        let stream: Box<dyn Stream> = match url.scheme() {
+            "wss" => tls(host, socket).await?,
+            "ws" => Box::new(socket),
+            scheme => return Err(Error::Gateway(format!("can't connect to {} URLs", scheme))),
+        };
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+
+        let key = BASE64.encode(rand::random::<[u8; 16]>());
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{}?{}", path, query);
+        }
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, host, key
+        );
+        writer
+            .write_all(request.as_bytes())
+            .await
+            .map_err(io_error)?;
+
+        let mut status = String::new();
+        reader.read_line(&mut status).await.map_err(io_error)?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            return Err(Error::Gateway(format!(
+                "{} refused the WebSocket: {}",
+                host,
+                status.trim()
+            )));
+        }
+        let mut accept = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await.map_err(io_error)? == 0 {
+                return Err(Error::Gateway(format!("{} hung up", host)));
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("sec-websocket-accept") {
+                    accept = Some(value.trim().to_string());
+                }
+            }
+        }
+        let expected = BASE64.encode(Sha1::digest(format!("{}{}", key, ACCEPT_GUID)));
+        if accept.as_deref() != Some(expected.as_str()) {
+            return Err(Error::Gateway(format!(
+                "{} answered the WebSocket handshake wrong",
+                host
+            )));
+        }
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (tx, messages) = mpsc::unbounded_channel();
+        tokio::spawn(read(reader, writer.clone(), tx).in_current_span());
+
+        Ok(Self { messages, writer })
+    }
+
+    /// The next text message, or `None` once the server has closed the connection.
+    pub async fn receive(&mut self) -> Result<Option<String>, Error> {
+        self.messages.recv().await.transpose()
+    }
+
+    pub async fn send(&self, text: &str) -> Result<(), Error> {
+        write(&self.writer, TEXT, text.as_bytes()).await
+    }
+
+    /// Closes the connection with `code`, e.g. 1000 for a normal goodbye.
+    pub async fn close(&self, code: u16) {
+        let _ = write(&self.writer, CLOSE, &code.to_be_bytes()).await;
+    }
+}
+
+/// Reads messages until the connection closes or nobody wants them any more.
+async fn read(
+    mut reader: BufReader<ReadHalf<Box<dyn Stream>>>,
+    writer: Writer,
+    messages: mpsc::UnboundedSender<Result<String, Error>>,
+) {
+    let mut message = Vec::new();
+    loop {
+        let frame = tokio::select! {
+            frame = frame(&mut reader) => frame,
+            _ = messages.closed() => return,
+        };
+        let (fin, opcode, payload) = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                let _ = messages.send(Err(e));
+                return;
+            }
+        };
+        let result = match opcode {
+            TEXT | BINARY | CONTINUATION => {
+                if message.len() + payload.len() > MAX_MESSAGE {
+                    let _ = messages.send(Err(Error::Gateway(String::from("message too big"))));
+                    return;
+                }
+                message.extend(payload);
+                if !fin {
+                    continue;
+                }
+                match String::from_utf8(std::mem::take(&mut message)) {
+                    Ok(text) => messages.send(Ok(text)).map_err(|_| ()),
+                    Err(_) => {
+                        debug!("Ignoring a binary message");
+                        Ok(())
+                    }
+                }
+            }
+            PING => write(&writer, PONG, &payload).await.map_err(|_| ()),
+            PONG => Ok(()),
+            CLOSE => {
+                let _ = write(&writer, CLOSE, &payload).await;
+                if let Some(code) = payload.get(..2) {
+                    debug!(
+                        "WebSocket closed with {}: {}",
+                        u16::from_be_bytes([code[0], code[1]]),
+                        String::from_utf8_lossy(&payload[2..])
+                    );
+                }
+                return;
+            }
+            opcode => {
+                let error = Error::Gateway(format!("unknown WebSocket opcode {}", opcode));
+                let _ = messages.send(Err(error));
+                return;
+            }
+        };
+        if result.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads a frame, returning whether it's the last of its message, its opcode and its payload.
+async fn frame(reader: &mut (impl AsyncRead + Unpin)) -> Result<(bool, u8, Vec<u8>), Error> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).await.map_err(io_error)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7f {
+        126 => reader.read_u16().await.map_err(io_error)? as u64,
+        127 => reader.read_u64().await.map_err(io_error)?,
+        length => length as u64,
+    };
+    if length > MAX_MESSAGE as u64 {
+        return Err(Error::Gateway(String::from("message too big")));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask).await.map_err(io_error)?;
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).await.map_err(io_error)?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok((fin, opcode, payload))
+}
+
+/// Writes a frame. Clients have to mask everything they send.
+async fn write(writer: &Writer, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(0x80 | length as u8),
+        length @ 126..=0xffff => {
+            frame.push(0x80 | 126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(0x80 | 127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    let mask: [u8; 4] = rand::random();
+    frame.extend(mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4]),
+    );
+
+    let mut writer = writer.lock().await;
+    writer.write_all(&frame).await.map_err(io_error)?;
+    writer.flush().await.map_err(io_error)
+}
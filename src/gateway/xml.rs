@@ -0,0 +1,323 @@
+//! Just enough XML for XMPP: a stream of elements inside one long-lived `<stream:stream>`,
+//! without comments, processing instructions or DTDs.
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+
+use std::fmt;
+
+use super::io_error;
+use crate::Error;
+
+/// Elements bigger than this are taken as the connection having gone wrong.
+const MAX_ELEMENT: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    /// With its prefix, if it has one, e.g. `stream:features`.
+    pub name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn attr(mut self, name: &str, value: &str) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(Node::Element(child));
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.children.push(Node::Text(text.to_string()));
+        self
+    }
+
+    /// The value of the attribute called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first child element called `name`.
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// The first child element called `name` in the namespace `xmlns`.
+    pub fn find_ns(&self, name: &str, xmlns: &str) -> Option<&Element> {
+        self.elements()
+            .find(|element| element.name == name && element.get("xmlns") == Some(xmlns))
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// All the text directly inside the element.
+    pub fn content(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (name, value) in &self.attributes {
+            write!(f, " {}='{}'", name, escape(value))?;
+        }
+        if self.children.is_empty() {
+            return write!(f, "/>");
+        }
+        write!(f, ">")?;
+        for child in &self.children {
+            match child {
+                Node::Element(element) => write!(f, "{}", element)?,
+                Node::Text(text) => write!(f, "{}", escape(text))?,
+            }
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
+
+/// What's next in a stream.
+pub enum Item {
+    /// The `<stream:stream>` that everything else is inside.
+    Opened,
+    Element(Element),
+    /// The other end is done with the stream.
+    Closed,
+}
+
+/// Reads items from a stream, keeping whatever's been read of the next one. Can be dropped in
+/// the middle of `next()` without losing anything.
+#[derive(Default)]
+pub struct Reader {
+    buffer: Vec<u8>,
+}
+
+impl Reader {
+    pub async fn next(&mut self, stream: &mut (impl AsyncRead + Unpin)) -> Result<Item, Error> {
+        loop {
+            // Stopping short in the middle of a character just means there's more to come
+            let text = match std::str::from_utf8(&self.buffer) {
+                Ok(text) => text,
+                Err(e) if e.error_len().is_none() => {
+                    std::str::from_utf8(&self.buffer[..e.valid_up_to()]).unwrap_or_default()
+                }
+                Err(_) => return Err(Error::Gateway(String::from("invalid UTF-8"))),
+            };
+            if let Some((item, length)) = item(text)? {
+                self.buffer.drain(..length);
+                return Ok(item);
+            }
+            if self.buffer.len() > MAX_ELEMENT {
+                return Err(Error::Gateway(String::from("element too big")));
+            }
+
+            let mut chunk = [0; 4096];
+            match stream.read(&mut chunk).await.map_err(io_error)? {
+                0 => return Ok(Item::Closed),
+                read => self.buffer.extend(&chunk[..read]),
+            }
+        }
+    }
+}
+
+/// The item at the start of `s` and how much of `s` it took, or `None` if `s` stops before it
+/// does.
+fn item(s: &str) -> Result<Option<(Item, usize)>, Error> {
+    // Whitespace between elements is how connections are kept alive
+    let start = s.len() - s.trim_start().len();
+    let rest = &s[start..];
+    if rest.is_empty() {
+        return Ok(None);
+    }
+
+    if rest.starts_with("<?") {
+        let Some(end) = rest.find("?>") else {
+            return Ok(None);
+        };
+        return Ok(item(&rest[end + 2..])?.map(|(item, length)| (item, start + end + 2 + length)));
+    }
+    if let Some(after) = rest.strip_prefix("</") {
+        let Some(end) = after.find('>') else {
+            return Ok(None);
+        };
+        return Ok(Some((Item::Closed, start + 2 + end + 1)));
+    }
+    if rest.starts_with("<stream:stream") {
+        let Some((_, _, length)) = start_tag(rest)? else {
+            return Ok(None);
+        };
+        return Ok(Some((Item::Opened, start + length)));
+    }
+
+    Ok(element(rest)?.map(|(element, length)| (Item::Element(element), start + length)))
+}
+
+/// The element at the start of `s` and how much of `s` it took.
+fn element(s: &str) -> Result<Option<(Element, usize)>, Error> {
+    let Some((mut element, closed, mut position)) = start_tag(s)? else {
+        return Ok(None);
+    };
+    if closed {
+        return Ok(Some((element, position)));
+    }
+
+    loop {
+        let rest = &s[position..];
+        if let Some(after) = rest.strip_prefix("</") {
+            let Some(end) = after.find('>') else {
+                return Ok(None);
+            };
+            if after[..end].trim() != element.name {
+                return Err(Error::Gateway(format!(
+                    "</{}> doesn't close <{}>",
+                    &after[..end],
+                    element.name
+                )));
+            }
+            return Ok(Some((element, position + 2 + end + 1)));
+        }
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let Some(end) = after.find("]]>") else {
+                return Ok(None);
+            };
+            element.children.push(Node::Text(after[..end].to_string()));
+            position += 9 + end + 3;
+        } else if rest.starts_with('<') {
+            let Some((child, length)) = self::element(rest)? else {
+                return Ok(None);
+            };
+            element.children.push(Node::Element(child));
+            position += length;
+        } else {
+            let Some(end) = rest.find('<') else {
+                return Ok(None);
+            };
+            element.children.push(Node::Text(unescape(&rest[..end])?));
+            position += end;
+        }
+    }
+}
+
+/// The start tag at the start of `s`, whether it's also the end of the element, and how much
+/// of `s` it took.
+fn start_tag(s: &str) -> Result<Option<(Element, bool, usize)>, Error> {
+    let invalid = || {
+        Error::Gateway(format!(
+            "invalid tag {:?}",
+            s.chars().take(40).collect::<String>()
+        ))
+    };
+    let Some(rest) = s.strip_prefix('<') else {
+        return Err(invalid());
+    };
+    let name_end = rest
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(rest.len());
+    let mut element = Element::new(&rest[..name_end]);
+    if element.name.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut position = 1 + name_end;
+    loop {
+        let rest = &s[position..];
+        let trimmed = rest.trim_start();
+        position += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            return Ok(None);
+        }
+        if trimmed.starts_with("/>") {
+            return Ok(Some((element, true, position + 2)));
+        }
+        if trimmed.starts_with('>') {
+            return Ok(Some((element, false, position + 1)));
+        }
+
+        let Some((name, after)) = trimmed.split_once('=') else {
+            return Ok(None);
+        };
+        let after_trimmed = after.trim_start();
+        let Some(quote) = after_trimmed.chars().next() else {
+            return Ok(None);
+        };
+        if quote != '\'' && quote != '"' {
+            return Err(invalid());
+        }
+        let Some(end) = after_trimmed[1..].find(quote) else {
+            return Ok(None);
+        };
+        let value = unescape(&after_trimmed[1..1 + end])?;
+        element.attributes.push((name.trim().to_string(), value));
+        position += name.len() + 1 + (after.len() - after_trimmed.len()) + 1 + end + 1;
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> Result<String, Error> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(';') else {
+            return Err(Error::Gateway(format!("unfinished entity in {:?}", text)));
+        };
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "apos" => Some('\''),
+            "quot" => Some('"'),
+            _ => match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        out.push(c.ok_or_else(|| Error::Gateway(format!("unknown entity &{};", entity)))?);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
@@ -0,0 +1,501 @@
+use async_trait::async_trait;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use tokio::io::AsyncWriteExt;
+use tokio::io::ReadHalf;
+use tokio::io::WriteHalf;
+use tokio::net::lookup_host;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::Interval;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::io_error;
+use super::tls;
+use super::xml::Element;
+use super::xml::Item;
+use super::xml::Reader;
+use super::ChatNetwork;
+use super::Event;
+use super::Kind;
+use super::Stream;
+use super::User;
+use crate::casemap::CaseMapping;
+use crate::config::XmppConfig;
+use crate::format;
+use crate::Error;
+
+/// Servers differ on how big a stanza can be, but they all take this.
+const MAX_LENGTH: usize = 8 * 1024;
+
+/// How often to send a space down an otherwise quiet connection, so it isn't dropped.
+const KEEPALIVE: Duration = Duration::from_secs(60);
+
+const MUC: &str = "http://jabber.org/protocol/muc";
+const MUC_USER: &str = "http://jabber.org/protocol/muc#user";
+/// XEP-0249 invitations, sent straight to us rather than through the room.
+const CONFERENCE: &str = "jabber:x:conference";
+
+/// An XMPP account. Multi-user chat rooms are channels, named `#` and the room's JID, and
+/// people go by their nick in the room, or the local part of their JID when talking to us
+/// directly, plus their domain when it isn't ours. Messages straight to us are private
+/// messages.
+pub struct Xmpp {
+    config: XmppConfig,
+    /// Ours, in rooms.
+    nickname: String,
+    connection: AsyncMutex<Option<Connection>>,
+    writer: AsyncMutex<Option<WriteHalf<Box<dyn Stream>>>>,
+    state: Mutex<State>,
+}
+
+struct Connection {
+    stream: ReadHalf<Box<dyn Stream>>,
+    reader: Reader,
+    keepalive: Interval,
+}
+
+#[derive(Default)]
+struct State {
+    /// Room JIDs, by folded channel name.
+    rooms: HashMap<String, String>,
+    /// Channel names, by room JID.
+    channels: HashMap<String, String>,
+    /// Where to send private messages, by nick.
+    direct: HashMap<String, String>,
+}
+
+impl Xmpp {
+    pub fn new(config: &XmppConfig, nickname: &str) -> Result<Self, Error> {
+        if !config.jid.contains('@') {
+            return Err(Error::Gateway(format!(
+                "{} isn't a JID like pickles@example.org",
+                config.jid
+            )));
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            nickname: nickname.to_string(),
+            connection: AsyncMutex::new(None),
+            writer: AsyncMutex::new(None),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Connects and logs in, then rejoins any rooms we were in.
+    async fn connect(&self) -> Result<Connection, Error> {
+        let (local, domain) = split_jid(&self.config.jid);
+        let host = self.config.server.as_deref().unwrap_or(domain);
+        let socket = TcpStream::connect((host, self.config.port))
+            .await
+            .map_err(|e| Error::Gateway(format!("unable to connect to {}: {}", host, e)))?;
+        let mut stream: Box<dyn Stream> = Box::new(socket);
+        let mut reader = Reader::default();
+
+        let mut features = open(&mut stream, &mut reader, domain).await?;
+        if features.find("starttls").is_some() {
+            let starttls =
+                Element::new("starttls").attr("xmlns", "urn:ietf:params:xml:ns:xmpp-tls");
+            write(&mut stream, &starttls).await?;
+            expect(&mut stream, &mut reader, "proceed").await?;
+            stream = tls(domain, stream).await?;
+            features = open(&mut stream, &mut reader, domain).await?;
+        } else if !loopback(host).await {
+            return Err(Error::Gateway(format!(
+                "{} doesn't offer TLS, not logging in without it",
+                host
+            )));
+        }
+
+        let plain = features.find("mechanisms").is_some_and(|mechanisms| {
+            mechanisms
+                .elements()
+                .any(|mechanism| mechanism.content() == "PLAIN")
+        });
+        if !plain {
+            return Err(Error::Gateway(format!("{} won't take a password", host)));
+        }
+        let credentials = BASE64.encode(format!("\0{}\0{}", local, self.config.password));
+        let auth = Element::new("auth")
+            .attr("xmlns", "urn:ietf:params:xml:ns:xmpp-sasl")
+            .attr("mechanism", "PLAIN")
+            .text(&credentials);
+        write(&mut stream, &auth).await?;
+        expect(&mut stream, &mut reader, "success").await?;
+        open(&mut stream, &mut reader, domain).await?;
+
+        let bind = Element::new("iq")
+            .attr("type", "set")
+            .attr("id", "bind")
+            .child(
+                Element::new("bind")
+                    .attr("xmlns", "urn:ietf:params:xml:ns:xmpp-bind")
+                    .child(Element::new("resource").text("pickles")),
+            );
+        write(&mut stream, &bind).await?;
+        let bound = expect(&mut stream, &mut reader, "iq").await?;
+        let jid = bound
+            .find("bind")
+            .and_then(|bind| bind.find("jid"))
+            .map(|jid| jid.content())
+            .unwrap_or_else(|| self.config.jid.clone());
+        info!("Logged in to XMPP as {}", jid);
+        write(&mut stream, &Element::new("presence")).await?;
+
+        let (stream, mut writer) = tokio::io::split(stream);
+        let rooms: Vec<String> = self
+            .state
+            .lock()
+            .expect("state lock poisoned")
+            .rooms
+            .values()
+            .cloned()
+            .collect();
+        for room in rooms {
+            write(&mut writer, &self.enter(&room)).await?;
+        }
+        *self.writer.lock().await = Some(writer);
+
+        Ok(Connection {
+            stream,
+            reader,
+            keepalive: time::interval_at(time::Instant::now() + KEEPALIVE, KEEPALIVE),
+        })
+    }
+
+    /// The presence that enters `room`, without any of its history.
+    fn enter(&self, room: &str) -> Element {
+        Element::new("presence")
+            .attr("to", &format!("{}/{}", room, self.nickname))
+            .child(
+                Element::new("x")
+                    .attr("xmlns", MUC)
+                    .child(Element::new("history").attr("maxstanzas", "0")),
+            )
+    }
+
+    async fn write(&self, element: &Element) -> Result<(), Error> {
+        match &mut *self.writer.lock().await {
+            Some(writer) => write(writer, element).await,
+            None => Err(Error::Gateway(String::from("not connected"))),
+        }
+    }
+
+    /// Deals with a stanza, returning anything in it that pickles should hear about.
+    async fn stanza(&self, stanza: Element) -> Result<Vec<Event>, Error> {
+        match stanza.name.as_str() {
+            "message" => Ok(self.message(&stanza).into_iter().collect()),
+            "iq" if matches!(stanza.get("type"), Some("get" | "set")) => {
+                // Everything has to be answered: pings with a pong, the rest with an error
+                let mut reply = Element::new("iq")
+                    .attr("to", stanza.get("from").unwrap_or_default())
+                    .attr("id", stanza.get("id").unwrap_or_default());
+                reply = match stanza.find("ping") {
+                    Some(_) => reply.attr("type", "result"),
+                    None => reply.attr("type", "error").child(
+                        Element::new("error").attr("type", "cancel").child(
+                            Element::new("service-unavailable")
+                                .attr("xmlns", "urn:ietf:params:xml:ns:xmpp-stanzas"),
+                        ),
+                    ),
+                };
+                self.write(&reply).await?;
+                Ok(Vec::new())
+            }
+            "presence" if stanza.get("type") == Some("error") => {
+                let from = stanza.get("from").unwrap_or_default();
+                let error = stanza
+                    .find("error")
+                    .and_then(|error| error.elements().next())
+                    .map(|condition| condition.name.as_str())
+                    .unwrap_or("unknown error");
+                warn!("Unable to join {}: {}", split_resource(from).0, error);
+                Ok(Vec::new())
+            }
+            "stream:error" => {
+                let error = stanza
+                    .elements()
+                    .next()
+                    .map(|condition| condition.name.clone());
+                Err(Error::Gateway(format!(
+                    "stream error: {}",
+                    error.unwrap_or_default()
+                )))
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn message(&self, message: &Element) -> Option<Event> {
+        let sender = message.get("from")?;
+        let (bare, resource) = split_resource(sender);
+
+        if let Some(invite) = self.invite(message, bare) {
+            return Some(invite);
+        }
+        // Anything with a delay is history, or was sent while we were away
+        let body = message.find("body")?.content();
+        if message.find("delay").is_some() {
+            return None;
+        }
+        let (kind, text) = match body.strip_prefix("/me ") {
+            Some(action) => (Kind::Action, action.to_string()),
+            None => (Kind::Message, body),
+        };
+
+        let mut state = self.state.lock().expect("state lock poisoned");
+        let in_room = state.channels.contains_key(bare);
+        let (from, channel) = match message.get("type") {
+            Some("groupchat") => {
+                let channel = state.channels.get(bare)?.clone();
+                // Without a nick it's the room itself, announcing something
+                if resource.is_empty() || resource == self.nickname {
+                    return None;
+                }
+                let from = User {
+                    nick: resource.to_string(),
+                    user: resource.to_string(),
+                    host: split_jid(bare).1.to_string(),
+                };
+                (from, Some(channel))
+            }
+            Some("error") => return None,
+            // Someone in a room we're in talking to us privately goes by their nick there
+            _ if in_room => {
+                let from = User {
+                    nick: resource.to_string(),
+                    user: resource.to_string(),
+                    host: split_jid(bare).1.to_string(),
+                };
+                state.direct.insert(from.nick.clone(), sender.to_string());
+                (from, None)
+            }
+            _ => {
+                let (local, domain) = split_jid(bare);
+                let nick = match domain == split_jid(&self.config.jid).1 {
+                    true => local.to_string(),
+                    false => format!("{}:{}", local, domain),
+                };
+                state.direct.insert(nick.clone(), sender.to_string());
+                let from = User {
+                    nick,
+                    user: local.to_string(),
+                    host: domain.to_string(),
+                };
+                (from, None)
+            }
+        };
+
+        Some(Event::Message {
+            from,
+            channel,
+            text,
+            kind,
+            mentioned: false,
+        })
+    }
+
+    /// An invitation to a room, through the room or straight from whoever's inviting us.
+    fn invite(&self, message: &Element, from: &str) -> Option<Event> {
+        let (room, inviter) = match message.find_ns("x", MUC_USER) {
+            Some(x) => (from, x.find("invite")?.get("from")?),
+            None => (message.find_ns("x", CONFERENCE)?.get("jid")?, from),
+        };
+        let (local, domain) = split_jid(split_resource(inviter).0);
+
+        Some(Event::Invite {
+            from: User {
+                nick: local.to_string(),
+                user: local.to_string(),
+                host: domain.to_string(),
+            },
+            channel: format!("#{}", room),
+        })
+    }
+}
+
+#[async_trait]
+impl ChatNetwork for Xmpp {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        let mut connection = self.connection.lock().await;
+        let current = match &mut *connection {
+            Some(current) => current,
+            None => connection.insert(self.connect().await?),
+        };
+
+        let result = loop {
+            let item = tokio::select! {
+                item = current.reader.next(&mut current.stream) => item,
+                _ = current.keepalive.tick() => {
+                    let mut writer = self.writer.lock().await;
+                    match &mut *writer {
+                        Some(writer) => match writer.write_all(b" ").await {
+                            Ok(()) => continue,
+                            Err(e) => Err(io_error(e)),
+                        },
+                        None => continue,
+                    }
+                }
+            };
+            match item {
+                Ok(Item::Element(stanza)) => match self.stanza(stanza).await {
+                    Ok(events) if events.is_empty() => continue,
+                    result => break result,
+                },
+                Ok(Item::Opened) => continue,
+                Ok(Item::Closed) => break Err(Error::Gateway(String::from("stream closed"))),
+                Err(e) => break Err(e),
+            }
+        };
+        if result.is_err() {
+            *connection = None;
+            *self.writer.lock().await = None;
+        }
+
+        result
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let room = channel.trim_start_matches('#').to_lowercase();
+        if !room.contains('@') {
+            return Err(Error::Gateway(format!(
+                "{} isn't a room like #room@conference.example.org",
+                channel
+            )));
+        }
+        {
+            let mut state = self.state.lock().expect("state lock poisoned");
+            state
+                .rooms
+                .insert(CaseMapping::Ascii.fold(channel), room.clone());
+            state.channels.insert(room.clone(), channel.to_string());
+        }
+
+        // Rooms are entered on connecting, if we aren't yet
+        if let Some(writer) = &mut *self.writer.lock().await {
+            write(writer, &self.enter(&room)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let (to, kind_attr) = {
+            let state = self.state.lock().expect("state lock poisoned");
+            match state.rooms.get(&CaseMapping::Ascii.fold(target)) {
+                Some(room) => (room.clone(), "groupchat"),
+                None => match state.direct.get(target) {
+                    Some(jid) => (jid.clone(), "chat"),
+                    None => return Err(Error::Gateway(format!("no idea who {} is", target))),
+                },
+            }
+        };
+
+        let mut body = text
+            .lines()
+            .map(format::strip)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if kind == Kind::Action {
+            body = format!("/me {}", body);
+        }
+        let message = Element::new("message")
+            .attr("to", &to)
+            .attr("type", kind_attr)
+            .child(Element::new("body").text(&body));
+
+        self.write(&message).await
+    }
+
+    fn max_length(&self) -> usize {
+        MAX_LENGTH
+    }
+}
+
+/// Starts a stream to `domain`, returning the features the server offers on it.
+async fn open(
+    stream: &mut Box<dyn Stream>,
+    reader: &mut Reader,
+    domain: &str,
+) -> Result<Element, Error> {
+    let header = format!(
+        "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' \
+         xmlns:stream='http://etherx.jabber.org/streams'>",
+        domain
+    );
+    stream
+        .write_all(header.as_bytes())
+        .await
+        .map_err(io_error)?;
+    loop {
+        match reader.next(stream).await? {
+            Item::Opened => continue,
+            Item::Element(features) if features.name == "stream:features" => return Ok(features),
+            Item::Element(other) => {
+                return Err(Error::Gateway(format!(
+                    "expected features, got <{}>",
+                    other.name
+                )))
+            }
+            Item::Closed => return Err(Error::Gateway(String::from("stream closed"))),
+        }
+    }
+}
+
+/// Reads the next element, which had better be called `name`.
+async fn expect(
+    stream: &mut Box<dyn Stream>,
+    reader: &mut Reader,
+    name: &str,
+) -> Result<Element, Error> {
+    match reader.next(stream).await? {
+        Item::Element(element) if element.name == name && element.get("type") != Some("error") => {
+            Ok(element)
+        }
+        Item::Element(element) => {
+            let reason = element
+                .elements()
+                .find(|child| child.name != "text")
+                .map(|child| child.name.as_str())
+                .unwrap_or(element.name.as_str());
+            Err(Error::Gateway(format!(
+                "expected <{}>, got {}",
+                name, reason
+            )))
+        }
+        _ => Err(Error::Gateway(String::from("stream closed"))),
+    }
+}
+
+async fn write(writer: &mut (impl AsyncWriteExt + Unpin), element: &Element) -> Result<(), Error> {
+    writer
+        .write_all(element.to_string().as_bytes())
+        .await
+        .map_err(io_error)
+}
+
+/// Whether `host` is this machine, where TLS doesn't matter.
+async fn loopback(host: &str) -> bool {
+    match lookup_host((host, 0)).await {
+        Ok(mut addresses) => addresses.all(|address| address.ip().is_loopback()),
+        Err(_) => false,
+    }
+}
+
+/// `pickles@example.org/phone` as `("pickles@example.org", "phone")`.
+fn split_resource(jid: &str) -> (&str, &str) {
+    jid.split_once('/').unwrap_or((jid, ""))
+}
+
+/// `pickles@example.org` as `("pickles", "example.org")`.
+fn split_jid(jid: &str) -> (&str, &str) {
+    jid.split_once('@').unwrap_or(("", jid))
+}
@@ -0,0 +1,164 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config::GreetingsConfig;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+
+/// Someone who quit in a netsplit and comes back within this long is rejoining, not arriving.
+const SPLIT_MEMORY: Duration = Duration::from_secs(30 * 60);
+
+/// Joins this close together count towards `max_burst`.
+const BURST_WINDOW: Duration = Duration::from_secs(10);
+
+const GREETING_PROMPT: &str = "You are {botnick}, a bot on IRC. {nick} just joined {channel}. \
+    Greet them in one short, friendly line. Reply with nothing but the greeting.";
+
+/// Decides who gets greeted, so that each person is greeted now and then rather than every
+/// time their connection drops, and a netsplit healing doesn't set off a greeting for everyone
+/// on the other side.
+pub struct Greeter {
+    config: GreetingsConfig,
+    /// When each nick was last greeted, by channel and nick.
+    greeted: Mutex<HashMap<(String, String), Instant>>,
+    /// When nicks quit in a netsplit.
+    split: Mutex<HashMap<String, Instant>>,
+    /// Recent joins, by channel.
+    joins: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Greeter {
+    pub fn new(config: &GreetingsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            greeted: Mutex::new(HashMap::new()),
+            split: Mutex::new(HashMap::new()),
+            joins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn generate(&self) -> bool {
+        self.config.generate
+    }
+
+    /// Notes `nick` quitting, so they aren't greeted if it was a netsplit and they're back
+    /// soon. Names are expected folded by the network's casemapping.
+    pub fn quit(&self, nick: &str, reason: &str) {
+        if is_netsplit(reason) {
+            let mut split = self.split.lock().expect("greetings lock poisoned");
+            split.retain(|_, at| at.elapsed() < SPLIT_MEMORY);
+            split.insert(nick.to_string(), Instant::now());
+        }
+    }
+
+    /// Whether to greet `nick` joining `channel`, `known` being whether we've seen them before.
+    /// Every join counts towards spotting a burst, greeted or not.
+    pub fn should_greet(&self, channel: &str, nick: &str, known: bool) -> bool {
+        let now = Instant::now();
+        let burst = {
+            let mut joins = self.joins.lock().expect("greetings lock poisoned");
+            let recent = joins.entry(channel.to_string()).or_default();
+            while recent.front().is_some_and(|at| now - *at >= BURST_WINDOW) {
+                recent.pop_front();
+            }
+            recent.push_back(now);
+            recent.len() > self.config.max_burst
+        };
+        if burst {
+            debug!(
+                "Not greeting {}, too many joins in {} at once",
+                nick, channel
+            );
+            return false;
+        }
+        let split = self
+            .split
+            .lock()
+            .expect("greetings lock poisoned")
+            .remove(nick);
+        if split.is_some_and(|at| at.elapsed() < SPLIT_MEMORY) {
+            debug!("Not greeting {}, they're back from a netsplit", nick);
+            return false;
+        }
+        if self.config.known_only && !known {
+            return false;
+        }
+        if !rand::thread_rng().gen_bool(self.config.probability.clamp(0.0, 1.0)) {
+            return false;
+        }
+
+        let mut greeted = self.greeted.lock().expect("greetings lock poisoned");
+        let cooldown = Duration::from_secs(self.config.cooldown_secs);
+        greeted.retain(|_, at| at.elapsed() < cooldown);
+        greeted
+            .insert((channel.to_string(), nick.to_string()), now)
+            .is_none()
+    }
+
+    /// One of the configured greetings for `nick`.
+    pub fn greeting(&self, nick: &str, channel: &str) -> Option<String> {
+        pick(&self.config.messages, nick, channel)
+    }
+
+    /// One of the configured farewells for `nick`, if there are any and the dice say so.
+    pub fn farewell(&self, nick: &str, channel: &str) -> Option<String> {
+        if !rand::thread_rng().gen_bool(self.config.probability.clamp(0.0, 1.0)) {
+            return None;
+        }
+
+        pick(&self.config.farewells, nick, channel)
+    }
+
+    /// A greeting the model came up with, or a configured one if it couldn't.
+    pub async fn generated(
+        &self,
+        backend: &dyn ChatBackend,
+        botnick: &str,
+        nick: &str,
+        channel: &str,
+    ) -> Option<String> {
+        let prompt = GREETING_PROMPT
+            .replace("{botnick}", botnick)
+            .replace("{nick}", nick)
+            .replace("{channel}", channel);
+        match backend.complete(&[ChatMessage::system(prompt)]).await {
+            Ok(completion) => completion
+                .content
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(String::from)
+                .or_else(|| self.greeting(nick, channel)),
+            Err(e) => {
+                warn!("Unable to come up with a greeting for {}: {}", nick, e);
+                self.greeting(nick, channel)
+            }
+        }
+    }
+}
+
+/// Whether a QUIT with `reason` was a netsplit, which servers give as the names of the two
+/// servers that lost each other.
+pub fn is_netsplit(reason: &str) -> bool {
+    let mut servers = reason.split(' ');
+    match (servers.next(), servers.next(), servers.next()) {
+        (Some(a), Some(b), None) => a.contains('.') && b.contains('.'),
+        _ => false,
+    }
+}
+
+fn pick(messages: &[String], nick: &str, channel: &str) -> Option<String> {
+    messages.choose(&mut rand::thread_rng()).map(|message| {
+        message
+            .replace("{nick}", nick)
+            .replace("{channel}", channel)
+    })
+}
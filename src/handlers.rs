@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::*;
+
+use std::sync::Arc;
+
+use crate::acl::Privilege;
+use crate::config::Config;
+use crate::config::NetworkConfig;
+use crate::flood::Throttle;
+use crate::irc_bot::NetworkState;
+use crate::isupport::ISupport;
+use crate::llm::ChatBackend;
+use crate::memory::Memory;
+use crate::moderation::Moderation;
+use crate::output::send_privmsg;
+use crate::output::Outgoing;
+use crate::paste::Paste;
+#[cfg(feature = "wasm")]
+use crate::plugins::Plugins;
+#[cfg(feature = "scripting")]
+use crate::scripts::Scripts;
+use crate::tags::Tags;
+use crate::Error;
+
+pub mod ambient;
+pub mod chat;
+pub mod commands;
+pub mod corrections;
+pub mod ctcp;
+pub mod greetings;
+pub mod ignore;
+pub mod karma;
+pub mod memos;
+#[cfg(feature = "wasm")]
+pub mod plugins;
+#[cfg(feature = "scripting")]
+pub mod scripts;
+pub mod seen;
+pub mod titles;
+pub mod triggers;
+pub mod trivia;
+
+/// Everything a handler needs to know about the message it's looking at and the connection it
+/// came in on.
+pub struct Context<'a> {
+    pub config: &'a Config,
+    pub network: &'a NetworkConfig,
+    pub state: &'a NetworkState,
+    pub backend: &'a Arc<dyn ChatBackend>,
+    pub memory: &'a Arc<Memory>,
+    pub out: &'a Throttle,
+    /// Our own `nick!user@host`, as far as we know it.
+    pub source: &'a str,
+    /// Our current nickname.
+    pub nickname: &'a str,
+    /// What the server says its limits and conventions are.
+    pub isupport: &'a ISupport,
+    /// For replies from tasks that outlive the handler, like anything that waits on the model.
+    pub outgoing: &'a mpsc::UnboundedSender<Outgoing>,
+    /// Work that outlives the handler. Whatever is still going when we disconnect is dropped.
+    pub responses: &'a mut JoinSet<()>,
+    pub paste: Option<&'a Paste>,
+    pub moderation: &'a Moderation,
+    pub message: &'a Message,
+    /// The message's IRCv3 tags, where the server sends them.
+    pub tags: Tags,
+    pub privilege: Privilege,
+}
+
+impl Context<'_> {
+    pub fn send(&self, target: &str, msg: &str) -> Result<(), Error> {
+        send_privmsg(
+            self.out,
+            self.source,
+            self.isupport.line_length,
+            target,
+            msg,
+            self.config.dry_run,
+        )
+    }
+
+    /// Where to answer a PRIVMSG sent to `target`: the channel, or the sender if it was sent to
+    /// us privately.
+    pub fn reply_target<'t>(&'t self, target: &'t str) -> &'t str {
+        match self.is_private(target) {
+            true => self.message.source_nickname().unwrap_or(target),
+            false => target,
+        }
+    }
+
+    pub fn is_private(&self, target: &str) -> bool {
+        self.isupport.casemapping.eq(target, self.nickname)
+    }
+}
+
+/// What a handler did with a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Let the rest of the pipeline see it too.
+    Continue,
+    /// Dealt with, nobody else gets it.
+    Consumed,
+}
+
+/// One step in working out what to do about an incoming message.
+#[async_trait]
+pub trait Handler: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error>;
+}
+
+/// The handlers every message goes through, in order, until one of them consumes it.
+pub struct Pipeline {
+    handlers: Vec<Box<dyn Handler>>,
+}
+
+impl Pipeline {
+    pub fn new(config: &Config) -> Self {
+        let mut pipeline = Self {
+            handlers: Vec::new(),
+        };
+        #[cfg(feature = "scripting")]
+        let scripts = config
+            .scripts
+            .as_ref()
+            .map(|scripts| Arc::new(Scripts::new(scripts)));
+        #[cfg(not(feature = "scripting"))]
+        if config.scripts.is_some() {
+            warn!("Ignoring [scripts], pickles was built without the scripting feature");
+        }
+
+        pipeline.register(greetings::Greet);
+        pipeline.register(seen::TrackActivity);
+        pipeline.register(memos::DeliverMemos);
+        pipeline.register(ignore::Ignore);
+        pipeline.register(ctcp::AnswerCtcp);
+        pipeline.register(commands::RunCommands);
+        pipeline.register(trivia::AnswerTrivia);
+        pipeline.register(karma::CountKarma);
+        pipeline.register(corrections::Correct);
+        pipeline.register(titles::AnnounceTitles);
+        for trigger in config.triggers.iter() {
+            if trigger.reply.is_none() && trigger.prompt.is_none() {
+                warn!(
+                    "Trigger {} has neither a reply nor a prompt",
+                    trigger.pattern
+                );
+            }
+        }
+        pipeline.register(triggers::RunTriggers);
+        #[cfg(feature = "wasm")]
+        if let Some(plugins) = &config.plugins {
+            match Plugins::load(plugins) {
+                Ok(plugins) => pipeline.register(plugins::RunPlugins(Arc::new(plugins))),
+                Err(e) => error!("{}", e),
+            }
+        }
+        #[cfg(not(feature = "wasm"))]
+        if config.plugins.is_some() {
+            warn!("Ignoring [plugins], pickles was built without the wasm feature");
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(scripts) = &scripts {
+            pipeline.register(scripts::RunScripts(scripts.clone()));
+        }
+        pipeline.register(chat::Chat {
+            #[cfg(feature = "scripting")]
+            scripts,
+        });
+        pipeline.register(ambient::Interject);
+
+        pipeline
+    }
+
+    /// Adds `handler` at the end, after everything already registered.
+    pub fn register(&mut self, handler: impl Handler + 'static) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    pub async fn run(&self, ctx: &mut Context<'_>) -> Result<(), Error> {
+        for handler in &self.handlers {
+            if handler.handle(ctx).await? == Flow::Consumed {
+                trace!("Message consumed by {}", handler.name());
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
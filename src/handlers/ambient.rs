@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::ambient;
+use crate::llm::ChatMessage;
+use crate::output::queue;
+use crate::prompt;
+use crate::prompt::PromptVars;
+use crate::Error;
+
+/// Now and then joins in on whatever's being said in channels with `ambient` on. Goes after
+/// `Chat`, so it only ever sees what nobody addressed to us.
+pub struct Interject;
+
+#[async_trait]
+impl Handler for Interject {
+    fn name(&self) -> &'static str {
+        "ambient"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Command::PRIVMSG(channel, msg) = &ctx.message.command else {
+            return Ok(Flow::Continue);
+        };
+        let (config, state) = (ctx.config, ctx.state);
+        let Some(channel_config) = state.channels.get(channel) else {
+            return Ok(Flow::Continue);
+        };
+        if !channel_config.ambient
+            || ctx.tags.is_bot()
+            || msg.starts_with(state.commands.prefix())
+            || state
+                .ledger
+                .exhausted(
+                    &config.quota,
+                    ctx.nickname,
+                    ctx.nickname,
+                    channel,
+                    ctx.isupport.casemapping,
+                )
+                .is_some()
+            || !state
+                .ambient
+                .should_interject(&ctx.isupport.casemapping.fold(channel))
+        {
+            return Ok(Flow::Continue);
+        }
+
+        let nick = ctx.message.source_nickname().unwrap_or_default();
+        let template = state
+            .personas
+            .prompt(channel)
+            .or(channel_config.system_prompt)
+            .unwrap_or_else(|| config.openai.system_prompt.clone());
+        let topic = state.channels.topic(channel);
+        let people = state.channels.members(channel);
+        let vars = PromptVars {
+            nick,
+            channel: Some(channel),
+            botnick: ctx.nickname,
+            topic: topic.as_deref(),
+            people: &people,
+        };
+        let system_prompt = format!(
+            "{}{}\n\n{}",
+            prompt::render(&template, &vars),
+            prompt::room_context(&vars),
+            state.ambient.config.prompt
+        );
+        let lines = state
+            .channels
+            .scrollback(channel, state.ambient.config.context_lines);
+        let request = [
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(ambient::transcript(&lines)),
+        ];
+
+        info!("Joining in in {}", channel);
+        let (backend, ledger, outgoing) = (
+            ctx.backend.clone(),
+            state.ledger.clone(),
+            ctx.outgoing.clone(),
+        );
+        let (botnick, channel) = (ctx.nickname.to_string(), channel.clone());
+        ctx.responses.spawn(
+            async move {
+                let completion = match backend.complete(&request).await {
+                    Ok(completion) => completion,
+                    Err(e) => {
+                        warn!("Unable to join in in {}: {}", channel, e);
+                        return;
+                    }
+                };
+                ledger
+                    .record(&botnick, &botnick, &channel, completion.usage)
+                    .await;
+                let line = completion
+                    .content
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty());
+                match line {
+                    Some(line) if line != ambient::PASS => {
+                        queue(&outgoing, &channel, line.to_string())
+                    }
+                    _ => debug!("Nothing to add in {}", channel),
+                }
+            }
+            .in_current_span(),
+        );
+
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,524 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tokio::time;
+use tracing::*;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::acl::Privilege;
+use crate::casemap::CaseMapping;
+use crate::config::TriggerMode;
+use crate::ctcp;
+use crate::filter::Filter;
+use crate::guard;
+use crate::language;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+use crate::llm::Completion;
+use crate::markov::Markov;
+use crate::memory;
+use crate::memory::Memory;
+use crate::metrics::metrics;
+use crate::moderation::Moderation;
+use crate::output::queue;
+use crate::output::say;
+use crate::output::Outgoing;
+use crate::paste::Paste;
+use crate::prompt;
+use crate::prompt::PromptVars;
+#[cfg(feature = "scripting")]
+use crate::scripts::Scripts;
+use crate::usage::Ledger;
+use crate::Error;
+
+/// Changes each line of an answer before it's said.
+pub type Rewrite = Box<dyn Fn(String) -> String + Send + Sync>;
+
+/// Answers whatever is addressed to us with the model: anything starting with the channel's
+/// trigger, and every private message. Comes after everything else that might want a message.
+#[derive(Default)]
+pub struct Chat {
+    /// Get to rewrite questions and answers.
+    #[cfg(feature = "scripting")]
+    pub scripts: Option<Arc<Scripts>>,
+}
+
+/// Answers still being worked on, so they can be dropped when there's nobody left to hear them.
+#[derive(Default)]
+pub struct Answering {
+    answers: Mutex<Vec<Answer>>,
+}
+
+struct Answer {
+    target: String,
+    nick: String,
+    task: AbortHandle,
+}
+
+impl Answering {
+    /// Keeps track of `task`, answering `nick` in `target`.
+    pub fn start(&self, target: &str, nick: &str, task: AbortHandle) {
+        let mut answers = self.answers.lock().expect("answering lock poisoned");
+        answers.retain(|answer| !answer.task.is_finished());
+        answers.push(Answer {
+            target: target.to_string(),
+            nick: nick.to_string(),
+            task,
+        });
+    }
+
+    /// Stops answering `nick`, who's gone.
+    pub fn quit(&self, nick: &str, casemapping: CaseMapping) {
+        let dropped = self.cancel(|answer| casemapping.eq(&answer.nick, nick));
+        if dropped > 0 {
+            info!("Stopped answering {}, they quit", nick);
+        }
+    }
+
+    /// Follows `old` to `new`, so they're still known when they go.
+    pub fn renamed(&self, old: &str, new: &str, casemapping: CaseMapping) {
+        let mut answers = self.answers.lock().expect("answering lock poisoned");
+        for answer in answers.iter_mut() {
+            if casemapping.eq(&answer.nick, old) {
+                answer.nick = new.to_string();
+            }
+        }
+    }
+
+    /// Stops answering anyone in `channel`, which we're no longer in.
+    pub fn kicked(&self, channel: &str, casemapping: CaseMapping) {
+        let dropped = self.cancel(|answer| casemapping.eq(&answer.target, channel));
+        if dropped > 0 {
+            info!(
+                "Stopped answering {} in {}, we were kicked",
+                dropped, channel
+            );
+        }
+    }
+
+    fn cancel(&self, which: impl Fn(&Answer) -> bool) -> usize {
+        let mut answers = self.answers.lock().expect("answering lock poisoned");
+        let mut dropped = 0;
+        answers.retain(|answer| {
+            if answer.task.is_finished() {
+                return false;
+            }
+            if which(answer) {
+                answer.task.abort();
+                dropped += 1;
+                return false;
+            }
+            true
+        });
+        dropped
+    }
+}
+
+#[async_trait]
+impl Handler for Chat {
+    fn name(&self) -> &'static str {
+        "chat"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Command::PRIVMSG(channel, msg) = &ctx.message.command else {
+            return Ok(Flow::Continue);
+        };
+        let (config, state) = (ctx.config, ctx.state);
+        // A `/me` reads to the model the way it looks on IRC, and is aimed at us if it names us
+        let action = ctcp::action(msg).map(|action| format!("*{}*", action));
+
+        // Who to answer, where, and with which prompt
+        let request = if let Some(channel_config) = state.channels.get(channel) {
+            let question = match &action {
+                Some(action) => mentions(action, ctx.nickname, ctx.isupport.casemapping)
+                    .then_some(action.as_str()),
+                None => {
+                    let casemapping = ctx.isupport.casemapping;
+                    casemapping
+                        .strip_prefix(msg, &channel_config.trigger(ctx.nickname))
+                        .or_else(|| {
+                            (channel_config.trigger_mode == TriggerMode::Mention
+                                && mentions(msg, ctx.nickname, casemapping))
+                            .then(|| unaddressed(msg, ctx.nickname, casemapping))
+                        })
+                }
+            };
+            question.map(|msg| {
+                let nick = extract_nick(ctx.message.prefix.clone());
+                // Someone's own persona beats the channel's, which beats the config
+                let template = state
+                    .personas
+                    .prompt(&nick)
+                    .or_else(|| state.personas.prompt(channel))
+                    .or(channel_config.system_prompt)
+                    .unwrap_or_else(|| config.openai.system_prompt.clone());
+                let topic = state.channels.topic(channel);
+                let people = state.channels.members(channel);
+                let vars = PromptVars {
+                    nick: &nick,
+                    channel: Some(channel),
+                    botnick: ctx.nickname,
+                    topic: topic.as_deref(),
+                    people: &people,
+                };
+                let mut system_prompt = prompt::render(&template, &vars);
+                if channel_config.room_context.unwrap_or(config.room_context) {
+                    system_prompt.push_str(&prompt::room_context(&vars));
+                }
+                let moderate = channel_config.moderation.unwrap_or(config.moderation);
+                let language = channel_config
+                    .language
+                    .or_else(|| config.language.default.clone());
+                (
+                    channel.clone(),
+                    nick,
+                    system_prompt,
+                    msg,
+                    moderate,
+                    language,
+                )
+            })
+        } else if ctx.is_private(channel) {
+            ctx.message
+                .response_target()
+                .filter(|nick| *nick != "DM")
+                .map(|nick| {
+                    let template = state
+                        .personas
+                        .prompt(nick)
+                        .unwrap_or_else(|| config.openai.system_prompt.clone());
+                    let system_prompt = prompt::render(
+                        &template,
+                        &PromptVars {
+                            nick,
+                            channel: None,
+                            botnick: ctx.nickname,
+                            topic: None,
+                            people: &[],
+                        },
+                    );
+                    (
+                        nick.to_string(),
+                        nick.to_string(),
+                        system_prompt,
+                        action.as_deref().unwrap_or(msg),
+                        config.moderation,
+                        config.language.default.clone(),
+                    )
+                })
+        } else {
+            None
+        };
+        let Some((target, nick, mut system_prompt, msg, moderate, language)) = request else {
+            return Ok(Flow::Continue);
+        };
+
+        if !state.loops.check(&nick, ctx.tags.is_bot()) {
+            return Ok(Flow::Consumed);
+        }
+        let casemapping = ctx.isupport.casemapping;
+        let identity = memory::identity(&nick, &ctx.tags, casemapping);
+        if ctx.privilege < Privilege::Trusted {
+            if let Err(wait) = state.limiter.check(&nick) {
+                info!("Rate limiting {} for another {:?}", nick, wait);
+                let msg = format!(
+                    "{}: whoa there, my brain is still steaming. try again in {}s",
+                    nick,
+                    wait.as_secs().max(1)
+                );
+                ctx.send(&target, &msg)?;
+                return Ok(Flow::Consumed);
+            }
+            let exhausted =
+                state
+                    .ledger
+                    .exhausted(&config.quota, &nick, &identity, &target, casemapping);
+            if let Some(exhausted) = exhausted {
+                info!("{} is out of quota for today", nick);
+                let reply = match state.markov.as_ref().and_then(|markov| markov.reply(msg)) {
+                    Some(babble) => format!("{}: {}", nick, babble),
+                    None => exhausted.apology(&nick),
+                };
+                ctx.send(&target, &reply)?;
+                return Ok(Flow::Consumed);
+            }
+        }
+
+        if let Some(language) = language::reply_in(msg, config.language.detect, language.as_deref())
+        {
+            system_prompt.push_str(&format!("\n\nReply in {}.", language));
+        }
+
+        #[cfg(feature = "scripting")]
+        let (msg, rewrite) = match &self.scripts {
+            Some(scripts) => {
+                let place = if ctx.is_private(channel) {
+                    ""
+                } else {
+                    channel.as_str()
+                };
+                let msg = scripts.on_question(&nick, place, msg.to_string());
+                let (scripts, nick, place) = (scripts.clone(), nick.clone(), place.to_string());
+                let rewrite: Rewrite = Box::new(move |line| scripts.on_answer(&nick, &place, line));
+                (msg, Some(rewrite))
+            }
+            None => (msg.to_string(), None),
+        };
+        #[cfg(not(feature = "scripting"))]
+        let (msg, rewrite) = (msg.to_string(), None);
+        let msg = guard::guard(&config.guard, &msg);
+        if config.guard.delimit {
+            system_prompt = format!("{}\n\n{}", system_prompt, guard::DELIMITED_PROMPT);
+        }
+
+        let task = ctx.responses.spawn(
+            respond(
+                ctx.outgoing.clone(),
+                ctx.backend.clone(),
+                ctx.memory.clone(),
+                state.ledger.clone(),
+                system_prompt,
+                target.clone(),
+                identity,
+                nick.clone(),
+                msg,
+                config.formatting,
+                ctx.paste.cloned(),
+                Filter::new(&config.filter, ctx.backend.clone()),
+                moderate.then(|| ctx.moderation.clone()),
+                rewrite,
+                state.markov.clone(),
+                time::Duration::from_secs(config.openai.answer_timeout_secs),
+            )
+            .in_current_span(),
+        );
+        state.answering.start(&target, &nick, task);
+
+        Ok(Flow::Consumed)
+    }
+}
+
+/// Remembers what `nick` said as `identity`, then streams the answer to `target` line by line as it's
+/// generated, by way of `outgoing`. If the model can't be asked, `markov` says something instead.
+/// An answer that takes longer than `timeout` is given up on.
+#[allow(clippy::too_many_arguments)]
+async fn respond(
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    backend: Arc<dyn ChatBackend>,
+    memory: Arc<Memory>,
+    ledger: Arc<Ledger>,
+    system_prompt: String,
+    target: String,
+    identity: String,
+    nick: String,
+    msg: String,
+    formatting: bool,
+    paste: Option<Paste>,
+    filter: Option<Filter>,
+    moderation: Option<Moderation>,
+    rewrite: Option<Rewrite>,
+    markov: Option<Arc<Markov>>,
+    timeout: time::Duration,
+) {
+    if let Some(moderation) = &moderation {
+        match moderation.flagged(&msg).await {
+            Ok(false) => (),
+            Ok(true) => {
+                info!("Not answering {}, their message was flagged", nick);
+                queue(
+                    &outgoing,
+                    &target,
+                    format!("{nick}: nope, not touching that one"),
+                );
+                return;
+            }
+            Err(e) => {
+                error!("Unable to moderate {}'s message: {}", nick, e);
+                metrics().errors.with_label_values(&["moderation"]).inc();
+                queue(
+                    &outgoing,
+                    &target,
+                    format!("{nick}: I can't check that right now, try me again in a bit"),
+                );
+                return;
+            }
+        }
+    }
+
+    memory
+        .remember(&identity, ChatMessage::user(msg.clone()))
+        .await;
+
+    let (lines, raw) = mpsc::unbounded_channel();
+    let (rewritten, rx) = mpsc::unbounded_channel();
+    let (response, (), ()) = tokio::join!(
+        time::timeout(
+            timeout,
+            ask_chatgpt(
+                backend.as_ref(),
+                &system_prompt,
+                &memory,
+                &identity,
+                &nick,
+                &msg,
+                moderation.as_ref(),
+                lines
+            )
+        )
+        .instrument(info_span!("ask")),
+        rewrite_lines(raw, rewritten, rewrite),
+        say(&outgoing, &target, rx, &nick, formatting, paste, filter).instrument(info_span!("say")),
+    );
+
+    match response {
+        Ok(Ok(completion)) => {
+            info!(
+                prompt_tokens = completion.usage.prompt_tokens,
+                completion_tokens = completion.usage.completion_tokens,
+                "Answered {}",
+                nick
+            );
+            ledger
+                .record(&nick, &identity, &target, completion.usage)
+                .await;
+            memory
+                .remember_exchange(&identity, &msg, &completion.content)
+                .await;
+            memory.summarize(&identity, backend.as_ref()).await;
+        }
+        Ok(Err(e)) => {
+            error!("Ow! I fell down: {e}");
+            metrics().errors.with_label_values(&["response"]).inc();
+            let reply = match markov.and_then(|markov| markov.reply(&msg)) {
+                Some(babble) => format!("{nick}: {babble}"),
+                None => {
+                    format!("{nick}: ow! I fell down and bumped my brain, try me again in a bit")
+                }
+            };
+            queue(&outgoing, &target, reply);
+        }
+        Err(_) => {
+            warn!("Gave up answering {} after {:?}", nick, timeout);
+            metrics().errors.with_label_values(&["timeout"]).inc();
+            queue(
+                &outgoing,
+                &target,
+                format!("{nick}: sorry, I got lost in thought there. ask me again?"),
+            );
+        }
+    }
+}
+
+/// Passes each line of the answer through `rewrite` on its way out, if there is one.
+async fn rewrite_lines(
+    mut raw: mpsc::UnboundedReceiver<String>,
+    rewritten: mpsc::UnboundedSender<String>,
+    rewrite: Option<Rewrite>,
+) {
+    while let Some(line) = raw.recv().await {
+        let line = match &rewrite {
+            Some(rewrite) => rewrite(line),
+            None => line,
+        };
+        let _ = rewritten.send(line);
+    }
+}
+
+/// Sends each completed line of the response to `lines` as soon as it arrives and returns the
+/// whole thing once the model is done.
+#[allow(clippy::too_many_arguments)]
+async fn ask_chatgpt(
+    backend: &dyn ChatBackend,
+    system_prompt: &str,
+    memory: &Memory,
+    identity: &str,
+    nick: &str,
+    question: &str,
+    moderation: Option<&Moderation>,
+    lines: mpsc::UnboundedSender<String>,
+) -> Result<Completion, Error> {
+    let mut history = memory.history(identity).unwrap_or_default();
+    if history.is_empty() {
+        // Opted out, so nothing was remembered and the question is all there is
+        history.push_back(ChatMessage::user(question));
+    }
+    let recollections = memory.recall(identity, question).await;
+    let mut prompt = system_prompt.to_string();
+    if let Some(summary) = memory.summary(identity) {
+        prompt.push_str(&format!(
+            "\n\nSummary of your conversation with {} so far:\n{}",
+            nick, summary
+        ));
+    }
+    if !recollections.is_empty() {
+        prompt.push_str(&format!(
+            "\n\nEarlier conversations with {} that might be relevant:\n\n{}",
+            nick,
+            recollections.join("\n\n")
+        ));
+    }
+    history.push_front(ChatMessage::system(prompt));
+
+    // Moderated responses are held back until the whole thing has been checked
+    let (held, mut held_rx) = mpsc::unbounded_channel();
+    let mut completion = backend
+        .complete_streaming(
+            history.make_contiguous(),
+            if moderation.is_some() { &held } else { &lines },
+        )
+        .await?;
+    if completion.content.is_empty() {
+        completion.content = String::from("hrmmm I'm not really sure...");
+        let _ = lines.send(completion.content.clone());
+        return Ok(completion);
+    }
+
+    if let Some(moderation) = moderation {
+        if moderation.flagged(&completion.content).await? {
+            warn!("Redacted a flagged response to {}", nick);
+            completion.content = String::from("[redacted] ...I'd better not say that");
+            let _ = lines.send(completion.content.clone());
+        } else {
+            while let Ok(line) = held_rx.try_recv() {
+                let _ = lines.send(line);
+            }
+        }
+    }
+
+    memory
+        .remember(identity, ChatMessage::assistant(completion.content.clone()))
+        .await;
+
+    Ok(completion)
+}
+
+/// Whether `nickname` appears in `text` as a word of its own.
+fn mentions(text: &str, nickname: &str, casemapping: CaseMapping) -> bool {
+    text.split(|c: char| !c.is_alphanumeric() && !"[]\\`_^{|}-".contains(c))
+        .any(|word| casemapping.eq(word, nickname))
+}
+
+/// `msg` without `nickname` in front of it, if it starts by addressing us like `pickles, hi`.
+fn unaddressed<'a>(msg: &'a str, nickname: &str, casemapping: CaseMapping) -> &'a str {
+    let without_at = msg.strip_prefix('@').unwrap_or(msg);
+    casemapping
+        .strip_prefix(without_at, nickname)
+        .and_then(|rest| rest.strip_prefix([',', ':']))
+        .map_or(msg, str::trim_start)
+}
+
+fn extract_nick(prefix: Option<irc::proto::Prefix>) -> String {
+    match prefix {
+        Some(irc::proto::Prefix::Nickname(nick, _, _)) => nick,
+        _ => String::from("Luser"),
+    }
+}
@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::commands;
+use crate::memory;
+use crate::Error;
+
+/// Runs `!commands` in our channels and private messages.
+pub struct RunCommands;
+
+#[async_trait]
+impl Handler for RunCommands {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Command::PRIVMSG(channel, msg) = &ctx.message.command else {
+            return Ok(Flow::Continue);
+        };
+        if ctx.state.channels.get(channel).is_none() && !ctx.is_private(channel) {
+            return Ok(Flow::Continue);
+        }
+
+        let state = ctx.state;
+        let nick = ctx.message.source_nickname().unwrap_or("Luser");
+        let identity = memory::identity(nick, &ctx.tags, ctx.isupport.casemapping);
+        let command_ctx = commands::Context {
+            config: ctx.config,
+            out: ctx.out,
+            source: ctx.source,
+            isupport: ctx.isupport,
+            network: ctx.network,
+            channels: &state.channels,
+            ignores: &state.ignores,
+            memory: ctx.memory.as_ref(),
+            ledger: &state.ledger,
+            seen: &state.seen,
+            memos: &state.memos,
+            reminders: &state.reminders,
+            schedule: &state.schedule,
+            karma: &state.karma,
+            locations: &state.locations,
+            trivia: &state.trivia,
+            word_games: &state.word_games,
+            archive: state.archive.as_ref(),
+            markov: state.markov.as_ref(),
+            ambient: &state.ambient,
+            personas: &state.personas,
+            commands: &state.commands,
+            backend: ctx.backend,
+            outgoing: ctx.outgoing,
+            paste: ctx.paste,
+            target: ctx.reply_target(channel),
+            nick,
+            identity: &identity,
+            privilege: ctx.privilege,
+            dry_run: ctx.config.dry_run,
+            started: state.started,
+        };
+
+        Ok(match state.commands.dispatch(&command_ctx, msg).await? {
+            true => Flow::Consumed,
+            false => Flow::Continue,
+        })
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::Error;
+
+/// Answers `s/foo/bar/` in channels with what the sender meant.
+pub struct Correct;
+
+#[async_trait]
+impl Handler for Correct {
+    fn name(&self) -> &'static str {
+        "corrections"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(channel, msg)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+        if ctx.is_private(channel) {
+            return Ok(Flow::Continue);
+        }
+
+        let casemapping = ctx.isupport.casemapping;
+        match ctx
+            .state
+            .corrections
+            .handle(channel, nick, msg, casemapping)
+        {
+            Some(correction) => {
+                ctx.send(channel, &correction)?;
+                Ok(Flow::Consumed)
+            }
+            None => Ok(Flow::Continue),
+        }
+    }
+}
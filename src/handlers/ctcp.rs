@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::ctcp;
+use crate::Error;
+
+/// Answers CTCP queries like VERSION and PING, and keeps them away from everything after it.
+/// `/me` isn't a query so it carries on like any other message.
+pub struct AnswerCtcp;
+
+#[async_trait]
+impl Handler for AnswerCtcp {
+    fn name(&self) -> &'static str {
+        "ctcp"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(_, msg)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+        let Some((command, params)) = ctcp::parse(msg) else {
+            return Ok(Flow::Continue);
+        };
+        if command.eq_ignore_ascii_case("ACTION") {
+            return Ok(Flow::Continue);
+        }
+
+        let config = &ctx.config.ctcp;
+        let reply = config
+            .enabled
+            .then(|| ctcp::reply(config, command, params))
+            .flatten();
+        match reply {
+            Some(reply) if ctx.config.dry_run => info!("(dry run) {nick} <- CTCP {reply}"),
+            Some(reply) => {
+                debug!("Answering CTCP {} from {}", command, nick);
+                ctx.out.send(Command::NOTICE(nick.to_string(), reply))?;
+            }
+            None => debug!("Not answering CTCP {} from {}", command, nick),
+        }
+
+        Ok(Flow::Consumed)
+    }
+}
@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::output::queue;
+use crate::Error;
+
+/// Greets people joining channels that want it, and sees them off when they part. Goes before
+/// `TrackActivity` so that it can still tell whether someone's been seen before.
+pub struct Greet;
+
+#[async_trait]
+impl Handler for Greet {
+    fn name(&self) -> &'static str {
+        "greetings"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Some(nick) = ctx.message.source_nickname() else {
+            return Ok(Flow::Continue);
+        };
+        let casemapping = ctx.isupport.casemapping;
+        let greeter = &ctx.state.greeter;
+        let channel = match &ctx.message.command {
+            Command::JOIN(channel, ..) | Command::PART(channel, _) => channel,
+            Command::QUIT(reason) => {
+                greeter.quit(
+                    &casemapping.fold(nick),
+                    reason.as_deref().unwrap_or_default(),
+                );
+                return Ok(Flow::Continue);
+            }
+            _ => return Ok(Flow::Continue),
+        };
+        let greet = ctx
+            .state
+            .channels
+            .get(channel)
+            .and_then(|channel_config| channel_config.greet)
+            .unwrap_or(ctx.config.greetings.enabled);
+        if !greet
+            || casemapping.eq(nick, ctx.nickname)
+            || ctx.tags.is_bot()
+            || ctx.state.ignores.is_ignored(ctx.message)
+        {
+            return Ok(Flow::Continue);
+        }
+
+        if let Command::PART(..) = ctx.message.command {
+            if let Some(farewell) = greeter.farewell(nick, channel) {
+                ctx.send(channel, &farewell)?;
+            }
+            return Ok(Flow::Continue);
+        }
+
+        let known = ctx.state.seen.get(nick).is_some();
+        if !greeter.should_greet(&casemapping.fold(channel), &casemapping.fold(nick), known) {
+            return Ok(Flow::Continue);
+        }
+        if !greeter.generate() {
+            if let Some(greeting) = greeter.greeting(nick, channel) {
+                ctx.send(channel, &greeting)?;
+            }
+            return Ok(Flow::Continue);
+        }
+
+        let (greeter, backend, outgoing) =
+            (greeter.clone(), ctx.backend.clone(), ctx.outgoing.clone());
+        let (botnick, nick, channel) = (
+            ctx.nickname.to_string(),
+            nick.to_string(),
+            channel.to_string(),
+        );
+        ctx.responses.spawn(
+            async move {
+                let greeting = greeter
+                    .generated(backend.as_ref(), &botnick, &nick, &channel)
+                    .await;
+                if let Some(greeting) = greeting {
+                    queue(&outgoing, &channel, greeting);
+                }
+            }
+            .in_current_span(),
+        );
+
+        Ok(Flow::Continue)
+    }
+}
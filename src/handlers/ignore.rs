@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::acl::Privilege;
+use crate::Error;
+
+/// Drops messages from anyone on the ignore list.
+pub struct Ignore;
+
+#[async_trait]
+impl Handler for Ignore {
+    fn name(&self) -> &'static str {
+        "ignore"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        if !matches!(ctx.message.command, Command::PRIVMSG(..)) {
+            return Ok(Flow::Continue);
+        }
+
+        // Admins can't be ignored, so nobody can lock them out
+        if ctx.privilege < Privilege::Admin && ctx.state.ignores.is_ignored(ctx.message) {
+            return Ok(Flow::Consumed);
+        }
+
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::Error;
+
+/// Counts `nick++` and `nick--`.
+pub struct CountKarma;
+
+#[async_trait]
+impl Handler for CountKarma {
+    fn name(&self) -> &'static str {
+        "karma"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        ctx.state.karma.saw(ctx.message, ctx.isupport).await;
+
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::Error;
+
+/// Passes on anything left with `!tell` for whoever just spoke or joined, where they did it.
+pub struct DeliverMemos;
+
+#[async_trait]
+impl Handler for DeliverMemos {
+    fn name(&self) -> &'static str {
+        "memos"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(target, _) | Command::JOIN(target, _, _)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+
+        let target = ctx.reply_target(target);
+        for memo in ctx.state.memos.deliver(nick).await {
+            ctx.send(target, &memo.delivery())?;
+        }
+
+        Ok(Flow::Continue)
+    }
+}
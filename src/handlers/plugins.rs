@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use std::sync::Arc;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::plugins::Event;
+use crate::plugins::Plugins;
+use crate::Error;
+
+/// Lets WebAssembly plugins see and answer messages.
+pub struct RunPlugins(pub Arc<Plugins>);
+
+#[async_trait]
+impl Handler for RunPlugins {
+    fn name(&self) -> &'static str {
+        "plugins"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(channel, msg)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+        let event = Event {
+            nick,
+            channel: if ctx.is_private(channel) { "" } else { channel },
+            text: msg,
+        };
+
+        let (replies, handled) = self.0.on_message(&event);
+        for reply in replies {
+            ctx.send(ctx.reply_target(channel), &reply)?;
+        }
+
+        Ok(match handled {
+            true => Flow::Consumed,
+            false => Flow::Continue,
+        })
+    }
+}
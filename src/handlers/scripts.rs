@@ -0,0 +1,38 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use std::sync::Arc;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::scripts::Scripts;
+use crate::Error;
+
+/// Lets scripts answer messages with their own triggers.
+pub struct RunScripts(pub Arc<Scripts>);
+
+#[async_trait]
+impl Handler for RunScripts {
+    fn name(&self) -> &'static str {
+        "scripts"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(channel, msg)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+        let place = if ctx.is_private(channel) { "" } else { channel };
+
+        match self.0.on_message(nick, place, msg) {
+            Some(reply) => {
+                ctx.send(ctx.reply_target(channel), &reply)?;
+                Ok(Flow::Consumed)
+            }
+            None => Ok(Flow::Continue),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::memory;
+use crate::Error;
+
+/// Notes who was last seen doing what, for `!seen`.
+pub struct TrackActivity;
+
+#[async_trait]
+impl Handler for TrackActivity {
+    fn name(&self) -> &'static str {
+        "seen"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let opted_out = ctx.message.source_nickname().is_some_and(|nick| {
+            let identity = memory::identity(nick, &ctx.tags, ctx.isupport.casemapping);
+            ctx.memory.is_opted_out(&identity)
+        });
+        ctx.state
+            .seen
+            .saw(ctx.message, ctx.isupport, opted_out)
+            .await;
+
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::Error;
+
+/// Says the titles of links posted in channels that want it.
+pub struct AnnounceTitles;
+
+#[async_trait]
+impl Handler for AnnounceTitles {
+    fn name(&self) -> &'static str {
+        "titles"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Command::PRIVMSG(channel, msg) = &ctx.message.command else {
+            return Ok(Flow::Continue);
+        };
+        let url_titles = ctx
+            .state
+            .channels
+            .get(channel)
+            .and_then(|channel_config| channel_config.url_titles)
+            .unwrap_or(ctx.config.url_titles);
+        if !url_titles || ctx.is_private(channel) {
+            return Ok(Flow::Continue);
+        }
+
+        let urls = ctx.state.titles.fresh(channel, msg);
+        if !urls.is_empty() {
+            ctx.responses.spawn(
+                ctx.state
+                    .titles
+                    .clone()
+                    .announce(ctx.outgoing.clone(), channel.clone(), urls)
+                    .in_current_span(),
+            );
+        }
+
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use regex::Captures;
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::acl::Privilege;
+use crate::filter::Filter;
+use crate::llm::ChatMessage;
+use crate::memory;
+use crate::output::queue;
+use crate::output::say;
+use crate::prompt;
+use crate::prompt::PromptVars;
+use crate::Error;
+
+/// Answers messages matching one of the configured `[[triggers]]`, the first that matches.
+pub struct RunTriggers;
+
+#[async_trait]
+impl Handler for RunTriggers {
+    fn name(&self) -> &'static str {
+        "triggers"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let Command::PRIVMSG(channel, msg) = &ctx.message.command else {
+            return Ok(Flow::Continue);
+        };
+        let (config, state) = (ctx.config, ctx.state);
+        let private = ctx.is_private(channel);
+        if !private && state.channels.get(channel).is_none() {
+            return Ok(Flow::Continue);
+        }
+        let casemapping = ctx.isupport.casemapping;
+        let Some((trigger, captures)) = config.triggers.iter().find_map(|trigger| {
+            let applies = trigger.channels.is_empty()
+                || (!private && trigger.channels.iter().any(|c| casemapping.eq(c, channel)));
+            applies
+                .then(|| trigger.pattern.captures(msg))
+                .flatten()
+                .map(|captures| (trigger, captures))
+        }) else {
+            return Ok(Flow::Continue);
+        };
+
+        let nick = ctx.message.source_nickname().unwrap_or_default();
+        if !state.loops.check(nick, ctx.tags.is_bot()) {
+            return Ok(Flow::Consumed);
+        }
+        let target = ctx.reply_target(channel).to_string();
+        let place = if private {
+            None
+        } else {
+            Some(channel.as_str())
+        };
+        let Some(template) = &trigger.prompt else {
+            if let Some(reply) = &trigger.reply {
+                ctx.send(&target, &fill(reply, &captures, nick, place))?;
+            }
+            return Ok(Flow::Consumed);
+        };
+
+        let identity = memory::identity(nick, &ctx.tags, casemapping);
+        if ctx.privilege < Privilege::Trusted {
+            if let Err(wait) = state.limiter.check(nick) {
+                info!("Rate limiting {} for another {:?}", nick, wait);
+                return Ok(Flow::Consumed);
+            }
+            let exhausted =
+                state
+                    .ledger
+                    .exhausted(&config.quota, nick, &identity, &target, casemapping);
+            if let Some(exhausted) = exhausted {
+                ctx.send(&target, &exhausted.apology(nick))?;
+                return Ok(Flow::Consumed);
+            }
+        }
+        let system_prompt = prompt::render(
+            &config.openai.system_prompt,
+            &PromptVars {
+                nick,
+                channel: place,
+                botnick: ctx.nickname,
+                topic: None,
+                people: &[],
+            },
+        );
+        let request = [
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(fill(template, &captures, nick, place)),
+        ];
+        let (backend, ledger, outgoing, paste) = (
+            ctx.backend.clone(),
+            state.ledger.clone(),
+            ctx.outgoing.clone(),
+            ctx.paste.cloned(),
+        );
+        let filter = Filter::new(&config.filter, backend.clone());
+        let (nick, formatting) = (nick.to_string(), config.formatting);
+        ctx.responses.spawn(
+            async move {
+                match backend.complete(&request).await {
+                    Ok(completion) => {
+                        ledger
+                            .record(&nick, &identity, &target, completion.usage)
+                            .await;
+                        let (lines, rx) = mpsc::unbounded_channel();
+                        for line in completion.content.lines() {
+                            let _ = lines.send(line.to_string());
+                        }
+                        drop(lines);
+                        say(&outgoing, &target, rx, &nick, formatting, paste, filter).await;
+                    }
+                    Err(e) => {
+                        error!("Unable to answer a trigger for {}: {}", nick, e);
+                        queue(
+                            &outgoing,
+                            &target,
+                            format!("{nick}: ow! I fell down and bumped my brain"),
+                        );
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+
+        Ok(Flow::Consumed)
+    }
+}
+
+/// `template` with `$1`, `${name}` and so on from what the pattern matched, and `{nick}` and
+/// `{channel}` from who said it where.
+fn fill(template: &str, captures: &Captures, nick: &str, channel: Option<&str>) -> String {
+    let mut filled = String::new();
+    captures.expand(template, &mut filled);
+
+    filled
+        .replace("{nick}", nick)
+        .replace("{channel}", channel.unwrap_or("a private message"))
+}
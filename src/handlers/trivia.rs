@@ -0,0 +1,38 @@
+use async_trait::async_trait;
+
+use irc::client::prelude::*;
+
+use super::Context;
+use super::Flow;
+use super::Handler;
+use crate::Error;
+
+/// Checks what's said in channels with a trivia game going against the question.
+pub struct AnswerTrivia;
+
+#[async_trait]
+impl Handler for AnswerTrivia {
+    fn name(&self) -> &'static str {
+        "trivia"
+    }
+
+    async fn handle(&self, ctx: &mut Context<'_>) -> Result<Flow, Error> {
+        let (Some(nick), Command::PRIVMSG(channel, msg)) =
+            (ctx.message.source_nickname(), &ctx.message.command)
+        else {
+            return Ok(Flow::Continue);
+        };
+        if ctx.is_private(channel) {
+            return Ok(Flow::Continue);
+        }
+
+        let key = ctx.isupport.casemapping.fold(channel);
+        match ctx.state.trivia.guess(&key, nick, msg).await {
+            Some(reply) => {
+                ctx.send(channel, &reply)?;
+                Ok(Flow::Consumed)
+            }
+            None => Ok(Flow::Continue),
+        }
+    }
+}
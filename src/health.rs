@@ -0,0 +1,108 @@
+use serde::Serialize;
+
+use tokio::time::Duration;
+use tokio::time::Instant;
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+
+/// A connection that hasn't heard a PING in this long is probably wedged.
+const STALE: Duration = Duration::from_secs(600);
+
+/// What `/healthz` and `/readyz` report on.
+#[derive(Default)]
+pub struct Health {
+    networks: Mutex<BTreeMap<String, NetworkHealth>>,
+    last_openai_success: Mutex<Option<Instant>>,
+}
+
+#[derive(Default)]
+struct NetworkHealth {
+    connected: bool,
+    last_ping: Option<Instant>,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub networks: BTreeMap<String, NetworkReport>,
+    pub last_openai_success_secs_ago: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct NetworkReport {
+    pub connected: bool,
+    pub last_ping_secs_ago: Option<u64>,
+}
+
+pub fn health() -> &'static Health {
+    &HEALTH
+}
+
+impl Health {
+    pub fn connected(&self, network: &str, connected: bool) {
+        let mut networks = self.networks.lock().expect("health lock poisoned");
+        let health = networks.entry(network.to_string()).or_default();
+        health.connected = connected;
+        if connected {
+            // Counts as proof of life until the first PING arrives
+            health.last_ping = Some(Instant::now());
+        }
+    }
+
+    pub fn pinged(&self, network: &str) {
+        let mut networks = self.networks.lock().expect("health lock poisoned");
+        networks.entry(network.to_string()).or_default().last_ping = Some(Instant::now());
+    }
+
+    pub fn openai_succeeded(&self) {
+        *self
+            .last_openai_success
+            .lock()
+            .expect("health lock poisoned") = Some(Instant::now());
+    }
+
+    /// Alive unless a connection has gone quiet for too long.
+    pub fn live(&self) -> bool {
+        self.networks
+            .lock()
+            .expect("health lock poisoned")
+            .values()
+            .all(|network| {
+                !network.connected || network.last_ping.is_some_and(|at| at.elapsed() < STALE)
+            })
+    }
+
+    /// Ready once every network is connected.
+    pub fn ready(&self) -> bool {
+        let networks = self.networks.lock().expect("health lock poisoned");
+        !networks.is_empty() && networks.values().all(|network| network.connected)
+    }
+
+    pub fn report(&self) -> Report {
+        let secs_ago = |at: Option<Instant>| at.map(|at| at.elapsed().as_secs());
+        Report {
+            networks: self
+                .networks
+                .lock()
+                .expect("health lock poisoned")
+                .iter()
+                .map(|(name, network)| {
+                    let report = NetworkReport {
+                        connected: network.connected,
+                        last_ping_secs_ago: secs_ago(network.last_ping),
+                    };
+                    (name.clone(), report)
+                })
+                .collect(),
+            last_openai_success_secs_ago: secs_ago(
+                *self
+                    .last_openai_success
+                    .lock()
+                    .expect("health lock poisoned"),
+            ),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+
+use tokio::net::TcpListener;
+use tracing::*;
+
+use crate::admin;
+use crate::config::HttpConfig;
+use crate::config::WebhookConfig;
+use crate::health::health;
+use crate::health::Report;
+use crate::metrics::metrics;
+use crate::webhook;
+use crate::Error;
+
+/// Serves `/metrics` for Prometheus to scrape, `/healthz` and `/readyz` for whatever is
+/// supervising us, `webhooks`, and the admin API if there's a token for it.
+pub async fn serve(config: &HttpConfig, webhooks: &[WebhookConfig]) -> Result<(), Error> {
+    let mut app = Router::new()
+        .route("/metrics", get(|| async { metrics().render() }))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .merge(webhook::routes(webhooks));
+    if let Some(token) = &config.admin_token {
+        app = app.merge(admin::routes(token));
+    }
+
+    let listener = TcpListener::bind(config.listen)
+        .await
+        .map_err(Error::HttpServer)?;
+    info!("Listening on http://{}", config.listen);
+    axum::serve(listener, app).await.map_err(Error::HttpServer)
+}
+
+async fn healthz() -> (StatusCode, Json<Report>) {
+    status(health().live())
+}
+
+async fn readyz() -> (StatusCode, Json<Report>) {
+    status(health().ready())
+}
+
+fn status(ok: bool) -> (StatusCode, Json<Report>) {
+    let code = match ok {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (code, Json(health().report()))
+}
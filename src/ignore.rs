@@ -0,0 +1,98 @@
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::acl::wildcard_match;
+use crate::storage::IgnoreStore;
+use crate::Error;
+
+/// People and bots pickles pretends not to hear on one network. Changes are written through
+/// to the store, if there is one.
+pub struct IgnoreList {
+    scope: String,
+    masks: Mutex<Vec<String>>,
+    store: Option<Arc<dyn IgnoreStore>>,
+}
+
+impl IgnoreList {
+    pub async fn load(scope: &str, store: Option<Arc<dyn IgnoreStore>>) -> Result<Self, Error> {
+        let masks = match &store {
+            Some(store) => store.ignores(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            masks: Mutex::new(masks),
+            store,
+        })
+    }
+
+    /// Turns a bare nick into a hostmask so both can be ignored the same way.
+    pub fn mask(nick_or_mask: &str) -> String {
+        if nick_or_mask.contains(['!', '@']) {
+            nick_or_mask.to_string()
+        } else {
+            format!("{}!*@*", nick_or_mask)
+        }
+    }
+
+    pub fn is_ignored(&self, message: &Message) -> bool {
+        let Some(Prefix::Nickname(nick, user, host)) = &message.prefix else {
+            return false;
+        };
+        let hostmask = format!("{}!{}@{}", nick, user, host);
+
+        self.masks
+            .lock()
+            .expect("ignore lock poisoned")
+            .iter()
+            .any(|mask| wildcard_match(mask, &hostmask))
+    }
+
+    pub fn masks(&self) -> Vec<String> {
+        self.masks.lock().expect("ignore lock poisoned").clone()
+    }
+
+    /// Returns false if `mask` was already ignored.
+    pub async fn ignore(&self, mask: &str) -> bool {
+        {
+            let mut masks = self.masks.lock().expect("ignore lock poisoned");
+            if masks.iter().any(|m| m.eq_ignore_ascii_case(mask)) {
+                return false;
+            }
+            masks.push(mask.to_string());
+        }
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.ignore(&self.scope, mask).await {
+                warn!("Unable to save ignore for {}: {}", mask, e);
+            }
+        }
+
+        true
+    }
+
+    /// Returns false if `mask` wasn't ignored.
+    pub async fn unignore(&self, mask: &str) -> bool {
+        let removed = {
+            let mut masks = self.masks.lock().expect("ignore lock poisoned");
+            let position = masks.iter().position(|m| m.eq_ignore_ascii_case(mask));
+            position.map(|i| masks.remove(i))
+        };
+        let Some(removed) = removed else {
+            return false;
+        };
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.unignore(&self.scope, &removed).await {
+                warn!("Unable to remove ignore for {}: {}", mask, e);
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,93 @@
+use async_openai::types::CreateImageRequestArgs;
+use async_openai::types::ImageData;
+use async_openai::types::ResponseFormat;
+
+use reqwest::multipart;
+
+use tracing::*;
+
+use crate::config::ImageSize;
+use crate::config::ImagesConfig;
+use crate::config::OpenAIConfig;
+use crate::llm::openai;
+use crate::Error;
+
+/// Draws pictures with DALL-E.
+#[derive(Clone)]
+pub struct Images {
+    openai: OpenAIConfig,
+    /// Shared with everything else that asks OpenAI.
+    openai_http: reqwest::Client,
+    config: ImagesConfig,
+    http: reqwest::Client,
+}
+
+impl Images {
+    pub fn new(
+        openai: &OpenAIConfig,
+        openai_http: &reqwest::Client,
+        config: &ImagesConfig,
+    ) -> Self {
+        Self {
+            openai: openai.clone(),
+            openai_http: openai_http.clone(),
+            config: config.clone(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Returns a link to a picture of `prompt`. `nick` is passed along for OpenAI's abuse
+    /// monitoring.
+    pub async fn generate(&self, prompt: &str, nick: &str) -> Result<String, Error> {
+        let size = match self.config.size {
+            ImageSize::Small => async_openai::types::ImageSize::S256x256,
+            ImageSize::Medium => async_openai::types::ImageSize::S512x512,
+            ImageSize::Large => async_openai::types::ImageSize::S1024x1024,
+        };
+        let request = CreateImageRequestArgs::default()
+            .prompt(prompt)
+            .n(1)
+            .size(size)
+            .response_format(ResponseFormat::Url)
+            .user(nick)
+            .build()?;
+
+        debug!("Asking DALL-E > {:?}", &request);
+        let response = openai::client(&self.openai, &self.openai_http, None)
+            .images()
+            .create(request)
+            .await?;
+        let url = match response.data.first().map(|image| image.as_ref()) {
+            Some(ImageData::Url(url)) => url.to_string(),
+            _ => return Err(Error::Image(String::from("no image in the response"))),
+        };
+
+        match &self.config.upload_url {
+            Some(upload_url) => self.rehost(&url, upload_url).await,
+            None => Ok(url),
+        }
+    }
+
+    /// Copies the image at `url` to a 0x0 compatible host, returning its new home.
+    async fn rehost(&self, url: &str, upload_url: &str) -> Result<String, Error> {
+        let image = self.http.get(url).send().await?.error_for_status()?;
+        let file = multipart::Part::bytes(image.bytes().await?.to_vec()).file_name("pickles.png");
+
+        let body = self
+            .http
+            .post(upload_url)
+            .header(reqwest::header::USER_AGENT, "pickles")
+            .multipart(multipart::Form::new().part("file", file))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let url = body.trim();
+        if !url.starts_with("http") {
+            return Err(Error::Image(format!("unexpected upload response: {}", url)));
+        }
+
+        Ok(url.to_string())
+    }
+}
@@ -0,0 +1,795 @@
+//! The connection to each IRC network and everything that happens on it.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use futures::stream::StreamExt;
+
+use irc::client::prelude::*;
+use irc::client::ClientStream;
+
+use tokio::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::task::JoinSet;
+use tokio::time;
+use tracing::*;
+
+use std::process;
+use std::sync::Arc;
+
+use crate::acl;
+use crate::admin;
+use crate::admin::admin;
+use crate::ambient::Ambient;
+use crate::archive::Archive;
+use crate::channels::Channels;
+use crate::commands::Commands;
+use crate::config;
+use crate::config::JobConfig;
+use crate::config::NetworkConfig;
+use crate::filter::Filter;
+use crate::flood::Throttle;
+use crate::gateway;
+use crate::greetings::Greeter;
+use crate::handlers;
+use crate::handlers::chat::Answering;
+use crate::handlers::Pipeline;
+use crate::health::health;
+use crate::http;
+use crate::ignore::IgnoreList;
+use crate::isupport::ISupport;
+use crate::karma::Karma;
+use crate::llm;
+use crate::llm::openai;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+use crate::loops::LoopDetector;
+use crate::markov::Markov;
+use crate::memory;
+use crate::memory::Memory;
+use crate::memory::SHARED_SCOPE;
+use crate::memos::Memos;
+use crate::metrics::metrics;
+use crate::moderation::Moderation;
+use crate::nickserv;
+use crate::nickserv::NickServ;
+use crate::output::say;
+use crate::output::send_privmsg;
+use crate::output::Outgoing;
+use crate::paste::Paste;
+use crate::persona::Personas;
+use crate::prompt;
+use crate::prompt::PromptVars;
+use crate::proxy;
+use crate::ratelimit::RateLimiter;
+use crate::recall::Recall;
+use crate::reconnect::Backoff;
+use crate::reminders::Reminders;
+use crate::reporting;
+use crate::sasl;
+use crate::schedule::Schedule;
+use crate::sed::Corrections;
+use crate::seen::Seen;
+use crate::split;
+use crate::storage;
+use crate::systemd;
+use crate::systemd::Watchdog;
+use crate::tags;
+use crate::tags::Tags;
+use crate::titles::Titles;
+use crate::trivia::Trivia;
+use crate::usage::Ledger;
+use crate::weather::Locations;
+use crate::word_games::WordGames;
+use crate::Error;
+
+/// How long to wait for the server to hang up after we QUIT.
+const QUIT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Everything about a network that outlives any one connection to it.
+pub struct NetworkState {
+    pub channels: Channels,
+    pub limiter: RateLimiter,
+    pub loops: LoopDetector,
+    pub ignores: IgnoreList,
+    pub personas: Personas,
+    pub commands: Commands,
+    pub ledger: Arc<Ledger>,
+    pub seen: Seen,
+    pub memos: Memos,
+    pub reminders: Reminders,
+    pub schedule: Schedule,
+    pub karma: Karma,
+    pub locations: Locations,
+    pub trivia: Arc<Trivia>,
+    pub word_games: WordGames,
+    pub corrections: Corrections,
+    pub titles: Arc<Titles>,
+    pub greeter: Arc<Greeter>,
+    /// The HTTP client everything that asks OpenAI shares, and its connection pool with it.
+    pub openai: reqwest::Client,
+    /// Something to say when the model can't be asked.
+    pub markov: Option<Arc<Markov>>,
+    pub ambient: Ambient,
+    /// Answers still being worked on.
+    pub answering: Answering,
+    /// Where channel logs go, if they're kept.
+    pub archive: Option<Archive>,
+    /// Anything the server says happened before this is bouncer playback.
+    pub started: DateTime<Utc>,
+    /// What happens to each incoming message.
+    pub pipeline: Pipeline,
+}
+
+/// Why `run()` stopped.
+enum Disconnect {
+    /// The server closed the connection, so reconnect.
+    Closed,
+    /// We were asked to exit.
+    Shutdown,
+}
+
+/// Talks to OpenAI on every network in `config` until we get Ctrl-C or SIGTERM.
+pub async fn start(config: config::Config) -> Result<(), Error> {
+    let openai = openai::http_client(&config.openai)?;
+    let backend = llm::backend(&config, &openai);
+
+    if let Some(reporting) = &config.error_reporting {
+        reporting::start(reporting);
+    }
+    if let Some(http) = config.http.clone() {
+        let webhooks = config.webhooks.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(&http, &webhooks).await {
+                error!("{}", e);
+            }
+        });
+    }
+
+    let (shutdown_tx, shutdown) = watch::channel(false);
+    tokio::spawn(async move {
+        terminated().await;
+        info!("Shutting down, interrupt again to exit immediately");
+        systemd::notify("STOPPING=1");
+        let _ = shutdown_tx.send(true);
+
+        terminated().await;
+        process::exit(1);
+    });
+
+    serve(config, backend, openai, shutdown).await
+}
+
+/// Opens storage and starts a connection to every network, answering with `backend`, then
+/// waits on them until `shutdown` is set. Anything else asking OpenAI goes through `openai`.
+pub async fn serve(
+    config: config::Config,
+    backend: Arc<dyn ChatBackend>,
+    openai: reqwest::Client,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    let mut config = config;
+    for network in config.networks.iter_mut() {
+        let span = info_span!("network", name = %network.name);
+        gateway::open(network).instrument(span.clone()).await?;
+        proxy::open(network).instrument(span).await?;
+    }
+    let config = Arc::new(config);
+    let store = match &config.storage {
+        Some(storage) => Some(storage::connect(storage).await?),
+        None => None,
+    };
+    let recall = || {
+        config
+            .recall
+            .as_ref()
+            .map(|recall| Recall::new(&config.openai, &openai, recall))
+    };
+    let shared_memory = match config.shared_memory {
+        true => Some(Arc::new(
+            Memory::load(
+                SHARED_SCOPE,
+                store.clone().map(|store| store as _),
+                recall(),
+                config.summarize,
+            )
+            .await?,
+        )),
+        false => None,
+    };
+    let markov = config
+        .markov
+        .as_ref()
+        .and_then(|markov| match Markov::load(markov) {
+            Ok(markov) => Some(Arc::new(markov)),
+            Err(e) => {
+                warn!("Nothing to fall back on when the model's down: {}", e);
+                None
+            }
+        });
+
+    let mut connections = Vec::new();
+    for network in config.networks.iter() {
+        health().connected(&network.name, false);
+        let memory = match &shared_memory {
+            Some(memory) => memory.clone(),
+            None => Arc::new(
+                Memory::load(
+                    &network.name,
+                    store.clone().map(|store| store as _),
+                    recall(),
+                    config.summarize,
+                )
+                .await?,
+            ),
+        };
+        let state = Arc::new(NetworkState {
+            channels: Channels::new(network),
+            limiter: RateLimiter::new(&config.rate_limit),
+            loops: LoopDetector::new(&config.loop_detection),
+            personas: Personas::new(&config.personas),
+            commands: Commands::new(&config, &openai),
+            ledger: Arc::new(
+                Ledger::load(&network.name, store.clone().map(|store| store as _)).await?,
+            ),
+            ignores: IgnoreList::load(&network.name, store.clone().map(|store| store as _)).await?,
+            seen: Seen::load(&network.name, store.clone().map(|store| store as _)).await?,
+            memos: Memos::load(&network.name, store.clone().map(|store| store as _)).await?,
+            reminders: Reminders::load(&network.name, store.clone().map(|store| store as _))
+                .await?,
+            schedule: Schedule::new(&config.schedule, &network.name),
+            karma: Karma::load(&network.name, store.clone().map(|store| store as _)).await?,
+            locations: Locations::load(&network.name, store.clone().map(|store| store as _))
+                .await?,
+            trivia: Arc::new(
+                Trivia::load(
+                    &network.name,
+                    &config.trivia,
+                    store.clone().map(|store| store as _),
+                )
+                .await?,
+            ),
+            word_games: WordGames::load(&network.name, store.clone().map(|store| store as _))
+                .await?,
+            corrections: Corrections::new(),
+            titles: Arc::new(Titles::new()),
+            greeter: Arc::new(Greeter::new(&config.greetings)),
+            openai: openai.clone(),
+            markov: markov.clone(),
+            ambient: Ambient::new(&config.ambient),
+            archive: config.archive.as_ref().map(|archive| {
+                Archive::new(
+                    &network.name,
+                    archive,
+                    store.clone().map(|store| store as _),
+                )
+            }),
+            answering: Answering::default(),
+            started: Utc::now(),
+            pipeline: Pipeline::new(&config),
+        });
+        let span = info_span!("network", name = %network.name);
+
+        connections.push(tokio::spawn(
+            supervise(
+                config.clone(),
+                network.clone(),
+                backend.clone(),
+                memory,
+                state,
+                shutdown.clone(),
+            )
+            .instrument(span),
+        ));
+    }
+
+    for result in futures::future::join_all(connections).await {
+        if let Err(e) = result {
+            error!("Connection task failed: {}", e);
+        }
+    }
+
+    if let Some(store) = store {
+        store.close().await;
+    }
+
+    Ok(())
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn terminated() {
+    let mut sigterm =
+        signal::unix::signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    tokio::select! {
+        _ = signal::ctrl_c() => (),
+        _ = sigterm.recv() => (),
+    }
+}
+
+/// Keeps a single network connected, reconnecting with backoff whenever `run()` returns until
+/// we're asked to shut down.
+async fn supervise(
+    config: Arc<config::Config>,
+    network: NetworkConfig,
+    backend: Arc<dyn ChatBackend>,
+    memory: Arc<Memory>,
+    state: Arc<NetworkState>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut backoff = Backoff::new(&network);
+    let mut watchdog = Watchdog::new();
+    // Unlike responses, what the admin API and webhooks say outlives any one connection
+    let (admin_tx, mut from_admin) = mpsc::unbounded_channel();
+    admin().register(
+        &network.name,
+        admin::Network {
+            config: config.clone(),
+            backend: backend.clone(),
+            state: state.clone(),
+            memory: memory.clone(),
+            outgoing: admin_tx,
+        },
+    );
+    loop {
+        let result = run(
+            &config,
+            &network,
+            &state,
+            &backend,
+            &memory,
+            &mut from_admin,
+            &mut backoff,
+            &mut shutdown,
+        )
+        .await;
+        health().connected(&network.name, false);
+        match result {
+            Ok(Disconnect::Shutdown) => return,
+            Ok(Disconnect::Closed) => (),
+            Err(e) => {
+                metrics().errors.with_label_values(&["connection"]).inc();
+                error!("Error: {}", e);
+                reporting::error(&network.name, &e);
+            }
+        }
+        if *shutdown.borrow() {
+            return;
+        }
+
+        let delay = backoff.disconnected();
+        info!("Reconnecting in {:?}", delay);
+        metrics()
+            .reconnects
+            .with_label_values(&[&network.name])
+            .inc();
+        // Still alive, just waiting
+        let reconnect = time::sleep(delay);
+        tokio::pin!(reconnect);
+        loop {
+            tokio::select! {
+                _ = &mut reconnect => break,
+                _ = watchdog.tick() => systemd::notify("WATCHDOG=1"),
+                _ = shutdown.changed() => return,
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run(
+    config: &config::Config,
+    network: &NetworkConfig,
+    state: &NetworkState,
+    backend: &Arc<dyn ChatBackend>,
+    memory: &Arc<Memory>,
+    from_admin: &mut mpsc::UnboundedReceiver<Outgoing>,
+    backoff: &mut Backoff,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<Disconnect, Error> {
+    let NetworkState {
+        channels,
+        reminders,
+        ..
+    } = state;
+    // We join once the server has told us how many channels fit in one JOIN, see below
+    let irc_config = irc::client::data::Config {
+        channels: Vec::new(),
+        ..network.irc_config()
+    };
+    let mut client = Client::from_config(irc_config).await?;
+    info!("Connecting to server...");
+    let mut stream = client.stream()?;
+    // Before anything else, or the relay hangs up
+    if let Some(token) = &network.relay_token {
+        client.send(Command::PASS(token.clone()))?;
+    }
+    for capability in tags::CAPABILITIES {
+        client.send_cap_req(&[Capability::Custom(capability)])?;
+    }
+    match &network.sasl {
+        Some(sasl) => sasl::identify(&client, &mut stream, network, sasl).await?,
+        None => client.identify()?,
+    }
+    info!("Connected");
+    systemd::notify(&format!("READY=1\nSTATUS=Connected to {}", network.name));
+    let (out, throttle) = Throttle::new(client.sender(), network);
+
+    let paste = config.paste.as_ref().map(Paste::new);
+    let moderation = Moderation::new(&config.openai, &state.openai);
+    let mut nickserv = NickServ::new(network);
+    let mut reclaim = time::interval(nickserv::RECLAIM_INTERVAL);
+    let mut watchdog = Watchdog::new();
+    // Responses are worked on in the background so a slow completion never holds up the
+    // connection. Dropping the set when we disconnect cancels whatever is still going.
+    let mut responses = JoinSet::new();
+    let (outgoing_tx, mut outgoing) = mpsc::unbounded_channel::<Outgoing>();
+    // Learned from the first thing the server echoes back from us, usually our JOINs
+    let mut userhost = None;
+    // Reminders wait until we're registered, there's nowhere to send them before that
+    let mut registered = false;
+    let mut isupport = ISupport::default();
+    channels.set_casemapping(isupport.casemapping);
+    channels.clear_members();
+
+    loop {
+        let source = split::source(nickserv.current_nickname(), userhost.as_deref(), &isupport);
+        let line_length = isupport.line_length;
+        let reminder_wait = reminders
+            .next_due()
+            .filter(|_| registered)
+            .map(|due| (due - Utc::now()).to_std().unwrap_or_default());
+        let schedule_wait = state
+            .schedule
+            .next_due()
+            .filter(|_| registered)
+            .map(|due| (due - Utc::now()).to_std().unwrap_or_default());
+        let message = tokio::select! {
+            message = stream.next() => match message.transpose()? {
+                Some(message) => message,
+                None => break,
+            },
+            _ = reclaim.tick() => {
+                nickserv.tick(&out)?;
+                continue;
+            }
+            _ = watchdog.tick() => {
+                systemd::notify("WATCHDOG=1");
+                continue;
+            }
+            _ = time::sleep(reminder_wait.unwrap_or_default()), if reminder_wait.is_some() => {
+                for reminder in reminders.due().await {
+                    let msg = format!("{}: reminder: {}", reminder.nick, reminder.text);
+                    send_privmsg(&out, &source, line_length, &reminder.target, &msg, config.dry_run)?;
+                }
+                continue;
+            }
+            _ = time::sleep(schedule_wait.unwrap_or_default()), if schedule_wait.is_some() => {
+                for job in state.schedule.due() {
+                    info!("Running {}", job.name);
+                    announce(
+                        config,
+                        state,
+                        backend,
+                        nickserv.current_nickname(),
+                        &outgoing_tx,
+                        &mut responses,
+                        paste.as_ref(),
+                        &job,
+                    );
+                }
+                continue;
+            }
+            Some(line) = outgoing.recv() => {
+                send_privmsg(&out, &source, line_length, &line.target, &line.msg, config.dry_run)?;
+                continue;
+            }
+            Some(line) = from_admin.recv(), if registered => {
+                send_privmsg(&out, &source, line_length, &line.target, &line.msg, config.dry_run)?;
+                continue;
+            }
+            Some(result) = responses.join_next() => {
+                if let Err(e) = result {
+                    error!("Response task failed: {}", e);
+                }
+                continue;
+            }
+            _ = shutdown.changed() => {
+                // Anything already said still goes out, anything still being thought about doesn't
+                while let Ok(line) = outgoing.try_recv() {
+                    send_privmsg(&out, &source, line_length, &line.target, &line.msg, config.dry_run)?;
+                }
+                quit(&mut stream, out, throttle, &config.quit_message).await?;
+                return Ok(Disconnect::Shutdown);
+            }
+        };
+
+        metrics()
+            .messages_received
+            .with_label_values(&[&network.name])
+            .inc();
+        nickserv.handle(&out, &message)?;
+        let casemapping = isupport.casemapping;
+        let is_us = |nick: &str| casemapping.eq(nick, nickserv.current_nickname());
+        if let Some(Prefix::Nickname(nick, user, host)) = &message.prefix {
+            if is_us(nick) && !user.is_empty() && !host.is_empty() {
+                userhost = Some(format!("{}@{}", user, host));
+            }
+        }
+
+        match &message.command {
+            Command::Response(Response::RPL_WELCOME, _) => {
+                registered = true;
+                backoff.registered();
+                health().connected(&network.name, true)
+            }
+            Command::PING(..) | Command::PONG(..) => health().pinged(&network.name),
+            // By the end of the MOTD the server has sent ISUPPORT
+            Command::Response(Response::RPL_ENDOFMOTD, _)
+            | Command::Response(Response::ERR_NOMOTD, _) => {
+                for batch in isupport.batch("JOIN", &channels.names()) {
+                    out.send_join(&batch)?;
+                }
+            }
+            Command::Response(Response::RPL_ISUPPORT, args) => {
+                let bot_mode = isupport.bot_mode.clone();
+                isupport.update(args);
+                channels.set_casemapping(isupport.casemapping);
+                // Lets people and other bots know not to get into long conversations with us
+                if let Some(mode) = isupport
+                    .bot_mode
+                    .as_ref()
+                    .filter(|&mode| bot_mode.as_ref() != Some(mode))
+                {
+                    let nick = nickserv.current_nickname().to_string();
+                    out.send(Command::Raw(
+                        String::from("MODE"),
+                        vec![nick, format!("+{}", mode)],
+                    ))?;
+                }
+            }
+            Command::Response(Response::RPL_TOPIC, args) if args.len() >= 3 => {
+                channels.set_topic(&args[1], &args[2])
+            }
+            Command::TOPIC(channel, Some(topic)) => channels.set_topic(channel, topic),
+            Command::KICK(channel, nick, _) if is_us(nick) => {
+                state.answering.kicked(channel, casemapping);
+                handle_kick(&out, network, channels, channel, &message)
+            }
+            Command::QUIT(_) => {
+                if let Some(nick) = message.source_nickname() {
+                    state.answering.quit(nick, casemapping);
+                }
+            }
+            Command::NICK(new) => {
+                if let Some(old) = message.source_nickname() {
+                    state.answering.renamed(old, new, casemapping);
+                }
+            }
+            Command::INVITE(nick, channel) if is_us(nick) => {
+                handle_invite(&out, network, channels, channel, &message)?
+            }
+            _ => (),
+        }
+        let tags = Tags::new(&message);
+        if let Some(archive) = &state.archive {
+            // Bouncer playback is logged for when it happened
+            let at = tags.time().unwrap_or_else(Utc::now);
+            let opted_out = message.source_nickname().is_some_and(|nick| {
+                memory.is_opted_out(&memory::identity(nick, &tags, casemapping))
+            });
+            archive.log(&message, channels, at, opted_out).await;
+        }
+        channels.track(&message, nickserv.current_nickname(), &isupport.prefixes);
+        if let Command::PRIVMSG(channel, msg) = &message.command {
+            debug!("{:?} -> {}: {}", &message.response_target(), &channel, &msg);
+        }
+
+        // Our own messages coming back with echo-message
+        if message.source_nickname().is_some_and(is_us) {
+            continue;
+        }
+        // Bouncers replay what we missed while we were away, which is too late to answer
+        if let Some(time) = tags.time().filter(|time| *time < state.started) {
+            trace!("Skipping playback from {}", time);
+            continue;
+        }
+
+        // Everything done about this message, answering it included, shares its request id.
+        // It's a trace of its own rather than one more part of the connection's.
+        let span = info_span!(
+            parent: None,
+            "message",
+            network = %network.name,
+            request = %request_id(),
+            channel = message.response_target().unwrap_or_default(),
+            nick = message.source_nickname().unwrap_or_default(),
+        );
+        reporting::handling(&network.name, &message);
+        let mut ctx = handlers::Context {
+            config,
+            network,
+            state,
+            backend,
+            memory,
+            out: &out,
+            source: &source,
+            nickname: nickserv.current_nickname(),
+            isupport: &isupport,
+            outgoing: &outgoing_tx,
+            responses: &mut responses,
+            paste: paste.as_ref(),
+            moderation: &moderation,
+            message: &message,
+            privilege: acl::privilege(&network.acl, &message, &tags),
+            tags,
+        };
+        state.pipeline.run(&mut ctx).instrument(span).await?;
+    }
+
+    Ok(Disconnect::Closed)
+}
+
+/// Tells the handling of one message apart from all the others in the logs.
+fn request_id() -> String {
+    format!("{:08x}", rand::random::<u32>())
+}
+
+/// Says goodbye after whatever is still queued, then gives the server a moment to hang up so
+/// it all actually goes out.
+async fn quit(
+    stream: &mut ClientStream,
+    out: Throttle,
+    throttle: JoinHandle<()>,
+    reason: &str,
+) -> Result<(), Error> {
+    info!("Quitting");
+    out.send(Command::QUIT(Some(reason.to_string())))?;
+    drop(out);
+    let _ = time::timeout(QUIT_TIMEOUT, async {
+        tokio::join!(throttle, async {
+            while let Some(Ok(_)) = stream.next().await {}
+        })
+    })
+    .await;
+
+    Ok(())
+}
+
+/// Says `job`'s message in each of its channels we're in, or asks the model its prompt there
+/// and says the answer, in the background like any other response.
+#[allow(clippy::too_many_arguments)]
+fn announce(
+    config: &config::Config,
+    state: &NetworkState,
+    backend: &Arc<dyn ChatBackend>,
+    nickname: &str,
+    outgoing: &mpsc::UnboundedSender<Outgoing>,
+    responses: &mut JoinSet<()>,
+    paste: Option<&Paste>,
+    job: &JobConfig,
+) {
+    for channel in &job.channels {
+        if state.channels.get(channel).is_none() {
+            warn!("Not running {} in {}, we're not in it", job.name, channel);
+            continue;
+        }
+
+        let topic = state.channels.topic(channel);
+        let people = state.channels.members(channel);
+        let vars = PromptVars {
+            nick: nickname,
+            channel: Some(channel),
+            botnick: nickname,
+            topic: topic.as_deref(),
+            people: &people,
+        };
+        let system_prompt = state
+            .personas
+            .prompt(channel)
+            .unwrap_or_else(|| config.openai.system_prompt.clone());
+        let request = job.prompt.as_ref().map(|template| {
+            [
+                ChatMessage::system(prompt::render(&system_prompt, &vars)),
+                ChatMessage::user(prompt::render(template, &vars)),
+            ]
+        });
+        let message = job
+            .message
+            .as_ref()
+            .map(|template| prompt::render(template, &vars));
+        let (backend, ledger, outgoing, paste) = (
+            backend.clone(),
+            state.ledger.clone(),
+            outgoing.clone(),
+            paste.cloned(),
+        );
+        let filter = Filter::new(&config.filter, backend.clone());
+        let (name, nickname, channel, formatting) = (
+            job.name.clone(),
+            nickname.to_string(),
+            channel.clone(),
+            config.formatting,
+        );
+        responses.spawn(
+            async move {
+                let text = match request {
+                    Some(request) => match backend.complete(&request).await {
+                        Ok(completion) => {
+                            ledger
+                                .record(&nickname, &nickname, &channel, completion.usage)
+                                .await;
+                            completion.content
+                        }
+                        Err(e) => {
+                            error!("Unable to run {} in {}: {}", name, channel, e);
+                            return;
+                        }
+                    },
+                    None => message.unwrap_or_default(),
+                };
+                let (lines, rx) = mpsc::unbounded_channel();
+                for line in text.lines() {
+                    let _ = lines.send(line.to_string());
+                }
+                drop(lines);
+                // Nobody asked, so there's nobody to send the rest of a long one to privately
+                say(&outgoing, &channel, rx, &channel, formatting, paste, filter).await;
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+fn handle_kick(
+    out: &Throttle,
+    network: &NetworkConfig,
+    channels: &Channels,
+    channel: &str,
+    message: &Message,
+) {
+    warn!(
+        "Kicked from {} by {}",
+        channel,
+        message.source_nickname().unwrap_or("the server")
+    );
+    if !network.rejoin_on_kick {
+        channels.part(channel);
+        return;
+    }
+
+    let out = out.clone();
+    let channel = channel.to_string();
+    let delay = time::Duration::from_secs(network.rejoin_delay);
+    tokio::spawn(
+        async move {
+            time::sleep(delay).await;
+            info!("Rejoining {}", channel);
+            if let Err(e) = out.send_join(&channel) {
+                error!("Unable to rejoin {}: {}", channel, e);
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+fn handle_invite(
+    out: &Throttle,
+    network: &NetworkConfig,
+    channels: &Channels,
+    channel: &str,
+    message: &Message,
+) -> Result<(), Error> {
+    let inviter = message.source_nickname().unwrap_or("someone");
+    if let Some(channel_config) = network.invite_channel(channel, channels.casemapping()) {
+        info!("Invited to {} by {}, joining", channel, inviter);
+        channels.join(channel_config.clone());
+        out.send_join(channel)?;
+    } else {
+        info!("Ignoring invite to {} from {}", channel, inviter);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use crate::casemap::CaseMapping;
+
+/// The longest line RFC 1459 allows, counting the trailing CRLF.
+const DEFAULT_LINE_LENGTH: usize = 512;
+
+const DEFAULT_CHANTYPES: &str = "#&";
+
+/// Channel operator and voice.
+const DEFAULT_PREFIXES: &str = "@+";
+
+/// Longest username and hostname most servers allow, for those that don't say.
+const DEFAULT_USERLEN: usize = 10;
+const DEFAULT_HOSTLEN: usize = 63;
+
+/// What the server says about itself in `RPL_ISUPPORT` (005): how long lines can be, how many
+/// targets a command takes, what channel names start with and how names compare. Anything it
+/// doesn't mention is taken to be as RFC 1459 has it.
+#[derive(Debug, Clone)]
+pub struct ISupport {
+    pub casemapping: CaseMapping,
+    /// Counting the trailing CRLF but not message tags.
+    pub line_length: usize,
+    pub chantypes: String,
+    /// Status symbols, like `@` for operators, that go in front of nicks in NAMES replies.
+    pub prefixes: String,
+    pub userlen: usize,
+    pub hostlen: usize,
+    /// The user mode that tells people we're a bot, if the server has one.
+    pub bot_mode: Option<String>,
+    /// From `TARGMAX`, by command. `None` takes any number of targets.
+    targmax: HashMap<String, Option<usize>>,
+    /// From `MAXTARGETS`, for commands `TARGMAX` doesn't list.
+    max_targets: Option<usize>,
+}
+
+impl Default for ISupport {
+    fn default() -> Self {
+        Self {
+            casemapping: CaseMapping::default(),
+            line_length: DEFAULT_LINE_LENGTH,
+            chantypes: DEFAULT_CHANTYPES.to_string(),
+            prefixes: DEFAULT_PREFIXES.to_string(),
+            userlen: DEFAULT_USERLEN,
+            hostlen: DEFAULT_HOSTLEN,
+            bot_mode: None,
+            targmax: HashMap::new(),
+            max_targets: None,
+        }
+    }
+}
+
+impl ISupport {
+    /// Takes in one 005 reply. Servers spread them over several lines, and can take back what
+    /// they said with `-TOKEN`.
+    pub fn update(&mut self, args: &[String]) {
+        // The first argument is our nick and the last is "are supported by this server"
+        let tokens = match args {
+            [_, tokens @ .., _] => tokens,
+            _ => return,
+        };
+        let default = Self::default();
+        for token in tokens {
+            if let Some(name) = token.strip_prefix('-') {
+                match name {
+                    "CASEMAPPING" => self.casemapping = default.casemapping,
+                    "LINELEN" => self.line_length = default.line_length,
+                    "CHANTYPES" => self.chantypes = default.chantypes.clone(),
+                    "PREFIX" => self.prefixes = default.prefixes.clone(),
+                    "USERLEN" => self.userlen = default.userlen,
+                    "HOSTLEN" => self.hostlen = default.hostlen,
+                    "BOT" => self.bot_mode = None,
+                    "TARGMAX" => self.targmax.clear(),
+                    "MAXTARGETS" => self.max_targets = None,
+                    _ => (),
+                }
+                continue;
+            }
+
+            let (name, value) = token.split_once('=').unwrap_or((token, ""));
+            match name {
+                "CASEMAPPING" => {
+                    self.casemapping = CaseMapping::from_isupport(value).unwrap_or_default()
+                }
+                "LINELEN" => self.line_length = value.parse().unwrap_or(DEFAULT_LINE_LENGTH),
+                "CHANTYPES" => self.chantypes = value.to_string(),
+                // Modes in brackets, then their symbols: `(ov)@+`
+                "PREFIX" => {
+                    self.prefixes = value
+                        .split_once(')')
+                        .map_or(value, |(_, symbols)| symbols)
+                        .to_string()
+                }
+                "USERLEN" => self.userlen = value.parse().unwrap_or(DEFAULT_USERLEN),
+                "HOSTLEN" => self.hostlen = value.parse().unwrap_or(DEFAULT_HOSTLEN),
+                "BOT" if !value.is_empty() => self.bot_mode = Some(value.to_string()),
+                "TARGMAX" => {
+                    self.targmax = value
+                        .split(',')
+                        .filter_map(|limit| limit.split_once(':'))
+                        .map(|(command, max)| (command.to_ascii_uppercase(), max.parse().ok()))
+                        .collect()
+                }
+                "MAXTARGETS" => self.max_targets = value.parse().ok(),
+                _ => (),
+            }
+        }
+    }
+
+    /// Whether `target` is a channel rather than a nick.
+    pub fn is_channel(&self, target: &str) -> bool {
+        target.starts_with(|c| self.chantypes.contains(c))
+    }
+
+    /// How many targets one `command` can have. Servers that don't say get one at a time.
+    pub fn max_targets(&self, command: &str) -> usize {
+        match self.targmax.get(command) {
+            Some(max) => max.unwrap_or(usize::MAX),
+            None => self.max_targets.unwrap_or(1),
+        }
+        .max(1)
+    }
+
+    /// `targets` joined into as few comma separated lists as `command` takes, each short enough
+    /// to send.
+    pub fn batch(&self, command: &str, targets: &[String]) -> Vec<String> {
+        let max_targets = self.max_targets(command);
+        let max_bytes = self
+            .line_length
+            .saturating_sub(command.len() + " \r\n".len());
+
+        let mut batches: Vec<(String, usize)> = Vec::new();
+        for target in targets {
+            match batches.last_mut() {
+                Some((batch, count))
+                    if *count < max_targets && batch.len() + 1 + target.len() <= max_bytes =>
+                {
+                    batch.push(',');
+                    batch.push_str(target);
+                    *count += 1;
+                }
+                _ => batches.push((target.clone(), 1)),
+            }
+        }
+
+        batches.into_iter().map(|(batch, _)| batch).collect()
+    }
+}
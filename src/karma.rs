@@ -0,0 +1,112 @@
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::isupport::ISupport;
+use crate::storage::KarmaStore;
+use crate::Error;
+
+/// Shorter things are more likely to be code, like `i++`, than someone's name.
+const MIN_NAME_LEN: usize = 2;
+
+/// Points handed out with `nick++` and taken away with `nick--` on one network. Changes are
+/// written through to the store, if there is one.
+pub struct Karma {
+    scope: String,
+    /// By lowercased name, with the name as it was first written.
+    scores: Mutex<HashMap<String, (String, i64)>>,
+    store: Option<Arc<dyn KarmaStore>>,
+}
+
+impl Karma {
+    pub async fn load(scope: &str, store: Option<Arc<dyn KarmaStore>>) -> Result<Self, Error> {
+        let scores = match &store {
+            Some(store) => store.karma(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            scores: Mutex::new(
+                scores
+                    .into_iter()
+                    .map(|(name, score)| (name.to_lowercase(), (name, score)))
+                    .collect(),
+            ),
+            store,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> i64 {
+        self.scores
+            .lock()
+            .expect("karma lock poisoned")
+            .get(&name.to_lowercase())
+            .map_or(0, |(_, score)| *score)
+    }
+
+    /// The `n` highest scores, highest first.
+    pub fn top(&self, n: usize) -> Vec<(String, i64)> {
+        let mut scores = self
+            .scores
+            .lock()
+            .expect("karma lock poisoned")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        scores.sort_by_key(|(_, score)| Reverse(*score));
+        scores.truncate(n);
+        scores
+    }
+
+    /// Applies every `name++` and `name--` in a channel message. Nobody gets to vote on
+    /// themselves.
+    pub async fn saw(&self, message: &Message, isupport: &ISupport) {
+        let (Some(nick), Command::PRIVMSG(target, msg)) =
+            (message.source_nickname(), &message.command)
+        else {
+            return;
+        };
+        if !isupport.is_channel(target) {
+            return;
+        }
+
+        for (name, delta) in votes(msg) {
+            if name.eq_ignore_ascii_case(nick) {
+                debug!("Ignoring {} voting on themselves", nick);
+                continue;
+            }
+            self.scores
+                .lock()
+                .expect("karma lock poisoned")
+                .entry(name.to_lowercase())
+                .or_insert_with(|| (name.to_string(), 0))
+                .1 += delta;
+
+            if let Some(store) = &self.store {
+                if let Err(e) = store.add_karma(&self.scope, name, delta).await {
+                    warn!("Unable to save karma for {}: {}", name, e);
+                }
+            }
+        }
+    }
+}
+
+/// Every `name++` and `name--` in `msg`.
+fn votes(msg: &str) -> impl Iterator<Item = (&str, i64)> {
+    msg.split_whitespace().filter_map(|word| {
+        let word = word.trim_end_matches([',', '.', '!', '?', ';', ':']);
+        let (name, delta) = match word.strip_suffix("++") {
+            Some(name) => (name, 1),
+            None => (word.strip_suffix("--")?, -1),
+        };
+        let name = name.trim_end_matches([',', ':']);
+        (name.chars().count() >= MIN_NAME_LEN && !name.ends_with(['+', '-']))
+            .then_some((name, delta))
+    })
+}
@@ -0,0 +1,207 @@
+//! Working out what language a message is in, so pickles can answer in it. Scripts other than
+//! Latin mostly give the language away; Latin ones are told apart by their common words.
+
+use std::cmp::Reverse;
+
+/// What pickles answers in unless there's reason not to.
+pub const ENGLISH: &str = "English";
+
+/// Common words in each language written in the Latin alphabet. Words shared between languages
+/// count for each of them, and the rest decide it.
+#[rustfmt::skip]
+const WORDS: &[(&str, &[&str])] = &[
+    (
+        ENGLISH,
+        &[
+            "the", "and", "is", "are", "you", "what", "this", "that", "with", "have", "it's",
+            "was", "for", "not", "how", "why", "does", "can", "i'm", "my", "your", "of", "it",
+            "hello", "thanks", "please", "there", "be",
+        ],
+    ),
+    (
+        "Spanish",
+        &[
+            "el", "los", "las", "que", "es", "y", "por", "para", "con", "una", "pero", "cómo",
+            "qué", "hola", "gracias", "estoy", "eres", "muy", "también", "está", "tengo", "dónde",
+            "porque", "del", "yo", "sí", "usted", "favor",
+        ],
+    ),
+    (
+        "French",
+        &[
+            "le", "les", "est", "et", "je", "tu", "vous", "pas", "une", "des", "avec", "pour",
+            "dans", "c'est", "bonjour", "merci", "oui", "qui", "mais", "suis", "très", "du", "au",
+            "nous", "ça", "j'ai", "quoi", "salut",
+        ],
+    ),
+    (
+        "German",
+        &[
+            "der", "die", "das", "und", "ist", "ich", "nicht", "du", "ein", "eine", "mit", "sie",
+            "wie", "was", "auch", "bitte", "danke", "hallo", "sind", "bin", "auf", "zu", "mir",
+            "dem", "den", "ja", "nein", "warum",
+        ],
+    ),
+    (
+        "Italian",
+        &[
+            "il", "che", "è", "di", "non", "sono", "per", "una", "gli", "della", "come", "ciao",
+            "grazie", "perché", "anche", "io", "mi", "sei", "questo", "molto", "cosa", "buongiorno",
+            "ho", "hai",
+        ],
+    ),
+    (
+        "Portuguese",
+        &[
+            "o", "os", "não", "é", "você", "uma", "com", "para", "que", "obrigado", "obrigada",
+            "olá", "está", "muito", "isso", "eu", "tudo", "bem", "como", "também", "das", "dos",
+            "do", "oi", "sim", "tenho",
+        ],
+    ),
+    (
+        "Dutch",
+        &[
+            "de", "het", "een", "is", "niet", "ik", "je", "van", "dat", "wat", "hoe", "met",
+            "voor", "ook", "maar", "zijn", "dank", "hallo", "jij", "bedankt", "heb", "goed", "nee",
+            "waarom",
+        ],
+    ),
+    (
+        "Swedish",
+        &[
+            "och", "är", "jag", "det", "inte", "att", "som", "på", "med", "du", "hej", "tack",
+            "vad", "hur", "för", "har", "vi", "mycket", "också", "varför", "nej", "bra",
+        ],
+    ),
+    (
+        "Polish",
+        &[
+            "nie", "jest", "się", "na", "że", "jak", "co", "czy", "dziękuję", "cześć", "ale", "tak",
+            "mam", "jestem", "dla", "bardzo", "ja", "dzień", "dobry", "dlaczego",
+        ],
+    ),
+    (
+        "Turkish",
+        &[
+            "ve", "bir", "bu", "ne", "nasıl", "değil", "için", "merhaba", "teşekkürler", "evet",
+            "hayır", "ben", "sen", "çok", "mi", "mı", "var", "yok", "neden", "iyi",
+        ],
+    ),
+    (
+        "Indonesian",
+        &[
+            "dan", "yang", "ini", "itu", "tidak", "saya", "apa", "dengan", "untuk", "terima",
+            "kasih", "ada", "bisa", "kamu", "aku", "sudah", "belum", "kenapa",
+        ],
+    ),
+];
+
+/// Letters only one of the Latin alphabet languages uses, worth as much as a word or two.
+const LETTERS: &[(&str, &str)] = &[
+    ("Spanish", "ñ¿¡"),
+    ("German", "ß"),
+    ("Portuguese", "ãõ"),
+    ("Turkish", "ığş"),
+    ("Polish", "łąężśźćń"),
+    ("French", "œ"),
+    ("Swedish", "å"),
+];
+
+/// The language `text` is written in, if it's clear enough to say.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let letters = text
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect::<Vec<_>>();
+    if letters.is_empty() {
+        return None;
+    }
+    let latin = letters
+        .iter()
+        .filter(|c| c.is_ascii_alphabetic() || is_latin(**c))
+        .count();
+    if latin * 2 < letters.len() {
+        return script(&letters);
+    }
+
+    let lower = text.to_lowercase();
+    // Links are in no language in particular
+    let words = lower
+        .split_whitespace()
+        .filter(|word| !word.contains("://") && !word.starts_with("www."))
+        .flat_map(|word| word.split(|c: char| !(c.is_alphabetic() || c == '\'')))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let mut scores = WORDS
+        .iter()
+        .map(|(language, common)| {
+            let words = words.iter().filter(|word| common.contains(word)).count();
+            let letters = LETTERS
+                .iter()
+                .filter(|(other, _)| other == language)
+                .map(|(_, letters)| lower.chars().filter(|c| letters.contains(*c)).count())
+                .sum::<usize>();
+            (*language, words + 2 * letters.min(1))
+        })
+        .collect::<Vec<_>>();
+    scores.sort_by_key(|&(_, score)| Reverse(score));
+
+    // A word or two is enough to go on in a short message, but only if nothing else matches
+    let (best, score) = scores[0];
+    let runner_up = scores[1].1;
+    let needed = if words.len() <= 3 { 1 } else { 2 };
+    (score >= needed && score > runner_up).then_some(best)
+}
+
+fn is_latin(c: char) -> bool {
+    matches!(c, '\u{00c0}'..='\u{024f}' | '\u{1e00}'..='\u{1eff}')
+}
+
+/// The language of text in some other script, going by its letters.
+fn script(letters: &[char]) -> Option<&'static str> {
+    let has = |range: &[(char, char)]| {
+        letters
+            .iter()
+            .any(|c| range.iter().any(|(from, to)| (*from..=*to).contains(c)))
+    };
+    let has_any = |chars: &str| letters.iter().any(|c| chars.contains(*c));
+
+    if has(&[('\u{3040}', '\u{30ff}')]) {
+        Some("Japanese")
+    } else if has(&[('\u{ac00}', '\u{d7af}'), ('\u{1100}', '\u{11ff}')]) {
+        Some("Korean")
+    } else if has(&[('\u{4e00}', '\u{9fff}'), ('\u{3400}', '\u{4dbf}')]) {
+        Some("Chinese")
+    } else if has(&[('\u{0400}', '\u{04ff}')]) {
+        match has_any("іїєґІЇЄҐ") {
+            true => Some("Ukrainian"),
+            false => Some("Russian"),
+        }
+    } else if has(&[('\u{0370}', '\u{03ff}')]) {
+        Some("Greek")
+    } else if has(&[('\u{0600}', '\u{06ff}')]) {
+        match has_any("پچژگ") {
+            true => Some("Persian"),
+            false => Some("Arabic"),
+        }
+    } else if has(&[('\u{0590}', '\u{05ff}')]) {
+        Some("Hebrew")
+    } else if has(&[('\u{0900}', '\u{097f}')]) {
+        Some("Hindi")
+    } else if has(&[('\u{0e00}', '\u{0e7f}')]) {
+        Some("Thai")
+    } else {
+        None
+    }
+}
+
+/// What language to tell the model to answer `msg` in: the one it's written in if `detect`
+/// is on and that's clear, or else `default`. `None` means English as usual.
+pub fn reply_in(msg: &str, detect_language: bool, default: Option<&str>) -> Option<String> {
+    let detected = detect_language.then(|| detect(msg)).flatten();
+    match (detected, default) {
+        (Some(ENGLISH), None) => None,
+        (Some(language), _) => Some(language.to_string()),
+        (None, default) => default.map(str::to_string),
+    }
+}
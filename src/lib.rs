@@ -0,0 +1,166 @@
+//! Just a silly IRC bot that answers with ChatGPT.
+//!
+//! The binary reads its config and hands it to [`irc_bot::start`], which is all it takes to
+//! embed pickles in something else.
+
+pub mod acl;
+pub mod admin;
+pub mod ambient;
+pub mod archive;
+pub mod calc;
+pub mod casemap;
+pub mod channels;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod ctcp;
+pub mod dictionary;
+pub mod export;
+pub mod fetch;
+pub mod filter;
+pub mod flood;
+pub mod format;
+pub mod gateway;
+pub mod greetings;
+pub mod guard;
+pub mod handlers;
+pub mod health;
+pub mod http;
+pub mod ignore;
+pub mod images;
+pub mod irc_bot;
+pub mod isupport;
+pub mod karma;
+pub mod language;
+pub mod llm;
+pub mod logging;
+pub mod loops;
+pub mod markov;
+pub mod memory;
+pub mod memos;
+pub mod metrics;
+pub mod moderation;
+pub mod nickserv;
+pub mod output;
+pub mod paste;
+pub mod persona;
+#[cfg(feature = "wasm")]
+pub mod plugins;
+pub mod prices;
+pub mod prompt;
+pub mod proxy;
+pub mod ratelimit;
+pub mod recall;
+pub mod reconnect;
+pub mod reminders;
+pub mod repl;
+pub mod reporting;
+pub mod sasl;
+pub mod schedule;
+#[cfg(feature = "scripting")]
+pub mod scripts;
+pub mod sed;
+pub mod seen;
+pub mod split;
+pub mod storage;
+pub mod systemd;
+pub mod tags;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod titles;
+pub mod tools;
+pub mod trivia;
+pub mod usage;
+pub mod weather;
+pub mod webhook;
+pub mod wiki;
+pub mod word_games;
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IRC error: {0}")]
+    Irc(#[from] irc::error::Error),
+
+    #[error("OpenAI error: {0}")]
+    OpenAI(#[from] async_openai::error::OpenAIError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Database migration error: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error("Gateway error: {0}")]
+    Gateway(String),
+
+    #[error("Redis error: {0}")]
+    Redis(String),
+
+    #[error("Export failed: {0}")]
+    Export(String),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("No answer after {0:?}")]
+    Timeout(Duration),
+
+    #[error("HTTP error: {status}")]
+    Status {
+        status: reqwest::StatusCode,
+        /// How long the server asked us to wait before trying again, if it said.
+        retry_after: Option<Duration>,
+    },
+
+    #[error("HTTP server error: {0}")]
+    HttpServer(io::Error),
+
+    #[error("REPL error: {0}")]
+    Repl(io::Error),
+
+    #[error("Paste failed: {0}")]
+    Paste(String),
+
+    #[error("Fetch failed: {0}")]
+    Fetch(String),
+
+    #[error("Image generation failed: {0}")]
+    Image(String),
+
+    #[error("Weather lookup failed: {0}")]
+    Weather(String),
+
+    #[error("Can't work that out: {0}")]
+    Calc(String),
+
+    #[error("No trivia questions: {0}")]
+    Trivia(String),
+
+    #[error("Markov chain error: {0}")]
+    Markov(String),
+
+    #[error("Channel log error: {0}")]
+    Archive(io::Error),
+
+    #[error("Plugin error: {0}")]
+    Plugin(String),
+
+    #[error("Proxy error: {0}")]
+    Proxy(String),
+
+    #[error("Disconnected from the server")]
+    Disconnected,
+
+    #[error("SASL authentication failed: {0}")]
+    Sasl(String),
+
+    #[error("Unable to read config {}: {source}", path.display())]
+    ConfigRead { path: PathBuf, source: io::Error },
+
+    #[error("Config error: {0}")]
+    Config(#[from] toml::de::Error),
+}
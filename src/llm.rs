@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+
+use tokio::sync::mpsc;
+
+use std::ops::AddAssign;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::tools::Tools;
+use crate::Error;
+
+pub mod keys;
+pub mod limit;
+pub mod openai;
+pub mod retry;
+pub mod tokens;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Role::System),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One turn of a conversation, independent of whichever backend ends up answering it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// Tokens spent on a completion, as reported by the server or counted ourselves when it
+/// doesn't say.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// The model's reply and what it cost.
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub content: String,
+    pub usage: Usage,
+}
+
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    /// Returns the model's reply to `history`, which starts with the system prompt.
+    async fn complete(&self, history: &[ChatMessage]) -> Result<Completion, Error>;
+
+    /// Like `complete()` but sends each line to `lines` as soon as it's ready. Backends that
+    /// can't stream send the whole reply at once when it's done.
+    async fn complete_streaming(
+        &self,
+        history: &[ChatMessage],
+        lines: &mpsc::UnboundedSender<String>,
+    ) -> Result<Completion, Error> {
+        let completion = self.complete(history).await?;
+        for line in completion.content.lines() {
+            let _ = lines.send(line.to_string());
+        }
+
+        Ok(completion)
+    }
+}
+
+/// OpenAI as configured, retrying what's worth retrying and capping how many requests are
+/// out at once.
+pub fn backend(config: &Config, http: &reqwest::Client) -> Arc<dyn ChatBackend> {
+    let openai = &config.openai;
+    Arc::new(retry::Retrying::new(
+        limit::Limited::new(
+            openai::OpenAI::new(openai.clone(), Tools::from_config(config)).with_http(http.clone()),
+            openai.max_concurrent_requests,
+        ),
+        openai.retry.clone(),
+    ))
+}
+
+/// Sends every complete line in `pending` to `lines`, leaving any partial line behind.
+fn flush_lines(pending: &mut String, lines: &mpsc::UnboundedSender<String>) {
+    while let Some(end) = pending.find('\n') {
+        let line = pending.drain(..=end).collect::<String>();
+        let _ = lines.send(line.trim_end().to_string());
+    }
+}
@@ -0,0 +1,123 @@
+use async_openai::error::OpenAIError;
+
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::config::OpenAIConfig;
+use crate::Error;
+
+/// How long a key that hit a rate limit sits out.
+const RATE_LIMITED: Duration = Duration::from_secs(60);
+
+/// How long a key that ran out of quota sits out. Quota comes back when someone pays the bill,
+/// so check now and then.
+const OUT_OF_QUOTA: Duration = Duration::from_secs(3600);
+
+/// API keys to spread requests over, in turn. A key that's rate limited or out of quota is
+/// benched for a while and then given another go.
+pub struct Keys {
+    keys: Vec<String>,
+    benched_until: Mutex<Vec<Option<Instant>>>,
+    next: AtomicUsize,
+}
+
+impl Keys {
+    pub fn new(config: &OpenAIConfig) -> Self {
+        let keys = config
+            .api_key
+            .iter()
+            .chain(config.api_keys.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Self {
+            benched_until: Mutex::new(vec![None; keys.len()]),
+            keys,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The next key that isn't benched, along with its index. When they all are, the one that's
+    /// due back soonest. `None` means no keys are configured and the client should fall back to
+    /// `OPENAI_API_KEY`.
+    pub fn pick(&self) -> Option<(usize, &str)> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let benched_until = self.benched_until.lock().expect("keys lock poisoned");
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = (0..self.keys.len())
+            .map(|offset| (start + offset) % self.keys.len())
+            .find(|&i| benched_until[i].is_none_or(|until| until <= now))
+            .unwrap_or_else(|| {
+                (0..self.keys.len())
+                    .min_by_key(|&i| benched_until[i])
+                    .expect("there is at least one key")
+            });
+
+        Some((index, &self.keys[index]))
+    }
+
+    /// Benches the key at `index` if `error` means it's saturated. Returns whether it was.
+    pub fn bench(&self, index: usize, error: &Error) -> bool {
+        let Some(duration) = saturated(error) else {
+            return false;
+        };
+
+        warn!(
+            "Benching API key {} of {} for {:?}: {}",
+            index + 1,
+            self.keys.len(),
+            duration,
+            error
+        );
+        self.benched_until.lock().expect("keys lock poisoned")[index] =
+            Some(Instant::now() + duration);
+
+        true
+    }
+}
+
+/// How long a key should sit out after failing with `error`, if the failure was the key's
+/// fault rather than the request's.
+fn saturated(error: &Error) -> Option<Duration> {
+    let error = match error {
+        Error::OpenAI(error) => error,
+        Error::Status {
+            status,
+            retry_after,
+        } if status.as_u16() == 429 => return Some(retry_after.unwrap_or(RATE_LIMITED)),
+        _ => return None,
+    };
+
+    match error {
+        OpenAIError::ApiError(e) => match e.r#type.as_deref().unwrap_or_default() {
+            "insufficient_quota" => Some(OUT_OF_QUOTA),
+            "requests" | "tokens" | "rate_limit_exceeded" => Some(RATE_LIMITED),
+            _ => None,
+        },
+        OpenAIError::Reqwest(e) => e
+            .status()
+            .filter(|status| status.as_u16() == 429)
+            .map(|_| RATE_LIMITED),
+        OpenAIError::StreamError(message) => {
+            message.contains("status code: 429").then_some(RATE_LIMITED)
+        }
+        _ => None,
+    }
+}
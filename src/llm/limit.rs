@@ -0,0 +1,41 @@
+use async_trait::async_trait;
+
+use tokio::sync::mpsc;
+use tokio::sync::Semaphore;
+
+use super::ChatBackend;
+use super::ChatMessage;
+use super::Completion;
+use crate::Error;
+
+/// Caps how many requests another backend works on at once, queueing the rest.
+pub struct Limited<B> {
+    inner: B,
+    permits: Semaphore,
+}
+
+impl<B: ChatBackend> Limited<B> {
+    pub fn new(inner: B, max_concurrent: usize) -> Self {
+        Self {
+            inner,
+            permits: Semaphore::new(max_concurrent.max(1)),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: ChatBackend> ChatBackend for Limited<B> {
+    async fn complete(&self, history: &[ChatMessage]) -> Result<Completion, Error> {
+        let _permit = self.permits.acquire().await.expect("semaphore closed");
+        self.inner.complete(history).await
+    }
+
+    async fn complete_streaming(
+        &self,
+        history: &[ChatMessage],
+        lines: &mpsc::UnboundedSender<String>,
+    ) -> Result<Completion, Error> {
+        let _permit = self.permits.acquire().await.expect("semaphore closed");
+        self.inner.complete_streaming(history, lines).await
+    }
+}
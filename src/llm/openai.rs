@@ -0,0 +1,518 @@
+use async_openai::config::Config;
+use async_openai::types::ChatCompletionFunctionsArgs;
+use async_openai::types::ChatCompletionRequestMessage;
+use async_openai::types::ChatCompletionRequestMessageArgs;
+use async_openai::types::CreateChatCompletionRequest;
+use async_openai::types::CreateChatCompletionRequestArgs;
+use async_openai::types::CreateChatCompletionResponse;
+use async_openai::types::FunctionCall;
+
+use async_trait::async_trait;
+
+use backoff::ExponentialBackoffBuilder;
+
+use futures::stream::StreamExt;
+
+use serde_json::json;
+
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use super::flush_lines;
+use super::keys::Keys;
+use super::retry;
+use super::tokens::TokenBudget;
+use super::ChatBackend;
+use super::ChatMessage;
+use super::Completion;
+use super::Role;
+use super::Usage;
+use crate::config::OpenAIConfig;
+use crate::fetch;
+use crate::fetch::Fetch;
+use crate::health::health;
+use crate::metrics::metrics;
+use crate::proxy;
+use crate::tools::Tools;
+use crate::Error;
+
+/// Only the first few images in a message are looked at.
+const MAX_IMAGES: usize = 2;
+
+/// One response from the API.
+struct Reply {
+    content: String,
+    call: Option<FunctionCall>,
+    /// Only reported for requests that weren't streamed.
+    usage: Option<Usage>,
+}
+
+pub struct OpenAI {
+    config: OpenAIConfig,
+    tokens: TokenBudget,
+    tools: Tools,
+    keys: Keys,
+    fetch: Fetch,
+    http: reqwest::Client,
+}
+
+impl OpenAI {
+    pub fn new(config: OpenAIConfig, tools: Tools) -> Self {
+        Self {
+            tokens: TokenBudget::new(&config),
+            keys: Keys::new(&config),
+            http: reqwest::Client::new(),
+            config,
+            tools,
+            fetch: Fetch::new(),
+        }
+    }
+
+    /// Sends requests through `http` rather than a client of its own, to share its
+    /// connections. Use `http_client()` for `[openai]`'s timeout and proxy.
+    pub fn with_http(self, http: reqwest::Client) -> Self {
+        Self { http, ..self }
+    }
+
+    /// Downloads images for the vision model with `fetch` rather than a `Fetch` of its own.
+    pub fn with_fetch(self, fetch: Fetch) -> Self {
+        Self { fetch, ..self }
+    }
+
+    /// Builds the request for `history`, along with how many tokens its prompt is.
+    fn request(
+        &self,
+        history: &[ChatMessage],
+    ) -> Result<(CreateChatCompletionRequest, usize), Error> {
+        let history = self.tokens.trim(history);
+        let prompt_tokens = history
+            .iter()
+            .map(|message| self.tokens.count(message))
+            .sum();
+        let messages = history
+            .into_iter()
+            .map(to_request_message)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .max_tokens(self.config.max_tokens)
+            .model(&self.config.model)
+            .messages(messages);
+        if let Some(temperature) = self.config.temperature {
+            request.temperature(temperature);
+        }
+        if self.config.tools && !self.tools.is_empty() {
+            let functions = self
+                .tools
+                .iter()
+                .map(|tool| {
+                    ChatCompletionFunctionsArgs::default()
+                        .name(tool.name())
+                        .description(tool.description())
+                        .parameters(tool.parameters())
+                        .build()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            request.functions(functions);
+        }
+
+        Ok((request.build()?, prompt_tokens))
+    }
+
+    fn record(&self, started: Instant, result: &Result<Completion, Error>) {
+        let metrics = metrics();
+        metrics
+            .completion_seconds
+            .observe(started.elapsed().as_secs_f64());
+        match result {
+            Ok(completion) => {
+                health().openai_succeeded();
+                metrics.openai_requests.with_label_values(&["ok"]).inc();
+                metrics
+                    .tokens
+                    .with_label_values(&["prompt"])
+                    .observe(completion.usage.prompt_tokens as f64);
+                metrics
+                    .tokens
+                    .with_label_values(&["completion"])
+                    .observe(completion.usage.completion_tokens as f64);
+            }
+            Err(_) => metrics.openai_requests.with_label_values(&["error"]).inc(),
+        }
+    }
+
+    /// Sends `request`, running whatever tools the model asks for and sending back their
+    /// results until it answers. After `max_tool_calls` rounds it has to answer without them.
+    async fn converse(
+        &self,
+        mut request: CreateChatCompletionRequest,
+        mut prompt_tokens: usize,
+        lines: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<Completion, Error> {
+        let images = self.images(&request).instrument(info_span!("images")).await;
+        let mut completion = Completion::default();
+        for round in 0..=self.config.max_tool_calls {
+            if round == self.config.max_tool_calls && request.functions.take().is_some() {
+                warn!(
+                    "Still calling tools after {} rounds, giving up on them",
+                    round
+                );
+            }
+
+            let span = info_span!(
+                "openai",
+                model = %request.model,
+                round,
+                first_token_ms = field::Empty,
+            );
+            let reply = self.send(&request, &images, lines).instrument(span).await?;
+            let count = |text: &str| self.tokens.count(&ChatMessage::assistant(text));
+            completion.usage += reply.usage.unwrap_or(Usage {
+                prompt_tokens: prompt_tokens as u64,
+                completion_tokens: count(&reply.content) as u64,
+            });
+            completion.content.push_str(&reply.content);
+            let Some(call) = reply.call else {
+                break;
+            };
+
+            let result = self
+                .tools
+                .call(&call.name, &call.arguments)
+                .instrument(info_span!("tool", name = %call.name))
+                .await;
+            // Every round sends everything again, plus the call and its result
+            prompt_tokens += count(&reply.content) + count(&call.arguments) + count(&result);
+            debug!("Tool {} said < {:?}", &call.name, &result);
+            request.messages.push(
+                ChatCompletionRequestMessageArgs::default()
+                    .role(async_openai::types::Role::Assistant)
+                    .content(reply.content)
+                    .function_call(call.clone())
+                    .build()?,
+            );
+            request.messages.push(
+                ChatCompletionRequestMessageArgs::default()
+                    .role(async_openai::types::Role::Function)
+                    .name(call.name)
+                    .content(result)
+                    .build()?,
+            );
+        }
+
+        Ok(completion)
+    }
+
+    /// Makes one request, moving on to the next key whenever the one it went out with turns
+    /// out to be saturated.
+    async fn send(
+        &self,
+        request: &CreateChatCompletionRequest,
+        images: &[String],
+        lines: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<Reply, Error> {
+        let mut tries = self.keys.len().max(1);
+        loop {
+            let picked = self.keys.pick();
+            let key = picked.map(|(_, key)| key);
+            let result = match lines {
+                _ if !images.is_empty() => {
+                    self.create_with_images(request, images, key, lines).await
+                }
+                Some(lines) => self.create_stream(request.clone(), key, lines).await,
+                None => self.create(request.clone(), key).await,
+            };
+
+            tries -= 1;
+            match (result, picked) {
+                (Err(e), Some((index, _))) if self.keys.bench(index, &e) && tries > 0 => continue,
+                (result, _) => return result,
+            }
+        }
+    }
+
+    async fn create(
+        &self,
+        request: CreateChatCompletionRequest,
+        key: Option<&str>,
+    ) -> Result<Reply, Error> {
+        let client = client(&self.config, &self.http, key);
+
+        debug!("Asking chatgpt > {:?}", &request);
+        let timeout = Duration::from_secs(self.config.request_timeout_secs);
+        let response = time::timeout(timeout, client.chat().create(request))
+            .await
+            .map_err(|_| Error::Timeout(timeout))??;
+
+        debug!("chatgpt said < {:?}", &response);
+        Ok(first_choice(response))
+    }
+
+    /// Downloads the images linked from the newest message, if there's a model that can see
+    /// them.
+    async fn images(&self, request: &CreateChatCompletionRequest) -> Vec<String> {
+        if self.config.vision_model.is_none() {
+            return Vec::new();
+        }
+        let newest = request
+            .messages
+            .last()
+            .filter(|message| message.role == async_openai::types::Role::User)
+            .and_then(|message| message.content.as_deref());
+        let Some(newest) = newest else {
+            return Vec::new();
+        };
+
+        let mut images = Vec::new();
+        for url in fetch::image_urls(newest).take(MAX_IMAGES) {
+            match self.fetch.image(url).await {
+                Ok(image) => images.push(image),
+                Err(e) => warn!("Unable to look at {}: {}", url, e),
+            }
+        }
+
+        images
+    }
+
+    /// async-openai can't send images, so requests with them are put together by hand and
+    /// sent to the vision model. They aren't streamed.
+    async fn create_with_images(
+        &self,
+        request: &CreateChatCompletionRequest,
+        images: &[String],
+        key: Option<&str>,
+        lines: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<Reply, Error> {
+        let mut body = serde_json::to_value(request).expect("requests should serialize");
+        if let Some(model) = &self.config.vision_model {
+            body["model"] = json!(model);
+        }
+        let newest = body["messages"].as_array_mut().and_then(|messages| {
+            messages
+                .iter_mut()
+                .rev()
+                .find(|message| message["role"] == "user")
+        });
+        if let Some(message) = newest {
+            let mut content = vec![json!({ "type": "text", "text": message["content"] })];
+            content.extend(
+                images
+                    .iter()
+                    .map(|url| json!({ "type": "image_url", "image_url": { "url": url } })),
+            );
+            message["content"] = json!(content);
+        }
+
+        debug!(
+            "Asking chatgpt with {} images > {:?}",
+            images.len(),
+            &request
+        );
+        let config = client_config(&self.config, key);
+        let response = self
+            .http
+            .post(config.url("/chat/completions"))
+            .headers(config.headers())
+            .json(&body)
+            .timeout(Duration::from_secs(self.config.request_timeout_secs))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Status {
+                status,
+                retry_after: retry::retry_after_header(response.headers()),
+            });
+        }
+        let response = response.json::<CreateChatCompletionResponse>().await?;
+
+        debug!("chatgpt said < {:?}", &response);
+        let reply = first_choice(response);
+        if let Some(lines) = lines {
+            for line in reply.content.lines() {
+                let _ = lines.send(line.to_string());
+            }
+        }
+
+        Ok(reply)
+    }
+
+    async fn create_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+        key: Option<&str>,
+        lines: &mpsc::UnboundedSender<String>,
+    ) -> Result<Reply, Error> {
+        let client = client(&self.config, &self.http, key);
+
+        debug!("Asking chatgpt > {:?}", &request);
+        let started = Instant::now();
+        let mut stream = client.chat().create_stream(request).await?;
+
+        let mut content = String::new();
+        let mut pending = String::new();
+        let mut call: Option<FunctionCall> = None;
+        while let Some(response) = stream.next().await.transpose()? {
+            let Some(choice) = response.choices.first() else {
+                continue;
+            };
+            if let Some(delta) = &choice.delta.content {
+                if content.is_empty() {
+                    Span::current().record("first_token_ms", started.elapsed().as_millis() as u64);
+                }
+                content.push_str(delta);
+                pending.push_str(delta);
+                flush_lines(&mut pending, lines);
+            }
+            // Function calls arrive in pieces like everything else
+            if let Some(delta) = &choice.delta.function_call {
+                let call = call.get_or_insert_with(|| FunctionCall {
+                    name: String::new(),
+                    arguments: String::new(),
+                });
+                call.name
+                    .push_str(delta.name.as_deref().unwrap_or_default());
+                call.arguments
+                    .push_str(delta.arguments.as_deref().unwrap_or_default());
+            }
+        }
+        if !pending.is_empty() {
+            let _ = lines.send(pending);
+        }
+
+        debug!("chatgpt said < {:?} {:?}", &content, &call);
+        Ok(Reply {
+            content,
+            call,
+            usage: None,
+        })
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OpenAI {
+    async fn complete(&self, history: &[ChatMessage]) -> Result<Completion, Error> {
+        let (request, prompt_tokens) = self.request(history)?;
+        let started = Instant::now();
+        let result = self.converse(request, prompt_tokens, None).await;
+        self.record(started, &result);
+
+        result
+    }
+
+    async fn complete_streaming(
+        &self,
+        history: &[ChatMessage],
+        lines: &mpsc::UnboundedSender<String>,
+    ) -> Result<Completion, Error> {
+        let (request, prompt_tokens) = self.request(history)?;
+        let started = Instant::now();
+        let result = self.converse(request, prompt_tokens, Some(lines)).await;
+        self.record(started, &result);
+
+        result
+    }
+}
+
+/// Connection settings using `key`, or the first configured key if that's `None`.
+fn client_config(config: &OpenAIConfig, key: Option<&str>) -> async_openai::config::OpenAIConfig {
+    let mut client_config = async_openai::config::OpenAIConfig::new();
+    if let Some(api_base) = &config.api_base {
+        client_config = client_config.with_api_base(api_base);
+    }
+    let key = key
+        .or(config.api_key.as_deref())
+        .or(config.api_keys.first().map(String::as_str));
+    if let Some(api_key) = key {
+        client_config = client_config.with_api_key(api_key);
+    }
+    if let Some(org_id) = &config.org_id {
+        client_config = client_config.with_org_id(org_id);
+    }
+
+    client_config
+}
+
+/// The HTTP client for everything pickles asks the API. Build it once and clone it, clones
+/// share its connection pool. There's no timeout for whole requests here, it would cut
+/// streamed answers off however well they were going; those that aren't streamed get
+/// `request_timeout_secs` each.
+pub fn http_client(config: &OpenAIConfig) -> Result<reqwest::Client, Error> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs));
+    if let Some(proxy) = &config.proxy {
+        let proxy = match proxy.split_once("://").map(|(scheme, _)| scheme) {
+            Some("http" | "https") => proxy.clone(),
+            Some("socks5" | "socks5h") => proxy::http_relay(proxy)?,
+            _ => {
+                return Err(Error::Proxy(String::from(
+                    "the API can only be reached through an http://, https://, socks5:// or \
+                     socks5h:// proxy",
+                )))
+            }
+        };
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| Error::Proxy(format!("invalid proxy: {}", e)))?;
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// A client for one request, sent through `http`.
+pub fn client(
+    config: &OpenAIConfig,
+    http: &reqwest::Client,
+    key: Option<&str>,
+) -> async_openai::Client<async_openai::config::OpenAIConfig> {
+    // Retries are handled by `Retrying` so they're capped and logged the same way for every
+    // backend.
+    let backoff = ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    async_openai::Client::with_config(client_config(config, key))
+        .with_http_client(http.clone())
+        .with_backoff(backoff)
+}
+
+/// The reply from the first choice in `response`.
+fn first_choice(response: CreateChatCompletionResponse) -> Reply {
+    let (content, call) = response
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| {
+            (
+                choice.message.content.unwrap_or_default(),
+                choice.message.function_call,
+            )
+        })
+        .unwrap_or_default();
+
+    Reply {
+        content,
+        call,
+        usage: response.usage.map(|usage| Usage {
+            prompt_tokens: u64::from(usage.prompt_tokens),
+            completion_tokens: u64::from(usage.completion_tokens),
+        }),
+    }
+}
+
+fn to_request_message(message: &ChatMessage) -> Result<ChatCompletionRequestMessage, Error> {
+    let role = match message.role {
+        Role::System => async_openai::types::Role::System,
+        Role::User => async_openai::types::Role::User,
+        Role::Assistant => async_openai::types::Role::Assistant,
+    };
+
+    Ok(ChatCompletionRequestMessageArgs::default()
+        .role(role)
+        .content(message.content.as_str())
+        .build()?)
+}
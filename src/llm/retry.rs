@@ -0,0 +1,201 @@
+use async_openai::error::OpenAIError;
+
+use async_trait::async_trait;
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use rand::Rng;
+
+use reqwest::header;
+use reqwest::header::HeaderMap;
+
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use super::ChatBackend;
+use super::ChatMessage;
+use super::Completion;
+use crate::config::RetryConfig;
+use crate::Error;
+
+/// Retries another backend with exponential backoff and jitter when it fails with something
+/// that's likely to go away on its own, like a 429 or a 5xx.
+pub struct Retrying<B> {
+    inner: B,
+    config: RetryConfig,
+}
+
+impl<B: ChatBackend> Retrying<B> {
+    pub fn new(inner: B, config: RetryConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// How long to wait before the given retry (counting from 1), unless the server told us.
+    fn delay(&self, retry: u32, hint: Option<Duration>) -> Duration {
+        let max = Duration::from_secs(self.config.max_delay_secs);
+        if let Some(hint) = hint {
+            return hint.min(max);
+        }
+
+        let base = Duration::from_millis(self.config.initial_delay_ms)
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(max);
+        // Full jitter over the top half so many clients don't retry in lockstep
+        base / 2 + base.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    }
+
+    async fn wait(&self, retry: u32, error: &Error) -> bool {
+        if retry >= self.config.max_attempts {
+            return false;
+        }
+        let Some(hint) = transient(error) else {
+            return false;
+        };
+
+        let delay = self.delay(retry, hint);
+        warn!(
+            "Attempt {} of {} failed, retrying in {:?}: {}",
+            retry, self.config.max_attempts, delay, error
+        );
+        time::sleep(delay).await;
+
+        true
+    }
+}
+
+#[async_trait]
+impl<B: ChatBackend> ChatBackend for Retrying<B> {
+    async fn complete(&self, history: &[ChatMessage]) -> Result<Completion, Error> {
+        let mut attempt = 1;
+        loop {
+            match self.inner.complete(history).await {
+                Err(e) if self.wait(attempt, &e).await => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    async fn complete_streaming(
+        &self,
+        history: &[ChatMessage],
+        lines: &mpsc::UnboundedSender<String>,
+    ) -> Result<Completion, Error> {
+        let mut attempt = 1;
+        loop {
+            // Only retry when nothing made it out yet, otherwise the channel would see the
+            // start of the response twice.
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let complete = async move {
+                let result = self.inner.complete_streaming(history, &tx).await;
+                drop(tx);
+                result
+            };
+            let forward = async {
+                let mut forwarded = 0;
+                while let Some(line) = rx.recv().await {
+                    let _ = lines.send(line);
+                    forwarded += 1;
+                }
+                forwarded
+            };
+
+            match tokio::join!(complete, forward) {
+                (Err(e), 0) if self.wait(attempt, &e).await => attempt += 1,
+                (result, _) => return result,
+            }
+        }
+    }
+}
+
+/// Returns `Some` when the error is worth retrying, along with how long the server asked us
+/// to wait if it said. Only requests we send ourselves, like those with images, get to see
+/// the Retry-After header; async-openai keeps the response to itself.
+fn transient(error: &Error) -> Option<Option<Duration>> {
+    let error = match error {
+        Error::OpenAI(error) => error,
+        Error::Http(e) => return unreachable(e).then_some(None),
+        Error::Timeout(_) => return Some(None),
+        Error::Status {
+            status,
+            retry_after,
+        } => {
+            let retryable = status.as_u16() == 429 || status.is_server_error();
+            return retryable.then_some(*retry_after);
+        }
+        _ => return None,
+    };
+
+    match error {
+        OpenAIError::Reqwest(e) => unreachable(e).then_some(None),
+        OpenAIError::ApiError(e) => {
+            let kind = e.r#type.as_deref().unwrap_or_default();
+            match kind {
+                "insufficient_quota" => None,
+                "requests" | "tokens" | "rate_limit_exceeded" | "server_error" => {
+                    Some(retry_after(&e.message))
+                }
+                _ => None,
+            }
+        }
+        OpenAIError::StreamError(message) => {
+            // The event stream only gives us the error as text, e.g. "Invalid status code: 429
+            // Too Many Requests"
+            match status_code(message) {
+                Some(status) if status == 429 || (500..600).contains(&status) => Some(None),
+                Some(_) => None,
+                None => Some(None),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether `e` is the network or a busy server letting us down, rather than the request.
+fn unreachable(e: &reqwest::Error) -> bool {
+    let retryable_status = e
+        .status()
+        .is_some_and(|status| status.as_u16() == 429 || status.is_server_error());
+    e.is_timeout() || e.is_connect() || retryable_status
+}
+
+/// How long a Retry-After header asks us to wait, given in seconds or as an HTTP date.
+pub fn retry_after_header(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+fn status_code(message: &str) -> Option<u16> {
+    let (_, rest) = message.split_once("status code: ")?;
+    rest.get(..3)?.parse().ok()
+}
+
+/// OpenAI puts its Retry-After hint in the message, e.g. "Please try again in 1.5s." or
+/// "Please try again in 20ms."
+fn retry_after(message: &str) -> Option<Duration> {
+    let (_, rest) = message.split_once("try again in ")?;
+    let amount = rest
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()?;
+    let unit = &rest[amount.len()..];
+    let amount = amount.parse::<f64>().ok()?;
+
+    if unit.starts_with("ms") {
+        Some(Duration::from_secs_f64(amount / 1000.0))
+    } else if unit.starts_with('s') {
+        Some(Duration::from_secs_f64(amount))
+    } else {
+        None
+    }
+}
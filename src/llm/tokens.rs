@@ -0,0 +1,70 @@
+use tiktoken_rs::CoreBPE;
+
+use tracing::*;
+
+use super::ChatMessage;
+use crate::config::OpenAIConfig;
+
+/// Role and separator overhead OpenAI adds to every message.
+const TOKENS_PER_MESSAGE: usize = 4;
+
+/// Context size to assume for models tiktoken doesn't know about.
+const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+
+/// Keeps requests inside the model's context window, leaving room for the reply.
+pub struct TokenBudget {
+    bpe: &'static CoreBPE,
+    budget: usize,
+}
+
+impl TokenBudget {
+    pub fn new(config: &OpenAIConfig) -> Self {
+        // Local models don't have a tiktoken encoding but cl100k is close enough to budget with
+        let bpe = tiktoken_rs::bpe_for_model(&config.model)
+            .unwrap_or_else(|_| tiktoken_rs::cl100k_base_singleton());
+        let context = config
+            .context_tokens
+            .or_else(|| tiktoken_rs::model::get_context_size(&config.model))
+            .unwrap_or(DEFAULT_CONTEXT_TOKENS);
+
+        Self {
+            bpe,
+            budget: context.saturating_sub(usize::from(config.max_tokens)),
+        }
+    }
+
+    pub fn count(&self, message: &ChatMessage) -> usize {
+        TOKENS_PER_MESSAGE + self.bpe.encode_with_special_tokens(&message.content).len()
+    }
+
+    /// Returns the system prompt at the start of `history` followed by as many of the most
+    /// recent turns as fit. The newest turn is always kept even if it's too big on its own.
+    pub fn trim<'a>(&self, history: &'a [ChatMessage]) -> Vec<&'a ChatMessage> {
+        let Some((system, turns)) = history.split_first() else {
+            return Vec::new();
+        };
+
+        let mut used = self.count(system);
+        let mut kept = turns
+            .iter()
+            .rev()
+            .enumerate()
+            .take_while(|(i, message)| {
+                used += self.count(message);
+                *i == 0 || used <= self.budget
+            })
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        if kept.len() < turns.len() {
+            debug!(
+                "Dropped {} old messages to fit in {} tokens",
+                turns.len() - kept.len(),
+                self.budget
+            );
+        }
+
+        kept.push(system);
+        kept.reverse();
+        kept
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::SecondsFormat;
+use chrono::Utc;
+
+use serde_json::Map;
+use serde_json::Value;
+
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::span;
+use tracing::Event;
+use tracing::Subscriber;
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::FmtContext;
+use tracing_subscriber::fmt::FormatEvent;
+use tracing_subscriber::fmt::FormatFields;
+use tracing_subscriber::fmt::FormattedFields;
+use tracing_subscriber::registry::LookupSpan;
+
+use std::fmt;
+
+/// Writes each event as one line of JSON, for log shippers like Loki or Elasticsearch. The
+/// fields of every span the event happened in are included alongside its own, so a message
+/// logged while answering someone carries their `network`, `channel` and `nick`.
+pub struct Json;
+
+impl<S> FormatEvent<S, JsonFields> for Json
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        fields.insert(
+            "timestamp".into(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .into(),
+        );
+        fields.insert("level".into(), metadata.level().as_str().into());
+        fields.insert("target".into(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            let mut spans = Vec::new();
+            for span in scope.from_root() {
+                spans.push(Value::from(span.name()));
+                let extensions = span.extensions();
+                let Some(recorded) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                if let Ok(Value::Object(span_fields)) = serde_json::from_str(&recorded.fields) {
+                    fields.extend(span_fields);
+                }
+            }
+            fields.insert("spans".into(), spans.into());
+        }
+        event.record(&mut Visitor(&mut fields));
+
+        writeln!(writer, "{}", Value::Object(fields))
+    }
+}
+
+/// Keeps span fields as a JSON object, for `Json` to pick up.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut recorded = Map::new();
+        fields.record(&mut Visitor(&mut recorded));
+
+        write!(writer, "{}", Value::Object(recorded))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &span::Record<'_>,
+    ) -> fmt::Result {
+        let mut recorded = match serde_json::from_str(&current.fields) {
+            Ok(Value::Object(recorded)) => recorded,
+            _ => Map::new(),
+        };
+        fields.record(&mut Visitor(&mut recorded));
+        current.fields = Value::Object(recorded).to_string();
+
+        Ok(())
+    }
+}
+
+/// Collects fields into a JSON object, keeping numbers and booleans as they are.
+pub(crate) struct Visitor<'a>(pub(crate) &'a mut Map<String, Value>);
+
+impl Visit for Visitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
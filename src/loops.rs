@@ -0,0 +1,77 @@
+use tokio::time::Duration;
+use tokio::time::Instant;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::config::LoopDetectionConfig;
+
+/// Keeps pickles from getting stuck talking to another bot forever. A nick that keeps asking
+/// in quick succession, faster and longer than people do, gets ignored for a while.
+pub struct LoopDetector {
+    config: LoopDetectionConfig,
+    chains: Mutex<HashMap<String, Chain>>,
+}
+
+/// Requests from one nick that each came soon after the one before.
+struct Chain {
+    last: Instant,
+    length: u32,
+    backoff_until: Option<Instant>,
+}
+
+impl LoopDetector {
+    pub fn new(config: &LoopDetectionConfig) -> Self {
+        Self {
+            config: config.clone(),
+            chains: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a request from `nick` and returns whether to answer it. `is_bot` is whether the
+    /// server told us they're a bot.
+    pub fn check(&self, nick: &str, is_bot: bool) -> bool {
+        if is_bot && self.config.ignore_bots {
+            debug!("Not answering {}, the server says they're a bot", nick);
+            return false;
+        }
+        if self.config.max_chain == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let gap = Duration::from_secs(self.config.chain_gap_secs);
+        let mut chains = self.chains.lock().expect("loop lock poisoned");
+        chains.retain(|_, chain| {
+            now - chain.last < gap || chain.backoff_until.is_some_and(|until| now < until)
+        });
+
+        let chain = chains.entry(nick.to_string()).or_insert(Chain {
+            last: now,
+            length: 0,
+            backoff_until: None,
+        });
+        if chain.backoff_until.is_some_and(|until| now < until) {
+            return false;
+        }
+        if now - chain.last >= gap {
+            chain.length = 0;
+        }
+        chain.last = now;
+        chain.length += 1;
+
+        if chain.length > self.config.max_chain {
+            warn!(
+                "{} asked {} times in a row, probably a bot, ignoring them for {}s",
+                nick, chain.length, self.config.backoff_secs
+            );
+            chain.length = 0;
+            chain.backoff_until = Some(now + Duration::from_secs(self.config.backoff_secs));
+            return false;
+        }
+
+        true
+    }
+}
@@ -0,0 +1,329 @@
+//! Something to say when the model can't be asked: a Markov chain of words learned from
+//! channel logs by `--train-markov`.
+
+use rand::Rng;
+
+use regex::Regex;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::RwLock;
+
+use crate::casemap::CaseMapping;
+use crate::config::Config;
+use crate::config::MarkovConfig;
+use crate::Error;
+
+/// `<nick> text`, with a timestamp or anything else before it and maybe a channel mode in
+/// front of the nick.
+static CHAT_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[^<]*<[ ~&@%+]*([^>\s]+)> (.*)$").expect("Invalid log line regex")
+});
+
+/// `bob: ` or `bob, ` at the start of a line, saying who it's for.
+static ADDRESSED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[^\s:,]+[:,]\s+").expect("Invalid addressed regex"));
+
+/// Longest reply made up, in words, in case the chain goes round in circles.
+const MAX_WORDS: usize = 40;
+
+/// Words this short say nothing about whether a reply fits the question.
+const MIN_TOPIC_LEN: usize = 4;
+
+/// What was learned from the logs, as written to `markov.model`.
+#[derive(Serialize, Deserialize)]
+pub struct Chain {
+    order: usize,
+    /// How many lines it learned from.
+    lines: usize,
+    /// The words seen after each run of `order` words, joined with spaces, and how often.
+    /// Empty words stand for the start and end of a line.
+    next: HashMap<String, Vec<(String, u32)>>,
+    /// The lines it learned from, hashed, so it can tell when it's only repeating one.
+    learned: HashSet<u64>,
+    /// Who opted out of being remembered, folded, so training again leaves them out too.
+    #[serde(default)]
+    forgotten: HashSet<String>,
+}
+
+impl Chain {
+    pub fn train(lines: impl IntoIterator<Item = impl AsRef<str>>, order: usize) -> Self {
+        let order = order.max(1);
+        let mut counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        let mut learned = HashSet::new();
+        for line in lines {
+            let words = line.as_ref().split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+            learned.insert(fingerprint(&words));
+
+            let mut state = vec![""; order];
+            for word in words.iter().copied().chain([""]) {
+                *counts
+                    .entry(state.join(" "))
+                    .or_default()
+                    .entry(word.to_string())
+                    .or_default() += 1;
+                state.remove(0);
+                state.push(word);
+            }
+        }
+
+        Self {
+            order,
+            lines: learned.len(),
+            next: counts
+                .into_iter()
+                .map(|(state, next)| (state, next.into_iter().collect()))
+                .collect(),
+            learned,
+            forgotten: HashSet::new(),
+        }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// A line made up by following the chain from the start of one until it reaches an end.
+    fn babble(&self, rng: &mut impl Rng) -> Option<Vec<&str>> {
+        let mut state = vec![""; self.order];
+        let mut words = Vec::new();
+        while words.len() < MAX_WORDS {
+            let next = self.next.get(&state.join(" "))?;
+            let total = next.iter().map(|(_, count)| count).sum::<u32>();
+            let mut pick = rng.gen_range(0..total);
+            let (word, _) = next.iter().find(|(_, count)| match pick < *count {
+                true => true,
+                false => {
+                    pick -= count;
+                    false
+                }
+            })?;
+            if word.is_empty() {
+                break;
+            }
+            words.push(word.as_str());
+            state.remove(0);
+            state.push(word);
+        }
+
+        Some(words)
+    }
+}
+
+/// The trained chain and how choosy to be about what it comes up with.
+pub struct Markov {
+    chain: RwLock<Chain>,
+    config: MarkovConfig,
+}
+
+impl Markov {
+    /// Reads the chain `--train-markov` wrote to `config.model`.
+    pub fn load(config: &MarkovConfig) -> Result<Self, Error> {
+        let json = fs::read_to_string(&config.model).map_err(|e| {
+            Error::Markov(format!("unable to read {}: {}", config.model.display(), e))
+        })?;
+        let chain: Chain = serde_json::from_str(&json).map_err(|e| {
+            Error::Markov(format!("{} isn't a chain: {}", config.model.display(), e))
+        })?;
+        if chain.lines < config.min_lines {
+            warn!(
+                "The Markov chain only learned {} lines, it needs {} to say anything",
+                chain.lines, config.min_lines
+            );
+        }
+
+        Ok(Self {
+            chain: RwLock::new(chain),
+            config: config.clone(),
+        })
+    }
+
+    /// Learns the logs over again without anything `nick` said, and writes the chain back so
+    /// they stay out of it, for when they opt out of being remembered. Reads every log, so
+    /// it's best kept off the runtime's threads.
+    pub fn forget(&self, config: &Config, nick: &str) -> Result<(), Error> {
+        let mut forgotten = self
+            .chain
+            .read()
+            .expect("chain lock poisoned")
+            .forgotten
+            .clone();
+        forgotten.insert(CaseMapping::default().fold(nick));
+        let (chain, _) = learn(config, &self.config, forgotten)?;
+        save(&chain, &self.config.model)?;
+        *self.chain.write().expect("chain lock poisoned") = chain;
+
+        Ok(())
+    }
+
+    /// Lets training learn from `nick` again, for when they opt back in. What they said
+    /// before stays out until the next `--train-markov`.
+    pub fn remember(&self, nick: &str) -> Result<(), Error> {
+        let mut chain = self.chain.write().expect("chain lock poisoned");
+        if chain.forgotten.remove(&CaseMapping::default().fold(nick)) {
+            save(&chain, &self.config.model)?;
+        }
+
+        Ok(())
+    }
+
+    /// Something to say to `question`, if the chain comes up with anything good enough: long
+    /// enough, not just a line it learned, and sharing the most words with the question.
+    pub fn reply(&self, question: &str) -> Option<String> {
+        let chain = self.chain.read().expect("chain lock poisoned");
+        if chain.lines < self.config.min_lines {
+            return None;
+        }
+        let topic = question
+            .split_whitespace()
+            .filter(|word| word.chars().count() >= MIN_TOPIC_LEN)
+            .map(str::to_lowercase)
+            .collect::<HashSet<_>>();
+        let mut rng = rand::thread_rng();
+
+        (0..self.config.attempts)
+            .filter_map(|_| chain.babble(&mut rng))
+            .filter(|words| words.len() >= self.config.min_words)
+            .filter(|words| !chain.learned.contains(&fingerprint(words)))
+            .max_by_key(|words| {
+                words
+                    .iter()
+                    .filter(|word| topic.contains(&word.to_lowercase()))
+                    .count()
+            })
+            .map(|words| words.join(" "))
+    }
+}
+
+/// FNV-1a of the words, lowercased, which unlike `DefaultHasher` stays the same from one
+/// build to the next.
+fn fingerprint(words: &[&str]) -> u64 {
+    words
+        .join(" ")
+        .to_lowercase()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Who said what in one line of a channel log, if it's someone talking rather than joining,
+/// leaving and so on.
+pub fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let [_, nick, text] = line.split('\t').collect::<Vec<_>>()[..] {
+        let nick = nick.trim_start_matches(['~', '&', '@', '%', '+']);
+        let is_nick = !nick.is_empty()
+            && nick
+                .chars()
+                .all(|c| c.is_alphanumeric() || "[]\\`_^{|}-".contains(c));
+        // WeeChat writes joins and parts as `-->` and `<--`
+        return (is_nick && !nick.starts_with('-')).then_some((nick, text));
+    }
+
+    let captures = CHAT_LINE.captures(line)?;
+    Some((captures.get(1)?.as_str(), captures.get(2)?.as_str()))
+}
+
+/// Trains a chain on the logs in `config.markov` and writes it where pickles will read it
+/// from. What pickles said itself and commands are left out, as is anyone the last chain
+/// forgot.
+pub fn train_from(config: &Config) -> Result<(), Error> {
+    let Some(markov) = &config.markov else {
+        return Err(Error::Markov(String::from("[markov] isn't configured")));
+    };
+
+    let forgotten = fs::read_to_string(&markov.model)
+        .ok()
+        .and_then(|json| serde_json::from_str::<Chain>(&json).ok())
+        .map(|chain| chain.forgotten)
+        .unwrap_or_default();
+    let (chain, logs) = learn(config, markov, forgotten)?;
+    save(&chain, &markov.model)?;
+    info!(
+        "Learned {} lines from {} logs into {}",
+        chain.lines,
+        logs,
+        markov.model.display()
+    );
+    if chain.lines < markov.min_lines {
+        warn!(
+            "That's fewer than min_lines = {}, so pickles won't use it",
+            markov.min_lines
+        );
+    }
+
+    Ok(())
+}
+
+/// A chain of the logs in `markov`, and how many logs there were, leaving out anything
+/// said by the `forgotten`.
+fn learn(
+    config: &Config,
+    markov: &MarkovConfig,
+    forgotten: HashSet<String>,
+) -> Result<(Chain, usize), Error> {
+    let mut files = Vec::new();
+    find_logs(&markov.logs, &mut files)?;
+    files.sort();
+
+    let mut lines = Vec::new();
+    for file in &files {
+        let bytes = fs::read(file)
+            .map_err(|e| Error::Markov(format!("unable to read {}: {}", file.display(), e)))?;
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            let Some((nick, text)) = parse_line(line) else {
+                continue;
+            };
+            let ours = config
+                .networks
+                .iter()
+                .any(|network| network.nickname.eq_ignore_ascii_case(nick));
+            let opted_out = forgotten.contains(&CaseMapping::default().fold(nick));
+            if ours || opted_out || text.starts_with(&config.command_prefix) || text.contains("://")
+            {
+                continue;
+            }
+            lines.push(ADDRESSED.replace(text, "").into_owned());
+        }
+    }
+
+    let mut chain = Chain::train(&lines, markov.order);
+    chain.forgotten = forgotten;
+
+    Ok((chain, files.len()))
+}
+
+fn save(chain: &Chain, model: &Path) -> Result<(), Error> {
+    let json = serde_json::to_string(chain).expect("Markov chain serializes");
+    fs::write(model, json)
+        .map_err(|e| Error::Markov(format!("unable to write {}: {}", model.display(), e)))
+}
+
+/// `path` if it's a file, or every file under it if it's a directory.
+fn find_logs(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(path)
+        .map_err(|e| Error::Markov(format!("unable to read {}: {}", path.display(), e)))?;
+    for entry in entries.flatten() {
+        find_logs(&entry.path(), files)?;
+    }
+
+    Ok(())
+}
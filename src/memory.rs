@@ -0,0 +1,424 @@
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::casemap::CaseMapping;
+use crate::export::Conversation;
+use crate::export::Message;
+use crate::export::Remembered;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+use crate::llm::Role;
+use crate::recall::Recall;
+use crate::recall::Recollection;
+use crate::storage::MemoryStore;
+use crate::tags::Tags;
+use crate::Error;
+
+pub const MAX_MEMORY: usize = 10;
+
+/// Storage scope for memory that's shared by every network.
+pub const SHARED_SCOPE: &str = "*";
+
+/// Marks memory kept by services account rather than by nick. Nicks can't contain a colon, so
+/// nobody can claim an account's memory by picking a nick.
+const ACCOUNT_PREFIX: &str = "account:";
+
+/// Messages left in memory after the rest have been folded into the summary: the last two
+/// exchanges.
+const KEPT_AFTER_SUMMARY: usize = 4;
+
+const SUMMARY_PROMPT: &str = "You keep a running summary of a conversation between you and \
+    someone on IRC. Update the summary with the new messages you're given. Keep names, facts, \
+    preferences and anything promised, drop small talk, and write at most a short paragraph. \
+    Reply with nothing but the updated summary.";
+
+/// Who a conversation is remembered as: the services account of whoever is logged in to one,
+/// so it follows them across nick changes and nobody else using their nick gets to see it, or
+/// else their nick, folded so that `Pickles` and `pickles` share a conversation.
+pub fn identity(nick: &str, tags: &Tags, casemapping: CaseMapping) -> String {
+    match tags.account() {
+        Some(account) => format!("{}{}", ACCOUNT_PREFIX, account.to_lowercase()),
+        None => casemapping.fold(nick),
+    }
+}
+
+/// What to call whoever's remembered as `identity` in transcripts.
+pub fn name(identity: &str) -> &str {
+    identity.strip_prefix(ACCOUNT_PREFIX).unwrap_or(identity)
+}
+
+/// Recent conversation with each `identity()`, and with `recall` every exchange further back too. With
+/// `summarize` whatever scrolls out of recent memory is condensed into a running summary. Lookups are served from memory and every change is written through to the store, if there
+/// is one, so nothing is lost on restart.
+pub struct Memory {
+    /// Keeps separate networks apart in a shared store.
+    scope: String,
+    cache: Mutex<HashMap<String, VecDeque<ChatMessage>>>,
+    recall: Option<Recall>,
+    recollections: Mutex<HashMap<String, Vec<Recollection>>>,
+    summarize: bool,
+    summaries: Mutex<HashMap<String, String>>,
+    /// Who asked with `!optout` for nothing they say to be kept.
+    opted_out: Mutex<HashSet<String>>,
+    store: Option<Arc<dyn MemoryStore>>,
+}
+
+impl Memory {
+    pub async fn load(
+        scope: &str,
+        store: Option<Arc<dyn MemoryStore>>,
+        recall: Option<Recall>,
+        summarize: bool,
+    ) -> Result<Self, Error> {
+        let cache = match &store {
+            Some(store) => store.load(scope).await?,
+            None => HashMap::new(),
+        };
+        if !cache.is_empty() {
+            info!("Remembered conversations with {} nicks", cache.len());
+        }
+        let recollections = match (&store, &recall) {
+            (Some(store), Some(_)) => store.recollections(scope).await?,
+            _ => HashMap::new(),
+        };
+        let summaries = match (&store, summarize) {
+            (Some(store), true) => store.summaries(scope).await?,
+            _ => HashMap::new(),
+        };
+        let opted_out = match &store {
+            Some(store) => store.opt_outs(scope).await?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            cache: Mutex::new(cache),
+            recall,
+            recollections: Mutex::new(recollections),
+            summarize,
+            summaries: Mutex::new(summaries),
+            opted_out: Mutex::new(opted_out),
+            store,
+        })
+    }
+
+    pub fn history(&self, nick: &str) -> Option<VecDeque<ChatMessage>> {
+        self.cache
+            .lock()
+            .expect("memory lock poisoned")
+            .get(nick)
+            .cloned()
+    }
+
+    pub async fn remember(&self, nick: &str, message: ChatMessage) {
+        if self.is_opted_out(nick) {
+            return;
+        }
+        {
+            let mut memory = self.cache.lock().expect("memory lock poisoned");
+            let history = memory.entry(nick.to_string()).or_default();
+            if history.len() > MAX_MEMORY {
+                history.pop_front();
+            }
+            history.push_back(message.clone());
+        }
+
+        if let Some(store) = &self.store {
+            let result = async {
+                store.push(&self.scope, nick, &message).await?;
+                store.truncate(&self.scope, nick, MAX_MEMORY + 1).await
+            };
+            if let Err(e) = result.await {
+                warn!("Unable to save memory for {}: {}", nick, e);
+            }
+        }
+    }
+
+    /// What's been said to `nick` before the messages still in memory, in a nutshell.
+    pub fn summary(&self, nick: &str) -> Option<String> {
+        self.summaries
+            .lock()
+            .expect("summary lock poisoned")
+            .get(nick)
+            .cloned()
+    }
+
+    /// Once the conversation with `nick` fills memory, has the model fold all but the last
+    /// couple of exchanges into the running summary so they aren't simply forgotten.
+    pub async fn summarize(&self, nick: &str, backend: &dyn ChatBackend) {
+        if !self.summarize {
+            return;
+        }
+        let oldest = {
+            let memory = self.cache.lock().expect("memory lock poisoned");
+            match memory.get(nick) {
+                Some(history) if history.len() >= MAX_MEMORY => history
+                    .range(..history.len() - KEPT_AFTER_SUMMARY)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                _ => return,
+            }
+        };
+
+        let transcript = oldest
+            .iter()
+            .map(|message| match message.role {
+                Role::User => format!("{}: {}", name(nick), message.content),
+                _ => format!("you: {}", message.content),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let request = [
+            ChatMessage::system(SUMMARY_PROMPT),
+            ChatMessage::user(format!(
+                "Summary so far:\n{}\n\nNew messages:\n{}",
+                self.summary(nick).as_deref().unwrap_or("(nothing yet)"),
+                transcript
+            )),
+        ];
+        let summary = match backend.complete(&request).await {
+            Ok(completion) if !completion.content.is_empty() => completion.content,
+            Ok(_) => return,
+            Err(e) => {
+                warn!("Unable to summarize conversation with {}: {}", nick, e);
+                return;
+            }
+        };
+        debug!("Summarized {} messages with {}", oldest.len(), nick);
+
+        let kept = {
+            let mut memory = self.cache.lock().expect("memory lock poisoned");
+            let Some(history) = memory.get_mut(nick) else {
+                // Forgotten while we were summarizing
+                return;
+            };
+            // Only drop what was summarized, in case something fell off the front meanwhile
+            let summarized = history
+                .iter()
+                .zip(&oldest)
+                .take_while(|(message, old)| message == old)
+                .count();
+            history.drain(..summarized);
+            self.summaries
+                .lock()
+                .expect("summary lock poisoned")
+                .insert(nick.to_string(), summary.clone());
+            history.len()
+        };
+
+        if let Some(store) = &self.store {
+            let result = async {
+                store.set_summary(&self.scope, nick, &summary).await?;
+                store.truncate(&self.scope, nick, kept).await
+            };
+            if let Err(e) = result.await {
+                warn!("Unable to save summary for {}: {}", nick, e);
+            }
+        }
+    }
+
+    /// Past exchanges with `nick` that have something to do with `question`, beyond the ones
+    /// still in recent memory.
+    pub async fn recall(&self, nick: &str, question: &str) -> Vec<String> {
+        let Some(recall) = &self.recall else {
+            return Vec::new();
+        };
+        if !self
+            .recollections
+            .lock()
+            .expect("recall lock poisoned")
+            .contains_key(nick)
+        {
+            return Vec::new();
+        }
+
+        let query = match recall.embed(question).await {
+            Ok(query) => query,
+            Err(e) => {
+                warn!("Unable to recall anything for {}: {}", nick, e);
+                return Vec::new();
+            }
+        };
+        let recollections = self.recollections.lock().expect("recall lock poisoned");
+        let Some(recollections) = recollections.get(nick) else {
+            return Vec::new();
+        };
+
+        recall
+            .best(&query, recollections, MAX_MEMORY / 2)
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Keeps a finished exchange for long term recall.
+    pub async fn remember_exchange(&self, nick: &str, question: &str, answer: &str) {
+        let Some(recall) = &self.recall else {
+            return;
+        };
+        if self.is_opted_out(nick) {
+            return;
+        }
+
+        let content = format!("{}: {}\nyou: {}", name(nick), question, answer);
+        let recollection = match recall.embed(&content).await {
+            Ok(embedding) => Recollection { content, embedding },
+            Err(e) => {
+                warn!("Unable to keep exchange with {} for recall: {}", nick, e);
+                return;
+            }
+        };
+        {
+            let mut recollections = self.recollections.lock().expect("recall lock poisoned");
+            let kept = recollections.entry(nick.to_string()).or_default();
+            kept.push(recollection.clone());
+            let excess = kept.len().saturating_sub(recall.config.max_per_nick);
+            kept.drain(..excess);
+        }
+
+        if let Some(store) = &self.store {
+            let result = async {
+                store
+                    .add_recollection(&self.scope, nick, &recollection)
+                    .await?;
+                store
+                    .truncate_recollections(&self.scope, nick, recall.config.max_per_nick)
+                    .await
+            };
+            if let Err(e) = result.await {
+                warn!("Unable to save recollection for {}: {}", nick, e);
+            }
+        }
+    }
+
+    pub fn is_opted_out(&self, nick: &str) -> bool {
+        self.opted_out
+            .lock()
+            .expect("opt out lock poisoned")
+            .contains(nick)
+    }
+
+    /// Stops remembering anything `nick` says and forgets what was already remembered.
+    /// Returns whether they weren't opted out already.
+    pub async fn opt_out(&self, nick: &str) -> bool {
+        let opted_out = self
+            .opted_out
+            .lock()
+            .expect("opt out lock poisoned")
+            .insert(nick.to_string());
+        self.forget(nick).await;
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.opt_out(&self.scope, nick).await {
+                warn!("Unable to save opt out for {}: {}", nick, e);
+            }
+        }
+
+        opted_out
+    }
+
+    /// Goes back to remembering what `nick` says. Returns whether they were opted out.
+    pub async fn opt_in(&self, nick: &str) -> bool {
+        let opted_in = self
+            .opted_out
+            .lock()
+            .expect("opt out lock poisoned")
+            .remove(nick);
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.opt_in(&self.scope, nick).await {
+                warn!("Unable to save opt in for {}: {}", nick, e);
+            }
+        }
+
+        opted_in
+    }
+
+    /// Everything remembered about `nick`, for them to take away, if there's anything.
+    pub fn export(&self, nick: &str) -> Option<Conversation> {
+        let conversation = Conversation {
+            messages: self
+                .history(nick)
+                .unwrap_or_default()
+                .iter()
+                .map(Message::from)
+                .collect(),
+            summary: self.summary(nick),
+            recollections: self
+                .recollections
+                .lock()
+                .expect("recall lock poisoned")
+                .get(nick)
+                .into_iter()
+                .flatten()
+                .map(|recollection| Remembered {
+                    content: recollection.content.clone(),
+                    embedding: Vec::new(),
+                })
+                .collect(),
+            opted_out: false,
+        };
+        let empty = conversation.messages.is_empty()
+            && conversation.summary.is_none()
+            && conversation.recollections.is_empty();
+
+        (!empty).then_some(conversation)
+    }
+
+    /// Drops everything remembered about `nick`. Returns whether there was anything to forget.
+    pub async fn forget(&self, nick: &str) -> bool {
+        let forgotten = self
+            .cache
+            .lock()
+            .expect("memory lock poisoned")
+            .remove(nick)
+            .is_some();
+        self.recollections
+            .lock()
+            .expect("recall lock poisoned")
+            .remove(nick);
+        self.summaries
+            .lock()
+            .expect("summary lock poisoned")
+            .remove(nick);
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.forget(&self.scope, nick).await {
+                warn!("Unable to forget {}: {}", nick, e);
+            }
+        }
+
+        forgotten
+    }
+
+    /// Drops every conversation. Returns how many nicks were forgotten.
+    pub async fn forget_all(&self) -> usize {
+        let forgotten = {
+            let mut memory = self.cache.lock().expect("memory lock poisoned");
+            let forgotten = memory.len();
+            memory.clear();
+            forgotten
+        };
+        self.recollections
+            .lock()
+            .expect("recall lock poisoned")
+            .clear();
+        self.summaries
+            .lock()
+            .expect("summary lock poisoned")
+            .clear();
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.forget_all(&self.scope).await {
+                warn!("Unable to forget everyone: {}", e);
+            }
+        }
+
+        forgotten
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::DateTime;
+use chrono::Utc;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::seen::ago;
+use crate::storage::MemoStore;
+use crate::Error;
+
+/// How many memos can wait for one nick, so nobody gets buried when they come back.
+pub const MAX_MEMOS: usize = 10;
+
+/// A message left with `!tell`.
+#[derive(Debug, Clone)]
+pub struct Memo {
+    pub from: String,
+    pub to: String,
+    pub at: DateTime<Utc>,
+    pub text: String,
+}
+
+impl Memo {
+    /// How it's read out to its recipient.
+    pub fn delivery(&self) -> String {
+        format!(
+            "{}: {} asked me to tell you {}: {}",
+            self.to,
+            self.from,
+            ago(Utc::now() - self.at),
+            self.text
+        )
+    }
+}
+
+/// Memos waiting for people on one network to show up. Changes are written through to the
+/// store, if there is one.
+pub struct Memos {
+    scope: String,
+    pending: Mutex<HashMap<String, Vec<Memo>>>,
+    store: Option<Arc<dyn MemoStore>>,
+}
+
+impl Memos {
+    pub async fn load(scope: &str, store: Option<Arc<dyn MemoStore>>) -> Result<Self, Error> {
+        let memos = match &store {
+            Some(store) => store.memos(scope).await?,
+            None => Vec::new(),
+        };
+        let mut pending: HashMap<String, Vec<Memo>> = HashMap::new();
+        for memo in memos {
+            pending
+                .entry(memo.to.to_lowercase())
+                .or_default()
+                .push(memo);
+        }
+
+        Ok(Self {
+            scope: scope.to_string(),
+            pending: Mutex::new(pending),
+            store,
+        })
+    }
+
+    /// Returns false if `memo.to` already has `MAX_MEMOS` waiting.
+    pub async fn leave(&self, memo: Memo) -> bool {
+        {
+            let mut pending = self.pending.lock().expect("memo lock poisoned");
+            let waiting = pending.entry(memo.to.to_lowercase()).or_default();
+            if waiting.len() >= MAX_MEMOS {
+                return false;
+            }
+            waiting.push(memo.clone());
+        }
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.add_memo(&self.scope, &memo).await {
+                warn!("Unable to save memo for {}: {}", memo.to, e);
+            }
+        }
+
+        true
+    }
+
+    /// Hands over everything waiting for `nick`, oldest first, and forgets it.
+    pub async fn deliver(&self, nick: &str) -> Vec<Memo> {
+        let memos = self
+            .pending
+            .lock()
+            .expect("memo lock poisoned")
+            .remove(&nick.to_lowercase())
+            .unwrap_or_default();
+        if memos.is_empty() {
+            return memos;
+        }
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.clear_memos(&self.scope, nick).await {
+                warn!("Unable to clear delivered memos for {}: {}", nick, e);
+            }
+        }
+
+        memos
+    }
+}
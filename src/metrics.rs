@@ -0,0 +1,159 @@
+use prometheus::core::Collector;
+use prometheus::proto::MetricFamily;
+use prometheus::Encoder;
+use prometheus::Histogram;
+use prometheus::HistogramOpts;
+use prometheus::HistogramVec;
+use prometheus::IntCounterVec;
+use prometheus::IntGaugeVec;
+use prometheus::Opts;
+use prometheus::Registry;
+use prometheus::TextEncoder;
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Everything pickles counts, served at `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    /// By network.
+    pub messages_received: IntCounterVec,
+    /// By network.
+    pub messages_sent: IntCounterVec,
+    /// By outcome, `ok` or `error`.
+    pub openai_requests: IntCounterVec,
+    /// By what failed, e.g. `connection` or `response`.
+    pub errors: IntCounterVec,
+    /// By network.
+    pub reconnects: IntCounterVec,
+    /// By network, reset once a connection registers.
+    pub connect_failures: IntGaugeVec,
+    /// By network, each time `alert_after` more connections in a row fail.
+    pub connect_alerts: IntCounterVec,
+    pub completion_seconds: Histogram,
+    /// By `prompt` or `completion`.
+    pub tokens: HistogramVec,
+    /// By network, channel (or `private`) and `prompt` or `completion`.
+    pub usage_tokens: IntCounterVec,
+}
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let counter = |name: &str, help: &str, label: &str| {
+            IntCounterVec::new(Opts::new(name, help).namespace("pickles"), &[label])
+                .expect("invalid counter")
+        };
+        let metrics = Self {
+            registry: Registry::new(),
+            messages_received: counter(
+                "messages_received_total",
+                "IRC messages received",
+                "network",
+            ),
+            messages_sent: counter("messages_sent_total", "IRC messages sent", "network"),
+            openai_requests: counter("openai_requests_total", "OpenAI requests made", "outcome"),
+            errors: counter("errors_total", "Errors by what failed", "kind"),
+            reconnects: counter("reconnects_total", "Reconnects to IRC", "network"),
+            connect_failures: IntGaugeVec::new(
+                Opts::new(
+                    "connect_failures",
+                    "Connections in a row that failed before registering",
+                )
+                .namespace("pickles"),
+                &["network"],
+            )
+            .expect("invalid gauge"),
+            connect_alerts: counter(
+                "connect_alerts_total",
+                "Times a network kept failing to connect",
+                "network",
+            ),
+            completion_seconds: Histogram::with_opts(
+                HistogramOpts::new("completion_seconds", "Time taken by OpenAI requests")
+                    .namespace("pickles")
+                    .buckets(vec![0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0, 80.0]),
+            )
+            .expect("invalid histogram"),
+            tokens: HistogramVec::new(
+                HistogramOpts::new("tokens", "Tokens used per OpenAI request")
+                    .namespace("pickles")
+                    .buckets(prometheus::exponential_buckets(16.0, 2.0, 10).expect("bad buckets")),
+                &["kind"],
+            )
+            .expect("invalid histogram"),
+            usage_tokens: IntCounterVec::new(
+                Opts::new("usage_tokens_total", "Tokens spent").namespace("pickles"),
+                &["network", "channel", "kind"],
+            )
+            .expect("invalid counter"),
+        };
+
+        let collectors: [Box<dyn prometheus::core::Collector>; 10] = [
+            Box::new(metrics.messages_received.clone()),
+            Box::new(metrics.messages_sent.clone()),
+            Box::new(metrics.openai_requests.clone()),
+            Box::new(metrics.errors.clone()),
+            Box::new(metrics.reconnects.clone()),
+            Box::new(metrics.connect_failures.clone()),
+            Box::new(metrics.connect_alerts.clone()),
+            Box::new(metrics.completion_seconds.clone()),
+            Box::new(metrics.tokens.clone()),
+            Box::new(metrics.usage_tokens.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("metric registered twice");
+        }
+
+        metrics
+    }
+
+    /// The average time taken by OpenAI requests so far, if there were any.
+    pub fn average_completion_seconds(&self) -> Option<f64> {
+        let count = self.completion_seconds.get_sample_count();
+        (count > 0).then(|| self.completion_seconds.get_sample_sum() / count as f64)
+    }
+
+    /// Everything as it stands right now.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.gather(), &mut buffer)
+            .expect("unable to encode metrics");
+
+        String::from_utf8(buffer).expect("metrics aren't UTF-8")
+    }
+}
+
+/// What `counter` has counted so far, by the value of its `label` and summed over any others.
+pub fn by_label(counter: &IntCounterVec, label: &str) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for metric in counter
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+    {
+        let value = metric
+            .get_label()
+            .iter()
+            .find(|pair| pair.get_name() == label)
+            .map(|pair| pair.get_value().to_string())
+            .unwrap_or_default();
+        *counts.entry(value).or_default() += metric.get_counter().get_value() as u64;
+    }
+
+    counts
+}
@@ -0,0 +1,39 @@
+use async_openai::types::CreateModerationRequestArgs;
+
+use tracing::*;
+
+use crate::config::OpenAIConfig;
+use crate::llm::openai;
+use crate::Error;
+
+/// Checks text against OpenAI's moderation endpoint, for channels that need to stay family
+/// friendly.
+#[derive(Clone)]
+pub struct Moderation {
+    openai: OpenAIConfig,
+    http: reqwest::Client,
+}
+
+impl Moderation {
+    pub fn new(openai: &OpenAIConfig, http: &reqwest::Client) -> Self {
+        Self {
+            openai: openai.clone(),
+            http: http.clone(),
+        }
+    }
+
+    pub async fn flagged(&self, text: &str) -> Result<bool, Error> {
+        let request = CreateModerationRequestArgs::default().input(text).build()?;
+        let response = openai::client(&self.openai, &self.http, None)
+            .moderations()
+            .create(request)
+            .await?;
+
+        let flagged = response.results.iter().any(|result| result.flagged);
+        if flagged {
+            debug!("Moderation flagged {:?}: {:?}", text, response.results);
+        }
+
+        Ok(flagged)
+    }
+}
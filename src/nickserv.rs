@@ -0,0 +1,138 @@
+use irc::client::prelude::*;
+
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use crate::config::NetworkConfig;
+use crate::config::NickServConfig;
+use crate::flood::Throttle;
+use crate::Error;
+
+/// How often to check whether our primary nick has become free again.
+pub const RECLAIM_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Don't GHOST more often than this while waiting for the old connection to go away.
+const GHOST_INTERVAL: Duration = Duration::from_secs(300);
+
+const SERVICE: &str = "NickServ";
+
+/// Tracks the nick we actually have on the server, identifies to NickServ, and wins the
+/// primary nick back when we had to settle for an alternate.
+pub struct NickServ {
+    primary: String,
+    current: String,
+    config: Option<NickServConfig>,
+    ghosted_at: Option<Instant>,
+}
+
+impl NickServ {
+    pub fn new(network: &NetworkConfig) -> Self {
+        Self {
+            primary: network.nickname.clone(),
+            current: network.nickname.clone(),
+            config: network.nickserv.clone(),
+            ghosted_at: None,
+        }
+    }
+
+    /// Our nick as the server sees it, which may be an alternate.
+    pub fn current_nickname(&self) -> &str {
+        &self.current
+    }
+
+    pub fn handle(&mut self, out: &Throttle, message: &Message) -> Result<(), Error> {
+        match &message.command {
+            Command::Response(Response::RPL_WELCOME, args) => {
+                if let Some(nick) = args.first() {
+                    self.current = nick.clone();
+                }
+            }
+            Command::Response(Response::RPL_ENDOFMOTD, _)
+            | Command::Response(Response::ERR_NOMOTD, _) => {
+                if self.current == self.primary {
+                    self.identify(out)?;
+                } else {
+                    warn!(
+                        "{} is in use, connected as {} instead",
+                        self.primary, self.current
+                    );
+                    self.tick(out)?;
+                }
+            }
+            Command::Response(Response::RPL_ISON, args) => {
+                let online = args.last().map_or("", String::as_str);
+                if self.current != self.primary {
+                    if online
+                        .split_whitespace()
+                        .any(|nick| nick.eq_ignore_ascii_case(&self.primary))
+                    {
+                        self.ghost(out)?;
+                    } else {
+                        info!("{} is free, reclaiming it", self.primary);
+                        out.send(Command::NICK(self.primary.clone()))?;
+                    }
+                }
+            }
+            Command::NICK(new_nick) if message.source_nickname() == Some(self.current.as_str()) => {
+                debug!("Nick changed from {} to {}", self.current, new_nick);
+                self.current = new_nick.clone();
+                if self.current == self.primary {
+                    info!("Reclaimed {}", self.primary);
+                    self.ghosted_at = None;
+                    self.identify(out)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Called every `RECLAIM_INTERVAL`. Asks the server whether our primary nick is still
+    /// taken; the answer is handled in `handle()`.
+    pub fn tick(&mut self, out: &Throttle) -> Result<(), Error> {
+        if self.current != self.primary {
+            out.send(Command::ISON(vec![self.primary.clone()]))?;
+        }
+
+        Ok(())
+    }
+
+    fn identify(&self, out: &Throttle) -> Result<(), Error> {
+        if let Some(config) = &self.config {
+            info!("Identifying to {}", SERVICE);
+            out.send_privmsg(SERVICE, &format!("IDENTIFY {}", config.password))?;
+        }
+
+        Ok(())
+    }
+
+    fn ghost(&mut self, out: &Throttle) -> Result<(), Error> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        if !config.ghost
+            || self
+                .ghosted_at
+                .is_some_and(|at| at.elapsed() < GHOST_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        info!(
+            "Asking {} to {} {}",
+            SERVICE, config.ghost_command, self.primary
+        );
+        out.send_privmsg(
+            SERVICE,
+            &format!(
+                "{} {} {}",
+                config.ghost_command, self.primary, config.password
+            ),
+        )?;
+        self.ghosted_at = Some(Instant::now());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! Getting responses out to IRC without flooding anyone.
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use crate::ctcp;
+use crate::filter::Filter;
+use crate::flood::Throttle;
+use crate::format::Formatter;
+use crate::paste::Paste;
+use crate::split;
+use crate::Error;
+
+/// Lines of a response that go to the channel before the rest is pasted or sent privately.
+const MAX_LINES: usize = 4;
+
+/// A line for `run()` to send on behalf of a response task.
+pub struct Outgoing {
+    pub target: String,
+    pub msg: String,
+}
+
+/// Sends lines to `channel` as they arrive. Past `MAX_LINES` the rest of the response goes to
+/// `private_message_nick` instead so we don't flood the channel, unless there's a `paste`
+/// service to put the whole thing on. With `formatting` Markdown is turned into IRC
+/// formatting, otherwise lines go out exactly as the model wrote them. Anything `filter` bans
+/// is dealt with first.
+pub async fn say(
+    outgoing: &mpsc::UnboundedSender<Outgoing>,
+    channel: &str,
+    mut lines: mpsc::UnboundedReceiver<String>,
+    private_message_nick: &str,
+    formatting: bool,
+    paste: Option<Paste>,
+    filter: Option<Filter>,
+) {
+    // Private messages can be as long as they like
+    let paste = paste.filter(|_| channel != private_message_nick);
+    let mut formatter = formatting.then(Formatter::default);
+    let mut response = Vec::new();
+    let mut held = Vec::new();
+    let mut sent = 0;
+    while let Some(line) = lines.recv().await {
+        let line = match &filter {
+            Some(filter) => match filter.check(line).await {
+                Some(line) => line,
+                None => {
+                    info!("Refused to finish an answer to {}", private_message_nick);
+                    queue(
+                        outgoing,
+                        channel,
+                        format!("{private_message_nick}: ...actually, I'd better not say that"),
+                    );
+                    return;
+                }
+            },
+            None => line,
+        };
+        if paste.is_some() {
+            response.push(line.clone());
+        }
+        // Lines the model wrote as *does something* go out as a /me, unless they're code
+        let action = match &formatter {
+            Some(formatter) if formatter.in_code_block() => None,
+            _ => ctcp::as_action(&line).map(ctcp::to_action),
+        };
+        let sentence = match (action, &mut formatter) {
+            (Some(action), _) => action,
+            (None, Some(formatter)) => match formatter.line(&line) {
+                Some(line) => line,
+                None => continue,
+            },
+            (None, None) => line,
+        };
+        if sentence.trim().is_empty() {
+            continue;
+        }
+        debug!("channel={channel} pm={private_message_nick} <- {sentence}");
+
+        if sent < MAX_LINES {
+            queue(outgoing, channel, sentence);
+        } else if paste.is_some() {
+            held.push(sentence);
+        } else {
+            if sent == MAX_LINES && channel != private_message_nick {
+                too_big(outgoing, channel, private_message_nick);
+            }
+            queue(outgoing, private_message_nick, sentence);
+        }
+        sent += 1;
+    }
+
+    let Some(paste) = paste.filter(|_| !held.is_empty()) else {
+        return;
+    };
+    match paste.upload(&response.join("\n")).await {
+        Ok(url) => queue(
+            outgoing,
+            channel,
+            format!("{private_message_nick}: it's a big one, the whole thing is at {url}"),
+        ),
+        Err(e) => {
+            warn!("Unable to paste the response: {}", e);
+            too_big(outgoing, channel, private_message_nick);
+            for sentence in held {
+                queue(outgoing, private_message_nick, sentence);
+            }
+        }
+    }
+}
+
+fn too_big(outgoing: &mpsc::UnboundedSender<Outgoing>, channel: &str, nick: &str) {
+    queue(
+        outgoing,
+        channel,
+        format!("{}: it's a big one so I'll send the rest to just you", nick),
+    );
+}
+
+/// Hands a line to `run()` to send. If the connection is gone there's nobody to say it to.
+pub fn queue(outgoing: &mpsc::UnboundedSender<Outgoing>, target: &str, msg: String) {
+    let _ = outgoing.send(Outgoing {
+        target: target.to_string(),
+        msg,
+    });
+}
+
+/// Sends `msg` to `target`, split into as many PRIVMSGs as it takes to fit in the server's
+/// `line_length` once it has put `source`, our own `nick!user@host`, in front.
+pub fn send_privmsg(
+    out: &Throttle,
+    source: &str,
+    line_length: usize,
+    target: &str,
+    msg: &str,
+    dry_run: bool,
+) -> Result<(), Error> {
+    // A /me is split inside its CTCP framing so every piece is still a /me
+    let (text, overhead) = match ctcp::action(msg) {
+        Some(action) => (action, ctcp::to_action("").len()),
+        None => (msg, 0),
+    };
+    let budget = split::privmsg_budget(source, target, line_length).saturating_sub(overhead);
+    for chunk in split::split(text, budget) {
+        let chunk = match overhead {
+            0 => chunk.to_string(),
+            _ => ctcp::to_action(chunk),
+        };
+        if dry_run {
+            info!("(dry run) {target} <- {chunk}");
+        } else {
+            out.send_privmsg(target, &chunk)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use reqwest::multipart;
+
+use tokio::time::Duration;
+
+use crate::config::PasteConfig;
+use crate::config::PasteService;
+use crate::Error;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Long enough to upload the longest answer to a slow service, short enough that a stuck
+/// one doesn't hold the answer up for good.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Uploads long responses somewhere they can be read in one piece.
+#[derive(Clone)]
+pub struct Paste {
+    http: reqwest::Client,
+    config: PasteConfig,
+}
+
+impl Paste {
+    pub fn new(config: &PasteConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+            config: config.clone(),
+        }
+    }
+
+    /// Returns the URL the text can be read at.
+    pub async fn upload(&self, text: &str) -> Result<String, Error> {
+        let request = match self.config.service {
+            PasteService::ZeroXZero => {
+                let file = multipart::Part::text(text.to_string()).file_name("pickles.txt");
+                self.http
+                    .post(self.config.url())
+                    .multipart(multipart::Form::new().part("file", file))
+            }
+            PasteService::Dpaste => self.http.post(self.config.url()).form(&[
+                ("content", text),
+                ("syntax", "markdown"),
+                ("expiry_days", &self.config.expiry_days.to_string()),
+            ]),
+        };
+
+        let body = request
+            .header(reqwest::header::USER_AGENT, "pickles")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let url = body.trim();
+        if !url.starts_with("http") {
+            return Err(Error::Paste(format!("unexpected response: {}", url)));
+        }
+
+        Ok(url.to_string())
+    }
+}
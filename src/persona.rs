@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The named personas from the config, and which channels and nicks have switched to one.
+pub struct Personas {
+    library: BTreeMap<String, String>,
+    /// Persona names by lowercased channel or nick.
+    active: Mutex<HashMap<String, String>>,
+}
+
+impl Personas {
+    pub fn new(library: &BTreeMap<String, String>) -> Self {
+        Self {
+            library: library.clone(),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.library.keys().map(String::as_str).collect()
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.library.contains_key(name)
+    }
+
+    /// Switches `who`, a channel or a nick, to the persona called `name`, or back to the
+    /// default with `None`.
+    pub fn set(&self, who: &str, name: Option<&str>) {
+        let mut active = self.active.lock().expect("persona lock poisoned");
+        match name {
+            Some(name) => active.insert(who.to_lowercase(), name.to_string()),
+            None => active.remove(&who.to_lowercase()),
+        };
+    }
+
+    /// Who has switched to which persona, by lowercased channel or nick.
+    pub fn active(&self) -> BTreeMap<String, String> {
+        let active = self.active.lock().expect("persona lock poisoned");
+        active
+            .iter()
+            .map(|(who, name)| (who.clone(), name.clone()))
+            .collect()
+    }
+
+    /// The system prompt `who` has switched to, if they have.
+    pub fn prompt(&self, who: &str) -> Option<String> {
+        let active = self.active.lock().expect("persona lock poisoned");
+        let name = active.get(&who.to_lowercase())?;
+        self.library.get(name).cloned()
+    }
+}
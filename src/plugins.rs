@@ -0,0 +1,200 @@
+use serde::Serialize;
+
+use tracing::*;
+
+use wasmtime::Caller;
+use wasmtime::Engine;
+use wasmtime::Extern;
+use wasmtime::Instance;
+use wasmtime::Linker;
+use wasmtime::Module;
+use wasmtime::Store;
+use wasmtime::TypedFunc;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::config::PluginsConfig;
+use crate::Error;
+
+/// Instructions a plugin gets to spend on each message before it's cut off, so a buggy one
+/// can't hang the connection.
+const FUEL_PER_CALL: u64 = 10_000_000;
+
+/// Longest string a plugin can hand back in one go.
+const MAX_STRING_BYTES: usize = 64 * 1024;
+
+/// What a plugin is told about each message, as JSON.
+#[derive(Serialize)]
+pub struct Event<'a> {
+    pub nick: &'a str,
+    /// Empty for private messages.
+    pub channel: &'a str,
+    pub text: &'a str,
+}
+
+/// WebAssembly plugins from the plugins directory.
+///
+/// The host interface, version 1. A plugin exports:
+///
+/// - `memory`
+/// - `pickles_alloc(len: i32) -> i32`, returning `len` bytes the host can write to
+/// - `pickles_on_message(ptr: i32, len: i32) -> i32`, given an [`Event`] as UTF-8 JSON and
+///   returning 1 if it dealt with the message, so nothing else should, or 0 if not
+///
+/// and can import from the `pickles` module:
+///
+/// - `reply(ptr: i32, len: i32)` to answer the message it was given
+/// - `log(ptr: i32, len: i32)` to write to pickles' log
+pub struct Plugins {
+    plugins: Vec<Mutex<Plugin>>,
+}
+
+/// Everything a plugin call can touch.
+#[derive(Default)]
+struct Host {
+    replies: Vec<String>,
+}
+
+struct Plugin {
+    name: String,
+    store: Store<Host>,
+    instance: Instance,
+    alloc: TypedFunc<i32, i32>,
+    on_message: TypedFunc<(i32, i32), i32>,
+}
+
+impl Plugins {
+    /// Compiles and starts every `.wasm` file in the plugins directory. A plugin that fails to
+    /// load is left out rather than keeping the rest from starting.
+    pub fn load(config: &PluginsConfig) -> Result<Self, Error> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).map_err(|e| Error::Plugin(e.to_string()))?;
+        let linker = linker(&engine).map_err(|e| Error::Plugin(e.to_string()))?;
+
+        let mut paths = fs::read_dir(&config.dir)
+            .map_err(|e| Error::Plugin(format!("{}: {}", config.dir.display(), e)))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "wasm")
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut plugins = Vec::new();
+        for path in paths {
+            match Plugin::load(&engine, &linker, &path) {
+                Ok(plugin) => {
+                    info!("Loaded plugin {}", plugin.name);
+                    plugins.push(Mutex::new(plugin));
+                }
+                Err(e) => warn!("Unable to load plugin {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self { plugins })
+    }
+
+    /// Hands `event` to each plugin in turn until one deals with it. Returns the replies to
+    /// send and whether the message was dealt with.
+    pub fn on_message(&self, event: &Event) -> (Vec<String>, bool) {
+        let event = serde_json::to_vec(event).expect("events serialize");
+        let mut replies = Vec::new();
+        for plugin in &self.plugins {
+            let mut plugin = plugin.lock().expect("plugin lock poisoned");
+            match plugin.on_message(&event) {
+                Ok((mut said, handled)) => {
+                    replies.append(&mut said);
+                    if handled {
+                        return (replies, true);
+                    }
+                }
+                Err(e) => warn!("Plugin {} failed: {}", plugin.name, e),
+            }
+        }
+
+        (replies, false)
+    }
+}
+
+impl Plugin {
+    fn load(engine: &Engine, linker: &Linker<Host>, path: &Path) -> wasmtime::Result<Self> {
+        let module = Module::from_file(engine, path)?;
+        let mut store = Store::new(engine, Host::default());
+        store.set_fuel(FUEL_PER_CALL)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        let alloc = instance.get_typed_func(&mut store, "pickles_alloc")?;
+        let on_message = instance.get_typed_func(&mut store, "pickles_on_message")?;
+
+        Ok(Self {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            store,
+            instance,
+            alloc,
+            on_message,
+        })
+    }
+
+    fn on_message(&mut self, event: &[u8]) -> wasmtime::Result<(Vec<String>, bool)> {
+        self.store.set_fuel(FUEL_PER_CALL)?;
+        let len = i32::try_from(event.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        let memory = self
+            .instance
+            .get_memory(&mut self.store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("no memory export"))?;
+        memory.write(&mut self.store, usize::try_from(ptr)?, event)?;
+
+        let handled = self.on_message.call(&mut self.store, (ptr, len))?;
+        let replies = std::mem::take(&mut self.store.data_mut().replies);
+
+        Ok((replies, handled != 0))
+    }
+}
+
+/// The functions plugins can import from the `pickles` module.
+fn linker(engine: &Engine) -> wasmtime::Result<Linker<Host>> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        "pickles",
+        "reply",
+        |mut caller: Caller<'_, Host>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let reply = read_string(&mut caller, ptr, len)?;
+            caller.data_mut().replies.push(reply);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "pickles",
+        "log",
+        |mut caller: Caller<'_, Host>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+            let line = read_string(&mut caller, ptr, len)?;
+            info!("Plugin says: {}", line);
+            Ok(())
+        },
+    )?;
+
+    Ok(linker)
+}
+
+/// Reads `len` bytes of UTF-8 at `ptr` in the calling plugin's memory.
+fn read_string(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return Err(wasmtime::Error::msg("no memory export"));
+    };
+    let len = usize::try_from(len)?;
+    if len > MAX_STRING_BYTES {
+        return Err(wasmtime::Error::msg("string too long"));
+    }
+    let mut bytes = vec![0; len];
+    memory.read(&caller, usize::try_from(ptr)?, &mut bytes)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -0,0 +1,141 @@
+//! Stock and crypto prices from whichever quote APIs are configured, so nobody has to take the
+//! model's word for what BTC is at.
+
+use serde_json::Value;
+
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::config::PriceConfig;
+use crate::webhook::field;
+use crate::Error;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest symbol worth asking about, like `BRK.B` or `BTC-USD`.
+const MAX_SYMBOL: usize = 16;
+
+#[derive(Clone)]
+pub struct Prices {
+    sources: Vec<PriceConfig>,
+    http: reqwest::Client,
+    /// The last quote for each symbol and when it goes stale.
+    cache: Arc<Mutex<HashMap<String, (Instant, String)>>>,
+}
+
+impl Prices {
+    pub fn new(sources: &[PriceConfig]) -> Self {
+        Self {
+            sources: sources.to_vec(),
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+            cache: Arc::default(),
+        }
+    }
+
+    /// A line with the price of `symbol` from the first source that has one, or `None` if
+    /// none of them do.
+    pub async fn quote(&self, symbol: &str) -> Result<Option<String>, Error> {
+        let symbol = symbol.to_uppercase();
+        if symbol.len() > MAX_SYMBOL
+            || !symbol
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '^' | '='))
+        {
+            return Ok(None);
+        }
+        if let Some((stale, quote)) = self
+            .cache
+            .lock()
+            .expect("prices lock poisoned")
+            .get(&symbol)
+        {
+            if Instant::now() < *stale {
+                return Ok(Some(quote.clone()));
+            }
+        }
+
+        let mut failed = None;
+        for source in &self.sources {
+            match self.ask(source, &symbol).await {
+                Ok(Some(quote)) => {
+                    self.cache.lock().expect("prices lock poisoned").insert(
+                        symbol,
+                        (
+                            Instant::now() + Duration::from_secs(source.cache_secs),
+                            quote.clone(),
+                        ),
+                    );
+                    return Ok(Some(quote));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("Unable to get a quote for {}: {}", symbol, e);
+                    failed = Some(e);
+                }
+            }
+        }
+
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    async fn ask(&self, source: &PriceConfig, symbol: &str) -> Result<Option<String>, Error> {
+        let response = self
+            .http
+            .get(source.url.replace("{symbol}", symbol))
+            .send()
+            .await?;
+        // Plenty of APIs say they don't know a symbol with a 400 or a 404
+        if response.status().is_client_error() {
+            return Ok(None);
+        }
+        let quote: Value = response.error_for_status()?.json().await?;
+        // Some say so with a price of 0
+        let Some(price) = number(field(&quote, &source.price)).filter(|&price| price > 0.0) else {
+            return Ok(None);
+        };
+
+        let mut line = format!("{}: {}", symbol, amount(price));
+        if let Some(currency) = &source.currency {
+            line.push_str(&format!(" {}", currency));
+        }
+        if let Some(change) = number(source.change.as_ref().and_then(|path| field(&quote, path))) {
+            line.push_str(&format!(" ({:+.2}% today)", change));
+        }
+
+        Ok(Some(line))
+    }
+}
+
+/// APIs are as likely to send numbers as strings as not.
+fn number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Cents for anything worth a dollar or more, and enough places to see something under that.
+fn amount(price: f64) -> String {
+    if price >= 1.0 {
+        return format!("{:.2}", price);
+    }
+
+    let written = format!("{:.8}", price);
+    written
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
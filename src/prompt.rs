@@ -0,0 +1,54 @@
+use chrono::Utc;
+
+/// Past this many people in a channel `{people}` just says how many more there are.
+const MAX_PEOPLE: usize = 30;
+
+/// What a system prompt can refer to.
+pub struct PromptVars<'a> {
+    /// Whoever we're responding to.
+    pub nick: &'a str,
+    /// `None` for private messages.
+    pub channel: Option<&'a str>,
+    pub botnick: &'a str,
+    pub topic: Option<&'a str>,
+    /// Everyone in the channel, us included. Empty for private messages.
+    pub people: &'a [String],
+}
+
+/// Fills in `{nick}`, `{channel}`, `{botnick}`, `{date}`, `{topic}` and `{people}` in
+/// `template`.
+pub fn render(template: &str, vars: &PromptVars) -> String {
+    template
+        .replace("{nick}", vars.nick)
+        .replace("{channel}", vars.channel.unwrap_or("a private message"))
+        .replace("{botnick}", vars.botnick)
+        .replace("{date}", &Utc::now().format("%A, %B %-d, %Y").to_string())
+        .replace("{topic}", vars.topic.unwrap_or(""))
+        .replace("{people}", &people(vars.people))
+}
+
+/// What to tell the model about the channel on top of the system prompt, for channels with
+/// `room_context` on.
+pub fn room_context(vars: &PromptVars) -> String {
+    let mut context = String::new();
+    if let Some(topic) = vars.topic {
+        context.push_str(&format!("\n\nThe channel topic is: {}", topic));
+    }
+    if !vars.people.is_empty() {
+        context.push_str(&format!(
+            "\n\nPeople in the channel: {}",
+            people(vars.people)
+        ));
+    }
+
+    context
+}
+
+fn people(people: &[String]) -> String {
+    let mut listed = people.iter().take(MAX_PEOPLE).cloned().collect::<Vec<_>>();
+    if people.len() > MAX_PEOPLE {
+        listed.push(format!("and {} more", people.len() - MAX_PEOPLE));
+    }
+
+    listed.join(", ")
+}
@@ -0,0 +1,531 @@
+//! Reaching IRC servers through a SOCKS5 proxy, such as Tor. A network with a proxy gets a
+//! relay on localhost that pickles connects to instead, which connects through the proxy and
+//! takes care of TLS itself, so the irc crate never needs to know. The API client gets a relay
+//! of its own that it uses as an HTTP proxy, since reqwest can't speak SOCKS without
+//! tokio-socks.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use native_tls::Certificate;
+use native_tls::Identity;
+
+use reqwest::Url;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::lookup_host;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use std::fs;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+
+use crate::config::NetworkConfig;
+use crate::config::ProxyConfig;
+use crate::config::TlsConfig;
+use crate::gateway;
+use crate::Error;
+
+/// How long the proxy gets to reach the server. Tor can take a while to build a circuit.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a client of the HTTP relay gets to say what it wants.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most an HTTP request's line and headers can take up.
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Whose name the HTTP relay's token goes under.
+const RELAY_USER: &str = "pickles";
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+const IPV4: u8 = 0x01;
+const DOMAIN: u8 = 0x03;
+const IPV6: u8 = 0x04;
+
+/// Where the relay connects to on pickles' behalf.
+#[derive(Clone)]
+struct Target {
+    server: String,
+    port: u16,
+    tls: TlsConfig,
+}
+
+/// Starts a relay through `network`'s proxy if it has one, and points it there.
+pub async fn open(network: &mut NetworkConfig) -> Result<(), Error> {
+    let Some(proxy) = network.proxy.clone() else {
+        return Ok(());
+    };
+    if network.transport.is_some() {
+        warn!("Ignoring the proxy, {} isn't on IRC", network.name);
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .map_err(|e| Error::Proxy(format!("unable to listen: {}", e)))?;
+    let target = Target {
+        server: network.server.clone(),
+        port: network.port,
+        tls: network.tls.clone(),
+    };
+    info!(
+        "Connecting to {}:{} through {}:{}",
+        target.server, target.port, proxy.host, proxy.port
+    );
+    network.server = Ipv4Addr::LOCALHOST.to_string();
+    network.port = listener
+        .local_addr()
+        .map_err(|e| Error::Proxy(format!("unable to listen: {}", e)))?
+        .port();
+    network.tls = TlsConfig::default();
+    let token = gateway::relay_token();
+    network.relay_token = Some(token.clone());
+
+    tokio::spawn(serve(listener, proxy, target, token).in_current_span());
+
+    Ok(())
+}
+
+/// Relays every connection pickles makes, reconnects included.
+async fn serve(listener: TcpListener, proxy: ProxyConfig, target: Target, token: String) {
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                error!("Proxy relay unable to accept: {}", e);
+                return;
+            }
+        };
+        let (proxy, target, token) = (proxy.clone(), target.clone(), token.clone());
+        tokio::spawn(
+            async move {
+                // Pickles sees the connection close and reconnects as usual
+                if let Err(e) = relay(socket, &proxy, &target, &token).await {
+                    warn!("{}", e);
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+/// Passes everything between `socket` and the server until one of them hangs up. Whoever's
+/// on the other end has to be pickles, or they'd get to use its client certificate.
+async fn relay(
+    socket: TcpStream,
+    proxy: &ProxyConfig,
+    target: &Target,
+    token: &str,
+) -> Result<(), Error> {
+    // Whatever else comes with the token is still in the buffer, and passed on with the rest
+    let mut socket = BufReader::new(socket);
+    if !gateway::authenticate(&mut socket, token).await {
+        return Err(Error::Proxy(String::from(
+            "turned away a connection that wasn't pickles",
+        )));
+    }
+    let server = time::timeout(CONNECT_TIMEOUT, connect(proxy, &target.server, target.port))
+        .await
+        .map_err(|_| Error::Proxy(String::from("timed out reaching the server")))??;
+    let result = match target.tls.enabled {
+        true => {
+            let connector = tokio_native_tls::TlsConnector::from(tls_connector(&target.tls)?);
+            let mut server = connector
+                .connect(&target.server, server)
+                .await
+                .map_err(|e| Error::Proxy(format!("TLS failed: {}", e)))?;
+            tokio::io::copy_bidirectional(&mut socket, &mut server).await
+        }
+        false => {
+            let mut server = server;
+            tokio::io::copy_bidirectional(&mut socket, &mut server).await
+        }
+    };
+    if let Err(e) = result {
+        debug!("Proxied connection closed: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Starts a relay on localhost that makes the HTTP proxy requests it's given, `CONNECT`s
+/// included, through the SOCKS5 proxy at `url`: `socks5://` to look names up here, or
+/// `socks5h://` to have the proxy do it. Returns the relay's URL to use as an HTTP proxy,
+/// with the credentials it wants in it.
+pub fn http_relay(url: &str) -> Result<String, Error> {
+    let invalid = |why: &str| Error::Proxy(format!("invalid proxy {}: {}", url, why));
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    let remote_dns = match parsed.scheme() {
+        "socks5" => false,
+        "socks5h" => true,
+        _ => return Err(invalid("not a SOCKS5 proxy")),
+    };
+    let proxy = ProxyConfig {
+        host: parsed
+            .host_str()
+            .ok_or_else(|| invalid("no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string(),
+        port: parsed.port().unwrap_or(1080),
+        username: Some(parsed.username())
+            .filter(|username| !username.is_empty())
+            .map(str::to_string),
+        password: parsed.password().map(str::to_string),
+    };
+
+    if tokio::runtime::Handle::try_current().is_err() {
+        return Err(Error::Proxy(String::from(
+            "the SOCKS relay needs to be started from async code",
+        )));
+    }
+    // Bound here rather than awaited, so clients can still be built without an await
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .map_err(|e| Error::Proxy(format!("unable to listen: {}", e)))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| Error::Proxy(format!("unable to listen: {}", e)))?
+        .port();
+    let listener = TcpListener::from_std(listener)
+        .map_err(|e| Error::Proxy(format!("unable to listen: {}", e)))?;
+    info!("Reaching the API through {}:{}", proxy.host, proxy.port);
+    let token = gateway::relay_token();
+    tokio::spawn(serve_http(listener, proxy, remote_dns, token.clone()).in_current_span());
+
+    Ok(format!(
+        "http://{}:{}@{}:{}",
+        RELAY_USER,
+        token,
+        Ipv4Addr::LOCALHOST,
+        port
+    ))
+}
+
+/// Tunnels every request the API client makes.
+async fn serve_http(listener: TcpListener, proxy: ProxyConfig, remote_dns: bool, token: String) {
+    let authorization = format!(
+        "Basic {}",
+        BASE64.encode(format!("{}:{}", RELAY_USER, token))
+    );
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                error!("SOCKS relay unable to accept: {}", e);
+                return;
+            }
+        };
+        let (proxy, authorization) = (proxy.clone(), authorization.clone());
+        tokio::spawn(
+            async move {
+                if let Err(e) = tunnel(socket, &proxy, remote_dns, &authorization).await {
+                    warn!("{}", e);
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+/// Makes one HTTP proxy request through `proxy`, and passes everything along both ways until
+/// one end hangs up. Plain HTTP requests are sent on with `Connection: close`, so the client
+/// never sends another down the same connection to what may be somewhere else.
+async fn tunnel(
+    socket: TcpStream,
+    proxy: &ProxyConfig,
+    remote_dns: bool,
+    authorization: &str,
+) -> Result<(), Error> {
+    let mut socket = BufReader::new(socket);
+    let head = time::timeout(REQUEST_TIMEOUT, read_head(&mut socket))
+        .await
+        .map_err(|_| Error::Proxy(String::from("timed out waiting for a request")))??;
+    let (request, headers) = head
+        .split_first()
+        .ok_or_else(|| Error::Proxy(String::from("empty request")))?;
+    let given = headers
+        .iter()
+        .filter_map(|header| header.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("proxy-authorization"))
+        .map(|(_, value)| value.trim())
+        .unwrap_or_default();
+    // Anyone else on the machine could connect too
+    if !gateway::same(given, authorization) {
+        let refusal = "HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n";
+        let _ = socket.get_mut().write_all(refusal.as_bytes()).await;
+        return Err(Error::Proxy(String::from(
+            "turned away an API request that wasn't pickles'",
+        )));
+    }
+
+    let mut parts = request.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Error::Proxy(format!("invalid request {:?}", request)));
+    };
+    let (host, port, forward) = match method {
+        "CONNECT" => {
+            let (host, port) = target
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+                .ok_or_else(|| Error::Proxy(format!("invalid CONNECT to {}", target)))?;
+            (host.to_string(), port, None)
+        }
+        _ => {
+            let url = Url::parse(target)
+                .ok()
+                .filter(|url| url.scheme() == "http")
+                .ok_or_else(|| Error::Proxy(format!("can't proxy {}", target)))?;
+            let mut path = url.path().to_string();
+            if let Some(query) = url.query() {
+                path = format!("{}?{}", path, query);
+            }
+            let mut forward = format!("{} {} {}\r\n", method, path, version);
+            for header in headers {
+                let name = header.split_once(':').map(|(name, _)| name.trim());
+                let hop = name.is_some_and(|name| {
+                    name.eq_ignore_ascii_case("connection")
+                        || name.to_ascii_lowercase().starts_with("proxy-")
+                });
+                if !hop {
+                    forward.push_str(&format!("{}\r\n", header));
+                }
+            }
+            forward.push_str("Connection: close\r\n\r\n");
+            let host = url.host_str().unwrap_or_default().to_string();
+            (
+                host,
+                url.port_or_known_default().unwrap_or(80),
+                Some(forward),
+            )
+        }
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let host = match remote_dns {
+        true => host.to_string(),
+        false => lookup_host((host, port))
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| Error::Proxy(format!("unable to look up {}", host)))?
+            .ip()
+            .to_string(),
+    };
+    let connected = time::timeout(CONNECT_TIMEOUT, connect(proxy, &host, port))
+        .await
+        .map_err(|_| Error::Proxy(format!("timed out reaching {}:{}", host, port)));
+    let mut server = match connected.and_then(|connected| connected) {
+        Ok(server) => server,
+        Err(e) => {
+            let failure = "HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n";
+            let _ = socket.get_mut().write_all(failure.as_bytes()).await;
+            return Err(e);
+        }
+    };
+    match forward {
+        Some(forward) => server.write_all(forward.as_bytes()).await.map_err(io)?,
+        None => socket
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .await
+            .map_err(io)?,
+    }
+    // Whatever came after the head, a body included, is still in the buffer
+    if let Err(e) = tokio::io::copy_bidirectional(&mut socket, &mut server).await {
+        debug!("Tunnel to {}:{} closed: {}", host, port, e);
+    }
+
+    Ok(())
+}
+
+/// The request line and headers of an HTTP request, up to the blank line after them.
+async fn read_head(socket: &mut BufReader<TcpStream>) -> Result<Vec<String>, Error> {
+    let mut limited = (&mut *socket).take(MAX_HEAD);
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = limited.read_line(&mut line).await;
+        if read.map_err(|e| Error::Proxy(format!("lost the API client: {}", e)))? == 0 {
+            return Err(Error::Proxy(String::from("request cut short")));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return Ok(head);
+        }
+        head.push(line.to_string());
+    }
+}
+
+/// The same TLS settings the irc crate would have used.
+fn tls_connector(tls: &TlsConfig) -> Result<native_tls::TlsConnector, Error> {
+    let read = |path| {
+        fs::read(path).map_err(|e| Error::Proxy(format!("unable to read {:?}: {}", path, e)))
+    };
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(tls.accept_invalid_certs);
+    if let Some(path) = &tls.ca_cert {
+        let cert = Certificate::from_der(&read(path)?)
+            .map_err(|e| Error::Proxy(format!("invalid CA certificate: {}", e)))?;
+        builder.add_root_certificate(cert);
+    }
+    if let Some(path) = &tls.client_cert {
+        let password = tls.client_cert_password.as_deref().unwrap_or_default();
+        let identity = Identity::from_pkcs12(&read(path)?, password)
+            .map_err(|e| Error::Proxy(format!("invalid client certificate: {}", e)))?;
+        builder.identity(identity);
+    }
+
+    builder
+        .build()
+        .map_err(|e| Error::Proxy(format!("TLS failed: {}", e)))
+}
+
+/// Connects to `host` and `port` through `proxy`.
+pub async fn connect(proxy: &ProxyConfig, host: &str, port: u16) -> Result<TcpStream, Error> {
+    let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port))
+        .await
+        .map_err(|e| {
+            Error::Proxy(format!(
+                "unable to reach {}:{}: {}",
+                proxy.host, proxy.port, e
+            ))
+        })?;
+    handshake(&mut stream, proxy, host, port)
+        .await
+        .map_err(|e| match e {
+            Error::Proxy(e) => Error::Proxy(e),
+            e => Error::Proxy(format!("lost the proxy: {}", e)),
+        })?;
+
+    Ok(stream)
+}
+
+/// RFC 1928, with RFC 1929 usernames and passwords.
+async fn handshake<S>(
+    stream: &mut S,
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let credentials = match (&proxy.username, &proxy.password) {
+        (None, None) => None,
+        (username, password) => Some((
+            username.as_deref().unwrap_or_default(),
+            password.as_deref().unwrap_or_default(),
+        )),
+    };
+    let greeting = match credentials {
+        Some(_) => vec![VERSION, 2, NO_AUTH, PASSWORD],
+        None => vec![VERSION, 1, NO_AUTH],
+    };
+    stream.write_all(&greeting).await.map_err(io)?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice).await.map_err(io)?;
+    match (choice, credentials) {
+        ([VERSION, NO_AUTH], _) => (),
+        ([VERSION, PASSWORD], Some((username, password))) => {
+            let mut login = vec![0x01];
+            for field in [username, password] {
+                let len = u8::try_from(field.len())
+                    .map_err(|_| Error::Proxy(String::from("username or password too long")))?;
+                login.push(len);
+                login.extend_from_slice(field.as_bytes());
+            }
+            stream.write_all(&login).await.map_err(io)?;
+            let mut status = [0; 2];
+            stream.read_exact(&mut status).await.map_err(io)?;
+            if status[1] != 0 {
+                return Err(Error::Proxy(String::from(
+                    "the proxy didn't accept the username and password",
+                )));
+            }
+        }
+        ([VERSION, NO_ACCEPTABLE_METHODS], None) => {
+            return Err(Error::Proxy(String::from(
+                "the proxy wants a username and password",
+            )))
+        }
+        _ => return Err(Error::Proxy(String::from("that isn't a SOCKS5 proxy"))),
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| Error::Proxy(format!("{} is too long a name", host)))?;
+            request.extend_from_slice(&[DOMAIN, len]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.map_err(io)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await.map_err(io)?;
+    if reply[1] != 0 {
+        return Err(Error::Proxy(format!(
+            "unable to reach {}:{}: {}",
+            host,
+            port,
+            refusal(reply[1])
+        )));
+    }
+    // Where the proxy connected from, which is no use to us
+    let bound = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN => usize::from(stream.read_u8().await.map_err(io)?),
+        _ => return Err(Error::Proxy(String::from("that isn't a SOCKS5 proxy"))),
+    };
+    let mut rest = vec![0; bound + 2];
+    stream.read_exact(&mut rest).await.map_err(io)?;
+
+    Ok(())
+}
+
+fn io(e: std::io::Error) -> Error {
+    Error::Proxy(format!("lost the proxy: {}", e))
+}
+
+/// Why the proxy wouldn't connect, by its reply code.
+fn refusal(code: u8) -> &'static str {
+    match code {
+        0x02 => "not allowed",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "timed out",
+        0x07 | 0x08 => "not supported by the proxy",
+        _ => "the proxy failed",
+    }
+}
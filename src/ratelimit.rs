@@ -0,0 +1,50 @@
+use tokio::time::Duration;
+use tokio::time::Instant;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::config::RateLimitConfig;
+
+/// Stops any one nick from asking more than `requests` questions in a sliding window, so a
+/// single person can't run up the API bill.
+pub struct RateLimiter {
+    requests: usize,
+    window: Duration,
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            requests: config.requests,
+            window: Duration::from_secs(config.per_secs),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `nick` if they're allowed one, otherwise returns how long until
+    /// they are.
+    pub fn check(&self, nick: &str) -> Result<(), Duration> {
+        if self.requests == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut recent = self.recent.lock().expect("rate limit lock poisoned");
+        // Nobody needs remembering once their window has passed
+        recent.retain(|_, asked| asked.back().is_some_and(|&at| now - at < self.window));
+
+        let asked = recent.entry(nick.to_string()).or_default();
+        while asked.front().is_some_and(|&at| now - at >= self.window) {
+            asked.pop_front();
+        }
+        if let Some(&oldest) = asked.front().filter(|_| asked.len() >= self.requests) {
+            return Err(self.window - (now - oldest));
+        }
+        asked.push_back(now);
+
+        Ok(())
+    }
+}
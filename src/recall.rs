@@ -0,0 +1,83 @@
+use async_openai::types::CreateEmbeddingRequestArgs;
+
+use crate::config::OpenAIConfig;
+use crate::config::RecallConfig;
+use crate::llm::openai;
+use crate::Error;
+
+/// A past exchange with someone, and where it sits in embedding space.
+#[derive(Debug, Clone)]
+pub struct Recollection {
+    pub content: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Turns text into embeddings so old exchanges can be found again by what they were about.
+pub struct Recall {
+    openai: OpenAIConfig,
+    http: reqwest::Client,
+    pub config: RecallConfig,
+}
+
+impl Recall {
+    pub fn new(openai: &OpenAIConfig, http: &reqwest::Client, config: &RecallConfig) -> Self {
+        Self {
+            openai: openai.clone(),
+            http: http.clone(),
+            config: config.clone(),
+        }
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, Error> {
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(&self.config.model)
+            .input(text)
+            .build()?;
+        let response = openai::client(&self.openai, &self.http, None)
+            .embeddings()
+            .create(request)
+            .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .next()
+            .map(|embedding| embedding.embedding)
+            .unwrap_or_default())
+    }
+
+    /// The `content` of the recollections most like `query`, best first. The newest `skip` are
+    /// left out since they're still in the short term window.
+    pub fn best<'a>(
+        &self,
+        query: &[f32],
+        recollections: &'a [Recollection],
+        skip: usize,
+    ) -> Vec<&'a str> {
+        let older = &recollections[..recollections.len().saturating_sub(skip)];
+        let mut scored = older
+            .iter()
+            .map(|recollection| (similarity(query, &recollection.embedding), recollection))
+            .filter(|(score, _)| *score >= self.config.min_similarity)
+            .collect::<Vec<_>>();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        scored
+            .into_iter()
+            .take(self.config.results)
+            .map(|(_, recollection)| recollection.content.as_str())
+            .collect()
+    }
+}
+
+/// Cosine similarity, from -1 for opposites to 1 for the same direction.
+fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        return 0.0;
+    }
+
+    dot / norms
+}
@@ -0,0 +1,69 @@
+use rand::Rng;
+
+use tokio::time::Duration;
+use tracing::*;
+
+use crate::config::NetworkConfig;
+use crate::config::ReconnectConfig;
+use crate::metrics::metrics;
+
+/// How long to wait before reconnecting to a network. Every connection that drops before it
+/// gets as far as registering counts as a failure, and each failure in a row doubles the wait.
+pub struct Backoff {
+    network: String,
+    config: ReconnectConfig,
+    failures: u32,
+    registered: bool,
+}
+
+impl Backoff {
+    pub fn new(network: &NetworkConfig) -> Self {
+        Self {
+            network: network.name.clone(),
+            config: network.reconnect.clone(),
+            failures: 0,
+            registered: false,
+        }
+    }
+
+    /// The current connection made it, so it doesn't count against the next one.
+    pub fn registered(&mut self) {
+        self.registered = true;
+        self.failures = 0;
+        metrics()
+            .connect_failures
+            .with_label_values(&[&self.network])
+            .set(0);
+    }
+
+    /// Counts the connection that just ended if it never registered, raising the alarm if
+    /// that's been happening a lot, and returns how long to wait before trying again.
+    pub fn disconnected(&mut self) -> Duration {
+        if !self.registered {
+            self.failures += 1;
+            metrics()
+                .connect_failures
+                .with_label_values(&[&self.network])
+                .set(i64::from(self.failures));
+            let alert_after = self.config.alert_after;
+            if alert_after > 0 && self.failures.is_multiple_of(alert_after) {
+                error!(
+                    "Unable to connect to {} {} times in a row",
+                    self.network, self.failures
+                );
+                metrics()
+                    .connect_alerts
+                    .with_label_values(&[&self.network])
+                    .inc();
+            }
+        }
+        self.registered = false;
+
+        let max = Duration::from_secs(self.config.max_delay_secs);
+        let base = Duration::from_secs(self.config.initial_delay_secs)
+            .saturating_mul(2u32.saturating_pow(self.failures.saturating_sub(1)))
+            .min(max);
+        // Jitter over the top half so a netsplit doesn't bring every bot back at once
+        base / 2 + base.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::DateTime;
+use chrono::TimeDelta;
+use chrono::Utc;
+
+use tracing::*;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::storage::ReminderStore;
+use crate::Error;
+
+/// How many reminders one nick can have waiting.
+pub const MAX_REMINDERS: usize = 10;
+
+/// A message to send back to someone once it's `due`.
+#[derive(Debug, Clone)]
+pub struct Reminder {
+    /// Set once the reminder has been stored.
+    pub id: Option<i64>,
+    pub nick: String,
+    /// Where to remind them: the channel they asked in, or their nick for private messages.
+    pub target: String,
+    pub due: DateTime<Utc>,
+    pub text: String,
+}
+
+/// Reminders waiting to go off on one network. Changes are written through to the store, if
+/// there is one, so they go off even if we restart in the meantime.
+pub struct Reminders {
+    scope: String,
+    pending: Mutex<Vec<Reminder>>,
+    store: Option<Arc<dyn ReminderStore>>,
+}
+
+impl Reminders {
+    pub async fn load(scope: &str, store: Option<Arc<dyn ReminderStore>>) -> Result<Self, Error> {
+        let pending = match &store {
+            Some(store) => store.reminders(scope).await?,
+            None => Vec::new(),
+        };
+        if !pending.is_empty() {
+            info!("{} reminders waiting to go off", pending.len());
+        }
+
+        Ok(Self {
+            scope: scope.to_string(),
+            pending: Mutex::new(pending),
+            store,
+        })
+    }
+
+    /// Returns false if `reminder.nick` already has `MAX_REMINDERS` waiting.
+    pub async fn add(&self, mut reminder: Reminder) -> bool {
+        let waiting = self
+            .pending
+            .lock()
+            .expect("reminder lock poisoned")
+            .iter()
+            .filter(|r| r.nick.eq_ignore_ascii_case(&reminder.nick))
+            .count();
+        if waiting >= MAX_REMINDERS {
+            return false;
+        }
+
+        if let Some(store) = &self.store {
+            match store.add_reminder(&self.scope, &reminder).await {
+                Ok(id) => reminder.id = Some(id),
+                Err(e) => warn!("Unable to save reminder for {}: {}", reminder.nick, e),
+            }
+        }
+        self.pending
+            .lock()
+            .expect("reminder lock poisoned")
+            .push(reminder);
+
+        true
+    }
+
+    /// When the next reminder goes off, if there are any.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.pending
+            .lock()
+            .expect("reminder lock poisoned")
+            .iter()
+            .map(|reminder| reminder.due)
+            .min()
+    }
+
+    /// Takes every reminder that has gone off, earliest first.
+    pub async fn due(&self) -> Vec<Reminder> {
+        let now = Utc::now();
+        let mut due = {
+            let mut pending = self.pending.lock().expect("reminder lock poisoned");
+            let (due, waiting) = pending.drain(..).partition(|reminder| reminder.due <= now);
+            *pending = waiting;
+            due
+        };
+        due.sort_by_key(|reminder: &Reminder| reminder.due);
+
+        if let Some(store) = &self.store {
+            for id in due.iter().filter_map(|reminder| reminder.id) {
+                if let Err(e) = store.remove_reminder(&self.scope, id).await {
+                    warn!("Unable to remove reminder {}: {}", id, e);
+                }
+            }
+        }
+
+        due
+    }
+}
+
+/// Reads a delay like "20m", "1h30m" or "2 hours" off the front of `text`, returning it and
+/// whatever follows.
+pub fn parse_delay(text: &str) -> Option<(TimeDelta, &str)> {
+    let mut seconds = 0i64;
+    let mut parsed = false;
+    let mut rest = text.trim_start();
+    loop {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            break;
+        }
+        let after = rest[digits..].trim_start();
+        let letters = after
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+        let unit = match after[..letters].to_ascii_lowercase().as_str() {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+            "d" | "day" | "days" => 24 * 60 * 60,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+            // A number that's part of the message, not the delay
+            _ => break,
+        };
+        let n = rest[..digits].parse::<i64>().ok()?;
+        seconds = seconds.checked_add(n.checked_mul(unit)?)?;
+        rest = after[letters..].trim_start();
+        parsed = true;
+    }
+
+    parsed
+        .then(|| TimeDelta::try_seconds(seconds))
+        .flatten()
+        .map(|delay| (delay, rest))
+}
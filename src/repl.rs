@@ -0,0 +1,159 @@
+//! Talking to pickles from a terminal, for working on prompts and formatting without going
+//! anywhere near a real network.
+
+use irc::client::prelude::*;
+
+use tokio::io;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::*;
+
+use std::net::Ipv4Addr;
+
+use crate::config::ChannelConfig;
+use crate::config::Config;
+use crate::config::FloodConfig;
+use crate::config::TlsConfig;
+use crate::ctcp;
+use crate::irc_bot;
+use crate::llm;
+use crate::llm::openai;
+use crate::Error;
+
+/// Where people talk when the config doesn't name any channels.
+const DEFAULT_CHANNEL: &str = "#repl";
+
+/// Runs pickles as configured for the first network, except that it connects to a server of
+/// our own that only we are on. Each line on stdin is said in the first channel, as `nick:
+/// message` or just `message` from "you", and whatever pickles says back is printed. Returns
+/// at the end of stdin.
+pub async fn start(config: Config) -> Result<(), Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .map_err(Error::Repl)?;
+    let mut network = config.networks.first().cloned().unwrap_or_default();
+    network.server = Ipv4Addr::LOCALHOST.to_string();
+    network.port = listener.local_addr().map_err(Error::Repl)?.port();
+    network.tls = TlsConfig::default();
+    network.sasl = None;
+    network.nickserv = None;
+    network.transport = None;
+    // There's nobody to flood
+    network.flood = FloodConfig {
+        burst: u32::MAX,
+        refill_ms: 1,
+    };
+    if network.channels.is_empty() {
+        network.channels = vec![ChannelConfig::new(DEFAULT_CHANNEL)];
+    }
+    let channel = network.channels[0].clone();
+    let mut nickname = network.nickname.clone();
+    let config = Config {
+        networks: vec![network],
+        ..config
+    };
+
+    let (shutdown_tx, shutdown) = watch::channel(false);
+    let openai = openai::http_client(&config.openai)?;
+    let backend = llm::backend(&config, &openai);
+    let bot = tokio::spawn(irc_bot::serve(config, backend, openai, shutdown));
+
+    let (socket, _) = listener.accept().await.map_err(Error::Repl)?;
+    let (reader, mut writer) = socket.into_split();
+    let mut from_bot = BufReader::new(reader).lines();
+    let mut input = BufReader::new(io::stdin()).lines();
+    let mut reading = true;
+    println!(
+        "Talking in {}, address pickles with e.g. `you: {}hi`. Ctrl-D to quit.",
+        channel.name,
+        channel.trigger(&nickname)
+    );
+
+    loop {
+        tokio::select! {
+            line = input.next_line(), if reading => match line.map_err(Error::Repl)? {
+                Some(line) if line.trim().is_empty() => (),
+                Some(line) => {
+                    let (nick, msg) = said(&line, &nickname);
+                    let privmsg = format!(":{0}!{0}@repl PRIVMSG {1} :{2}", nick, channel.name, msg);
+                    send(&mut writer, &privmsg).await?;
+                }
+                None => {
+                    reading = false;
+                    let _ = shutdown_tx.send(true);
+                }
+            },
+            line = from_bot.next_line() => {
+                let Some(line) = line.map_err(Error::Repl)? else {
+                    break;
+                };
+                let message = match line.parse::<Message>() {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Unable to parse {:?}: {}", line, e);
+                        continue;
+                    }
+                };
+                match message.command {
+                    Command::NICK(nick) => nickname = nick,
+                    Command::USER(..) => {
+                        send(&mut writer, &format!(":repl 001 {} :Welcome", nickname)).await?;
+                        send(&mut writer, &format!(":repl 422 {} :No MOTD", nickname)).await?;
+                    }
+                    Command::JOIN(channels, ..) => {
+                        for channel in channels.split(',') {
+                            let join = format!(":{0}!{0}@repl JOIN {1}", nickname, channel);
+                            send(&mut writer, &join).await?;
+                        }
+                    }
+                    Command::PING(server, _) => {
+                        send(&mut writer, &format!(":repl PONG repl :{}", server)).await?;
+                    }
+                    Command::PRIVMSG(target, msg) | Command::NOTICE(target, msg) => {
+                        match ctcp::action(&msg) {
+                            Some(action) => println!("{} * {} {}", target, nickname, action),
+                            None => println!("{} <{}> {}", target, nickname, msg),
+                        }
+                    }
+                    Command::QUIT(_) => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    // Hanging up lets pickles finish quitting straight away
+    drop(writer);
+    let _ = shutdown_tx.send(true);
+    match bot.await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("REPL task failed: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Splits `alice: hi there` into who said it and what they said. `pickles: hi` is talking to
+/// pickles rather than pickles talking.
+fn said<'a>(line: &'a str, nickname: &str) -> (&'a str, &'a str) {
+    match line.split_once(": ") {
+        Some((nick, msg))
+            if !nick.is_empty() && !nick.contains(' ') && !nick.eq_ignore_ascii_case(nickname) =>
+        {
+            (nick, msg)
+        }
+        _ => ("you", line),
+    }
+}
+
+async fn send(writer: &mut OwnedWriteHalf, line: &str) -> Result<(), Error> {
+    writer
+        .write_all(format!("{}\r\n", line).as_bytes())
+        .await
+        .map_err(Error::Repl)
+}
@@ -0,0 +1,232 @@
+use chrono::SecondsFormat;
+use chrono::Utc;
+
+use irc::proto::Message;
+
+use reqwest::Url;
+
+use serde_json::json;
+
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use tokio::time::Instant;
+use tracing::*;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::panic;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use crate::config::ErrorReportingConfig;
+use crate::Error;
+
+/// Set by `start()`. Until then nothing is reported.
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+
+struct Reporter {
+    reports: mpsc::UnboundedSender<Report>,
+    cooldown: Duration,
+    /// The last message each network started handling, by network.
+    handling: Mutex<BTreeMap<String, String>>,
+    /// When each error was last reported, and how many times it's happened since.
+    recent: Mutex<HashMap<String, (Instant, u64)>>,
+}
+
+/// Something that went wrong, on its way to Sentry or the webhook.
+struct Report {
+    /// `panic` or `error`.
+    kind: &'static str,
+    /// The network it happened on, if it's known.
+    network: Option<String>,
+    message: String,
+    /// The last message each network was handling when it happened.
+    handling: BTreeMap<String, String>,
+    /// How many more times it happened since it was last reported.
+    repeats: u64,
+}
+
+/// Starts reporting panics, and errors passed to `error()`, to Sentry and the webhook in
+/// `config`. Only the first call does anything.
+pub fn start(config: &ErrorReportingConfig) {
+    let sentry = config.sentry_dsn.as_deref().and_then(|dsn| {
+        let sentry = Sentry::new(dsn);
+        if sentry.is_none() {
+            error!("Ignoring [error_reporting] sentry_dsn, it isn't a valid DSN");
+        }
+        sentry
+    });
+    if sentry.is_none() && config.webhook_url.is_none() {
+        return;
+    }
+
+    let (reports, rx) = mpsc::unbounded_channel();
+    let reporter = Reporter {
+        reports,
+        cooldown: Duration::from_secs(config.cooldown_secs),
+        handling: Mutex::new(BTreeMap::new()),
+        recent: Mutex::new(HashMap::new()),
+    };
+    if REPORTER.set(reporter).is_err() {
+        return;
+    }
+    tokio::spawn(send(sentry, config.webhook_url.clone(), rx));
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report("panic", None, info.to_string());
+        previous(info);
+    }));
+}
+
+/// Remembers that `network` is now handling `message`, to go with anything that goes wrong
+/// before the next one.
+pub fn handling(network: &str, message: &Message) {
+    if let Some(reporter) = REPORTER.get() {
+        reporter
+            .handling
+            .lock()
+            .expect("handling lock poisoned")
+            .insert(
+                network.to_string(),
+                message.to_string().trim_end().to_string(),
+            );
+    }
+}
+
+/// Reports an error that cost `network` its connection.
+pub fn error(network: &str, error: &Error) {
+    report("error", Some(network), error.to_string());
+}
+
+fn report(kind: &'static str, network: Option<&str>, message: String) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+
+    // The same thing going wrong over and over is reported once per cooldown, with a count
+    let key = format!("{}:{}:{}", kind, network.unwrap_or_default(), message);
+    let repeats = {
+        let mut recent = reporter.recent.lock().expect("recent lock poisoned");
+        let now = Instant::now();
+        match recent.get_mut(&key) {
+            Some((last, repeats)) if now.duration_since(*last) < reporter.cooldown => {
+                *repeats += 1;
+                return;
+            }
+            Some((last, repeats)) => {
+                *last = now;
+                std::mem::take(repeats)
+            }
+            None => {
+                recent.insert(key, (now, 0));
+                0
+            }
+        }
+    };
+    let handling = reporter
+        .handling
+        .lock()
+        .expect("handling lock poisoned")
+        .clone();
+
+    let _ = reporter.reports.send(Report {
+        kind,
+        network: network.map(str::to_string),
+        message,
+        handling,
+        repeats,
+    });
+}
+
+async fn send(
+    sentry: Option<Sentry>,
+    webhook_url: Option<String>,
+    mut reports: mpsc::UnboundedReceiver<Report>,
+) {
+    let http = reqwest::Client::new();
+    while let Some(report) = reports.recv().await {
+        if let Some(sentry) = &sentry {
+            if let Err(e) = sentry.send(&http, &report).await {
+                warn!("Unable to report to Sentry: {}", e);
+            }
+        }
+        if let Some(url) = &webhook_url {
+            let body = json!({
+                "kind": report.kind,
+                "network": report.network,
+                "message": report.message,
+                "handling": report.handling,
+                "repeats": report.repeats,
+            });
+            let result = http
+                .post(url)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!("Unable to report to {}: {}", url, e);
+            }
+        }
+    }
+}
+
+/// Where a Sentry DSN says to send events, and the key to send them with.
+struct Sentry {
+    store: Url,
+    key: String,
+}
+
+impl Sentry {
+    /// From a DSN like `https://<key>@o0.ingest.sentry.io/<project>`.
+    fn new(dsn: &str) -> Option<Self> {
+        let url = Url::parse(dsn).ok()?;
+        let key = url.username().to_string();
+        let (prefix, project) = url.path().trim_end_matches('/').rsplit_once('/')?;
+        if key.is_empty() || project.is_empty() {
+            return None;
+        }
+
+        let mut store = url.clone();
+        let _ = store.set_username("");
+        let _ = store.set_password(None);
+        store.set_path(&format!("{}/api/{}/store/", prefix, project));
+        Some(Self { store, key })
+    }
+
+    async fn send(&self, http: &reqwest::Client, report: &Report) -> Result<(), Error> {
+        let auth = format!(
+            "Sentry sentry_version=7, sentry_key={}, sentry_client=pickles/{}",
+            self.key,
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut tags = json!({ "kind": report.kind });
+        if let Some(network) = &report.network {
+            tags["network"] = json!(network);
+        }
+        let event = json!({
+            "event_id": format!("{:032x}", rand::random::<u128>()),
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "platform": "other",
+            "level": if report.kind == "panic" { "fatal" } else { "error" },
+            "logger": "pickles",
+            "release": concat!("pickles@", env!("CARGO_PKG_VERSION")),
+            "message": { "formatted": report.message },
+            "tags": tags,
+            "extra": {
+                "handling": report.handling,
+                "repeats": report.repeats,
+            },
+        });
+
+        http.post(self.store.clone())
+            .header("X-Sentry-Auth", auth)
+            .json(&event)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
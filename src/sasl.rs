@@ -0,0 +1,126 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use futures::stream::StreamExt;
+
+use irc::client::prelude::*;
+use irc::client::ClientStream;
+use irc::proto::CapSubCommand;
+
+use tokio::time;
+use tracing::*;
+
+use crate::config::NetworkConfig;
+use crate::config::SaslConfig;
+use crate::config::SaslMechanism;
+use crate::Error;
+
+/// AUTHENTICATE payloads longer than this have to be sent in pieces.
+const CHUNK_SIZE: usize = 400;
+
+/// How long to wait on the server before giving up on the whole exchange.
+const TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+/// Registers with the server like `Client::identify()` but authenticates with SASL before
+/// ending capability negotiation. Returns once the server accepts the credentials.
+pub async fn identify(
+    client: &Client,
+    stream: &mut ClientStream,
+    network: &NetworkConfig,
+    sasl: &SaslConfig,
+) -> Result<(), Error> {
+    client.send_cap_req(&[Capability::Sasl])?;
+    client.send(Command::NICK(network.nickname.clone()))?;
+    client.send(Command::USER(
+        network.nickname.clone(),
+        "0".to_owned(),
+        network.nickname.clone(),
+    ))?;
+
+    match time::timeout(TIMEOUT, negotiate(client, stream, sasl)).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Sasl(String::from(
+            "timed out waiting for the server",
+        ))),
+    }
+}
+
+async fn negotiate(
+    client: &Client,
+    stream: &mut ClientStream,
+    sasl: &SaslConfig,
+) -> Result<(), Error> {
+    while let Some(message) = stream.next().await.transpose()? {
+        match message.command {
+            Command::CAP(_, CapSubCommand::ACK, Some(caps), _) if has_sasl(&caps) => {
+                debug!("Server acknowledged SASL, authenticating");
+                match sasl.mechanism {
+                    SaslMechanism::Plain => client.send_sasl_plain()?,
+                    SaslMechanism::External => client.send_sasl_external()?,
+                }
+            }
+            Command::CAP(_, CapSubCommand::NAK, Some(caps), _) if has_sasl(&caps) => {
+                client.send(Command::CAP(None, CapSubCommand::END, None, None))?;
+                return Err(Error::Sasl(String::from("server does not support SASL")));
+            }
+            Command::AUTHENTICATE(ref challenge) if challenge == "+" => match sasl.mechanism {
+                SaslMechanism::Plain => {
+                    for chunk in plain_payload(sasl) {
+                        client.send_sasl(chunk)?;
+                    }
+                }
+                // The server already knows who we are from the client certificate.
+                SaslMechanism::External => client.send_sasl("+")?,
+            },
+            Command::Response(Response::RPL_LOGGEDIN, ref args) => {
+                info!("Logged in as {}", args.get(2).map_or("", String::as_str));
+            }
+            Command::Response(Response::RPL_SASLSUCCESS, _) => {
+                client.send(Command::CAP(None, CapSubCommand::END, None, None))?;
+                return Ok(());
+            }
+            Command::Response(
+                response @ (Response::ERR_NICKLOCKED
+                | Response::ERR_SASLFAIL
+                | Response::ERR_SASLTOOLONG
+                | Response::ERR_SASLABORT
+                | Response::ERR_SASLALREADY),
+                ref args,
+            ) => {
+                client.send(Command::CAP(None, CapSubCommand::END, None, None))?;
+                let reason = args
+                    .last()
+                    .cloned()
+                    .unwrap_or_else(|| format!("{:?}", response));
+                return Err(Error::Sasl(reason));
+            }
+            _ => (),
+        }
+    }
+
+    Err(Error::Sasl(String::from(
+        "connection closed during authentication",
+    )))
+}
+
+fn has_sasl(caps: &str) -> bool {
+    caps.split_whitespace().any(|cap| cap == "sasl")
+}
+
+/// Base64 encodes the PLAIN credentials and splits them into AUTHENTICATE sized chunks. A
+/// payload that's an exact multiple of the chunk size is terminated with a lone `+`.
+fn plain_payload(sasl: &SaslConfig) -> Vec<String> {
+    let credentials = format!("{0}\0{0}\0{1}", sasl.username, sasl.password);
+    let encoded = BASE64.encode(credentials);
+
+    let mut chunks = encoded
+        .as_bytes()
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>();
+    if encoded.len() % CHUNK_SIZE == 0 {
+        chunks.push(String::from("+"));
+    }
+
+    chunks
+}
@@ -0,0 +1,308 @@
+//! Announcements on a cron schedule, said as written or asked of the model.
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono::NaiveDateTime;
+use chrono::NaiveTime;
+use chrono::TimeDelta;
+use chrono::TimeZone;
+use chrono::Timelike;
+use chrono::Utc;
+
+use serde::Deserialize;
+
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::config::JobConfig;
+use crate::config::ScheduleConfig;
+
+/// Runs missed by more than this, say while disconnected, are skipped rather than late.
+const MAX_LATE: TimeDelta = TimeDelta::minutes(10);
+
+/// How far ahead to look for the next run. Covers the 29th of February even across 2100.
+const MAX_DAYS_AHEAD: u64 = 8 * 366;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A standard five field cron expression, `minute hour day-of-month month day-of-week`, or one
+/// of `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cron {
+    expression: String,
+    /// Each field as a bit per allowed value.
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// A day has to match both day fields, unless both are restricted, when either will do.
+    either_day: bool,
+}
+
+impl fmt::Debug for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.expression)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            fields => fields,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("{:?} doesn't have five fields", expression));
+        };
+        let weekdays = field(weekdays, 0, 7, &WEEKDAYS)?;
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59, &[])?,
+            hours: field(hours, 0, 23, &[])?,
+            days: field(days, 1, 31, &[])?,
+            months: field(months, 1, 12, &MONTHS)? >> 1,
+            // Sunday is both 0 and 7
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !days.starts_with('*') && !fields[4].starts_with('*'),
+            expression,
+        })
+    }
+}
+
+/// One field's allowed values from `min` to `max` as bits, from a list of values, ranges
+/// and steps like `1,15`, `mon-fri` or `*/10`. `names` are words for values from `min` up.
+fn field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |value: &str| {
+        let named = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+            .map(|i| i as u32 + min);
+        named
+            .or_else(|| value.parse().ok())
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("{:?} isn't between {} and {}", value, min, max))
+    };
+
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("{:?} isn't a step", step)),
+            },
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` is every 15 from 5
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl Cron {
+    /// The first time after `after` the expression matches, to the minute.
+    fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let is_set = |bits: u64, value: u32| bits & (1 << value) != 0;
+        for date in start.date().iter_days().take(MAX_DAYS_AHEAD as usize) {
+            let day = is_set(self.days, date.day());
+            let weekday = is_set(self.weekdays, date.weekday().num_days_from_sunday());
+            let day = match self.either_day {
+                true => day || weekday,
+                false => day && weekday,
+            };
+            if !day || !is_set(self.months, date.month0()) {
+                continue;
+            }
+
+            for hour in (0..24).filter(|&hour| is_set(self.hours, hour)) {
+                for minute in (0..60).filter(|&minute| is_set(self.minutes, minute)) {
+                    let time = date.and_time(NaiveTime::from_hms_opt(hour, minute, 0)?);
+                    if time >= start {
+                        return Some(time);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// What time zone a schedule is in: the machine's own, which `TZ` can change, e.g.
+/// `TZ=Europe/Berlin`, or a fixed offset from UTC.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Zone {
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(zone: String) -> Result<Self, Self::Error> {
+        if zone.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if zone.eq_ignore_ascii_case("utc") || zone == "Z" {
+            return Ok(Self::Fixed(
+                FixedOffset::east_opt(0).expect("UTC is an offset"),
+            ));
+        }
+
+        // +05:30, -08 and so on
+        let invalid = || format!("{:?} isn't local, UTC or an offset like +05:30", zone);
+        let (sign, offset) = match zone.split_at_checked(1) {
+            Some(("+", offset)) => (1, offset),
+            Some(("-", offset)) => (-1, offset),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+        let (Ok(hours), Ok(minutes)) = (hours.parse::<i32>(), minutes.parse::<i32>()) else {
+            return Err(invalid());
+        };
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(Self::Fixed)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Zone {
+    fn local(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => time.with_timezone(&Local).naive_local(),
+            Self::Fixed(offset) => time.with_timezone(offset).naive_local(),
+        }
+    }
+
+    /// `None` for times skipped when the clocks go forward.
+    fn utc(&self, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let time = match self {
+            Self::Local => Local.from_local_datetime(&time).earliest()?.fixed_offset(),
+            Self::Fixed(offset) => offset.from_local_datetime(&time).earliest()?,
+        };
+
+        Some(time.to_utc())
+    }
+
+    /// `time` as it's written in this zone.
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        const FORMAT: &str = "%a %-d %b %H:%M %:z";
+        match self {
+            Self::Local => time.with_timezone(&Local).format(FORMAT).to_string(),
+            Self::Fixed(offset) => time.with_timezone(offset).format(FORMAT).to_string(),
+        }
+    }
+
+    /// The first time after `after` that `cron` matches in this zone.
+    fn next(&self, cron: &Cron, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut local = self.local(after);
+        loop {
+            local = cron.next_after(local)?;
+            match self.utc(local) {
+                Some(time) if time > after => return Some(time),
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// One network's scheduled jobs and when each runs next.
+pub struct Schedule {
+    jobs: Vec<Job>,
+}
+
+struct Job {
+    config: JobConfig,
+    zone: Zone,
+    next: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Schedule {
+    /// The jobs in `config` that run on `network`.
+    pub fn new(config: &ScheduleConfig, network: &str) -> Self {
+        let now = Utc::now();
+        let jobs = config
+            .jobs
+            .iter()
+            .filter(|job| job.network.as_ref().is_none_or(|name| name == network))
+            .map(|job| {
+                let zone = job.timezone.unwrap_or(config.timezone);
+                Job {
+                    config: job.clone(),
+                    zone,
+                    next: Mutex::new(zone.next(&job.cron, now)),
+                }
+            })
+            .collect();
+
+        Self { jobs }
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.jobs
+            .iter()
+            .filter_map(|job| *job.next.lock().expect("schedule lock poisoned"))
+            .min()
+    }
+
+    /// The jobs due to run now, each moved on to its next run. Any that are too late to bother
+    /// with are just moved on.
+    pub fn due(&self) -> Vec<JobConfig> {
+        let now = Utc::now();
+        let mut due = Vec::new();
+        for job in &self.jobs {
+            let mut next = job.next.lock().expect("schedule lock poisoned");
+            let Some(at) = next.filter(|&at| at <= now) else {
+                continue;
+            };
+            if now - at <= MAX_LATE {
+                due.push(job.config.clone());
+            }
+            *next = job.zone.next(&job.config.cron, now);
+        }
+
+        due
+    }
+
+    /// Every job with when it next runs, written in its own time zone.
+    pub fn list(&self) -> Vec<(&JobConfig, Option<String>)> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                let next = *job.next.lock().expect("schedule lock poisoned");
+                (&job.config, next.map(|next| job.zone.format(next)))
+            })
+            .collect()
+    }
+}
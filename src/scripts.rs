@@ -0,0 +1,223 @@
+use regex::RegexBuilder;
+
+use rhai::Array;
+use rhai::Dynamic;
+use rhai::Engine;
+use rhai::Scope;
+use rhai::AST;
+
+use tracing::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use crate::config::ScriptsConfig;
+
+/// How often the scripts directory is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Keeps a runaway script from hanging the connection.
+const MAX_OPERATIONS: u64 = 100_000;
+
+const MAX_REGEX_SIZE: usize = 1 << 16;
+
+/// Called with `(nick, channel, text)` for every message nothing else handled. Returning a
+/// string replies with it.
+const ON_MESSAGE: &str = "on_message";
+
+/// Called with `(nick, channel, text)` before a question goes to the model. Returning a string
+/// asks that instead.
+const ON_QUESTION: &str = "on_question";
+
+/// Called with `(nick, channel, line)` for each line of the model's answer. Returning a string
+/// says that instead.
+const ON_ANSWER: &str = "on_answer";
+
+/// Rhai scripts from the scripts directory, reloaded whenever they change. In private messages
+/// `channel` is empty.
+pub struct Scripts {
+    dir: PathBuf,
+    engine: Engine,
+    loaded: Mutex<Loaded>,
+}
+
+#[derive(Default)]
+struct Loaded {
+    checked: Option<Instant>,
+    /// Each script's path and when it was last modified, to spot changes.
+    stamps: Vec<(PathBuf, SystemTime)>,
+    scripts: Vec<(String, AST)>,
+}
+
+impl Scripts {
+    pub fn new(config: &ScriptsConfig) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.register_fn("matches", |text: &str, pattern: &str| {
+            RegexBuilder::new(pattern)
+                .size_limit(MAX_REGEX_SIZE)
+                .build()
+                .is_ok_and(|regex| regex.is_match(text))
+        });
+        engine.register_fn("captures", |text: &str, pattern: &str| -> Array {
+            let Ok(regex) = RegexBuilder::new(pattern)
+                .size_limit(MAX_REGEX_SIZE)
+                .build()
+            else {
+                return Array::new();
+            };
+            regex
+                .captures(text)
+                .map(|captures| {
+                    captures
+                        .iter()
+                        .map(|group| group.map_or("", |group| group.as_str()).into())
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+
+        Self {
+            dir: config.dir.clone(),
+            engine,
+            loaded: Mutex::new(Loaded::default()),
+        }
+    }
+
+    /// A reply to `text` from the first script that has one.
+    pub fn on_message(&self, nick: &str, channel: &str, text: &str) -> Option<String> {
+        self.call(ON_MESSAGE, nick, channel, text, |_, reply| Some(reply))
+    }
+
+    /// `question` as the scripts would rather have the model see it.
+    pub fn on_question(&self, nick: &str, channel: &str, question: String) -> String {
+        self.rewrite(ON_QUESTION, nick, channel, question)
+    }
+
+    /// `line` of an answer as the scripts would rather have it said.
+    pub fn on_answer(&self, nick: &str, channel: &str, line: String) -> String {
+        self.rewrite(ON_ANSWER, nick, channel, line)
+    }
+
+    /// Passes `text` through every script defining `function`, in order.
+    fn rewrite(&self, function: &str, nick: &str, channel: &str, text: String) -> String {
+        let mut text = text;
+        self.call(
+            function,
+            nick,
+            channel,
+            &text.clone(),
+            |script, rewritten| {
+                debug!("{} rewrote {:?} as {:?}", script, text, rewritten);
+                text = rewritten;
+                None::<()>
+            },
+        );
+        text
+    }
+
+    /// Calls `function` in each script that defines it until `done` returns something for a
+    /// string it returned. Scripts returning anything else are passed over.
+    fn call<T>(
+        &self,
+        function: &str,
+        nick: &str,
+        channel: &str,
+        text: &str,
+        mut done: impl FnMut(&str, String) -> Option<T>,
+    ) -> Option<T> {
+        let mut loaded = self.loaded.lock().expect("scripts lock poisoned");
+        self.reload(&mut loaded);
+
+        let mut text = text.to_string();
+        for (name, ast) in &loaded.scripts {
+            if !ast.iter_functions().any(|f| f.name == function) {
+                continue;
+            }
+            let args = (nick.to_string(), channel.to_string(), text.clone());
+            let result = self
+                .engine
+                .call_fn::<Dynamic>(&mut Scope::new(), ast, function, args);
+            match result {
+                Ok(value) => {
+                    if let Ok(value) = value.into_string() {
+                        text = value.clone();
+                        if let Some(done) = done(name, value) {
+                            return Some(done);
+                        }
+                    }
+                }
+                Err(e) => warn!("Script {} failed in {}: {}", name, function, e),
+            }
+        }
+
+        None
+    }
+
+    /// Recompiles everything if any script has been added, changed or removed.
+    fn reload(&self, loaded: &mut Loaded) {
+        if loaded
+            .checked
+            .is_some_and(|checked| checked.elapsed() < RELOAD_INTERVAL)
+        {
+            return;
+        }
+        let first = loaded.checked.replace(Instant::now()).is_none();
+
+        let stamps = match stamps(&self.dir) {
+            Ok(stamps) => stamps,
+            Err(e) => {
+                if first {
+                    warn!("Unable to read scripts from {}: {}", self.dir.display(), e);
+                }
+                Vec::new()
+            }
+        };
+        if stamps == loaded.stamps {
+            return;
+        }
+        loaded.scripts = stamps
+            .iter()
+            .filter_map(|(path, _)| {
+                let name = path.file_name()?.to_string_lossy().into_owned();
+                match self.engine.compile_file(path.clone()) {
+                    Ok(ast) => Some((name, ast)),
+                    Err(e) => {
+                        warn!("Unable to load script {}: {}", name, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        loaded.stamps = stamps;
+        info!(
+            "Loaded {} scripts from {}",
+            loaded.scripts.len(),
+            self.dir.display()
+        );
+    }
+}
+
+/// Every `.rhai` file in `dir` with when it was last modified, sorted by name.
+fn stamps(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut stamps = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "rhai")
+        })
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect::<Vec<_>>();
+    stamps.sort();
+    Ok(stamps)
+}
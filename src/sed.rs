@@ -0,0 +1,144 @@
+use regex::Regex;
+use regex::RegexBuilder;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::casemap::CaseMapping;
+
+/// How many of someone's recent lines in a channel a correction can reach back to.
+const RECENT_LINES: usize = 5;
+/// Keeps patterns from blowing up into huge automatons.
+const MAX_REGEX_SIZE: usize = 1 << 16;
+
+/// `s/foo/bar/` fixes for what people said recently, for when typos matter.
+#[derive(Default)]
+pub struct Corrections {
+    /// By folded channel and nick, oldest line first.
+    recent: Mutex<HashMap<(String, String), VecDeque<String>>>,
+}
+
+impl Corrections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `msg` to `nick`'s most recent line in `channel` that it matches if it's a
+    /// substitution, and returns what they meant. Anything else is remembered to be corrected
+    /// later. Channels and nicks are compared the way `casemapping` says.
+    pub fn handle(
+        &self,
+        channel: &str,
+        nick: &str,
+        msg: &str,
+        casemapping: CaseMapping,
+    ) -> Option<String> {
+        let mut recent = self.recent.lock().expect("corrections lock poisoned");
+        let lines = recent
+            .entry((casemapping.fold(channel), casemapping.fold(nick)))
+            .or_default();
+
+        let Some(substitution) = Substitution::parse(msg) else {
+            if lines.len() >= RECENT_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(msg.to_string());
+            return None;
+        };
+        let line = lines
+            .iter_mut()
+            .rev()
+            .find(|line| substitution.regex.is_match(line))?;
+        *line = substitution.apply(line);
+
+        Some(format!("{} meant: {}", nick, line))
+    }
+}
+
+struct Substitution {
+    regex: Regex,
+    /// In the regex crate's syntax, `$1` rather than sed's `\1`.
+    replacement: String,
+    global: bool,
+}
+
+impl Substitution {
+    /// Parses `s/pattern/replacement/flags`, where the flags are any of `g` and `i` and the
+    /// last slash is optional.
+    fn parse(msg: &str) -> Option<Self> {
+        let parts = split_unescaped(msg.strip_prefix("s/")?);
+        let (pattern, replacement, flags) = match parts.as_slice() {
+            [pattern, replacement] => (pattern, replacement, ""),
+            [pattern, replacement, flags] => (pattern, replacement, flags.as_str()),
+            _ => return None,
+        };
+        if pattern.is_empty() || !flags.chars().all(|flag| matches!(flag, 'g' | 'i')) {
+            return None;
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(flags.contains('i'))
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .ok()?;
+
+        Some(Self {
+            regex,
+            replacement: to_regex_replacement(replacement),
+            global: flags.contains('g'),
+        })
+    }
+
+    fn apply(&self, line: &str) -> String {
+        let replacement = self.replacement.as_str();
+        match self.global {
+            true => self.regex.replace_all(line, replacement).into_owned(),
+            false => self.regex.replace(line, replacement).into_owned(),
+        }
+    }
+}
+
+/// Splits on slashes that aren't escaped with a backslash, unescaping them.
+fn split_unescaped(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => parts.last_mut().expect("never empty").push('/'),
+                Some(c) => {
+                    let part = parts.last_mut().expect("never empty");
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => parts.last_mut().expect("never empty").push('\\'),
+            },
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().expect("never empty").push(c),
+        }
+    }
+
+    parts
+}
+
+/// Turns sed's `&` and `\1` into the regex crate's `${0}` and `${1}`, and escapes `$`.
+fn to_regex_replacement(sed: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = sed.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => replacement.push_str("$$"),
+            '&' => replacement.push_str("${0}"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => replacement.push_str(&format!("${{{}}}", digit)),
+                Some('$') => replacement.push_str("$$"),
+                Some(c) => replacement.push(c),
+                None => replacement.push('\\'),
+            },
+            c => replacement.push(c),
+        }
+    }
+
+    replacement
+}
@@ -0,0 +1,165 @@
+use chrono::DateTime;
+use chrono::TimeDelta;
+use chrono::Utc;
+
+use irc::client::prelude::*;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::isupport::ISupport;
+use crate::storage::SeenStore;
+use crate::Error;
+
+/// What somebody was doing when they were last seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Activity {
+    Said(String),
+    /// Said something, from someone who'd rather it wasn't kept.
+    Spoke,
+    Joined,
+    Parted,
+    Quit(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Sighting {
+    pub nick: String,
+    pub at: DateTime<Utc>,
+    /// None for quits, which happen everywhere at once.
+    pub channel: Option<String>,
+    pub activity: Activity,
+}
+
+impl Sighting {
+    /// Where and doing what, e.g. "in #rust, saying: hi".
+    pub fn describe(&self) -> String {
+        let place = match &self.channel {
+            Some(channel) => format!("in {}, ", channel),
+            None => String::new(),
+        };
+        match &self.activity {
+            Activity::Said(msg) => format!("{}saying: {}", place, msg),
+            Activity::Spoke => format!("{}talking", place),
+            Activity::Joined => format!("{}joining", place),
+            Activity::Parted => format!("{}leaving", place),
+            Activity::Quit(reason) if reason.is_empty() => String::from("quitting"),
+            Activity::Quit(reason) => format!("quitting ({})", reason),
+        }
+    }
+}
+
+/// When everyone on one network was last active and what they were up to, from everything
+/// that goes by in our channels. Changes are written through to the store, if there is one.
+pub struct Seen {
+    scope: String,
+    sightings: Mutex<HashMap<String, Sighting>>,
+    store: Option<Arc<dyn SeenStore>>,
+}
+
+impl Seen {
+    pub async fn load(scope: &str, store: Option<Arc<dyn SeenStore>>) -> Result<Self, Error> {
+        let sightings = match &store {
+            Some(store) => store.sightings(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            sightings: Mutex::new(
+                sightings
+                    .into_iter()
+                    .map(|sighting| (sighting.nick.to_lowercase(), sighting))
+                    .collect(),
+            ),
+            store,
+        })
+    }
+
+    pub fn get(&self, nick: &str) -> Option<Sighting> {
+        self.sightings
+            .lock()
+            .expect("seen lock poisoned")
+            .get(&nick.to_lowercase())
+            .cloned()
+    }
+
+    /// Notes whoever sent `message` if it's something worth reporting. Private messages stay
+    /// private, and so does what people who've opted out of being remembered say.
+    pub async fn saw(&self, message: &Message, isupport: &ISupport, opted_out: bool) {
+        let Some(Prefix::Nickname(nick, _, _)) = &message.prefix else {
+            return;
+        };
+        let (channel, activity) = match &message.command {
+            Command::PRIVMSG(target, _) if isupport.is_channel(target) && opted_out => {
+                (Some(target), Activity::Spoke)
+            }
+            Command::PRIVMSG(target, msg) if isupport.is_channel(target) => {
+                (Some(target), Activity::Said(msg.clone()))
+            }
+            Command::JOIN(channel, _, _) => (Some(channel), Activity::Joined),
+            Command::PART(channel, _) => (Some(channel), Activity::Parted),
+            Command::QUIT(_) if opted_out => (None, Activity::Quit(String::new())),
+            Command::QUIT(reason) => (None, Activity::Quit(reason.clone().unwrap_or_default())),
+            _ => return,
+        };
+        self.record(Sighting {
+            nick: nick.clone(),
+            at: Utc::now(),
+            channel: channel.cloned(),
+            activity,
+        })
+        .await;
+    }
+
+    /// Forgets the words of whatever `nick` was last seen saying, for when they opt out.
+    pub async fn forget_words(&self, nick: &str) {
+        let sighting = self.get(nick).map(|sighting| match sighting.activity {
+            Activity::Said(_) => Sighting {
+                activity: Activity::Spoke,
+                ..sighting
+            },
+            Activity::Quit(_) => Sighting {
+                activity: Activity::Quit(String::new()),
+                ..sighting
+            },
+            _ => sighting,
+        });
+        if let Some(sighting) = sighting {
+            self.record(sighting).await;
+        }
+    }
+
+    async fn record(&self, sighting: Sighting) {
+        self.sightings
+            .lock()
+            .expect("seen lock poisoned")
+            .insert(sighting.nick.to_lowercase(), sighting.clone());
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.saw(&self.scope, &sighting).await {
+                warn!("Unable to save sighting of {}: {}", sighting.nick, e);
+            }
+        }
+    }
+}
+
+/// Roughly how long ago something `elapsed` back happened, e.g. "3 hours ago".
+pub fn ago(elapsed: TimeDelta) -> String {
+    let plural = |n: i64, unit: &str| match n {
+        1 => format!("1 {} ago", unit),
+        n => format!("{} {}s ago", n, unit),
+    };
+    if elapsed.num_minutes() < 1 {
+        String::from("just now")
+    } else if elapsed.num_hours() < 1 {
+        plural(elapsed.num_minutes(), "minute")
+    } else if elapsed.num_days() < 1 {
+        plural(elapsed.num_hours(), "hour")
+    } else {
+        plural(elapsed.num_days(), "day")
+    }
+}
@@ -0,0 +1,56 @@
+use crate::isupport::ISupport;
+
+/// Who the server says we are, which it puts in front of everything it relays for us. Until
+/// we've seen what it calls us, leaves room for as long a `user@host` as it allows.
+pub fn source(nick: &str, userhost: Option<&str>, isupport: &ISupport) -> String {
+    match userhost {
+        Some(userhost) => format!("{}!{}", nick, userhost),
+        None => format!(
+            "{}!{}@{}",
+            nick,
+            "x".repeat(isupport.userlen),
+            "x".repeat(isupport.hostlen)
+        ),
+    }
+}
+
+/// How many bytes of text fit in one PRIVMSG to `target` once the server has relayed it as
+/// `:<source> PRIVMSG <target> :<text>\r\n`, in lines of at most `line_length`.
+pub fn privmsg_budget(source: &str, target: &str, line_length: usize) -> usize {
+    let overhead = ":".len() + source.len() + " PRIVMSG ".len() + target.len() + " :\r\n".len();
+    line_length.saturating_sub(overhead).max(1)
+}
+
+/// Splits `text` into pieces of at most `max_bytes`, only ever on UTF-8 boundaries and after
+/// the last space where there is one.
+pub fn split(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+
+    let mut remaining = text;
+    while remaining.len() > max_bytes {
+        let mut end = max_bytes;
+        while !remaining.is_char_boundary(end) {
+            end -= 1;
+        }
+        // A single character wider than the budget still has to go somewhere
+        if end == 0 {
+            end = remaining
+                .chars()
+                .next()
+                .map_or(remaining.len(), char::len_utf8);
+        }
+        if let Some(space) = remaining[..end].rfind(' ').filter(|&space| space > 0) {
+            end = space + 1;
+        }
+
+        let (chunk, rest) = remaining.split_at(end);
+        let chunk = chunk.trim_end();
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        remaining = rest;
+    }
+    chunks.push(remaining);
+
+    chunks
+}
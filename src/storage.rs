@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::archive::LogLine;
+use crate::casemap::CaseMapping;
+use crate::config::StorageConfig;
+use crate::llm::ChatMessage;
+use crate::memos::Memo;
+use crate::recall::Recollection;
+use crate::reminders::Reminder;
+use crate::seen::Sighting;
+use crate::usage::UsageRecord;
+use crate::Error;
+
+pub mod postgres;
+pub mod redis;
+pub mod sqlite;
+
+/// Everything pickles keeps across restarts.
+#[async_trait]
+pub trait Store:
+    MemoryStore
+    + IgnoreStore
+    + UsageStore
+    + SeenStore
+    + MemoStore
+    + ReminderStore
+    + KarmaStore
+    + LocationStore
+    + TriviaStore
+    + WordGameStore
+    + ArchiveStore
+{
+    /// Waits for outstanding writes and closes the store.
+    async fn close(&self);
+}
+
+/// Somewhere conversation memory survives restarts.
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Every conversation remembered in `scope`, oldest message first.
+    async fn load(&self, scope: &str) -> Result<HashMap<String, VecDeque<ChatMessage>>, Error>;
+
+    async fn push(&self, scope: &str, nick: &str, message: &ChatMessage) -> Result<(), Error>;
+
+    /// Drops all but the newest `keep` messages remembered for `nick`.
+    async fn truncate(&self, scope: &str, nick: &str, keep: usize) -> Result<(), Error>;
+
+    /// Running summaries of conversations in `scope` that outgrew memory, by nick.
+    async fn summaries(&self, scope: &str) -> Result<HashMap<String, String>, Error>;
+
+    /// Replaces the running summary of the conversation with `nick`.
+    async fn set_summary(&self, scope: &str, nick: &str, summary: &str) -> Result<(), Error>;
+
+    /// Drops everything remembered about `nick`, recollections and summary included.
+    async fn forget(&self, scope: &str, nick: &str) -> Result<(), Error>;
+
+    async fn forget_all(&self, scope: &str) -> Result<(), Error>;
+
+    /// Every exchange kept for long term recall in `scope`, oldest first.
+    async fn recollections(&self, scope: &str)
+        -> Result<HashMap<String, Vec<Recollection>>, Error>;
+
+    async fn add_recollection(
+        &self,
+        scope: &str,
+        nick: &str,
+        recollection: &Recollection,
+    ) -> Result<(), Error>;
+
+    /// Drops all but the newest `keep` recollections of `nick`.
+    async fn truncate_recollections(
+        &self,
+        scope: &str,
+        nick: &str,
+        keep: usize,
+    ) -> Result<(), Error>;
+
+    /// Everyone in `scope` who asked for nothing they say to be kept.
+    async fn opt_outs(&self, scope: &str) -> Result<Vec<String>, Error>;
+
+    async fn opt_out(&self, scope: &str, nick: &str) -> Result<(), Error>;
+
+    async fn opt_in(&self, scope: &str, nick: &str) -> Result<(), Error>;
+}
+
+/// The hostmasks pickles ignores.
+#[async_trait]
+pub trait IgnoreStore: Send + Sync {
+    async fn ignores(&self, scope: &str) -> Result<Vec<String>, Error>;
+
+    async fn ignore(&self, scope: &str, mask: &str) -> Result<(), Error>;
+
+    async fn unignore(&self, scope: &str, mask: &str) -> Result<(), Error>;
+}
+
+/// Tokens spent, by day, nick and channel.
+#[async_trait]
+pub trait UsageStore: Send + Sync {
+    async fn usage(&self, scope: &str) -> Result<Vec<UsageRecord>, Error>;
+
+    /// Adds to whatever is already recorded for the same day, nick and channel.
+    async fn add_usage(&self, scope: &str, record: &UsageRecord) -> Result<(), Error>;
+}
+
+/// When everyone was last seen.
+#[async_trait]
+pub trait SeenStore: Send + Sync {
+    async fn sightings(&self, scope: &str) -> Result<Vec<Sighting>, Error>;
+
+    /// Replaces whatever `sighting.nick` was last seen doing.
+    async fn saw(&self, scope: &str, sighting: &Sighting) -> Result<(), Error>;
+}
+
+/// Messages left with `!tell` that haven't been delivered yet.
+#[async_trait]
+pub trait MemoStore: Send + Sync {
+    async fn memos(&self, scope: &str) -> Result<Vec<Memo>, Error>;
+
+    async fn add_memo(&self, scope: &str, memo: &Memo) -> Result<(), Error>;
+
+    /// Drops every memo for `to`, once they've been delivered.
+    async fn clear_memos(&self, scope: &str, to: &str) -> Result<(), Error>;
+}
+
+/// Reminders that haven't gone off yet.
+#[async_trait]
+pub trait ReminderStore: Send + Sync {
+    async fn reminders(&self, scope: &str) -> Result<Vec<Reminder>, Error>;
+
+    /// Returns the id to remove the reminder by once it's gone off.
+    async fn add_reminder(&self, scope: &str, reminder: &Reminder) -> Result<i64, Error>;
+
+    async fn remove_reminder(&self, scope: &str, id: i64) -> Result<(), Error>;
+}
+
+/// Karma scores.
+#[async_trait]
+pub trait KarmaStore: Send + Sync {
+    /// Every score in `scope`, by name as it was first written.
+    async fn karma(&self, scope: &str) -> Result<Vec<(String, i64)>, Error>;
+
+    async fn add_karma(&self, scope: &str, name: &str, delta: i64) -> Result<(), Error>;
+}
+
+/// Where people want the weather for when they don't say.
+#[async_trait]
+pub trait LocationStore: Send + Sync {
+    /// Every saved location in `scope`, by nick.
+    async fn locations(&self, scope: &str) -> Result<Vec<(String, String)>, Error>;
+
+    /// Replaces whatever location `nick` had saved.
+    async fn set_location(&self, scope: &str, nick: &str, location: &str) -> Result<(), Error>;
+}
+
+/// Points scored at `!trivia`.
+#[async_trait]
+pub trait TriviaStore: Send + Sync {
+    /// Every score in `scope`, by nick as it was first written.
+    async fn trivia_scores(&self, scope: &str) -> Result<Vec<(String, i64)>, Error>;
+
+    async fn add_trivia_score(&self, scope: &str, nick: &str, points: i64) -> Result<(), Error>;
+}
+
+/// Points scored at the word games, by game: `wordle` or `hangman`.
+#[async_trait]
+pub trait WordGameStore: Send + Sync {
+    /// Every score at `game` in `scope`, by nick as it was first written.
+    async fn word_game_scores(&self, scope: &str, game: &str) -> Result<Vec<(String, i64)>, Error>;
+
+    async fn add_word_game_score(
+        &self,
+        scope: &str,
+        game: &str,
+        nick: &str,
+        points: i64,
+    ) -> Result<(), Error>;
+}
+
+/// Channel logs, when `[archive]` keeps them in the database.
+#[async_trait]
+pub trait ArchiveStore: Send + Sync {
+    async fn log_line(&self, scope: &str, line: &LogLine) -> Result<(), Error>;
+
+    /// The last `n` lines logged in `channel`, oldest first.
+    async fn logged(&self, scope: &str, channel: &str, n: usize) -> Result<Vec<LogLine>, Error>;
+
+    /// Drops every line `nick` said in any channel, as `archive::said_by()` tells.
+    async fn forget_said(
+        &self,
+        scope: &str,
+        nick: &str,
+        casemapping: CaseMapping,
+    ) -> Result<(), Error>;
+}
+
+/// Opens whichever store `config.url` points at, by its scheme.
+pub async fn connect(config: &StorageConfig) -> Result<Arc<dyn Store>, Error> {
+    Ok(match config.url.split_once("://") {
+        Some(("postgres" | "postgresql", _)) => {
+            Arc::new(postgres::Postgres::connect(&config.url).await?)
+        }
+        Some(("redis", _)) => Arc::new(redis::Redis::connect(&config.url).await?),
+        _ => Arc::new(sqlite::Sqlite::connect(&config.url).await?),
+    })
+}
@@ -0,0 +1,740 @@
+use async_trait::async_trait;
+
+use sqlx::postgres::PgPool;
+use sqlx::Row;
+
+use reqwest::Url;
+
+use chrono::DateTime;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use super::ArchiveStore;
+use super::IgnoreStore;
+use super::KarmaStore;
+use super::LocationStore;
+use super::MemoStore;
+use super::MemoryStore;
+use super::ReminderStore;
+use super::SeenStore;
+use super::Store;
+use super::TriviaStore;
+use super::UsageStore;
+use super::WordGameStore;
+use crate::archive;
+use crate::archive::LogLine;
+use crate::casemap::CaseMapping;
+use crate::llm::ChatMessage;
+use crate::llm::Role;
+use crate::llm::Usage;
+use crate::memos::Memo;
+use crate::recall::Recollection;
+use crate::reminders::Reminder;
+use crate::seen::Activity;
+use crate::seen::Sighting;
+use crate::usage::Spent;
+use crate::usage::UsageRecord;
+use crate::Error;
+
+pub struct Postgres {
+    pool: PgPool,
+}
+
+impl Postgres {
+    /// Connects to the database at `url`, e.g. `postgres://pickles@localhost/pickles`, and
+    /// brings its schema up to date.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let pool = PgPool::connect(url).await?;
+        sqlx::migrate!("migrations/postgres").run(&pool).await?;
+        info!("Connected to database {}", redacted(url));
+
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl MemoryStore for Postgres {
+    async fn load(&self, scope: &str) -> Result<HashMap<String, VecDeque<ChatMessage>>, Error> {
+        let rows =
+            sqlx::query("SELECT nick, role, content FROM memory WHERE scope = $1 ORDER BY id")
+                .bind(scope)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut memory: HashMap<String, VecDeque<ChatMessage>> = HashMap::new();
+        for row in rows {
+            let role = row.get::<&str, _>("role");
+            let Ok(role) = role.parse::<Role>() else {
+                warn!("Skipping remembered message with unknown role {}", role);
+                continue;
+            };
+            memory
+                .entry(row.get("nick"))
+                .or_default()
+                .push_back(ChatMessage {
+                    role,
+                    content: row.get("content"),
+                });
+        }
+
+        Ok(memory)
+    }
+
+    async fn push(&self, scope: &str, nick: &str, message: &ChatMessage) -> Result<(), Error> {
+        sqlx::query("INSERT INTO memory (scope, nick, role, content) VALUES ($1, $2, $3, $4)")
+            .bind(scope)
+            .bind(nick)
+            .bind(message.role.as_str())
+            .bind(&message.content)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn truncate(&self, scope: &str, nick: &str, keep: usize) -> Result<(), Error> {
+        sqlx::query(
+            "DELETE FROM memory WHERE scope = $1 AND nick = $2 AND id NOT IN \
+             (SELECT id FROM memory WHERE scope = $1 AND nick = $2 ORDER BY id DESC LIMIT $3)",
+        )
+        .bind(scope)
+        .bind(nick)
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn summaries(&self, scope: &str) -> Result<HashMap<String, String>, Error> {
+        let rows = sqlx::query("SELECT nick, content FROM summaries WHERE scope = $1")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("nick"), row.get("content")))
+            .collect())
+    }
+
+    async fn set_summary(&self, scope: &str, nick: &str, summary: &str) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO summaries (scope, nick, content) VALUES ($1, $2, $3) \
+             ON CONFLICT (scope, nick) DO UPDATE SET \
+             content = excluded.content, updated_at = extract(epoch FROM now())::BIGINT",
+        )
+        .bind(scope)
+        .bind(nick)
+        .bind(summary)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn forget(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        for table in ["memory", "recall", "summaries"] {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE scope = $1 AND nick = $2",
+                table
+            ))
+            .bind(scope)
+            .bind(nick)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn forget_all(&self, scope: &str) -> Result<(), Error> {
+        for table in ["memory", "recall", "summaries"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE scope = $1", table))
+                .bind(scope)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn recollections(
+        &self,
+        scope: &str,
+    ) -> Result<HashMap<String, Vec<Recollection>>, Error> {
+        let rows =
+            sqlx::query("SELECT nick, content, embedding FROM recall WHERE scope = $1 ORDER BY id")
+                .bind(scope)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut recollections: HashMap<String, Vec<Recollection>> = HashMap::new();
+        for row in rows {
+            let embedding = row
+                .get::<&[u8], _>("embedding")
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().expect("chunks are 4 bytes")))
+                .collect();
+            recollections
+                .entry(row.get("nick"))
+                .or_default()
+                .push(Recollection {
+                    content: row.get("content"),
+                    embedding,
+                });
+        }
+
+        Ok(recollections)
+    }
+
+    async fn add_recollection(
+        &self,
+        scope: &str,
+        nick: &str,
+        recollection: &Recollection,
+    ) -> Result<(), Error> {
+        let embedding = recollection
+            .embedding
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        sqlx::query("INSERT INTO recall (scope, nick, content, embedding) VALUES ($1, $2, $3, $4)")
+            .bind(scope)
+            .bind(nick)
+            .bind(&recollection.content)
+            .bind(embedding)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn truncate_recollections(
+        &self,
+        scope: &str,
+        nick: &str,
+        keep: usize,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "DELETE FROM recall WHERE scope = $1 AND nick = $2 AND id NOT IN \
+             (SELECT id FROM recall WHERE scope = $1 AND nick = $2 ORDER BY id DESC LIMIT $3)",
+        )
+        .bind(scope)
+        .bind(nick)
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn opt_outs(&self, scope: &str) -> Result<Vec<String>, Error> {
+        let rows = sqlx::query("SELECT nick FROM opt_outs WHERE scope = $1 ORDER BY created_at")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("nick")).collect())
+    }
+
+    async fn opt_out(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        sqlx::query("INSERT INTO opt_outs (scope, nick) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(scope)
+            .bind(nick)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn opt_in(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM opt_outs WHERE scope = $1 AND nick = $2")
+            .bind(scope)
+            .bind(nick)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IgnoreStore for Postgres {
+    async fn ignores(&self, scope: &str) -> Result<Vec<String>, Error> {
+        let rows = sqlx::query("SELECT mask FROM ignores WHERE scope = $1 ORDER BY created_at")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("mask")).collect())
+    }
+
+    async fn ignore(&self, scope: &str, mask: &str) -> Result<(), Error> {
+        sqlx::query("INSERT INTO ignores (scope, mask) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(scope)
+            .bind(mask)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unignore(&self, scope: &str, mask: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM ignores WHERE scope = $1 AND mask = $2")
+            .bind(scope)
+            .bind(mask)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UsageStore for Postgres {
+    async fn usage(&self, scope: &str) -> Result<Vec<UsageRecord>, Error> {
+        let rows = sqlx::query(
+            "SELECT day, nick, channel, requests, prompt_tokens, completion_tokens FROM usage \
+             WHERE scope = $1",
+        )
+        .bind(scope)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let day = row.get::<&str, _>("day");
+            let Ok(day) = day.parse() else {
+                warn!("Skipping usage recorded on unknown day {}", day);
+                continue;
+            };
+            records.push(UsageRecord {
+                day,
+                nick: row.get("nick"),
+                channel: row.get("channel"),
+                spent: Spent {
+                    requests: row.get::<i64, _>("requests") as u64,
+                    usage: Usage {
+                        prompt_tokens: row.get::<i64, _>("prompt_tokens") as u64,
+                        completion_tokens: row.get::<i64, _>("completion_tokens") as u64,
+                    },
+                },
+            });
+        }
+
+        Ok(records)
+    }
+
+    async fn add_usage(&self, scope: &str, record: &UsageRecord) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO usage \
+             (scope, day, nick, channel, requests, prompt_tokens, completion_tokens) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             ON CONFLICT (scope, day, nick, channel) DO UPDATE SET \
+             requests = usage.requests + excluded.requests, \
+             prompt_tokens = usage.prompt_tokens + excluded.prompt_tokens, \
+             completion_tokens = usage.completion_tokens + excluded.completion_tokens",
+        )
+        .bind(scope)
+        .bind(record.day.to_string())
+        .bind(&record.nick)
+        .bind(&record.channel)
+        .bind(record.spent.requests as i64)
+        .bind(record.spent.usage.prompt_tokens as i64)
+        .bind(record.spent.usage.completion_tokens as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SeenStore for Postgres {
+    async fn sightings(&self, scope: &str) -> Result<Vec<Sighting>, Error> {
+        let rows =
+            sqlx::query("SELECT nick, at, channel, activity, content FROM seen WHERE scope = $1")
+                .bind(scope)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut sightings = Vec::new();
+        for row in rows {
+            let content = row.get::<String, _>("content");
+            let activity = match row.get::<&str, _>("activity") {
+                "said" => Activity::Said(content),
+                "spoke" => Activity::Spoke,
+                "joined" => Activity::Joined,
+                "parted" => Activity::Parted,
+                "quit" => Activity::Quit(content),
+                activity => {
+                    warn!("Skipping sighting with unknown activity {}", activity);
+                    continue;
+                }
+            };
+            let Some(at) = DateTime::from_timestamp(row.get("at"), 0) else {
+                continue;
+            };
+            sightings.push(Sighting {
+                nick: row.get("nick"),
+                at,
+                channel: row.get("channel"),
+                activity,
+            });
+        }
+
+        Ok(sightings)
+    }
+
+    async fn saw(&self, scope: &str, sighting: &Sighting) -> Result<(), Error> {
+        let (activity, content) = match &sighting.activity {
+            Activity::Said(msg) => ("said", msg.as_str()),
+            Activity::Spoke => ("spoke", ""),
+            Activity::Joined => ("joined", ""),
+            Activity::Parted => ("parted", ""),
+            Activity::Quit(reason) => ("quit", reason.as_str()),
+        };
+        sqlx::query(
+            "INSERT INTO seen (scope, key, nick, at, channel, activity, content) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             ON CONFLICT (scope, key) DO UPDATE SET \
+             nick = excluded.nick, at = excluded.at, channel = excluded.channel, \
+             activity = excluded.activity, content = excluded.content",
+        )
+        .bind(scope)
+        .bind(sighting.nick.to_lowercase())
+        .bind(&sighting.nick)
+        .bind(sighting.at.timestamp())
+        .bind(&sighting.channel)
+        .bind(activity)
+        .bind(content)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MemoStore for Postgres {
+    async fn memos(&self, scope: &str) -> Result<Vec<Memo>, Error> {
+        let rows = sqlx::query(
+            "SELECT sender, recipient, at, content FROM memos WHERE scope = $1 ORDER BY id",
+        )
+        .bind(scope)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut memos = Vec::new();
+        for row in rows {
+            let Some(at) = DateTime::from_timestamp(row.get("at"), 0) else {
+                continue;
+            };
+            memos.push(Memo {
+                from: row.get("sender"),
+                to: row.get("recipient"),
+                at,
+                text: row.get("content"),
+            });
+        }
+
+        Ok(memos)
+    }
+
+    async fn add_memo(&self, scope: &str, memo: &Memo) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO memos (scope, key, sender, recipient, at, content) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(scope)
+        .bind(memo.to.to_lowercase())
+        .bind(&memo.from)
+        .bind(&memo.to)
+        .bind(memo.at.timestamp())
+        .bind(&memo.text)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn clear_memos(&self, scope: &str, to: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM memos WHERE scope = $1 AND key = $2")
+            .bind(scope)
+            .bind(to.to_lowercase())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReminderStore for Postgres {
+    async fn reminders(&self, scope: &str) -> Result<Vec<Reminder>, Error> {
+        let rows =
+            sqlx::query("SELECT id, nick, target, due, content FROM reminders WHERE scope = $1")
+                .bind(scope)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut reminders = Vec::new();
+        for row in rows {
+            let Some(due) = DateTime::from_timestamp(row.get("due"), 0) else {
+                continue;
+            };
+            reminders.push(Reminder {
+                id: Some(row.get("id")),
+                nick: row.get("nick"),
+                target: row.get("target"),
+                due,
+                text: row.get("content"),
+            });
+        }
+
+        Ok(reminders)
+    }
+
+    async fn add_reminder(&self, scope: &str, reminder: &Reminder) -> Result<i64, Error> {
+        let row = sqlx::query(
+            "INSERT INTO reminders (scope, nick, target, due, content) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        )
+        .bind(scope)
+        .bind(&reminder.nick)
+        .bind(&reminder.target)
+        .bind(reminder.due.timestamp())
+        .bind(&reminder.text)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("id"))
+    }
+
+    async fn remove_reminder(&self, scope: &str, id: i64) -> Result<(), Error> {
+        sqlx::query("DELETE FROM reminders WHERE scope = $1 AND id = $2")
+            .bind(scope)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl KarmaStore for Postgres {
+    async fn karma(&self, scope: &str) -> Result<Vec<(String, i64)>, Error> {
+        let rows = sqlx::query("SELECT name, score FROM karma WHERE scope = $1")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("name"), row.get("score")))
+            .collect())
+    }
+
+    async fn add_karma(&self, scope: &str, name: &str, delta: i64) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO karma (scope, key, name, score) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (scope, key) DO UPDATE SET score = karma.score + excluded.score",
+        )
+        .bind(scope)
+        .bind(name.to_lowercase())
+        .bind(name)
+        .bind(delta)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LocationStore for Postgres {
+    async fn locations(&self, scope: &str) -> Result<Vec<(String, String)>, Error> {
+        let rows = sqlx::query("SELECT nick, location FROM locations WHERE scope = $1")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("nick"), row.get("location")))
+            .collect())
+    }
+
+    async fn set_location(&self, scope: &str, nick: &str, location: &str) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO locations (scope, nick, location) VALUES ($1, $2, $3) \
+             ON CONFLICT (scope, nick) DO UPDATE SET location = excluded.location",
+        )
+        .bind(scope)
+        .bind(nick)
+        .bind(location)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TriviaStore for Postgres {
+    async fn trivia_scores(&self, scope: &str) -> Result<Vec<(String, i64)>, Error> {
+        let rows = sqlx::query("SELECT nick, score FROM trivia_scores WHERE scope = $1")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("nick"), row.get("score")))
+            .collect())
+    }
+
+    async fn add_trivia_score(&self, scope: &str, nick: &str, points: i64) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO trivia_scores (scope, key, nick, score) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (scope, key) DO UPDATE SET score = trivia_scores.score + excluded.score",
+        )
+        .bind(scope)
+        .bind(nick.to_lowercase())
+        .bind(nick)
+        .bind(points)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WordGameStore for Postgres {
+    async fn word_game_scores(&self, scope: &str, game: &str) -> Result<Vec<(String, i64)>, Error> {
+        let rows =
+            sqlx::query("SELECT nick, score FROM word_game_scores WHERE scope = $1 AND game = $2")
+                .bind(scope)
+                .bind(game)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("nick"), row.get("score")))
+            .collect())
+    }
+
+    async fn add_word_game_score(
+        &self,
+        scope: &str,
+        game: &str,
+        nick: &str,
+        points: i64,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO word_game_scores (scope, game, key, nick, score) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (scope, game, key) DO UPDATE SET score = word_game_scores.score + excluded.score",
+        )
+        .bind(scope)
+        .bind(game)
+        .bind(nick.to_lowercase())
+        .bind(nick)
+        .bind(points)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for Postgres {
+    async fn log_line(&self, scope: &str, line: &LogLine) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO channel_log (scope, channel, at, content) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(scope)
+        .bind(&line.channel)
+        .bind(line.at.timestamp())
+        .bind(&line.text)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn logged(&self, scope: &str, channel: &str, n: usize) -> Result<Vec<LogLine>, Error> {
+        let rows = sqlx::query(
+            "SELECT at, content FROM channel_log WHERE scope = $1 AND channel = $2 \
+             ORDER BY id DESC LIMIT $3",
+        )
+        .bind(scope)
+        .bind(channel)
+        .bind(n as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut lines = Vec::new();
+        for row in rows.iter().rev() {
+            let Some(at) = DateTime::from_timestamp(row.get("at"), 0) else {
+                continue;
+            };
+            lines.push(LogLine {
+                at,
+                channel: channel.to_string(),
+                text: row.get("content"),
+            });
+        }
+
+        Ok(lines)
+    }
+
+    async fn forget_said(
+        &self,
+        scope: &str,
+        nick: &str,
+        casemapping: CaseMapping,
+    ) -> Result<(), Error> {
+        let rows = sqlx::query("SELECT id, content FROM channel_log WHERE scope = $1")
+            .bind(scope)
+            .fetch_all(&self.pool)
+            .await?;
+
+        for row in rows {
+            if archive::said_by(row.get("content"), nick, casemapping) {
+                sqlx::query("DELETE FROM channel_log WHERE id = $1")
+                    .bind(row.get::<i64, _>("id"))
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for Postgres {
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
+
+/// `url` without its password, for the logs.
+fn redacted(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("..."));
+            url.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
@@ -0,0 +1,1026 @@
+use async_trait::async_trait;
+
+use chrono::DateTime;
+
+use reqwest::Url;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufStream;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use tracing::*;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+
+use super::ArchiveStore;
+use super::IgnoreStore;
+use super::KarmaStore;
+use super::LocationStore;
+use super::MemoStore;
+use super::MemoryStore;
+use super::ReminderStore;
+use super::SeenStore;
+use super::Store;
+use super::TriviaStore;
+use super::UsageStore;
+use super::WordGameStore;
+use crate::archive;
+use crate::archive::LogLine;
+use crate::casemap::CaseMapping;
+use crate::llm::ChatMessage;
+use crate::llm::Role;
+use crate::llm::Usage;
+use crate::memos::Memo;
+use crate::recall::Recollection;
+use crate::reminders::Reminder;
+use crate::seen::Activity;
+use crate::seen::Sighting;
+use crate::usage::Spent;
+use crate::usage::UsageRecord;
+use crate::Error;
+
+/// Every key pickles writes starts with this, then the scope.
+const PREFIX: &str = "pickles";
+
+/// Keeps everything in Redis, so that several pickles can share it and nothing is lost with the
+/// container. Keys look like `pickles:<scope>:memory:<nick>`.
+pub struct Redis {
+    url: Url,
+    /// Opened on first use, and again after the connection drops.
+    connection: Mutex<Option<Connection>>,
+}
+
+impl Redis {
+    /// Connects to the server at `url`, e.g. `redis://:password@localhost:6379/0`.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let url = Url::parse(url).map_err(|e| Error::Redis(e.to_string()))?;
+        if url.scheme() != "redis" {
+            return Err(Error::Redis(format!("unsupported scheme {}", url.scheme())));
+        }
+        let connection = Connection::open(&url).await?;
+        info!(
+            "Connected to Redis at {}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port().unwrap_or(6379)
+        );
+
+        Ok(Self {
+            url,
+            connection: Mutex::new(Some(connection)),
+        })
+    }
+
+    async fn query(&self, command: Cmd) -> Result<Value, Error> {
+        Ok(self.transaction(vec![command]).await?.remove(0))
+    }
+
+    /// Runs `commands` in one MULTI/EXEC, so either all of them happen or none do.
+    async fn transaction(&self, commands: Vec<Cmd>) -> Result<Vec<Value>, Error> {
+        let mut connection = self.connection.lock().await;
+        // Out of the slot until the whole reply is in. If we're cancelled half way, as when an
+        // answer times out, the next caller gets a fresh connection rather than our reply.
+        let mut open = match connection.take() {
+            Some(open) => open,
+            None => Connection::open(&self.url).await?,
+        };
+        let result = match commands.len() {
+            1 => open.query(&commands[0]).await.map(|value| vec![value]),
+            _ => open.transaction(&commands).await,
+        };
+        // Start over on a fresh connection after an error rather than work out whether this
+        // one is still in step.
+        if result.is_ok() {
+            *connection = Some(open);
+        }
+
+        result
+    }
+
+    fn key(scope: &str, name: &str) -> String {
+        format!("{}:{}:{}", PREFIX, scope, name)
+    }
+
+    /// Every nick with remembered messages or recollections in `scope`.
+    async fn nicks(&self, scope: &str) -> Result<Vec<String>, Error> {
+        self.query(Cmd::new("SMEMBERS").arg(Self::key(scope, "nicks")))
+            .await?
+            .into_strings()
+    }
+
+    async fn keep_newest(&self, key: String, keep: usize) -> Result<(), Error> {
+        match keep {
+            0 => self.query(Cmd::new("DEL").arg(key)).await?,
+            keep => {
+                self.query(
+                    Cmd::new("LTRIM")
+                        .arg(key)
+                        .arg(format!("-{}", keep))
+                        .arg("-1"),
+                )
+                .await?
+            }
+        };
+
+        Ok(())
+    }
+
+    async fn hash(&self, key: String) -> Result<Vec<(String, String)>, Error> {
+        let fields = self
+            .query(Cmd::new("HGETALL").arg(key))
+            .await?
+            .into_strings()?;
+
+        Ok(fields
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect())
+    }
+}
+
+/// Entries are stored as JSON tuples, skipped with a warning if they don't parse.
+fn decode<T: serde::de::DeserializeOwned>(what: &str, entry: &str) -> Option<T> {
+    match serde_json::from_str(entry) {
+        Ok(decoded) => Some(decoded),
+        Err(e) => {
+            warn!("Skipping {} that doesn't parse: {}", what, e);
+            None
+        }
+    }
+}
+
+fn encode<T: serde::Serialize>(entry: &T) -> String {
+    serde_json::to_string(entry).expect("tuples of strings and numbers serialize")
+}
+
+#[async_trait]
+impl MemoryStore for Redis {
+    async fn load(&self, scope: &str) -> Result<HashMap<String, VecDeque<ChatMessage>>, Error> {
+        let mut memory: HashMap<String, VecDeque<ChatMessage>> = HashMap::new();
+        for nick in self.nicks(scope).await? {
+            let key = Self::key(scope, &format!("memory:{}", nick));
+            let entries = self
+                .query(Cmd::new("LRANGE").arg(key).arg("0").arg("-1"))
+                .await?
+                .into_strings()?;
+            for entry in entries {
+                let Some((role, content)) =
+                    decode::<(String, String)>("remembered message", &entry)
+                else {
+                    continue;
+                };
+                let Ok(role) = role.parse::<Role>() else {
+                    warn!("Skipping remembered message with unknown role {}", role);
+                    continue;
+                };
+                memory
+                    .entry(nick.clone())
+                    .or_default()
+                    .push_back(ChatMessage { role, content });
+            }
+        }
+
+        Ok(memory)
+    }
+
+    async fn push(&self, scope: &str, nick: &str, message: &ChatMessage) -> Result<(), Error> {
+        self.transaction(vec![
+            Cmd::new("SADD").arg(Self::key(scope, "nicks")).arg(nick),
+            Cmd::new("RPUSH")
+                .arg(Self::key(scope, &format!("memory:{}", nick)))
+                .arg(encode(&(message.role.as_str(), &message.content))),
+        ])
+        .await?;
+
+        Ok(())
+    }
+
+    async fn truncate(&self, scope: &str, nick: &str, keep: usize) -> Result<(), Error> {
+        self.keep_newest(Self::key(scope, &format!("memory:{}", nick)), keep)
+            .await
+    }
+
+    async fn summaries(&self, scope: &str) -> Result<HashMap<String, String>, Error> {
+        Ok(self
+            .hash(Self::key(scope, "summaries"))
+            .await?
+            .into_iter()
+            .collect())
+    }
+
+    async fn set_summary(&self, scope: &str, nick: &str, summary: &str) -> Result<(), Error> {
+        self.query(
+            Cmd::new("HSET")
+                .arg(Self::key(scope, "summaries"))
+                .arg(nick)
+                .arg(summary),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn forget(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        self.transaction(vec![
+            Cmd::new("DEL")
+                .arg(Self::key(scope, &format!("memory:{}", nick)))
+                .arg(Self::key(scope, &format!("recall:{}", nick))),
+            Cmd::new("HDEL")
+                .arg(Self::key(scope, "summaries"))
+                .arg(nick),
+            Cmd::new("SREM").arg(Self::key(scope, "nicks")).arg(nick),
+        ])
+        .await?;
+
+        Ok(())
+    }
+
+    async fn forget_all(&self, scope: &str) -> Result<(), Error> {
+        let mut forget = Cmd::new("DEL")
+            .arg(Self::key(scope, "nicks"))
+            .arg(Self::key(scope, "summaries"));
+        for nick in self.nicks(scope).await? {
+            forget = forget
+                .arg(Self::key(scope, &format!("memory:{}", nick)))
+                .arg(Self::key(scope, &format!("recall:{}", nick)));
+        }
+        self.query(forget).await?;
+
+        Ok(())
+    }
+
+    async fn recollections(
+        &self,
+        scope: &str,
+    ) -> Result<HashMap<String, Vec<Recollection>>, Error> {
+        let mut recollections: HashMap<String, Vec<Recollection>> = HashMap::new();
+        for nick in self.nicks(scope).await? {
+            let key = Self::key(scope, &format!("recall:{}", nick));
+            let entries = self
+                .query(Cmd::new("LRANGE").arg(key).arg("0").arg("-1"))
+                .await?
+                .into_strings()?;
+            for entry in entries {
+                let Some((content, embedding)) = decode("recollection", &entry) else {
+                    continue;
+                };
+                recollections
+                    .entry(nick.clone())
+                    .or_default()
+                    .push(Recollection { content, embedding });
+            }
+        }
+
+        Ok(recollections)
+    }
+
+    async fn add_recollection(
+        &self,
+        scope: &str,
+        nick: &str,
+        recollection: &Recollection,
+    ) -> Result<(), Error> {
+        self.transaction(vec![
+            Cmd::new("SADD").arg(Self::key(scope, "nicks")).arg(nick),
+            Cmd::new("RPUSH")
+                .arg(Self::key(scope, &format!("recall:{}", nick)))
+                .arg(encode(&(&recollection.content, &recollection.embedding))),
+        ])
+        .await?;
+
+        Ok(())
+    }
+
+    async fn truncate_recollections(
+        &self,
+        scope: &str,
+        nick: &str,
+        keep: usize,
+    ) -> Result<(), Error> {
+        self.keep_newest(Self::key(scope, &format!("recall:{}", nick)), keep)
+            .await
+    }
+
+    async fn opt_outs(&self, scope: &str) -> Result<Vec<String>, Error> {
+        self.query(Cmd::new("SMEMBERS").arg(Self::key(scope, "opt_outs")))
+            .await?
+            .into_strings()
+    }
+
+    async fn opt_out(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        self.query(Cmd::new("SADD").arg(Self::key(scope, "opt_outs")).arg(nick))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn opt_in(&self, scope: &str, nick: &str) -> Result<(), Error> {
+        self.query(Cmd::new("SREM").arg(Self::key(scope, "opt_outs")).arg(nick))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IgnoreStore for Redis {
+    async fn ignores(&self, scope: &str) -> Result<Vec<String>, Error> {
+        self.query(
+            Cmd::new("ZRANGE")
+                .arg(Self::key(scope, "ignores"))
+                .arg("0")
+                .arg("-1"),
+        )
+        .await?
+        .into_strings()
+    }
+
+    async fn ignore(&self, scope: &str, mask: &str) -> Result<(), Error> {
+        // Scored by when they were added, so they list in that order.
+        self.query(
+            Cmd::new("ZADD")
+                .arg(Self::key(scope, "ignores"))
+                .arg("NX")
+                .arg(chrono::Utc::now().timestamp().to_string())
+                .arg(mask),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unignore(&self, scope: &str, mask: &str) -> Result<(), Error> {
+        self.query(Cmd::new("ZREM").arg(Self::key(scope, "ignores")).arg(mask))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Usage is kept as one counter per hash, all keyed by day, nick and channel.
+const USAGE_COUNTERS: [&str; 3] = [
+    "usage:requests",
+    "usage:prompt_tokens",
+    "usage:completion_tokens",
+];
+
+#[async_trait]
+impl UsageStore for Redis {
+    async fn usage(&self, scope: &str) -> Result<Vec<UsageRecord>, Error> {
+        let mut counted: HashMap<String, [u64; 3]> = HashMap::new();
+        for (i, counter) in USAGE_COUNTERS.iter().enumerate() {
+            for (field, count) in self.hash(Self::key(scope, counter)).await? {
+                counted.entry(field).or_default()[i] = count.parse().unwrap_or_default();
+            }
+        }
+
+        let mut records = Vec::new();
+        for (field, [requests, prompt_tokens, completion_tokens]) in counted {
+            let Some((day, nick, channel)) = decode::<(String, String, String)>("usage", &field)
+            else {
+                continue;
+            };
+            let Ok(day) = day.parse() else {
+                warn!("Skipping usage recorded on unknown day {}", day);
+                continue;
+            };
+            records.push(UsageRecord {
+                day,
+                nick,
+                channel,
+                spent: Spent {
+                    requests,
+                    usage: Usage {
+                        prompt_tokens,
+                        completion_tokens,
+                    },
+                },
+            });
+        }
+
+        Ok(records)
+    }
+
+    async fn add_usage(&self, scope: &str, record: &UsageRecord) -> Result<(), Error> {
+        let field = encode(&(record.day.to_string(), &record.nick, &record.channel));
+        let counts = [
+            record.spent.requests,
+            record.spent.usage.prompt_tokens,
+            record.spent.usage.completion_tokens,
+        ];
+        self.transaction(
+            USAGE_COUNTERS
+                .iter()
+                .zip(counts)
+                .map(|(counter, count)| {
+                    Cmd::new("HINCRBY")
+                        .arg(Self::key(scope, counter))
+                        .arg(&field)
+                        .arg(count.to_string())
+                })
+                .collect(),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SeenStore for Redis {
+    async fn sightings(&self, scope: &str) -> Result<Vec<Sighting>, Error> {
+        let mut sightings = Vec::new();
+        for (_, entry) in self.hash(Self::key(scope, "seen")).await? {
+            let Some((nick, at, channel, activity, content)) =
+                decode::<(String, i64, Option<String>, String, String)>("sighting", &entry)
+            else {
+                continue;
+            };
+            let activity = match activity.as_str() {
+                "said" => Activity::Said(content),
+                "spoke" => Activity::Spoke,
+                "joined" => Activity::Joined,
+                "parted" => Activity::Parted,
+                "quit" => Activity::Quit(content),
+                activity => {
+                    warn!("Skipping sighting with unknown activity {}", activity);
+                    continue;
+                }
+            };
+            let Some(at) = DateTime::from_timestamp(at, 0) else {
+                continue;
+            };
+            sightings.push(Sighting {
+                nick,
+                at,
+                channel,
+                activity,
+            });
+        }
+
+        Ok(sightings)
+    }
+
+    async fn saw(&self, scope: &str, sighting: &Sighting) -> Result<(), Error> {
+        let (activity, content) = match &sighting.activity {
+            Activity::Said(msg) => ("said", msg.as_str()),
+            Activity::Spoke => ("spoke", ""),
+            Activity::Joined => ("joined", ""),
+            Activity::Parted => ("parted", ""),
+            Activity::Quit(reason) => ("quit", reason.as_str()),
+        };
+        let entry = encode(&(
+            &sighting.nick,
+            sighting.at.timestamp(),
+            &sighting.channel,
+            activity,
+            content,
+        ));
+        self.query(
+            Cmd::new("HSET")
+                .arg(Self::key(scope, "seen"))
+                .arg(sighting.nick.to_lowercase())
+                .arg(entry),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MemoStore for Redis {
+    async fn memos(&self, scope: &str) -> Result<Vec<Memo>, Error> {
+        let recipients = self
+            .query(Cmd::new("SMEMBERS").arg(Self::key(scope, "memo_recipients")))
+            .await?
+            .into_strings()?;
+
+        let mut memos = Vec::new();
+        for recipient in recipients {
+            let key = Self::key(scope, &format!("memos:{}", recipient));
+            let entries = self
+                .query(Cmd::new("LRANGE").arg(key).arg("0").arg("-1"))
+                .await?
+                .into_strings()?;
+            for entry in entries {
+                let Some((from, to, at, text)) =
+                    decode::<(String, String, i64, String)>("memo", &entry)
+                else {
+                    continue;
+                };
+                let Some(at) = DateTime::from_timestamp(at, 0) else {
+                    continue;
+                };
+                memos.push(Memo { from, to, at, text });
+            }
+        }
+
+        Ok(memos)
+    }
+
+    async fn add_memo(&self, scope: &str, memo: &Memo) -> Result<(), Error> {
+        let recipient = memo.to.to_lowercase();
+        self.transaction(vec![
+            Cmd::new("SADD")
+                .arg(Self::key(scope, "memo_recipients"))
+                .arg(&recipient),
+            Cmd::new("RPUSH")
+                .arg(Self::key(scope, &format!("memos:{}", recipient)))
+                .arg(encode(&(
+                    &memo.from,
+                    &memo.to,
+                    memo.at.timestamp(),
+                    &memo.text,
+                ))),
+        ])
+        .await?;
+
+        Ok(())
+    }
+
+    async fn clear_memos(&self, scope: &str, to: &str) -> Result<(), Error> {
+        let recipient = to.to_lowercase();
+        self.transaction(vec![
+            Cmd::new("DEL").arg(Self::key(scope, &format!("memos:{}", recipient))),
+            Cmd::new("SREM")
+                .arg(Self::key(scope, "memo_recipients"))
+                .arg(&recipient),
+        ])
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReminderStore for Redis {
+    async fn reminders(&self, scope: &str) -> Result<Vec<Reminder>, Error> {
+        let mut reminders = Vec::new();
+        for (id, entry) in self.hash(Self::key(scope, "reminders")).await? {
+            let Some((nick, target, due, text)) =
+                decode::<(String, String, i64, String)>("reminder", &entry)
+            else {
+                continue;
+            };
+            let (Ok(id), Some(due)) = (id.parse(), DateTime::from_timestamp(due, 0)) else {
+                continue;
+            };
+            reminders.push(Reminder {
+                id: Some(id),
+                nick,
+                target,
+                due,
+                text,
+            });
+        }
+
+        Ok(reminders)
+    }
+
+    async fn add_reminder(&self, scope: &str, reminder: &Reminder) -> Result<i64, Error> {
+        let id = self
+            .query(Cmd::new("INCR").arg(Self::key(scope, "reminder_id")))
+            .await?
+            .into_int()?;
+        self.query(
+            Cmd::new("HSET")
+                .arg(Self::key(scope, "reminders"))
+                .arg(id.to_string())
+                .arg(encode(&(
+                    &reminder.nick,
+                    &reminder.target,
+                    reminder.due.timestamp(),
+                    &reminder.text,
+                ))),
+        )
+        .await?;
+
+        Ok(id)
+    }
+
+    async fn remove_reminder(&self, scope: &str, id: i64) -> Result<(), Error> {
+        self.query(
+            Cmd::new("HDEL")
+                .arg(Self::key(scope, "reminders"))
+                .arg(id.to_string()),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl KarmaStore for Redis {
+    async fn karma(&self, scope: &str) -> Result<Vec<(String, i64)>, Error> {
+        let names: HashMap<_, _> = self
+            .hash(Self::key(scope, "karma_names"))
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(self
+            .hash(Self::key(scope, "karma"))
+            .await?
+            .into_iter()
+            .filter_map(|(key, score)| {
+                let name = names.get(&key).cloned().unwrap_or(key);
+                Some((name, score.parse().ok()?))
+            })
+            .collect())
+    }
+
+    async fn add_karma(&self, scope: &str, name: &str, delta: i64) -> Result<(), Error> {
+        let key = name.to_lowercase();
+        self.transaction(vec![
+            Cmd::new("HSETNX")
+                .arg(Self::key(scope, "karma_names"))
+                .arg(&key)
+                .arg(name),
+            Cmd::new("HINCRBY")
+                .arg(Self::key(scope, "karma"))
+                .arg(&key)
+                .arg(delta.to_string()),
+        ])
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LocationStore for Redis {
+    async fn locations(&self, scope: &str) -> Result<Vec<(String, String)>, Error> {
+        self.hash(Self::key(scope, "locations")).await
+    }
+
+    async fn set_location(&self, scope: &str, nick: &str, location: &str) -> Result<(), Error> {
+        self.query(
+            Cmd::new("HSET")
+                .arg(Self::key(scope, "locations"))
+                .arg(nick)
+                .arg(location),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TriviaStore for Redis {
+    async fn trivia_scores(&self, scope: &str) -> Result<Vec<(String, i64)>, Error> {
+        let nicks: HashMap<_, _> = self
+            .hash(Self::key(scope, "trivia_nicks"))
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(self
+            .hash(Self::key(scope, "trivia"))
+            .await?
+            .into_iter()
+            .filter_map(|(key, score)| {
+                let nick = nicks.get(&key).cloned().unwrap_or(key);
+                Some((nick, score.parse().ok()?))
+            })
+            .collect())
+    }
+
+    async fn add_trivia_score(&self, scope: &str, nick: &str, points: i64) -> Result<(), Error> {
+        let key = nick.to_lowercase();
+        self.transaction(vec![
+            Cmd::new("HSETNX")
+                .arg(Self::key(scope, "trivia_nicks"))
+                .arg(&key)
+                .arg(nick),
+            Cmd::new("HINCRBY")
+                .arg(Self::key(scope, "trivia"))
+                .arg(&key)
+                .arg(points.to_string()),
+        ])
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WordGameStore for Redis {
+    async fn word_game_scores(&self, scope: &str, game: &str) -> Result<Vec<(String, i64)>, Error> {
+        let nicks: HashMap<_, _> = self
+            .hash(Self::key(scope, &format!("{}_nicks", game)))
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(self
+            .hash(Self::key(scope, game))
+            .await?
+            .into_iter()
+            .filter_map(|(key, score)| {
+                let nick = nicks.get(&key).cloned().unwrap_or(key);
+                Some((nick, score.parse().ok()?))
+            })
+            .collect())
+    }
+
+    async fn add_word_game_score(
+        &self,
+        scope: &str,
+        game: &str,
+        nick: &str,
+        points: i64,
+    ) -> Result<(), Error> {
+        let key = nick.to_lowercase();
+        self.transaction(vec![
+            Cmd::new("HSETNX")
+                .arg(Self::key(scope, &format!("{}_nicks", game)))
+                .arg(&key)
+                .arg(nick),
+            Cmd::new("HINCRBY")
+                .arg(Self::key(scope, game))
+                .arg(&key)
+                .arg(points.to_string()),
+        ])
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for Redis {
+    async fn log_line(&self, scope: &str, line: &LogLine) -> Result<(), Error> {
+        self.transaction(vec![
+            Cmd::new("SADD")
+                .arg(Self::key(scope, "log_channels"))
+                .arg(&line.channel),
+            Cmd::new("RPUSH")
+                .arg(Self::key(scope, &format!("log:{}", line.channel)))
+                .arg(encode(&(line.at.timestamp(), &line.text))),
+        ])
+        .await?;
+
+        Ok(())
+    }
+
+    async fn logged(&self, scope: &str, channel: &str, n: usize) -> Result<Vec<LogLine>, Error> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        let entries = self
+            .query(
+                Cmd::new("LRANGE")
+                    .arg(Self::key(scope, &format!("log:{}", channel)))
+                    .arg(format!("-{}", n))
+                    .arg("-1"),
+            )
+            .await?
+            .into_strings()?;
+
+        Ok(entries
+            .iter()
+            .filter_map(|entry| {
+                let (at, text) = decode::<(i64, String)>("logged line", entry)?;
+                Some(LogLine {
+                    at: DateTime::from_timestamp(at, 0)?,
+                    channel: channel.to_string(),
+                    text,
+                })
+            })
+            .collect())
+    }
+
+    async fn forget_said(
+        &self,
+        scope: &str,
+        nick: &str,
+        casemapping: CaseMapping,
+    ) -> Result<(), Error> {
+        let channels = self
+            .query(Cmd::new("SMEMBERS").arg(Self::key(scope, "log_channels")))
+            .await?
+            .into_strings()?;
+        for channel in channels {
+            let key = Self::key(scope, &format!("log:{}", channel));
+            let entries = self
+                .query(Cmd::new("LRANGE").arg(&key).arg("0").arg("-1"))
+                .await?
+                .into_strings()?;
+            for entry in entries {
+                let said = decode::<(i64, String)>("logged line", &entry)
+                    .is_some_and(|(_, text)| archive::said_by(&text, nick, casemapping));
+                if said {
+                    self.query(Cmd::new("LREM").arg(&key).arg("0").arg(&entry))
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for Redis {
+    async fn close(&self) {
+        // Every write is waited on, so there's nothing left to flush.
+        self.connection.lock().await.take();
+    }
+}
+
+/// A command and its arguments, as sent to the server.
+struct Cmd(Vec<Vec<u8>>);
+
+impl Cmd {
+    fn new(name: &str) -> Self {
+        Self(vec![name.as_bytes().to_vec()])
+    }
+
+    fn arg(mut self, arg: impl AsRef<[u8]>) -> Self {
+        self.0.push(arg.as_ref().to_vec());
+        self
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(format!("*{}\r\n", self.0.len()).as_bytes());
+        for arg in &self.0 {
+            buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buf.extend_from_slice(arg);
+            buf.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+/// A reply from the server.
+enum Value {
+    Nil,
+    Int(i64),
+    Status,
+    Data(Vec<u8>),
+    Array(Vec<Value>),
+    Error(String),
+}
+
+impl Value {
+    /// The value, unless the server replied with an error.
+    fn ok(self) -> Result<Self, Error> {
+        match self {
+            Value::Error(e) => Err(Error::Redis(e)),
+            value => Ok(value),
+        }
+    }
+
+    fn into_int(self) -> Result<i64, Error> {
+        match self {
+            Value::Int(i) => Ok(i),
+            _ => Err(Error::Redis("expected an integer reply".to_string())),
+        }
+    }
+
+    /// The strings in an array reply.
+    fn into_strings(self) -> Result<Vec<String>, Error> {
+        let Value::Array(values) = self else {
+            return Err(Error::Redis("expected an array reply".to_string()));
+        };
+
+        values
+            .into_iter()
+            .map(|value| match value {
+                Value::Data(data) => String::from_utf8(data)
+                    .map_err(|_| Error::Redis("reply isn't UTF-8".to_string())),
+                _ => Err(Error::Redis("expected a string reply".to_string())),
+            })
+            .collect()
+    }
+}
+
+/// One connection speaking RESP, the Redis protocol.
+struct Connection {
+    stream: BufStream<TcpStream>,
+}
+
+impl Connection {
+    async fn open(url: &Url) -> Result<Self, Error> {
+        let host = url.host_str().unwrap_or("localhost");
+        let stream = TcpStream::connect((host, url.port().unwrap_or(6379)))
+            .await
+            .map_err(|e| Error::Redis(format!("unable to connect to {}: {}", host, e)))?;
+        let mut connection = Self {
+            stream: BufStream::new(stream),
+        };
+
+        if let Some(password) = url.password() {
+            let mut auth = Cmd::new("AUTH");
+            if !url.username().is_empty() {
+                auth = auth.arg(url.username());
+            }
+            connection.query(&auth.arg(password)).await?;
+        }
+        let db = url.path().trim_start_matches('/');
+        if !db.is_empty() {
+            connection.query(&Cmd::new("SELECT").arg(db)).await?;
+        }
+
+        Ok(connection)
+    }
+
+    async fn query(&mut self, command: &Cmd) -> Result<Value, Error> {
+        let mut buf = Vec::new();
+        command.write_to(&mut buf);
+        self.send(&buf).await?;
+
+        self.read().await?.ok()
+    }
+
+    async fn transaction(&mut self, commands: &[Cmd]) -> Result<Vec<Value>, Error> {
+        let mut buf = Vec::new();
+        Cmd::new("MULTI").write_to(&mut buf);
+        for command in commands {
+            command.write_to(&mut buf);
+        }
+        Cmd::new("EXEC").write_to(&mut buf);
+        self.send(&buf).await?;
+
+        // +OK for MULTI and +QUEUED for each command, then what each of them returned.
+        let mut refused = None;
+        for _ in 0..=commands.len() {
+            if let Err(e) = self.read().await?.ok() {
+                refused.get_or_insert(e);
+            }
+        }
+        let exec = self.read().await?;
+        if let Some(e) = refused {
+            return Err(e);
+        }
+        match exec.ok()? {
+            Value::Array(values) => values.into_iter().map(Value::ok).collect(),
+            _ => Err(Error::Redis("transaction was aborted".to_string())),
+        }
+    }
+
+    async fn send(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.stream.write_all(buf).await.map_err(io_error)?;
+        self.stream.flush().await.map_err(io_error)
+    }
+
+    /// Reads one reply, errors the server replied with included.
+    fn read(&mut self) -> Pin<Box<dyn Future<Output = Result<Value, Error>> + Send + '_>> {
+        Box::pin(async move {
+            let mut line = Vec::new();
+            self.stream
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(io_error)?;
+            if !line.ends_with(b"\r\n") {
+                return Err(Error::Redis("connection closed".to_string()));
+            }
+            line.truncate(line.len() - 2);
+            if line.is_empty() {
+                return Err(Error::Redis("empty reply".to_string()));
+            }
+            let (kind, rest) = (line[0], String::from_utf8_lossy(&line[1..]).into_owned());
+            let length = || {
+                rest.parse::<i64>()
+                    .map_err(|_| Error::Redis(format!("bad reply {}", rest)))
+            };
+
+            Ok(match kind {
+                b'+' => Value::Status,
+                b'-' => Value::Error(rest),
+                b':' => Value::Int(length()?),
+                b'$' => match usize::try_from(length()?) {
+                    Ok(len) => {
+                        let mut data = vec![0; len + 2];
+                        self.stream.read_exact(&mut data).await.map_err(io_error)?;
+                        data.truncate(len);
+                        Value::Data(data)
+                    }
+                    Err(_) => Value::Nil,
+                },
+                b'*' => match usize::try_from(length()?) {
+                    Ok(len) => {
+                        let mut values = Vec::with_capacity(len);
+                        for _ in 0..len {
+                            values.push(self.read().await?);
+                        }
+                        Value::Array(values)
+                    }
+                    Err(_) => Value::Nil,
+                },
+                _ => return Err(Error::Redis(format!("unexpected reply {}", rest))),
+            })
+        })
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Redis(e.to_string())
+}
@@ -0,0 +1,79 @@
+use tokio::time;
+use tokio::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::*;
+
+use std::env;
+use std::ffi::OsStr;
+use std::future;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+
+/// Tells systemd about `state`, e.g. `READY=1`, if it started us with `Type=notify`. Does
+/// nothing otherwise.
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(path.as_bytes(), state) {
+        warn!("Unable to notify systemd: {}", e);
+    }
+}
+
+fn send(path: &[u8], state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    // A leading @ means a socket in the abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &address)?;
+        return Ok(());
+    }
+    socket.send_to(state.as_bytes(), OsStr::from_bytes(path))?;
+
+    Ok(())
+}
+
+/// Keeps systemd's watchdog fed, when it's configured with `WatchdogSec=`.
+pub struct Watchdog(Option<time::Interval>);
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self(interval().map(|interval| {
+            let mut interval = time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        }))
+    }
+
+    /// Resolves each time systemd is due to hear from us, or never if it isn't listening.
+    pub async fn tick(&mut self) {
+        match &mut self.0 {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => future::pending().await,
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Half of `WatchdogSec=`, to leave room to spare, if the watchdog is meant for us.
+fn interval() -> Option<Duration> {
+    let usecs: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+
+    Some(Duration::from_micros(usecs / 2))
+}
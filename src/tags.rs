@@ -0,0 +1,52 @@
+//! IRCv3 message tags, and the capabilities that get the server to send them.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use irc::client::prelude::*;
+
+use std::collections::HashMap;
+
+/// Asked for one at a time so a server that doesn't know one still grants the rest.
+///
+/// - `server-time` says when each message was really sent, so bouncer playback can be skipped
+/// - `message-tags` gets us tags in general, like `bot`
+/// - `echo-message` sends our own messages back, which tells us our hostmask early on
+/// - `account-tag` names the services account behind each message, for the ACL
+pub const CAPABILITIES: [&str; 4] = ["server-time", "message-tags", "echo-message", "account-tag"];
+
+/// The tags on one message, by name. Tags without a value map to an empty string.
+#[derive(Debug, Clone, Default)]
+pub struct Tags(HashMap<String, String>);
+
+impl Tags {
+    pub fn new(message: &Message) -> Self {
+        let tags = message.tags.iter().flatten();
+
+        Self(
+            tags.map(|tag| (tag.0.clone(), tag.1.clone().unwrap_or_default()))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// When the server says the message was sent.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        let time = DateTime::parse_from_rfc3339(self.get("time")?).ok()?;
+
+        Some(time.to_utc())
+    }
+
+    /// The services account the sender is logged in to.
+    pub fn account(&self) -> Option<&str> {
+        self.get("account").filter(|account| *account != "*")
+    }
+
+    /// Whether the server has flagged the sender as a bot.
+    pub fn is_bot(&self) -> bool {
+        self.0.contains_key("bot")
+    }
+}
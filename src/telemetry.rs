@@ -0,0 +1,334 @@
+use prometheus::proto::Metric;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
+
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+use tokio::sync::mpsc;
+use tokio::time;
+
+use tracing::info;
+use tracing::span;
+use tracing::warn;
+use tracing::Event;
+use tracing::Level;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::config::TelemetryConfig;
+use crate::logging::Visitor;
+use crate::metrics::metrics;
+
+/// Where finished spans go once `start()` has been called. Until then nothing is recorded.
+static SPANS: OnceLock<mpsc::UnboundedSender<Value>> = OnceLock::new();
+
+/// Spans are sent as soon as this many are waiting, without waiting for the next interval.
+const MAX_BATCH: usize = 512;
+
+/// Records spans for `start()` to export as OTLP traces.
+pub struct Otlp;
+
+/// What's kept about a span until it closes.
+struct Recorded {
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    start: SystemTime,
+    attributes: Map<String, Value>,
+    events: Vec<Value>,
+    /// The last error logged in the span, which marks it as failed.
+    error: Option<String>,
+}
+
+impl<S> Layer<S> for Otlp
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if SPANS.get().is_none() {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span.parent().and_then(|parent| {
+            let extensions = parent.extensions();
+            let recorded = extensions.get::<Recorded>()?;
+            Some((recorded.trace_id, recorded.span_id))
+        });
+        let (trace_id, parent_id) = match parent {
+            Some((trace_id, parent_id)) => (trace_id, Some(parent_id)),
+            None => (rand::random(), None),
+        };
+        let mut attributes = Map::new();
+        attrs.record(&mut Visitor(&mut attributes));
+
+        span.extensions_mut().insert(Recorded {
+            trace_id,
+            span_id: rand::random(),
+            parent_id,
+            start: SystemTime::now(),
+            attributes,
+            events: Vec::new(),
+            error: None,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(recorded) = extensions.get_mut::<Recorded>() {
+            values.record(&mut Visitor(&mut recorded.attributes));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(recorded) = extensions.get_mut::<Recorded>() else {
+            return;
+        };
+        let mut fields = Map::new();
+        event.record(&mut Visitor(&mut fields));
+        let name = match fields.remove("message") {
+            Some(Value::String(message)) => message,
+            _ => event.metadata().name().to_string(),
+        };
+        if *event.metadata().level() == Level::ERROR {
+            recorded.error = Some(name.clone());
+        }
+
+        recorded.events.push(json!({
+            "timeUnixNano": nanos(SystemTime::now()),
+            "name": name,
+            "attributes": attributes(fields),
+        }));
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let (Some(spans), Some(span)) = (SPANS.get(), ctx.span(&id)) else {
+            return;
+        };
+        let Some(recorded) = span.extensions_mut().remove::<Recorded>() else {
+            return;
+        };
+        let status = match recorded.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({}),
+        };
+
+        let _ = spans.send(json!({
+            "traceId": format!("{:032x}", recorded.trace_id),
+            "spanId": format!("{:016x}", recorded.span_id),
+            "parentSpanId": recorded.parent_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+            "name": span.name(),
+            "kind": 1,
+            "startTimeUnixNano": nanos(recorded.start),
+            "endTimeUnixNano": nanos(SystemTime::now()),
+            "attributes": attributes(recorded.attributes),
+            "events": recorded.events,
+            "status": status,
+        }));
+    }
+}
+
+/// Starts exporting spans recorded by `Otlp`, and everything in `metrics()`, to the collector
+/// in `config`. Only the first call does anything.
+pub fn start(config: &TelemetryConfig) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    if SPANS.set(tx).is_err() {
+        return;
+    }
+
+    info!("Exporting traces and metrics to {}", config.endpoint);
+    tokio::spawn(export(config.clone(), rx));
+}
+
+async fn export(config: TelemetryConfig, mut spans: mpsc::UnboundedReceiver<Value>) {
+    let http = reqwest::Client::new();
+    let started = nanos(SystemTime::now());
+    let resource = json!({
+        "attributes": [{
+            "key": "service.name",
+            "value": { "stringValue": config.service_name },
+        }],
+    });
+    let scope = json!({ "name": "pickles", "version": env!("CARGO_PKG_VERSION") });
+    let mut interval = time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    let mut batch = Vec::new();
+    loop {
+        tokio::select! {
+            span = spans.recv() => {
+                let Some(span) = span else {
+                    break;
+                };
+                batch.push(span);
+                if batch.len() < MAX_BATCH {
+                    continue;
+                }
+            }
+            _ = interval.tick() => {
+                let metrics: Vec<Value> = metrics()
+                    .gather()
+                    .iter()
+                    .filter_map(|family| metric(family, &started))
+                    .collect();
+                let body = json!({
+                    "resourceMetrics": [{
+                        "resource": resource,
+                        "scopeMetrics": [{ "scope": scope, "metrics": metrics }],
+                    }],
+                });
+                send(&http, &config, "metrics", &body).await;
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        let body = json!({
+            "resourceSpans": [{
+                "resource": resource,
+                "scopeSpans": [{ "scope": scope, "spans": std::mem::take(&mut batch) }],
+            }],
+        });
+        send(&http, &config, "traces", &body).await;
+    }
+}
+
+async fn send(http: &reqwest::Client, config: &TelemetryConfig, signal: &str, body: &Value) {
+    let url = format!("{}/v1/{}", config.endpoint.trim_end_matches('/'), signal);
+    let mut request = http.post(url).json(body);
+    for (name, value) in &config.headers {
+        request = request.header(name, value);
+    }
+
+    if let Err(e) = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+    {
+        warn!("Unable to export {}: {}", signal, e);
+    }
+}
+
+/// `family` as an OTLP metric, cumulative since `started`, if it's a kind pickles uses.
+fn metric(family: &MetricFamily, started: &str) -> Option<Value> {
+    let now = nanos(SystemTime::now());
+    let points = |value: fn(&Metric) -> Value| -> Vec<Value> {
+        family
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels: Value = metric
+                    .get_label()
+                    .iter()
+                    .map(|pair| {
+                        json!({
+                            "key": pair.get_name(),
+                            "value": { "stringValue": pair.get_value() },
+                        })
+                    })
+                    .collect();
+                let mut point = json!({
+                    "attributes": labels,
+                    "startTimeUnixNano": started,
+                    "timeUnixNano": now,
+                });
+                if let (Some(point), Value::Object(value)) = (point.as_object_mut(), value(metric))
+                {
+                    point.extend(value);
+                }
+                point
+            })
+            .collect()
+    };
+    let (kind, data) = match family.get_field_type() {
+        MetricType::COUNTER => (
+            "sum",
+            json!({
+                "dataPoints": points(|metric| json!({ "asDouble": metric.get_counter().get_value() })),
+                "aggregationTemporality": 2,
+                "isMonotonic": true,
+            }),
+        ),
+        MetricType::GAUGE => (
+            "gauge",
+            json!({
+                "dataPoints": points(|metric| json!({ "asDouble": metric.get_gauge().get_value() })),
+            }),
+        ),
+        MetricType::HISTOGRAM => (
+            "histogram",
+            json!({
+                "dataPoints": points(histogram),
+                "aggregationTemporality": 2,
+            }),
+        ),
+        _ => return None,
+    };
+
+    Some(json!({
+        "name": family.get_name(),
+        "description": family.get_help(),
+        kind: data,
+    }))
+}
+
+/// Prometheus counts each bucket with everything below it, OTLP doesn't.
+fn histogram(metric: &Metric) -> Value {
+    let histogram = metric.get_histogram();
+    let (mut bounds, mut counts, mut below) = (Vec::new(), Vec::new(), 0);
+    for bucket in histogram.get_bucket() {
+        if bucket.get_upper_bound().is_infinite() {
+            continue;
+        }
+        bounds.push(bucket.get_upper_bound());
+        counts.push((bucket.get_cumulative_count() - below).to_string());
+        below = bucket.get_cumulative_count();
+    }
+    counts.push((histogram.get_sample_count() - below).to_string());
+
+    json!({
+        "count": histogram.get_sample_count().to_string(),
+        "sum": histogram.get_sample_sum(),
+        "bucketCounts": counts,
+        "explicitBounds": bounds,
+    })
+}
+
+fn attributes(fields: Map<String, Value>) -> Value {
+    fields
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Bool(value) => json!({ "boolValue": value }),
+                Value::Number(value) if value.is_f64() => json!({ "doubleValue": value }),
+                Value::Number(value) => json!({ "intValue": value.to_string() }),
+                Value::String(value) => json!({ "stringValue": value }),
+                value => json!({ "stringValue": value.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
@@ -0,0 +1,87 @@
+use tokio::sync::mpsc;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::fetch;
+use crate::fetch::Fetch;
+use crate::output::queue;
+use crate::output::Outgoing;
+
+/// How long a link has to go unmentioned in a channel before its title is announced again.
+const REANNOUNCE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// More links than this in one message are probably a paste, not something to read out.
+const MAX_LINKS: usize = 3;
+
+/// Titles longer than this are cut short.
+const MAX_TITLE_CHARS: usize = 200;
+
+/// Announces the titles of links posted in channels, once an hour per link at most.
+pub struct Titles {
+    fetch: Fetch,
+    announced: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Default for Titles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Titles {
+    pub fn new() -> Self {
+        Self {
+            fetch: Fetch::new(),
+            announced: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The links in `msg` that haven't had their titles announced in `channel` lately. They
+    /// count as announced from now on.
+    pub fn fresh(&self, channel: &str, msg: &str) -> Vec<String> {
+        let mut announced = self.announced.lock().expect("titles lock poisoned");
+        announced.retain(|_, at| at.elapsed() < REANNOUNCE_AFTER);
+
+        fetch::urls(msg)
+            .take(MAX_LINKS)
+            .filter(|url| {
+                announced
+                    .insert((channel.to_string(), url.to_string()), Instant::now())
+                    .is_none()
+            })
+            .map(String::from)
+            .collect()
+    }
+
+    /// Fetches each of `urls` and says its title in `channel`. Links without one, or that
+    /// can't be fetched, are passed over quietly.
+    pub async fn announce(
+        self: Arc<Self>,
+        outgoing: mpsc::UnboundedSender<Outgoing>,
+        channel: String,
+        urls: Vec<String>,
+    ) {
+        for url in urls {
+            let title = match self.fetch.page(&url).await {
+                Ok(page) => page.title,
+                Err(e) => {
+                    debug!("No title for {}: {}", url, e);
+                    None
+                }
+            };
+            let Some(title) = title.filter(|title| !title.is_empty()) else {
+                continue;
+            };
+            let title = match title.char_indices().nth(MAX_TITLE_CHARS) {
+                Some((end, _)) => format!("{}...", &title[..end]),
+                None => title,
+            };
+            queue(&outgoing, &channel, format!("[ {} ]", title));
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+
+use tracing::*;
+
+use crate::config::Config;
+use crate::Error;
+
+mod calc;
+mod fetch;
+mod time;
+mod weather;
+
+/// Something the model can ask us to do while it works out a reply.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// What the model calls it. Letters, digits, underscores and dashes only.
+    fn name(&self) -> &'static str;
+
+    /// Tells the model what the tool is for and when to use it.
+    fn description(&self) -> &'static str;
+
+    /// JSON schema for the arguments object.
+    fn parameters(&self) -> serde_json::Value;
+
+    /// Runs the tool, returning what the model should be told.
+    async fn execute(&self, arguments: serde_json::Value) -> Result<String, Error>;
+}
+
+/// The registry of tools offered to the model with every request.
+pub struct Tools {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tools {
+    pub fn new() -> Self {
+        let mut tools = Self { tools: Vec::new() };
+        tools.register(time::Time);
+        tools.register(calc::Calculate);
+        tools.register(fetch::FetchUrl::new());
+
+        tools
+    }
+
+    /// The built in tools, plus those `config` turns on.
+    pub fn from_config(config: &Config) -> Self {
+        let mut tools = Self::new();
+        if let Some(weather) = &config.weather {
+            tools.register(weather::GetWeather::new(weather));
+        }
+
+        tools
+    }
+
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        self.tools.push(Box::new(tool));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Tool> {
+        self.tools.iter().map(|tool| tool.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Runs the tool called `name`. Failures are reported back to the model rather than
+    /// returned so it can apologise or try something else.
+    pub async fn call(&self, name: &str, arguments: &str) -> String {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name() == name) else {
+            warn!("Model asked for unknown tool {}", name);
+            return format!("error: there is no tool called {}", name);
+        };
+        let arguments = match serde_json::from_str(arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("error: arguments aren't valid JSON: {}", e),
+        };
+
+        info!("Calling tool {} with {}", name, arguments);
+        match tool.execute(arguments).await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Tool {} failed: {}", name, e);
+                format!("error: {}", e)
+            }
+        }
+    }
+}
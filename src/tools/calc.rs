@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+
+use serde_json::json;
+
+use super::Tool;
+use crate::calc;
+use crate::Error;
+
+/// The model is confidently bad at arithmetic, so it gets a calculator.
+pub struct Calculate;
+
+#[async_trait]
+impl Tool for Calculate {
+    fn name(&self) -> &'static str {
+        "calculate"
+    }
+
+    fn description(&self) -> &'static str {
+        "Work out arithmetic exactly, convert units or change number bases. Use this for any \
+         maths rather than doing it yourself"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "e.g. \"(3 + 4) * 2^10\", \"sqrt(2)\", \"5 ft 11 in to cm\", \"100 km/h to mph\", \"20 C to F\" or \"255 to hex\""
+                }
+            },
+            "required": ["expression"]
+        })
+    }
+
+    async fn execute(&self, arguments: serde_json::Value) -> Result<String, Error> {
+        calc::evaluate(arguments["expression"].as_str().unwrap_or_default())
+    }
+}
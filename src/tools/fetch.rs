@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+
+use serde_json::json;
+
+use super::Tool;
+use crate::fetch::Fetch;
+use crate::Error;
+
+/// Lets the model read links people ask it about.
+pub struct FetchUrl {
+    fetch: Fetch,
+}
+
+impl FetchUrl {
+    pub fn new() -> Self {
+        Self {
+            fetch: Fetch::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FetchUrl {
+    fn name(&self) -> &'static str {
+        "fetch_url"
+    }
+
+    fn description(&self) -> &'static str {
+        "Read the text of a web page"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "description": "The http or https URL to read" }
+            },
+            "required": ["url"]
+        })
+    }
+
+    async fn execute(&self, arguments: serde_json::Value) -> Result<String, Error> {
+        let url = arguments["url"].as_str().unwrap_or_default();
+        Ok(self.fetch.page(url).await?.excerpt())
+    }
+}
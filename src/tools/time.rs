@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+
+use serde_json::json;
+
+use super::Tool;
+use crate::Error;
+
+/// The model has no clock of its own.
+pub struct Time;
+
+#[async_trait]
+impl Tool for Time {
+    fn name(&self) -> &'static str {
+        "current_time"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current date and time in UTC"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    async fn execute(&self, _arguments: serde_json::Value) -> Result<String, Error> {
+        Ok(chrono::Utc::now()
+            .format("%A, %B %-d, %Y %H:%M:%S UTC")
+            .to_string())
+    }
+}
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+
+use serde_json::json;
+
+use super::Tool;
+use crate::config::WeatherConfig;
+use crate::weather::Weather;
+use crate::Error;
+
+/// Lets the model answer "what's it like out in Lisbon?" with the real thing.
+pub struct GetWeather {
+    weather: Weather,
+}
+
+impl GetWeather {
+    pub fn new(config: &WeatherConfig) -> Self {
+        Self {
+            weather: Weather::new(config),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for GetWeather {
+    fn name(&self) -> &'static str {
+        "get_weather"
+    }
+
+    fn description(&self) -> &'static str {
+        "Get the current weather and today's high and low for a place"
+    }
+
+    fn parameters(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "location": { "type": "string", "description": "A city or town, e.g. \"Paris\"" }
+            },
+            "required": ["location"]
+        })
+    }
+
+    async fn execute(&self, arguments: serde_json::Value) -> Result<String, Error> {
+        let location = arguments["location"].as_str().unwrap_or_default();
+        self.weather.current(location).await
+    }
+}
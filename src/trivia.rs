@@ -0,0 +1,562 @@
+//! `!trivia`: questions asked in a channel on a timer of their own, with hints as time runs
+//! out and points for whoever answers first.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use serde::Deserialize;
+
+use tokio::sync::mpsc;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tokio::time;
+use tokio::time::Duration;
+use tracing::*;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::config::TriviaConfig;
+use crate::config::TriviaSource;
+use crate::llm::ChatBackend;
+use crate::llm::ChatMessage;
+use crate::llm::Usage;
+use crate::output::queue;
+use crate::output::Outgoing;
+use crate::storage::TriviaStore;
+use crate::Error;
+
+const OPEN_TRIVIA_DB: &str = "https://opentdb.com/api.php";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Open Trivia DB won't hand out more at once.
+const MAX_QUESTIONS: usize = 50;
+
+/// Between one question being answered and the next.
+const PAUSE: Duration = Duration::from_secs(5);
+
+/// Answers this short have to be spelled right.
+const MAX_EXACT_LEN: usize = 4;
+
+/// A guess can have this many more words than the answer, as in `is it Paris?`.
+const MAX_EXTRA_WORDS: usize = 3;
+
+const PROMPT: &str = "Write {n} trivia questions{about} for a game in an IRC channel. Each \
+                      answer should be a name, a number or a few words that someone could \
+                      type, not a sentence, and shouldn't be given away by its question. Reply \
+                      with only a JSON array of objects with \"question\" and \"answer\" keys.";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Question {
+    pub question: String,
+    pub answer: String,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// Open Trivia DB's answer with `encode=base64`, every string encoded.
+#[derive(Deserialize)]
+struct OpenTdb {
+    response_code: u64,
+    #[serde(default)]
+    results: Vec<OpenTdbQuestion>,
+}
+
+#[derive(Deserialize)]
+struct OpenTdbQuestion {
+    category: String,
+    question: String,
+    correct_answer: String,
+}
+
+/// A game going on in one channel.
+#[derive(Default)]
+struct Game {
+    /// Who started it, who can stop it.
+    started_by: String,
+    /// The question waiting on an answer, between it being asked and answered or given away.
+    asking: Option<Asking>,
+    /// Points this game, by lowercased nick, with the nick as it was written.
+    points: HashMap<String, (String, i64)>,
+    task: Option<AbortHandle>,
+}
+
+struct Asking {
+    question: Question,
+    /// How many hints have been given so far.
+    hints: usize,
+    /// Tells the game someone got it, so it can move on without waiting for the next hint.
+    answered: Arc<Notify>,
+}
+
+/// Trivia games on one network and everyone's points from all of them. Points are written
+/// through to the store, if there is one.
+pub struct Trivia {
+    config: TriviaConfig,
+    http: reqwest::Client,
+    scope: String,
+    /// By channel, folded by the server's casemapping.
+    games: Mutex<HashMap<String, Game>>,
+    /// By lowercased nick, with the nick as it was first written.
+    scores: Mutex<HashMap<String, (String, i64)>>,
+    store: Option<Arc<dyn TriviaStore>>,
+}
+
+impl Trivia {
+    pub async fn load(
+        scope: &str,
+        config: &TriviaConfig,
+        store: Option<Arc<dyn TriviaStore>>,
+    ) -> Result<Self, Error> {
+        let scores = match &store {
+            Some(store) => store.trivia_scores(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+            scope: scope.to_string(),
+            games: Mutex::new(HashMap::new()),
+            scores: Mutex::new(
+                scores
+                    .into_iter()
+                    .map(|(nick, score)| (nick.to_lowercase(), (nick, score)))
+                    .collect(),
+            ),
+            store,
+        })
+    }
+
+    pub fn source(&self) -> TriviaSource {
+        self.config.source
+    }
+
+    pub fn score(&self, nick: &str) -> i64 {
+        self.scores
+            .lock()
+            .expect("trivia lock poisoned")
+            .get(&nick.to_lowercase())
+            .map_or(0, |(_, score)| *score)
+    }
+
+    /// The `n` highest scores, highest first.
+    pub fn top(&self, n: usize) -> Vec<(String, i64)> {
+        let mut scores = self
+            .scores
+            .lock()
+            .expect("trivia lock poisoned")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        scores.sort_by_key(|(_, score)| Reverse(*score));
+        scores.truncate(n);
+        scores
+    }
+
+    /// A game's worth of questions, about `topic` if the model is writing them, and what the
+    /// model spent on them.
+    pub async fn questions(
+        &self,
+        topic: Option<&str>,
+        backend: &dyn ChatBackend,
+    ) -> Result<(Vec<Question>, Option<Usage>), Error> {
+        let n = self.config.questions.clamp(1, MAX_QUESTIONS);
+        match self.config.source {
+            TriviaSource::OpenTdb => Ok((self.open_trivia_db(n).await?, None)),
+            TriviaSource::Model => {
+                let about = topic.map(|topic| format!(" about {}", topic));
+                let prompt = PROMPT
+                    .replace("{n}", &n.to_string())
+                    .replace("{about}", about.as_deref().unwrap_or(""));
+                let completion = backend.complete(&[ChatMessage::user(prompt)]).await?;
+                let mut questions = parse(&completion.content)?;
+                questions.truncate(n);
+                Ok((questions, Some(completion.usage)))
+            }
+        }
+    }
+
+    async fn open_trivia_db(&self, n: usize) -> Result<Vec<Question>, Error> {
+        let mut query = vec![
+            ("amount", n.to_string()),
+            ("type", String::from("multiple")),
+            ("encode", String::from("base64")),
+        ];
+        if let Some(difficulty) = &self.config.difficulty {
+            query.push(("difficulty", difficulty.to_lowercase()));
+        }
+        let results: OpenTdb = self
+            .http
+            .get(OPEN_TRIVIA_DB)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if results.response_code != 0 {
+            return Err(Error::Trivia(format!(
+                "Open Trivia DB said {}",
+                results.response_code
+            )));
+        }
+
+        let decode = |text: &str| {
+            BASE64
+                .decode(text)
+                .map(|text| String::from_utf8_lossy(&text).into_owned())
+                .map_err(|e| Error::Trivia(e.to_string()))
+        };
+        results
+            .results
+            .iter()
+            .map(|result| {
+                Ok(Question {
+                    question: decode(&result.question)?,
+                    answer: decode(&result.correct_answer)?,
+                    category: Some(decode(&result.category)?),
+                })
+            })
+            .collect()
+    }
+
+    /// Starts a game in `channel` with whatever `questions` comes up with, unless there's one
+    /// going already. Returns whether it started.
+    pub fn start(
+        self: &Arc<Self>,
+        channel: &str,
+        key: &str,
+        nick: &str,
+        outgoing: &mpsc::UnboundedSender<Outgoing>,
+        questions: impl Future<Output = Result<Vec<Question>, Error>> + Send + 'static,
+    ) -> bool {
+        let mut games = self.games.lock().expect("trivia lock poisoned");
+        if games.contains_key(key) {
+            return false;
+        }
+
+        let trivia = self.clone();
+        let channel = channel.to_string();
+        let key = key.to_string();
+        let outgoing = outgoing.clone();
+        let task = tokio::spawn(
+            {
+                let key = key.clone();
+                async move {
+                    let sorry = match questions.await {
+                        Ok(questions) if !questions.is_empty() => {
+                            return trivia.play(&channel, &key, &outgoing, questions).await;
+                        }
+                        Ok(_) => "I'm out of questions",
+                        Err(e) => {
+                            warn!("Unable to get trivia questions: {}", e);
+                            "I can't think of any questions right now"
+                        }
+                    };
+                    trivia
+                        .games
+                        .lock()
+                        .expect("trivia lock poisoned")
+                        .remove(&key);
+                    queue(&outgoing, &channel, sorry.to_string());
+                }
+            }
+            .in_current_span(),
+        );
+        games.insert(
+            key,
+            Game {
+                started_by: nick.to_string(),
+                task: Some(task.abort_handle()),
+                ..Default::default()
+            },
+        );
+
+        true
+    }
+
+    /// Asks each question in turn, hinting at the answer as time runs out, then says who won.
+    async fn play(
+        &self,
+        channel: &str,
+        key: &str,
+        outgoing: &mpsc::UnboundedSender<Outgoing>,
+        questions: Vec<Question>,
+    ) {
+        let count = questions.len();
+        let wait = Duration::from_secs(self.config.hint_secs);
+        for (i, question) in questions.into_iter().enumerate() {
+            if i > 0 {
+                time::sleep(PAUSE).await;
+            }
+            // The connection went away and the game with it
+            if outgoing.is_closed() {
+                return;
+            }
+
+            let asked = match &question.category {
+                Some(category) => format!(
+                    "Question {}/{} ({}): {}",
+                    i + 1,
+                    count,
+                    category,
+                    question.question
+                ),
+                None => format!("Question {}/{}: {}", i + 1, count, question.question),
+            };
+            let answered = Arc::new(Notify::new());
+            {
+                let mut games = self.games.lock().expect("trivia lock poisoned");
+                let Some(game) = games.get_mut(key) else {
+                    return;
+                };
+                game.asking = Some(Asking {
+                    question,
+                    hints: 0,
+                    answered: answered.clone(),
+                });
+            }
+            queue(outgoing, channel, asked);
+
+            loop {
+                tokio::select! {
+                    _ = answered.notified() => break,
+                    _ = time::sleep(wait) => {
+                        let mut games = self.games.lock().expect("trivia lock poisoned");
+                        let Some(game) = games.get_mut(key) else {
+                            return;
+                        };
+                        // Answered just as the time ran out
+                        let Some(asking) = &mut game.asking else {
+                            break;
+                        };
+                        if asking.hints < self.config.hints {
+                            asking.hints += 1;
+                            let hint = hint(&asking.question.answer, asking.hints, self.config.hints);
+                            queue(outgoing, channel, format!("Hint: {}", hint));
+                        } else {
+                            let answer = &asking.question.answer;
+                            queue(outgoing, channel, format!("Time's up, it was {}", answer));
+                            game.asking = None;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let game = self.games.lock().expect("trivia lock poisoned").remove(key);
+        if let Some(game) = game {
+            queue(
+                outgoing,
+                channel,
+                format!("That's the game! {}", standings(&game)),
+            );
+        }
+    }
+
+    /// Who started the game going on in `channel`, if there is one.
+    pub fn started_by(&self, key: &str) -> Option<String> {
+        self.games
+            .lock()
+            .expect("trivia lock poisoned")
+            .get(key)
+            .map(|game| game.started_by.clone())
+    }
+
+    /// Ends the game in `channel` early. Returns what to say about it, or `None` if there's no
+    /// game to stop.
+    pub fn stop(&self, key: &str) -> Option<String> {
+        let game = self
+            .games
+            .lock()
+            .expect("trivia lock poisoned")
+            .remove(key)?;
+        if let Some(task) = &game.task {
+            task.abort();
+        }
+        let mut stopped = String::from("Trivia's over.");
+        if let Some(asking) = &game.asking {
+            stopped.push_str(&format!(" It was {}.", asking.question.answer));
+        }
+        Some(format!("{} {}", stopped, standings(&game)))
+    }
+
+    /// Checks `msg` against the question being asked in `channel`. If it's right, `nick`
+    /// scores and gets told so.
+    pub async fn guess(&self, key: &str, nick: &str, msg: &str) -> Option<String> {
+        let (answer, points) = {
+            let mut games = self.games.lock().expect("trivia lock poisoned");
+            let game = games.get_mut(key)?;
+            if !is_right(msg, &game.asking.as_ref()?.question.answer) {
+                return None;
+            }
+            let asking = game.asking.take()?;
+            asking.answered.notify_one();
+
+            // Fewer points the more it took
+            let points = (self.config.hints + 1).saturating_sub(asking.hints).max(1) as i64;
+            game.points
+                .entry(nick.to_lowercase())
+                .or_insert_with(|| (nick.to_string(), 0))
+                .1 += points;
+            (asking.question.answer, points)
+        };
+
+        let total = {
+            let mut scores = self.scores.lock().expect("trivia lock poisoned");
+            let score = scores
+                .entry(nick.to_lowercase())
+                .or_insert_with(|| (nick.to_string(), 0));
+            score.1 += points;
+            score.1
+        };
+        if let Some(store) = &self.store {
+            if let Err(e) = store.add_trivia_score(&self.scope, nick, points).await {
+                warn!("Unable to save {}'s trivia score: {}", nick, e);
+            }
+        }
+
+        Some(format!(
+            "{} got it: {} (+{}, {} in all)",
+            nick, answer, points, total
+        ))
+    }
+}
+
+/// The questions in what the model wrote, which should be a JSON array but might come with
+/// something either side of it.
+fn parse(content: &str) -> Result<Vec<Question>, Error> {
+    let (Some(start), Some(end)) = (content.find('['), content.rfind(']')) else {
+        return Err(Error::Trivia(String::from("the model didn't write any")));
+    };
+    let questions: Vec<Question> = serde_json::from_str(content.get(start..=end).unwrap_or(""))
+        .map_err(|e| Error::Trivia(format!("the model wrote them wrong: {}", e)))?;
+
+    Ok(questions
+        .into_iter()
+        .filter(|question| {
+            !question.question.trim().is_empty() && !normalize(&question.answer).is_empty()
+        })
+        .collect())
+}
+
+/// Who scored what this game, best first.
+fn standings(game: &Game) -> String {
+    let mut points = game.points.values().collect::<Vec<_>>();
+    if points.is_empty() {
+        return String::from("Nobody scored.");
+    }
+    points.sort_by_key(|(_, points)| Reverse(*points));
+    points
+        .iter()
+        .map(|(nick, points)| format!("{} {}", nick, points))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `text` lowercased, without punctuation or a leading article, so `The Beatles!` and
+/// `beatles` are the same.
+fn normalize(text: &str) -> String {
+    let text = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    match words.split_first() {
+        Some((&("the" | "a" | "an"), rest)) if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+/// Whether `guess` is close enough to `answer`. Small typos are forgiven in longer answers,
+/// and the answer can come with a few words around it, but numbers have to be exact.
+pub fn is_right(guess: &str, answer: &str) -> bool {
+    let (guess, answer) = (normalize(guess), normalize(answer));
+    if answer.is_empty() {
+        return false;
+    }
+    if guess == answer {
+        return true;
+    }
+
+    let answer_words = answer.split(' ').count();
+    let guess_words = guess.split(' ').collect::<Vec<_>>();
+    if guess_words.len() <= answer_words + MAX_EXTRA_WORDS
+        && guess_words
+            .windows(answer_words)
+            .any(|words| words.join(" ") == answer)
+    {
+        return true;
+    }
+
+    let typos = match answer.chars().count() {
+        _ if answer.chars().any(|c| c.is_ascii_digit()) => 0,
+        len if len <= MAX_EXACT_LEN => 0,
+        len if len <= 8 => 1,
+        _ => 2,
+    };
+    typos > 0 && distance(&guess, &answer) <= typos
+}
+
+/// How many letters have to be added, removed, changed or swapped with the next to turn `a`
+/// into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // d[i][j] is the distance between the first i letters of a and the first j of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in d[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let changed = d[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = changed.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// `answer` with its letters blanked out for hint number `level` of `hints`. The first shows
+/// only its shape, and each after that fills in more of every word from the front.
+pub fn hint(answer: &str, level: usize, hints: usize) -> String {
+    answer
+        .split(' ')
+        .map(|word| {
+            let letters = word.chars().filter(|c| c.is_alphanumeric()).count();
+            let shown = (letters * level.saturating_sub(1)).div_ceil(hints.max(1));
+            let mut seen = 0;
+            word.chars()
+                .map(|c| {
+                    if !c.is_alphanumeric() {
+                        return c;
+                    }
+                    seen += 1;
+                    match seen <= shown {
+                        true => c,
+                        false => '_',
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
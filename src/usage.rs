@@ -0,0 +1,186 @@
+use chrono::NaiveDate;
+use chrono::Utc;
+
+use tracing::*;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::casemap::CaseMapping;
+use crate::config::QuotaConfig;
+use crate::llm::Usage;
+use crate::metrics::metrics;
+use crate::storage::UsageStore;
+use crate::Error;
+
+/// What private messages are filed under, since they don't have a channel.
+const PRIVATE: &str = "";
+
+/// Requests made and tokens spent answering them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Spent {
+    pub requests: u64,
+    pub usage: Usage,
+}
+
+impl AddAssign for Spent {
+    fn add_assign(&mut self, other: Self) {
+        self.requests += other.requests;
+        self.usage += other.usage;
+    }
+}
+
+/// What one person spent in one channel on one day.
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub day: NaiveDate,
+    /// Who they're remembered as, see `memory::identity()`, so a new nick is no new budget.
+    pub nick: String,
+    pub channel: String,
+    pub spent: Spent,
+}
+
+/// Which daily budget has run out.
+pub enum Exhausted {
+    Nick,
+    Channel,
+}
+
+impl Exhausted {
+    pub fn apology(&self, nick: &str) -> String {
+        match self {
+            Exhausted::Nick => format!(
+                "{}: you've used up your share of my brain for today, try again tomorrow",
+                nick
+            ),
+            Exhausted::Channel => format!(
+                "{}: this channel has used up my brain for today, try again tomorrow",
+                nick
+            ),
+        }
+    }
+}
+
+/// Who has spent how many tokens where, by day, so the bill can be traced back to someone.
+/// Changes are written through to the store, if there is one.
+pub struct Ledger {
+    scope: String,
+    records: Mutex<HashMap<(NaiveDate, String, String), Spent>>,
+    store: Option<Arc<dyn UsageStore>>,
+}
+
+impl Ledger {
+    pub async fn load(scope: &str, store: Option<Arc<dyn UsageStore>>) -> Result<Self, Error> {
+        let records = match &store {
+            Some(store) => store.usage(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            records: Mutex::new(
+                records
+                    .into_iter()
+                    .map(|record| ((record.day, record.nick, record.channel), record.spent))
+                    .collect(),
+            ),
+            store,
+        })
+    }
+
+    /// Charges `usage` to `identity`, as `memory::identity()` has it, where `nick` was
+    /// answered: a channel, or `nick` itself for a private message.
+    pub async fn record(&self, nick: &str, identity: &str, target: &str, usage: Usage) {
+        let channel = (target != nick).then_some(target);
+        let record = UsageRecord {
+            day: Utc::now().date_naive(),
+            nick: identity.to_string(),
+            channel: channel.unwrap_or(PRIVATE).to_string(),
+            spent: Spent { requests: 1, usage },
+        };
+        *self
+            .records
+            .lock()
+            .expect("ledger lock poisoned")
+            .entry((record.day, record.nick.clone(), record.channel.clone()))
+            .or_default() += record.spent;
+
+        let channel = channel.unwrap_or("private");
+        let tokens = &metrics().usage_tokens;
+        tokens
+            .with_label_values(&[&self.scope, channel, "prompt"])
+            .inc_by(usage.prompt_tokens);
+        tokens
+            .with_label_values(&[&self.scope, channel, "completion"])
+            .inc_by(usage.completion_tokens);
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.add_usage(&self.scope, &record).await {
+                warn!("Unable to save usage for {}: {}", nick, e);
+            }
+        }
+    }
+
+    /// Everything spent since `since` by whichever nicks and channels `matches` picks.
+    pub fn spent(&self, since: NaiveDate, matches: impl Fn(&str, &str) -> bool) -> Spent {
+        let mut total = Spent::default();
+        for ((day, nick, channel), spent) in
+            self.records.lock().expect("ledger lock poisoned").iter()
+        {
+            if *day >= since && matches(nick, channel) {
+                total += *spent;
+            }
+        }
+
+        total
+    }
+
+    /// Checks whether `identity`, or the channel `nick` would be answered in, has used up
+    /// today's budget. The arguments are as for `record()`, with channels compared the way
+    /// `casemapping` says.
+    pub fn exhausted(
+        &self,
+        quota: &QuotaConfig,
+        nick: &str,
+        identity: &str,
+        target: &str,
+        casemapping: CaseMapping,
+    ) -> Option<Exhausted> {
+        let over = |spent: Spent, requests: u64, tokens: u64| {
+            (requests > 0 && spent.requests >= requests)
+                || (tokens > 0 && spent.usage.total() >= tokens)
+        };
+        let today = Utc::now().date_naive();
+
+        let by_nick = self.spent(today, |n, _| casemapping.eq(n, identity));
+        if over(by_nick, quota.nick_requests, quota.nick_tokens) {
+            return Some(Exhausted::Nick);
+        }
+        if target != nick {
+            let by_channel = self.spent(today, |_, channel| casemapping.eq(channel, target));
+            if over(by_channel, quota.channel_requests, quota.channel_tokens) {
+                return Some(Exhausted::Channel);
+            }
+        }
+
+        None
+    }
+
+    /// The `n` nicks who've spent the most since `since`, biggest first.
+    pub fn top(&self, since: NaiveDate, n: usize) -> Vec<(String, Spent)> {
+        let mut by_nick: HashMap<String, Spent> = HashMap::new();
+        for ((day, nick, _), spent) in self.records.lock().expect("ledger lock poisoned").iter() {
+            if *day >= since {
+                *by_nick.entry(nick.clone()).or_default() += *spent;
+            }
+        }
+
+        let mut top = by_nick.into_iter().collect::<Vec<_>>();
+        top.sort_by_key(|(_, spent)| Reverse(spent.usage.total()));
+        top.truncate(n);
+        top
+    }
+}
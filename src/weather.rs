@@ -0,0 +1,281 @@
+use serde_json::Value;
+
+use tokio::time::Duration;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::config::Units;
+use crate::config::WeatherConfig;
+use crate::config::WeatherProvider;
+use crate::storage::LocationStore;
+use crate::Error;
+
+const OPEN_METEO_GEOCODING: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const OPEN_METEO_FORECAST: &str = "https://api.open-meteo.com/v1/forecast";
+const OPENWEATHERMAP: &str = "https://api.openweathermap.org/data/2.5/weather";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Looks up the weather, from Open-Meteo or OpenWeatherMap.
+#[derive(Clone)]
+pub struct Weather {
+    config: WeatherConfig,
+    http: reqwest::Client,
+}
+
+impl Weather {
+    pub fn new(config: &WeatherConfig) -> Self {
+        Self {
+            config: config.clone(),
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+        }
+    }
+
+    /// The weather at `location` right now and today's high and low, in a line.
+    pub async fn current(&self, location: &str) -> Result<String, Error> {
+        match self.config.provider {
+            WeatherProvider::OpenMeteo => self.open_meteo(location).await,
+            WeatherProvider::OpenWeatherMap => self.openweathermap(location).await,
+        }
+    }
+
+    async fn get(&self, url: &str, query: &[(&str, &str)]) -> Result<Value, Error> {
+        Ok(self
+            .http
+            .get(url)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn open_meteo(&self, location: &str) -> Result<String, Error> {
+        let places = self
+            .get(
+                OPEN_METEO_GEOCODING,
+                &[("name", location), ("count", "1"), ("format", "json")],
+            )
+            .await?;
+        let place = &places["results"][0];
+        let (Some(latitude), Some(longitude)) =
+            (place["latitude"].as_f64(), place["longitude"].as_f64())
+        else {
+            return Err(Error::Weather(format!("can't find {}", location)));
+        };
+        let name = ["name", "admin1", "country"]
+            .iter()
+            .filter_map(|field| place[field].as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let (temperature_unit, wind_speed_unit) = match self.config.units {
+            Units::Metric => ("celsius", "kmh"),
+            Units::Imperial => ("fahrenheit", "mph"),
+        };
+        let forecast = self
+            .get(
+                OPEN_METEO_FORECAST,
+                &[
+                    ("latitude", &latitude.to_string()),
+                    ("longitude", &longitude.to_string()),
+                    (
+                        "current",
+                        "temperature_2m,apparent_temperature,relative_humidity_2m,weather_code,wind_speed_10m",
+                    ),
+                    ("daily", "temperature_2m_max,temperature_2m_min"),
+                    ("timezone", "auto"),
+                    ("forecast_days", "1"),
+                    ("temperature_unit", temperature_unit),
+                    ("wind_speed_unit", wind_speed_unit),
+                ],
+            )
+            .await?;
+        let current = &forecast["current"];
+        let daily = &forecast["daily"];
+
+        Ok(self.describe(&Conditions {
+            place: name,
+            description: current["weather_code"]
+                .as_u64()
+                .map(wmo_description)
+                .unwrap_or("who knows")
+                .to_string(),
+            temperature: current["temperature_2m"].as_f64(),
+            feels_like: current["apparent_temperature"].as_f64(),
+            humidity: current["relative_humidity_2m"].as_f64(),
+            wind: current["wind_speed_10m"].as_f64(),
+            low: daily["temperature_2m_min"][0].as_f64(),
+            high: daily["temperature_2m_max"][0].as_f64(),
+        }))
+    }
+
+    async fn openweathermap(&self, location: &str) -> Result<String, Error> {
+        let Some(api_key) = &self.config.api_key else {
+            return Err(Error::Weather(String::from(
+                "OpenWeatherMap needs an api_key",
+            )));
+        };
+        let units = match self.config.units {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+        };
+        let response = self
+            .http
+            .get(OPENWEATHERMAP)
+            .query(&[("q", location), ("appid", api_key), ("units", units)])
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::Weather(format!("can't find {}", location)));
+        }
+        let weather: Value = response.error_for_status()?.json().await?;
+        let main = &weather["main"];
+        let place = ["name", "country"]
+            .iter()
+            .filter_map(|field| weather[field].as_str().or(weather["sys"][field].as_str()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // Metres a second, unless it's miles an hour already
+        let wind = weather["wind"]["speed"]
+            .as_f64()
+            .map(|speed| match self.config.units {
+                Units::Metric => speed * 3.6,
+                Units::Imperial => speed,
+            });
+
+        Ok(self.describe(&Conditions {
+            place,
+            description: weather["weather"][0]["description"]
+                .as_str()
+                .unwrap_or("who knows")
+                .to_string(),
+            temperature: main["temp"].as_f64(),
+            feels_like: main["feels_like"].as_f64(),
+            humidity: main["humidity"].as_f64(),
+            wind,
+            low: main["temp_min"].as_f64(),
+            high: main["temp_max"].as_f64(),
+        }))
+    }
+
+    fn describe(&self, conditions: &Conditions) -> String {
+        let (degrees, speed) = match self.config.units {
+            Units::Metric => ("°C", "km/h"),
+            Units::Imperial => ("°F", "mph"),
+        };
+        let mut parts = vec![conditions.description.clone()];
+        if let Some(temperature) = conditions.temperature {
+            let mut part = format!("{:.0}{}", temperature, degrees);
+            if let Some(feels_like) = conditions.feels_like {
+                if (feels_like - temperature).abs() >= 1.0 {
+                    part.push_str(&format!(" (feels like {:.0}{})", feels_like, degrees));
+                }
+            }
+            parts.push(part);
+        }
+        if let Some(humidity) = conditions.humidity {
+            parts.push(format!("humidity {:.0}%", humidity));
+        }
+        if let Some(wind) = conditions.wind {
+            parts.push(format!("wind {:.0} {}", wind, speed));
+        }
+        let mut line = format!("{}: {}", conditions.place, parts.join(", "));
+        if let (Some(low), Some(high)) = (conditions.low, conditions.high) {
+            line.push_str(&format!("; today {:.0} to {:.0}{}", low, high, degrees));
+        }
+
+        line
+    }
+}
+
+/// What the weather's doing somewhere, in whichever units were asked for.
+struct Conditions {
+    place: String,
+    description: String,
+    temperature: Option<f64>,
+    feels_like: Option<f64>,
+    humidity: Option<f64>,
+    wind: Option<f64>,
+    low: Option<f64>,
+    high: Option<f64>,
+}
+
+/// What a WMO weather code means, as Open-Meteo uses them.
+fn wmo_description(code: u64) -> &'static str {
+    match code {
+        0 => "clear sky",
+        1 => "mainly clear",
+        2 => "partly cloudy",
+        3 => "overcast",
+        45 | 48 => "fog",
+        51 | 53 | 55 => "drizzle",
+        56 | 57 => "freezing drizzle",
+        61 => "light rain",
+        63 => "rain",
+        65 => "heavy rain",
+        66 | 67 => "freezing rain",
+        71 => "light snow",
+        73 => "snow",
+        75 => "heavy snow",
+        77 => "snow grains",
+        80..=82 => "rain showers",
+        85 | 86 => "snow showers",
+        95 => "thunderstorm",
+        96 | 99 => "thunderstorm with hail",
+        _ => "who knows",
+    }
+}
+
+/// Where everyone on one network wants the weather for when they don't say. Changes are
+/// written through to the store, if there is one.
+pub struct Locations {
+    scope: String,
+    /// By whoever they're remembered as, see `memory::identity()`.
+    saved: Mutex<HashMap<String, String>>,
+    store: Option<Arc<dyn LocationStore>>,
+}
+
+impl Locations {
+    pub async fn load(scope: &str, store: Option<Arc<dyn LocationStore>>) -> Result<Self, Error> {
+        let saved = match &store {
+            Some(store) => store.locations(scope).await?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            scope: scope.to_string(),
+            saved: Mutex::new(saved.into_iter().collect()),
+            store,
+        })
+    }
+
+    pub fn get(&self, identity: &str) -> Option<String> {
+        self.saved
+            .lock()
+            .expect("locations lock poisoned")
+            .get(identity)
+            .cloned()
+    }
+
+    pub async fn set(&self, identity: &str, location: &str) {
+        self.saved
+            .lock()
+            .expect("locations lock poisoned")
+            .insert(identity.to_string(), location.to_string());
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store.set_location(&self.scope, identity, location).await {
+                warn!("Unable to save {}'s location: {}", identity, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Relaying what other services POST, like GitHub pushes or Grafana alerts, into channels.
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+
+use regex::Captures;
+use regex::Regex;
+
+use serde_json::Value;
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::LazyLock;
+
+use crate::admin::admin;
+use crate::config::WebhookConfig;
+use crate::filter::Filter;
+use crate::output::say;
+use crate::ratelimit::RateLimiter;
+
+static FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{([\w.-]+)\}").expect("Invalid field regex"));
+
+struct Webhook {
+    config: WebhookConfig,
+    limiter: RateLimiter,
+}
+
+type Webhooks = Arc<HashMap<String, Webhook>>;
+
+/// `/webhook/<token>` for each of `webhooks`.
+pub fn routes(webhooks: &[WebhookConfig]) -> Router {
+    let webhooks: Webhooks = Arc::new(
+        webhooks
+            .iter()
+            .map(|config| {
+                let webhook = Webhook {
+                    config: config.clone(),
+                    limiter: RateLimiter::new(&config.rate_limit),
+                };
+                (config.token.clone(), webhook)
+            })
+            .collect(),
+    );
+
+    Router::new()
+        .route("/webhook/:token", post(deliver))
+        .with_state(webhooks)
+}
+
+async fn deliver(
+    State(webhooks): State<Webhooks>,
+    Path(token): Path<String>,
+    Json(payload): Json<Value>,
+) -> StatusCode {
+    let Some(webhook) = webhooks.get(&token) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let WebhookConfig {
+        network, channel, ..
+    } = &webhook.config;
+    if let Err(wait) = webhook.limiter.check(channel) {
+        info!(
+            "Turning away a webhook for {} on {} for another {:?}",
+            channel, network, wait
+        );
+        return StatusCode::TOO_MANY_REQUESTS;
+    }
+    let Some(network_state) = admin().get(network) else {
+        warn!(
+            "Webhook for {} on {}, which isn't a network",
+            channel, network
+        );
+        return StatusCode::SERVICE_UNAVAILABLE;
+    };
+
+    info!("Relaying a webhook to {} on {}", channel, network);
+    let (lines, rx) = mpsc::unbounded_channel();
+    for line in fill(&webhook.config.template, &payload).lines() {
+        let _ = lines.send(line.to_string());
+    }
+    drop(lines);
+    let config = &network_state.config;
+    let filter = Filter::new(&config.filter, network_state.backend.clone());
+    let (outgoing, channel, formatting) = (
+        network_state.outgoing.clone(),
+        channel.clone(),
+        config.formatting,
+    );
+    // Said to the channel as its own private message nick, so long payloads aren't sent
+    // privately to anyone
+    tokio::spawn(
+        async move { say(&outgoing, &channel, rx, &channel, formatting, None, filter).await }
+            .in_current_span(),
+    );
+
+    StatusCode::ACCEPTED
+}
+
+/// `template` with each `{field}` filled in from `payload`, or left empty where the payload
+/// doesn't have it. Values are put on one line so they can't add lines of their own.
+fn fill(template: &str, payload: &Value) -> String {
+    FIELD
+        .replace_all(template, |captures: &Captures| {
+            let text = match field(payload, &captures[1]) {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            };
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .into_owned()
+}
+
+/// What's at a dotted `path` like `commits.0.message` in `payload`, if anything.
+pub fn field<'a>(payload: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(payload, |value, key| match value {
+        Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
@@ -0,0 +1,160 @@
+//! Looking things up on Wikipedia, which is cheaper and more reliable than asking the model.
+
+use reqwest::StatusCode;
+use reqwest::Url;
+
+use serde::Deserialize;
+
+use tokio::time::Duration;
+
+use crate::Error;
+
+const SUMMARY: &str = "https://en.wikipedia.org/api/rest_v1/page/summary";
+const SEARCH: &str = "https://en.wikipedia.org/w/rest.php/v1/search/page";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest a summary gets before it's cut short at the end of a sentence.
+const MAX_CHARS: usize = 350;
+
+/// How many pages a disambiguation lists.
+const MAX_MEANINGS: usize = 5;
+
+#[derive(Deserialize)]
+struct Summary {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    #[serde(default)]
+    extract: String,
+    content_urls: ContentUrls,
+}
+
+#[derive(Deserialize)]
+struct ContentUrls {
+    desktop: PageUrls,
+}
+
+#[derive(Deserialize)]
+struct PageUrls {
+    page: String,
+}
+
+#[derive(Deserialize)]
+struct Results {
+    #[serde(default)]
+    pages: Vec<Found>,
+}
+
+#[derive(Deserialize)]
+struct Found {
+    title: String,
+}
+
+#[derive(Clone)]
+pub struct Wikipedia {
+    http: reqwest::Client,
+}
+
+impl Default for Wikipedia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wikipedia {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .user_agent("pickles")
+                .timeout(TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+        }
+    }
+
+    /// The first paragraph of the article `query` is about and a link to it, or what it
+    /// might mean if that's ambiguous. `None` if there's no such article.
+    pub async fn summary(&self, query: &str) -> Result<Option<String>, Error> {
+        // Titles are exact, so fall back on searching for "albert einstein" and the like
+        let summary = match self.page(query).await? {
+            Some(summary) => summary,
+            None => match self.search(query, 1).await?.into_iter().next() {
+                Some(title) => match self.page(&title).await? {
+                    Some(summary) => summary,
+                    None => return Ok(None),
+                },
+                None => return Ok(None),
+            },
+        };
+
+        if summary.kind == "disambiguation" {
+            let meanings = self
+                .search(&summary.title, MAX_MEANINGS + 1)
+                .await?
+                .into_iter()
+                .filter(|title| *title != summary.title)
+                .take(MAX_MEANINGS)
+                .collect::<Vec<_>>();
+            if !meanings.is_empty() {
+                return Ok(Some(format!(
+                    "{} could mean {}. {}",
+                    summary.title,
+                    meanings.join(", "),
+                    summary.content_urls.desktop.page
+                )));
+            }
+        }
+
+        Ok(Some(format!(
+            "{} {}",
+            first_sentences(&summary.extract),
+            summary.content_urls.desktop.page
+        )))
+    }
+
+    async fn page(&self, title: &str) -> Result<Option<Summary>, Error> {
+        let mut url = Url::parse(SUMMARY).expect("Invalid Wikipedia URL");
+        url.path_segments_mut()
+            .expect("Wikipedia URL has a path")
+            .push(&title.replace(' ', "_"));
+        let response = self.http.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    /// The titles of the pages that best match `query`.
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<String>, Error> {
+        let results: Results = self
+            .http
+            .get(SEARCH)
+            .query(&[("q", query), ("limit", &limit.to_string())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(results.pages.into_iter().map(|page| page.title).collect())
+    }
+}
+
+/// As many whole sentences of `text` as fit in `MAX_CHARS`, or the first cut short.
+fn first_sentences(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= MAX_CHARS {
+        return text;
+    }
+
+    let end = text
+        .char_indices()
+        .nth(MAX_CHARS)
+        .map_or(text.len(), |(end, _)| end);
+    match text[..end].rfind(". ") {
+        Some(stop) => text[..=stop].to_string(),
+        None => format!("{}...", text[..end].trim_end()),
+    }
+}
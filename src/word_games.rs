@@ -0,0 +1,444 @@
+//! `!wordle` and `!hangman`, played by everyone in a channel together. There's one Wordle a
+//! day, the same word everywhere, and hangman games go on until the word's found or the
+//! lives run out.
+
+use chrono::NaiveDate;
+use chrono::Utc;
+
+use rand::seq::SliceRandom;
+
+use tracing::*;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::storage::WordGameStore;
+use crate::Error;
+
+pub const WORDLE: &str = "wordle";
+pub const HANGMAN: &str = "hangman";
+
+/// Guesses at each day's Wordle.
+const WORDLE_GUESSES: usize = 6;
+
+/// Wrong guesses allowed at hangman.
+const HANGMAN_LIVES: usize = 6;
+
+/// Wordle number one.
+const FIRST_DAY: NaiveDate = match NaiveDate::from_ymd_opt(2024, 1, 1) {
+    Some(day) => day,
+    None => panic!("Invalid first Wordle"),
+};
+
+/// Steps through `WORDLE_WORDS` a day at a time without the words coming round in order.
+/// Shares no factor with how many there are, so every word comes up once before any repeat.
+const STRIDE: i64 = 7919;
+
+/// Today's Wordle is one of these. Any five letters will do as a guess.
+#[rustfmt::skip]
+const WORDLE_WORDS: &[&str] = &[
+    "about", "above", "actor", "acute", "admit", "adopt", "adult", "after", "again", "agent",
+    "agree", "ahead", "alarm", "album", "alert", "alike", "alive", "allow", "alone", "along",
+    "amber", "angel", "anger", "angle", "angry", "apart", "apple", "apply", "arena", "argue",
+    "arise", "aside", "asset", "audio", "avoid", "awake", "award", "aware", "bacon", "badge",
+    "basic", "beach", "beard", "beast", "begin", "below", "bench", "berry", "birth", "black",
+    "blade", "blame", "blank", "blast", "blend", "bless", "blind", "block", "blood", "bloom",
+    "board", "boast", "bonus", "boost", "booth", "bound", "brain", "brake", "brand", "brave",
+    "bread", "break", "brick", "bride", "brief", "bring", "broad", "brown", "brush", "build",
+    "burst", "cabin", "cable", "camel", "candy", "canoe", "cargo", "carry", "catch", "cause",
+    "chain", "chair", "chalk", "charm", "chart", "chase", "cheap", "check", "cheek", "chess",
+    "chest", "chief", "child", "chill", "choir", "civic", "claim", "clash", "class", "clean",
+    "clear", "clerk", "click", "cliff", "climb", "clock", "close", "cloth", "cloud", "coach",
+    "coast", "cocoa", "couch", "count", "court", "cover", "crane", "crash", "crazy", "cream",
+    "crime", "crisp", "cross", "crowd", "crown", "crush", "curve", "cycle", "daily", "dance",
+    "delay", "depth", "diary", "dirty", "doubt", "dough", "draft", "drain", "drama", "dream",
+    "dress", "drink", "drive", "eager", "eagle", "early", "earth", "eight", "elbow", "elder",
+    "empty", "enemy", "enjoy", "enter", "entry", "equal", "error", "event", "exact", "exist",
+    "extra", "faith", "false", "fancy", "feast", "fence", "fever", "field", "fifty", "fight",
+    "final", "flame", "flash", "fleet", "float", "flood", "floor", "flour", "fluid", "focus",
+    "force", "forge", "forum", "frame", "fresh", "front", "frost", "fruit", "funny", "ghost",
+    "giant", "glass", "globe", "glory", "glove", "grace", "grade", "grain", "grand", "grant",
+    "grape", "graph", "grass", "great", "green", "greet", "grief", "grill", "grind", "group",
+    "guard", "guess", "guest", "guide", "habit", "happy", "harsh", "heart", "heavy", "hedge",
+    "honey", "horse", "hotel", "house", "human", "humor", "hurry", "ideal", "image", "index",
+    "inner", "input", "issue", "ivory", "jelly", "jewel", "joint", "judge", "juice", "knife",
+    "knock", "label", "large", "laser", "laugh", "layer", "learn", "lemon", "level", "light",
+    "limit", "linen", "liver", "local", "logic", "loose", "lower", "loyal", "lucky", "lunch",
+    "magic", "major", "mango", "maple", "march", "match", "mayor", "medal", "melon", "mercy",
+    "metal", "minor", "model", "money", "month", "moral", "motor", "mount", "mouse", "mouth",
+    "movie", "music", "nerve", "night", "noble", "noise", "north", "novel", "nurse", "ocean",
+    "offer", "olive", "onion", "opera", "orbit", "order", "other", "owner", "paint", "panel",
+    "panic", "paper", "party", "pasta", "patch", "pause", "peace", "peach", "pearl", "penny",
+    "phase", "phone", "photo", "piano", "piece", "pilot", "pitch", "pizza", "place", "plain",
+    "plane", "plant", "plate", "point", "polar", "pound", "power", "press", "price", "pride",
+    "prime", "print", "prize", "proof", "proud", "pulse", "punch", "queen", "quick", "quiet",
+    "radio", "raise", "ranch", "range", "rapid", "reach", "ready", "realm", "rebel", "relax",
+    "reply", "rider", "ridge", "right", "rival", "river", "roast", "robot", "rocky", "rough",
+    "round", "route", "royal", "salad", "sauce", "scale", "scene", "score", "sense", "serve",
+    "seven", "shade", "shake", "shape", "share", "shark", "sharp", "sheep", "shelf", "shell",
+    "shift", "shine", "shirt", "shock", "shoot", "short", "shout", "sight", "silly", "skill",
+    "skirt", "skull", "sleep", "slice", "slide", "smart", "smell", "smile", "smoke", "snake",
+    "solid", "solve", "sound", "south", "space", "spare", "spark", "speak", "speed", "spell",
+    "spend", "spice", "spine", "spoon", "sport", "spray", "squad", "stack", "staff", "stage",
+    "stair", "stamp", "stand", "start", "state", "steam", "steel", "steep", "stick", "still",
+    "stock", "stone", "stool", "storm", "story", "stove", "straw", "strip", "study", "style",
+    "sugar", "sunny", "sweet", "swing", "sword", "table", "taste", "teach", "thank", "theme",
+    "thick", "thief", "thing", "think", "third", "thumb", "tiger", "tight", "toast", "today",
+    "token", "tooth", "topic", "torch", "total", "touch", "tough", "tower", "track", "trade",
+    "trail", "train", "treat", "trend", "trial", "tribe", "trick", "truck", "trust", "truth",
+    "twice", "twist", "uncle", "under", "union", "upper", "upset", "urban", "usual", "valid",
+    "value", "vapor", "video", "virus", "visit", "vital", "vivid", "vocal", "voice", "waste",
+    "watch", "water", "whale", "wheat", "wheel", "while", "white", "whole", "woman", "world",
+    "worry", "worth", "wound", "wrist", "write", "wrong", "yacht", "yield", "young", "zebra",
+];
+
+#[rustfmt::skip]
+const HANGMAN_WORDS: &[&str] = &[
+    "adventure", "airplane", "alphabet", "anchor", "apricot", "astronaut", "avalanche",
+    "backpack", "balcony", "bamboo", "banana", "barbecue", "basket", "battery", "beehive",
+    "bicycle", "blanket", "blizzard", "bookshelf", "boomerang", "butterfly", "cabbage", "cactus",
+    "calendar", "camera", "candle", "canyon", "caravan", "carnival", "castle", "caterpillar",
+    "cathedral", "champion", "chimney", "chocolate", "cinnamon", "compass", "computer",
+    "crocodile", "crystal", "cucumber", "daffodil", "diamond", "dinosaur", "dolphin", "dragon",
+    "elephant", "envelope", "escalator", "festival", "firework", "flamingo", "fountain",
+    "galaxy", "giraffe", "glacier", "gorilla", "guitar", "hammock", "harmonica", "helicopter",
+    "hedgehog", "horizon", "iceberg", "island", "jaguar", "jellyfish", "journey", "kangaroo",
+    "keyboard", "kitchen", "lantern", "lighthouse", "lobster", "magnet", "mammoth", "marathon",
+    "meadow", "microscope", "mountain", "mushroom", "necklace", "notebook", "octopus",
+    "orchestra", "ostrich", "oxygen", "pancake", "parachute", "parrot", "peacock", "penguin",
+    "pineapple", "planet", "pumpkin", "puzzle", "pyramid", "rainbow", "raccoon", "rhythm",
+    "saxophone", "scissors", "scorpion", "skeleton", "snowflake", "spaghetti", "squirrel",
+    "strawberry", "submarine", "sunflower", "telescope", "thunder", "tornado", "tortoise",
+    "treasure", "trombone", "trumpet", "tunnel", "umbrella", "unicorn", "vampire", "velvet",
+    "violin", "volcano", "waffle", "walrus", "waterfall", "whistle", "wizard", "xylophone",
+    "yogurt", "zeppelin", "zucchini",
+];
+
+/// Wordle's number for `day`, and its word.
+pub fn daily_word(day: NaiveDate) -> (i64, &'static str) {
+    let number = (day - FIRST_DAY).num_days() + 1;
+    let index = (number * STRIDE).rem_euclid(WORDLE_WORDS.len() as i64);
+    (number, WORDLE_WORDS[index as usize])
+}
+
+/// How `guess` compares to `word`, a square a letter: green where it's right, yellow where
+/// it's somewhere else, and black where it isn't in the word, or not that many times.
+pub fn marks(guess: &str, word: &str) -> String {
+    let guess = guess.chars().collect::<Vec<_>>();
+    let word = word.chars().collect::<Vec<_>>();
+    let mut marks = vec!['⬛'; guess.len()];
+    let mut left = Vec::new();
+    for (i, &c) in word.iter().enumerate() {
+        match guess.get(i) == Some(&c) {
+            true => marks[i] = '🟩',
+            false => left.push(c),
+        }
+    }
+    for (i, c) in guess.iter().enumerate() {
+        if marks[i] == '🟩' {
+            continue;
+        }
+        if let Some(found) = left.iter().position(|other| other == c) {
+            left.swap_remove(found);
+            marks[i] = '🟨';
+        }
+    }
+
+    marks.into_iter().collect()
+}
+
+/// By lowercased nick, with the nick as it was first written.
+type Scores = HashMap<String, (String, i64)>;
+
+/// One channel's go at the day's Wordle.
+struct Wordle {
+    day: NaiveDate,
+    guesses: Vec<String>,
+    solved: bool,
+}
+
+struct Hangman {
+    word: &'static str,
+    /// Letters and words tried so far, right or wrong.
+    tried: Vec<String>,
+    lives: usize,
+}
+
+impl Hangman {
+    /// The word with the letters nobody's guessed yet blanked out.
+    fn shown(&self) -> String {
+        self.word
+            .chars()
+            .map(|c| match self.tried.contains(&c.to_string()) {
+                true => c,
+                false => '_',
+            })
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn found(&self) -> bool {
+        self.word
+            .chars()
+            .all(|c| self.tried.contains(&c.to_string()))
+    }
+
+    fn status(&self) -> String {
+        let wrong = self
+            .tried
+            .iter()
+            .filter(|tried| !(tried.chars().count() == 1 && self.word.contains(tried.as_str())))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut status = format!("{} ({} lives left", self.shown(), self.lives);
+        if !wrong.is_empty() {
+            status.push_str(&format!(", not {}", wrong.join(" ")));
+        }
+        status.push(')');
+        status
+    }
+}
+
+/// Wordle and hangman games on one network, and everyone's points from them. Points are
+/// written through to the store, if there is one.
+pub struct WordGames {
+    scope: String,
+    /// By channel, folded by the server's casemapping.
+    wordles: Mutex<HashMap<String, Wordle>>,
+    hangmen: Mutex<HashMap<String, Hangman>>,
+    /// By game.
+    scores: Mutex<HashMap<&'static str, Scores>>,
+    store: Option<Arc<dyn WordGameStore>>,
+}
+
+impl WordGames {
+    pub async fn load(scope: &str, store: Option<Arc<dyn WordGameStore>>) -> Result<Self, Error> {
+        let mut scores = HashMap::new();
+        for game in [WORDLE, HANGMAN] {
+            let loaded = match &store {
+                Some(store) => store.word_game_scores(scope, game).await?,
+                None => Vec::new(),
+            };
+            scores.insert(
+                game,
+                loaded
+                    .into_iter()
+                    .map(|(nick, score)| (nick.to_lowercase(), (nick, score)))
+                    .collect(),
+            );
+        }
+
+        Ok(Self {
+            scope: scope.to_string(),
+            wordles: Mutex::new(HashMap::new()),
+            hangmen: Mutex::new(HashMap::new()),
+            scores: Mutex::new(scores),
+            store,
+        })
+    }
+
+    pub fn score(&self, game: &str, nick: &str) -> i64 {
+        self.scores
+            .lock()
+            .expect("word games lock poisoned")
+            .get(game)
+            .and_then(|scores| scores.get(&nick.to_lowercase()))
+            .map_or(0, |(_, score)| *score)
+    }
+
+    /// The `n` highest scores at `game`, highest first.
+    pub fn top(&self, game: &str, n: usize) -> Vec<(String, i64)> {
+        let mut scores = self
+            .scores
+            .lock()
+            .expect("word games lock poisoned")
+            .get(game)
+            .map(|scores| scores.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        scores.sort_by_key(|(_, score)| Reverse(*score));
+        scores.truncate(n);
+        scores
+    }
+
+    /// Gives `nick` `points` at `game` and returns their total.
+    async fn award(&self, game: &'static str, nick: &str, points: i64) -> i64 {
+        let total = {
+            let mut scores = self.scores.lock().expect("word games lock poisoned");
+            let score = scores
+                .entry(game)
+                .or_default()
+                .entry(nick.to_lowercase())
+                .or_insert_with(|| (nick.to_string(), 0));
+            score.1 += points;
+            score.1
+        };
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .add_word_game_score(&self.scope, game, nick, points)
+                .await
+            {
+                warn!("Unable to save {}'s {} score: {}", nick, game, e);
+            }
+        }
+
+        total
+    }
+
+    /// How today's Wordle is going in `channel`.
+    pub fn wordle(&self, key: &str) -> String {
+        let today = Utc::now().date_naive();
+        let (number, _) = daily_word(today);
+        let wordles = self.wordles.lock().expect("word games lock poisoned");
+        let Some(wordle) = wordles.get(key).filter(|wordle| wordle.day == today) else {
+            return format!(
+                "Wordle {}: nobody's had a guess yet, {} to go",
+                number, WORDLE_GUESSES
+            );
+        };
+
+        let (_, word) = daily_word(today);
+        let board = wordle
+            .guesses
+            .iter()
+            .map(|guess| format!("{} {}", guess.to_uppercase(), marks(guess, word)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let state = match (wordle.solved, wordle.guesses.len()) {
+            (true, _) => String::from("solved"),
+            (false, WORDLE_GUESSES) => String::from("out of guesses"),
+            (false, guesses) => format!("{}/{}", guesses, WORDLE_GUESSES),
+        };
+        format!("Wordle {} ({}): {}", number, state, board)
+    }
+
+    /// Has `nick` guess at today's Wordle in `channel`.
+    pub async fn guess_wordle(&self, key: &str, nick: &str, guess: &str) -> String {
+        let guess = guess.to_lowercase();
+        if guess.chars().count() != 5 || !guess.chars().all(|c| c.is_ascii_alphabetic()) {
+            return format!("{}: guesses are five letter words", nick);
+        }
+
+        let today = Utc::now().date_naive();
+        let (number, word) = daily_word(today);
+        let (tries, solved) = {
+            let mut wordles = self.wordles.lock().expect("word games lock poisoned");
+            let wordle = wordles.entry(key.to_string()).or_insert_with(|| Wordle {
+                day: today,
+                guesses: Vec::new(),
+                solved: false,
+            });
+            // A new day, a new word
+            if wordle.day != today {
+                *wordle = Wordle {
+                    day: today,
+                    guesses: Vec::new(),
+                    solved: false,
+                };
+            }
+            if wordle.solved || wordle.guesses.len() >= WORDLE_GUESSES {
+                return format!("{}: that's it for Wordle {}, back tomorrow", nick, number);
+            }
+            if wordle.guesses.contains(&guess) {
+                return format!("{}: {} was tried already", nick, guess.to_uppercase());
+            }
+
+            wordle.guesses.push(guess.clone());
+            wordle.solved = guess == word;
+            (wordle.guesses.len(), wordle.solved)
+        };
+
+        let line = format!(
+            "{} {} ({}/{})",
+            guess.to_uppercase(),
+            marks(&guess, word),
+            tries,
+            WORDLE_GUESSES
+        );
+        if solved {
+            let points = (WORDLE_GUESSES + 1 - tries) as i64;
+            let total = self.award(WORDLE, nick, points).await;
+            format!(
+                "{} - {} got it! (+{}, {} in all)",
+                line, nick, points, total
+            )
+        } else if tries == WORDLE_GUESSES {
+            format!(
+                "{} - out of guesses, it was {}. Back tomorrow",
+                line,
+                word.to_uppercase()
+            )
+        } else {
+            line
+        }
+    }
+
+    /// Starts a hangman game in `channel`, or says how the one going is getting on.
+    pub fn hangman(&self, key: &str) -> String {
+        let mut hangmen = self.hangmen.lock().expect("word games lock poisoned");
+        let hangman = hangmen.entry(key.to_string()).or_insert_with(|| Hangman {
+            word: HANGMAN_WORDS
+                .choose(&mut rand::thread_rng())
+                .expect("There are hangman words"),
+            tried: Vec::new(),
+            lives: HANGMAN_LIVES,
+        });
+        format!("Hangman: {}", hangman.status())
+    }
+
+    /// Has `nick` guess a letter, or the whole word, at the hangman game in `channel`.
+    pub async fn guess_hangman(&self, key: &str, nick: &str, guess: &str) -> String {
+        let guess = guess.to_lowercase();
+        if guess.is_empty() || !guess.chars().all(|c| c.is_alphabetic()) {
+            return format!("{}: guess a letter or the word", nick);
+        }
+
+        let (word, lives) = {
+            let mut hangmen = self.hangmen.lock().expect("word games lock poisoned");
+            let Some(hangman) = hangmen.get_mut(key) else {
+                return format!("{}: there's no game going, start one with hangman", nick);
+            };
+            if hangman.tried.contains(&guess) {
+                return format!("{}: {} was tried already", nick, guess);
+            }
+
+            let (right, won) = match guess.chars().count() {
+                1 => (hangman.word.contains(&guess), false),
+                _ => (guess == hangman.word, guess == hangman.word),
+            };
+            hangman.tried.push(guess);
+            if !right {
+                hangman.lives -= 1;
+            }
+            if !won && !hangman.found() && hangman.lives > 0 {
+                return match right {
+                    true => hangman.status(),
+                    false => format!("Nope: {}", hangman.status()),
+                };
+            }
+
+            let hangman = hangmen.remove(key).expect("The game is going");
+            (hangman.word, hangman.lives)
+        };
+
+        if lives == 0 {
+            return format!("Hanged! It was {}", word);
+        }
+        let points = lives as i64;
+        let total = self.award(HANGMAN, nick, points).await;
+        format!("{} got it: {} (+{}, {} in all)", nick, word, points, total)
+    }
+
+    /// Gives up on the hangman game in `channel`, if there is one, and says what the word was.
+    pub fn stop_hangman(&self, key: &str) -> Option<String> {
+        let hangman = self
+            .hangmen
+            .lock()
+            .expect("word games lock poisoned")
+            .remove(key)?;
+
+        Some(format!("Hangman's over, it was {}", hangman.word))
+    }
+}
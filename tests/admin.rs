@@ -0,0 +1,156 @@
+mod common;
+
+use reqwest::header;
+use reqwest::StatusCode;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::net::TcpListener;
+
+use std::sync::Arc;
+
+use pickles::admin;
+use pickles::config::Config;
+
+use common::mock::Scripted;
+use common::Bot;
+use common::Connection;
+use common::Server;
+use common::CHANNEL;
+use common::NICK;
+
+const TOKEN: &str = "hunter2";
+
+/// pickles on a network called `name`, which has to be different for each test as every bot
+/// in the process shares the admin API, and the API's address.
+async fn start(server: &Server, name: &str, backend: Arc<Scripted>) -> (Bot, Connection, String) {
+    let mut network = server.network();
+    network.name = name.to_string();
+    let config = Config {
+        networks: vec![network],
+        ..Config::default()
+    };
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let url = format!(
+        "http://{}/admin/networks/{}",
+        listener.local_addr().expect("Not listening"),
+        name
+    );
+    tokio::spawn(async move { axum::serve(listener, admin::routes(TOKEN)).await });
+
+    (bot, irc, url)
+}
+
+async fn post(url: &str, body: Value) -> StatusCode {
+    reqwest::Client::new()
+        .post(url)
+        .bearer_auth(TOKEN)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Unable to reach the admin API")
+        .status()
+}
+
+async fn delete(url: &str) -> StatusCode {
+    reqwest::Client::new()
+        .delete(url)
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .expect("Unable to reach the admin API")
+        .status()
+}
+
+#[tokio::test]
+async fn wants_the_token() {
+    let server = Server::bind().await;
+    let (bot, irc, url) = start(&server, "admin-token", Arc::new(Scripted::new())).await;
+    let client = reqwest::Client::new();
+
+    let anonymous = client
+        .get(format!("{}/usage", url))
+        .send()
+        .await
+        .expect("Unable to reach");
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    let wrong = client
+        .get(format!("{}/usage", url))
+        .bearer_auth("hunter3")
+        .send()
+        .await
+        .expect("Unable to reach");
+    assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+    let right = client
+        .get(format!("{}/usage", url))
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .expect("Unable to reach");
+    assert_eq!(right.status(), StatusCode::OK);
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn says_what_it_is_sent() {
+    let server = Server::bind().await;
+    let (bot, mut irc, url) = start(&server, "admin-send", Arc::new(Scripted::new())).await;
+    let messages = format!("{}/messages", url);
+
+    let status = post(
+        &messages,
+        json!({ "target": "#nowhere", "text": "anyone?" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let status = post(
+        &messages,
+        json!({ "target": CHANNEL, "text": "hello there\rQUIT :bye\n\none more" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(
+        irc.expect("PRIVMSG").await,
+        format!("PRIVMSG {} :hello there", CHANNEL)
+    );
+    assert_eq!(
+        irc.expect("").await,
+        format!("PRIVMSG {} :QUIT :bye", CHANNEL)
+    );
+    assert_eq!(
+        irc.expect("").await,
+        format!("PRIVMSG {} :one more", CHANNEL)
+    );
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn forgets_on_request() {
+    let server = Server::bind().await;
+    let backend = Arc::new(Scripted::new().answer("hi alice"));
+    let (bot, mut irc, url) = start(&server, "admin-forget", backend).await;
+
+    irc.privmsg("alice", CHANNEL, &format!("{}: hi", NICK))
+        .await;
+    irc.expect("PRIVMSG").await;
+    assert_eq!(
+        delete(&format!("{}/memory/Alice", url)).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        delete(&format!("{}/memory/Alice", url)).await,
+        StatusCode::NOT_FOUND
+    );
+
+    bot.stop(irc).await;
+}
@@ -0,0 +1,92 @@
+use chrono::TimeZone;
+use chrono::Utc;
+
+use irc::client::prelude::*;
+
+use pickles::archive::Archive;
+use pickles::channels::Channels;
+use pickles::config::ArchiveConfig;
+use pickles::config::ChannelConfig;
+use pickles::config::NetworkConfig;
+use pickles::markov::parse_line;
+
+fn message(line: &str) -> Message {
+    line.parse().expect("Invalid message")
+}
+
+#[tokio::test]
+async fn logs_channel_traffic_to_daily_files() {
+    let dir = std::env::temp_dir().join(format!("pickles-archive-test-{}", std::process::id()));
+    let archive = Archive::new(
+        "test",
+        &ArchiveConfig {
+            dir: dir.clone(),
+            keep: 1,
+            ..ArchiveConfig::default()
+        },
+        None,
+    );
+    let network = NetworkConfig {
+        channels: vec![ChannelConfig::new("#Test")],
+        ..NetworkConfig::default()
+    };
+    let channels = Channels::new(&network);
+    channels.track(
+        &message(":server 353 pickles = #Test :pickles @bob"),
+        "pickles",
+        "@+",
+    );
+
+    let yesterday = Utc.with_ymd_and_hms(2024, 5, 1, 23, 59, 0).unwrap();
+    let today = Utc.with_ymd_and_hms(2024, 5, 2, 9, 30, 0).unwrap();
+    for (line, at) in [
+        (":bob!b@host PRIVMSG #Test :nobody keeps this", yesterday),
+        (":bob!b@host PRIVMSG #Test :morning all", today),
+        (":bob!b@host PRIVMSG #Test :\u{1}ACTION waves\u{1}", today),
+        (":bob!b@host PRIVMSG #Test :\u{1}VERSION\u{1}", today),
+        (":bob!b@host PRIVMSG pickles :just between us", today),
+        (":carol!c@host JOIN #Test", today),
+        (":bob!b@host QUIT :Ping timeout", today),
+    ] {
+        let message = message(line);
+        archive.log(&message, &channels, at, false).await;
+        channels.track(&message, "pickles", "@+");
+    }
+    // Opted out of being remembered
+    archive
+        .log(
+            &message(":carol!c@host PRIVMSG #Test :don't keep this"),
+            &channels,
+            today,
+            true,
+        )
+        .await;
+
+    let lines = archive
+        .recent("#test", 10)
+        .await
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "[2024-05-02 09:30:00] <bob> morning all",
+            "[2024-05-02 09:30:00] * bob waves",
+            "[2024-05-02 09:30:00] *** carol joined",
+            "[2024-05-02 09:30:00] *** bob quit (Ping timeout)",
+        ]
+    );
+    assert_eq!(archive.recent("#test", 1).await.len(), 1);
+    assert!(!dir.join("test/#test/2024-05-01.log").exists());
+
+    let file = std::fs::read_to_string(dir.join("test/#test/2024-05-02.log"))
+        .expect("Unable to read the log");
+    assert_eq!(file.lines().count(), 4);
+    assert_eq!(
+        file.lines().next().and_then(parse_line),
+        Some(("bob", "morning all"))
+    );
+
+    std::fs::remove_dir_all(&dir).expect("Unable to clean up");
+}
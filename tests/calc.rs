@@ -0,0 +1,57 @@
+use pickles::calc::evaluate;
+
+fn calc(expression: &str) -> String {
+    evaluate(expression).unwrap_or_else(|e| format!("error: {}", e))
+}
+
+#[test]
+fn does_arithmetic() {
+    assert_eq!(calc("1 + 2 * 3"), "7");
+    assert_eq!(calc("(1 + 2) * 3"), "9");
+    assert_eq!(calc("2^10"), "1024");
+    assert_eq!(calc("2**3**2"), "512");
+    assert_eq!(calc("-2^2"), "-4");
+    assert_eq!(calc("0.1 + 0.2"), "0.3");
+    assert_eq!(calc("10 / 4"), "2.5");
+    assert_eq!(calc("17 % 5"), "2");
+    assert_eq!(calc("5!"), "120");
+    assert_eq!(calc("sqrt(2)"), "1.41421356237");
+    assert_eq!(calc("sin(30 deg)"), "0.5");
+    assert_eq!(calc("1e3 + 1.5e-1"), "1000.15");
+    assert_eq!(calc("2 * pi"), calc("tau"));
+}
+
+#[test]
+fn converts_units() {
+    assert_eq!(calc("5 ft 11 in to cm"), "180.34 cm");
+    assert_eq!(calc("12 in in cm"), "30.48 cm");
+    assert_eq!(calc("100 km/h in mph"), "62.1371192237 mph");
+    assert_eq!(calc("20 C to F"), "68 F");
+    assert_eq!(calc("-40 °F to °C"), "-40 °C");
+    assert_eq!(calc("0 K to C"), "-273.15 C");
+    assert_eq!(calc("1 GiB to MB"), "1073.741824 MB");
+    assert_eq!(calc("3 m^2 to ft^2"), "32.2917312501 ft^2");
+    assert_eq!(calc("5 km + 300 m"), "5.3 km");
+    assert_eq!(calc("100 km / 2 h"), "50 km/h");
+    assert_eq!(calc("12 in"), "12 in");
+}
+
+#[test]
+fn changes_bases() {
+    assert_eq!(calc("255 to hex"), "0xff");
+    assert_eq!(calc("0xff + 1 to bin"), "0b100000000");
+    assert_eq!(calc("0o17 as dec"), "15");
+    assert_eq!(calc("-10 in hex"), "-0xa");
+}
+
+#[test]
+fn explains_what_it_cannot_do() {
+    assert!(evaluate("5 kg to m").is_err());
+    assert!(evaluate("2 +").is_err());
+    assert!(evaluate("(1 + 2").is_err());
+    assert!(evaluate("2.5 to hex").is_err());
+    assert!(evaluate("10^400").is_err());
+    assert!(evaluate("((m^8)^8)^8").is_err());
+    assert!(evaluate(&vec!["m^8"; 16].join("*")).is_err());
+    assert!(evaluate("rm -rf /").is_err());
+}
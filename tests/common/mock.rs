@@ -0,0 +1,93 @@
+//! A chat backend that answers from a script instead of asking a model.
+
+use async_openai::error::OpenAIError;
+
+use async_trait::async_trait;
+
+use tokio::time;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use pickles::llm::ChatBackend;
+use pickles::llm::ChatMessage;
+use pickles::llm::Completion;
+use pickles::llm::Usage;
+use pickles::Error;
+
+enum Reply {
+    Answer(String),
+    Fail(String),
+}
+
+/// Gives its canned replies in order, each after `latency`, and keeps every history it was
+/// asked about. Running out of replies is a test bug, so it panics.
+#[derive(Default)]
+pub struct Scripted {
+    replies: Mutex<VecDeque<Reply>>,
+    latency: time::Duration,
+    requests: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl Scripted {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn answer(self, content: &str) -> Self {
+        self.push(Reply::Answer(content.to_string()))
+    }
+
+    /// Fails the next request the way OpenAI would if it dropped the connection part way.
+    pub fn fail(self, message: &str) -> Self {
+        self.push(Reply::Fail(message.to_string()))
+    }
+
+    pub fn latency(self, latency: time::Duration) -> Self {
+        Self { latency, ..self }
+    }
+
+    fn push(self, reply: Reply) -> Self {
+        self.replies
+            .lock()
+            .expect("script lock poisoned")
+            .push_back(reply);
+        self
+    }
+
+    /// Every history asked about so far, oldest first.
+    pub fn requests(&self) -> Vec<Vec<ChatMessage>> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl ChatBackend for Scripted {
+    async fn complete(&self, history: &[ChatMessage]) -> Result<Completion, Error> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .push(history.to_vec());
+        let reply = self
+            .replies
+            .lock()
+            .expect("script lock poisoned")
+            .pop_front()
+            .expect("Asked more than the script answers");
+        time::sleep(self.latency).await;
+
+        match reply {
+            Reply::Answer(content) => Ok(Completion {
+                usage: Usage {
+                    prompt_tokens: history.len() as u64,
+                    completion_tokens: content.lines().count() as u64,
+                },
+                content,
+            }),
+            Reply::Fail(message) => Err(Error::OpenAI(OpenAIError::StreamError(message))),
+        }
+    }
+}
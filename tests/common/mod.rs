@@ -0,0 +1,203 @@
+//! A fake IRC server to run pickles against, so tests can play the part of the network and
+//! check what pickles says back.
+
+#![allow(dead_code)]
+
+pub mod mock;
+pub mod websocket;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::io::Lines;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use std::sync::Arc;
+
+use pickles::config::ChannelConfig;
+use pickles::config::Config;
+use pickles::config::FloodConfig;
+use pickles::config::NetworkConfig;
+use pickles::config::ReconnectConfig;
+use pickles::irc_bot;
+use pickles::llm::openai;
+use pickles::llm::ChatBackend;
+use pickles::Error;
+
+/// How long to wait for pickles to say something before giving up on it.
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+pub const NICK: &str = "pickles";
+pub const CHANNEL: &str = "#test";
+
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    pub async fn bind() -> Self {
+        Self {
+            listener: TcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("Unable to listen"),
+        }
+    }
+
+    /// A network on this server with one channel, no flood limit and no delay rejoining or
+    /// reconnecting.
+    pub fn network(&self) -> NetworkConfig {
+        NetworkConfig {
+            name: String::from("test"),
+            nickname: String::from(NICK),
+            server: String::from("127.0.0.1"),
+            port: self.listener.local_addr().expect("Not listening").port(),
+            channels: vec![ChannelConfig::new(CHANNEL)],
+            rejoin_delay: 0,
+            flood: FloodConfig {
+                burst: 100,
+                refill_ms: 1,
+            },
+            reconnect: ReconnectConfig {
+                initial_delay_secs: 0,
+                ..ReconnectConfig::default()
+            },
+            ..NetworkConfig::default()
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        Config {
+            networks: vec![self.network()],
+            ..Config::default()
+        }
+    }
+
+    pub async fn accept(&self) -> Connection {
+        let (socket, _) = time::timeout(TIMEOUT, self.listener.accept())
+            .await
+            .expect("pickles never connected")
+            .expect("Unable to accept");
+        let (reader, writer) = socket.into_split();
+
+        Connection {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+}
+
+/// Our end of pickles' connection.
+pub struct Connection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    /// Sends a raw line, e.g. `:alice!a@host PRIVMSG #test :hi`.
+    pub async fn send(&mut self, line: &str) {
+        self.writer
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .await
+            .expect("Unable to send");
+    }
+
+    /// Says `msg` in `target` as `nick`.
+    pub async fn privmsg(&mut self, nick: &str, target: &str, msg: &str) {
+        self.send(&format!(
+            ":{0}!{0}@example.com PRIVMSG {1} :{2}",
+            nick, target, msg
+        ))
+        .await
+    }
+
+    /// The next line pickles sends, if it sends one in time.
+    pub async fn next(&mut self, timeout: time::Duration) -> Option<String> {
+        time::timeout(timeout, self.lines.next_line())
+            .await
+            .ok()?
+            .expect("Unable to read")
+    }
+
+    /// Skips lines until one starts with `prefix` and returns it.
+    pub async fn expect(&mut self, prefix: &str) -> String {
+        let mut skipped = Vec::new();
+        let deadline = time::Instant::now() + TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(time::Instant::now());
+            match self.next(left).await {
+                Some(line) if line.starts_with(prefix) => return line,
+                Some(line) => skipped.push(line),
+                None => panic!("Expected {:?}, only got {:?}", prefix, skipped),
+            }
+        }
+    }
+
+    /// Makes sure pickles doesn't send anything starting with `prefix` for a little while.
+    pub async fn refute(&mut self, prefix: &str) {
+        while let Some(line) = self.next(time::Duration::from_millis(500)).await {
+            assert!(!line.starts_with(prefix), "Didn't expect {:?}", line);
+        }
+    }
+
+    /// Welcomes pickles once it's introduced itself and waits for it to join `CHANNEL`.
+    pub async fn register(&mut self) {
+        self.expect(&format!("NICK {}", NICK)).await;
+        self.expect("USER ").await;
+        self.welcome(NICK).await;
+        self.expect(&format!("JOIN {}", CHANNEL)).await;
+    }
+
+    pub async fn welcome(&mut self, nick: &str) {
+        self.send(&format!(":irc.test 001 {} :Welcome", nick)).await;
+        self.send(&format!(":irc.test 422 {} :MOTD File is missing", nick))
+            .await;
+    }
+}
+
+/// pickles running in the background until it's stopped.
+pub struct Bot {
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<Result<(), Error>>,
+}
+
+impl Bot {
+    pub fn start(config: Config, backend: Arc<dyn ChatBackend>) -> Self {
+        let (shutdown, rx) = watch::channel(false);
+        let openai = openai::http_client(&config.openai).expect("HTTP client should build");
+
+        Self {
+            shutdown,
+            task: tokio::spawn(irc_bot::serve(config, backend, openai, rx)),
+        }
+    }
+
+    /// Asks pickles to quit, as if it got SIGTERM, hangs up once it has and waits for it to
+    /// finish. Returns the QUIT.
+    pub async fn stop(self, mut irc: Connection) -> String {
+        let _ = self.shutdown.send(true);
+        let quit = irc.expect("QUIT").await;
+        drop(irc);
+        time::timeout(TIMEOUT, self.task)
+            .await
+            .expect("pickles didn't stop")
+            .expect("pickles panicked")
+            .expect("pickles failed");
+        quit
+    }
+
+    /// Asks pickles to quit and waits for it to finish, for when it isn't connected to a
+    /// `Server` of ours.
+    pub async fn shut_down(self) {
+        let _ = self.shutdown.send(true);
+        time::timeout(TIMEOUT, self.task)
+            .await
+            .expect("pickles didn't stop")
+            .expect("pickles panicked")
+            .expect("pickles failed");
+    }
+}
@@ -0,0 +1,121 @@
+//! A WebSocket server to play Discord's gateway or Slack's Socket Mode with.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use sha1::Digest;
+use sha1::Sha1;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufStream;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::time;
+
+use super::TIMEOUT;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Our end of a WebSocket pickles opened.
+pub struct Socket {
+    stream: BufStream<TcpStream>,
+}
+
+impl Socket {
+    /// Waits for pickles to connect and finishes the handshake.
+    pub async fn accept(listener: &TcpListener) -> Self {
+        let (socket, _) = time::timeout(TIMEOUT, listener.accept())
+            .await
+            .expect("pickles never connected")
+            .expect("Unable to accept");
+        let mut stream = BufStream::new(socket);
+
+        let mut key = None;
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).await.expect("No handshake");
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("sec-websocket-key") {
+                    key = Some(value.trim().to_string());
+                }
+            }
+        }
+        let key = key.expect("No key in the handshake");
+        let accept = BASE64.encode(Sha1::digest(format!("{}{}", key, ACCEPT_GUID)));
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        );
+        stream
+            .write_all(response.as_bytes())
+            .await
+            .expect("Unable to answer the handshake");
+        stream
+            .flush()
+            .await
+            .expect("Unable to answer the handshake");
+
+        Self { stream }
+    }
+
+    /// Sends `text` as a text message.
+    pub async fn send(&mut self, text: &str) {
+        let mut frame = vec![0x81];
+        match text.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(text.as_bytes());
+        self.stream.write_all(&frame).await.expect("Unable to send");
+        self.stream.flush().await.expect("Unable to send");
+    }
+
+    /// The next text message pickles sends, or `None` if it closes the connection.
+    pub async fn receive(&mut self) -> Option<String> {
+        loop {
+            let (opcode, payload) = time::timeout(TIMEOUT, self.frame())
+                .await
+                .expect("pickles didn't send anything")?;
+            match opcode {
+                0x1 => return Some(String::from_utf8(payload).expect("Not text")),
+                0x8 => return None,
+                _ => continue,
+            }
+        }
+    }
+
+    async fn frame(&mut self) -> Option<(u8, Vec<u8>)> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head).await.ok()?;
+        let len = match head[1] & 0x7f {
+            126 => usize::from(self.stream.read_u16().await.ok()?),
+            127 => usize::try_from(self.stream.read_u64().await.ok()?).ok()?,
+            len => usize::from(len),
+        };
+        let mut mask = [0; 4];
+        if head[1] & 0x80 != 0 {
+            self.stream.read_exact(&mut mask).await.ok()?;
+        }
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload).await.ok()?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Some((head[0] & 0x0f, payload))
+    }
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use axum::extract::Path;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::net::TcpListener;
+use tokio::time;
+
+use pickles::config::DiscordConfig;
+use pickles::gateway::discord::Discord;
+use pickles::gateway::ChatNetwork;
+use pickles::gateway::Event;
+use pickles::gateway::Kind;
+use pickles::gateway::User;
+
+use common::websocket::Socket;
+use common::TIMEOUT;
+
+const TOKEN: &str = "Mz.token";
+const OURS: &str = "99";
+
+/// A Discord bot talking to a fake of as much of the HTTP API as connecting and finding
+/// channels by ID takes, and the gateway it points to.
+async fn discord() -> (Discord, TcpListener) {
+    let gateway = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let url = format!("ws://{}", gateway.local_addr().expect("Not listening"));
+    let app = Router::new()
+        .route(
+            "/gateway/bot",
+            get(move || async move { Json(json!({ "url": url })) }),
+        )
+        .route(
+            "/channels/:id",
+            get(|Path(id): Path<String>| async move { Json(json!({ "id": id })) }),
+        );
+    let api = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let api_base = format!("http://{}", api.local_addr().expect("Not listening"));
+    tokio::spawn(async move { axum::serve(api, app).await });
+
+    let config = DiscordConfig {
+        token: String::from(TOKEN),
+        api_base: Some(api_base),
+    };
+    let discord = Discord::new(&config).expect("Unable to make a Discord bot");
+
+    (discord, gateway)
+}
+
+/// Plays the gateway: says hello, waits to be identified, says who we are and then sends
+/// each of `messages` as it's created.
+async fn play(gateway: TcpListener, messages: Vec<Value>) {
+    let mut socket = Socket::accept(&gateway).await;
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": 45000 } });
+    socket.send(&hello.to_string()).await;
+    let identify: Value = serde_json::from_str(&socket.receive().await.expect("Hung up"))
+        .expect("Identified with something that isn't JSON");
+    assert_eq!(identify["op"], 2);
+    assert_eq!(identify["d"]["token"], TOKEN);
+
+    let ready = json!({
+        "op": 0,
+        "t": "READY",
+        "s": 1,
+        "d": {
+            "user": { "id": OURS, "username": "pickles" },
+            "session_id": "session",
+            "resume_gateway_url": "ws://127.0.0.1:1",
+        },
+    });
+    socket.send(&ready.to_string()).await;
+    for (sequence, message) in (2..).zip(messages) {
+        let dispatch = json!({ "op": 0, "t": "MESSAGE_CREATE", "s": sequence, "d": message });
+        socket.send(&dispatch.to_string()).await;
+    }
+    // Heartbeats, until the test is done
+    while socket.receive().await.is_some() {}
+}
+
+async fn receive(discord: &Discord) -> Event {
+    let mut events = time::timeout(TIMEOUT, discord.receive())
+        .await
+        .expect("Nothing happened")
+        .expect("Unable to receive");
+    assert_eq!(events.len(), 1, "{:?}", events);
+    events.remove(0)
+}
+
+fn user(nick: &str, id: &str) -> User {
+    User {
+        nick: nick.to_string(),
+        user: id.to_string(),
+        host: String::from("discord"),
+    }
+}
+
+#[tokio::test]
+async fn turns_messages_into_events() {
+    let (discord, gateway) = discord().await;
+    discord.join("#111").await.expect("Unable to join");
+    let messages = vec![
+        json!({
+            "type": 0,
+            "author": { "id": "1", "username": "alice" },
+            "channel_id": "111",
+            "guild_id": "7",
+            "content": "<@99> what does <@!2> think of <#111>? <:wave:5>",
+            "mentions": [
+                { "id": OURS, "username": "pickles" },
+                { "id": "2", "username": "bob" },
+            ],
+        }),
+        // Our own, which we don't need to hear about
+        json!({
+            "type": 0,
+            "author": { "id": OURS, "username": "pickles" },
+            "channel_id": "111",
+            "guild_id": "7",
+            "content": "hello",
+        }),
+        // Someone joining the server
+        json!({
+            "type": 7,
+            "author": { "id": "3", "username": "carol" },
+            "channel_id": "111",
+            "guild_id": "7",
+            "content": "",
+        }),
+        // A channel we aren't in
+        json!({
+            "type": 0,
+            "author": { "id": "3", "username": "carol" },
+            "channel_id": "222",
+            "guild_id": "7",
+            "content": "over here",
+        }),
+        // A reply, in a direct message
+        json!({
+            "type": 19,
+            "author": { "id": "2", "username": "bob" },
+            "channel_id": "555",
+            "content": "psst",
+        }),
+    ];
+    tokio::spawn(play(gateway, messages));
+
+    assert_eq!(
+        receive(&discord).await,
+        Event::Message {
+            from: user("alice", "1"),
+            channel: Some(String::from("#111")),
+            text: String::from(" what does @bob think of #111? :wave:"),
+            kind: Kind::Message,
+            mentioned: true,
+        }
+    );
+    assert_eq!(
+        receive(&discord).await,
+        Event::Message {
+            from: user("bob", "2"),
+            channel: None,
+            text: String::from("psst"),
+            kind: Kind::Message,
+            mentioned: false,
+        }
+    );
+}
@@ -0,0 +1,53 @@
+use axum::Router;
+
+use tokio::net::TcpListener;
+
+use std::net::IpAddr;
+
+use pickles::fetch::is_public;
+use pickles::fetch::Fetch;
+
+#[tokio::test]
+async fn refuses_to_read_the_local_network() {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let port = listener.local_addr().expect("Not listening").port();
+    let app = Router::new().fallback(|| async { "secret metadata" });
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let fetch = Fetch::new();
+    for url in [
+        format!("http://127.0.0.1:{}/latest/meta-data/", port),
+        format!("http://localhost:{}/metrics", port),
+        format!("http://[::ffff:127.0.0.1]:{}/", port),
+        format!("http://127.0.0.1:{}/cat.png", port),
+    ] {
+        assert!(fetch.page(&url).await.is_err(), "Read {}", url);
+        assert!(fetch.image(&url).await.is_err(), "Read {}", url);
+    }
+}
+
+#[test]
+fn tells_public_addresses_from_private_ones() {
+    let public = |ip: &str| is_public(ip.parse::<IpAddr>().expect("Not an address"));
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:10.0.0.1",
+    ] {
+        assert!(!public(ip), "{} isn't public", ip);
+    }
+    for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+        assert!(public(ip), "{} is public", ip);
+    }
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use async_trait::async_trait;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::sync::Mutex;
+use tokio::time;
+
+use std::sync::Arc;
+
+use pickles::config::ChannelConfig;
+use pickles::config::Config;
+use pickles::config::NetworkConfig;
+use pickles::gateway;
+use pickles::gateway::ChatNetwork;
+use pickles::gateway::Event;
+use pickles::gateway::Kind;
+use pickles::gateway::User;
+use pickles::llm::openai::OpenAI;
+use pickles::tools::Tools;
+use pickles::Error;
+
+use common::Bot;
+use common::Server;
+use common::CHANNEL;
+use common::TIMEOUT;
+
+/// A network that's whatever the test says happens on it.
+struct Fake {
+    events: Mutex<mpsc::UnboundedReceiver<Event>>,
+    sent: mpsc::UnboundedSender<(String, String, Kind)>,
+    joined: mpsc::UnboundedSender<String>,
+}
+
+#[async_trait]
+impl ChatNetwork for Fake {
+    async fn receive(&self) -> Result<Vec<Event>, Error> {
+        match self.events.lock().await.recv().await {
+            Some(event) => Ok(vec![event]),
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn join(&self, channel: &str) -> Result<(), Error> {
+        let _ = self.joined.send(channel.to_string());
+        Ok(())
+    }
+
+    async fn send(&self, target: &str, text: &str, kind: Kind) -> Result<(), Error> {
+        let _ = self.sent.send((target.to_string(), text.to_string(), kind));
+        Ok(())
+    }
+
+    fn max_length(&self) -> usize {
+        400
+    }
+}
+
+struct Network {
+    events: mpsc::UnboundedSender<Event>,
+    sent: mpsc::UnboundedReceiver<(String, String, Kind)>,
+    joined: mpsc::UnboundedReceiver<String>,
+}
+
+impl Network {
+    fn say(&self, nick: &str, channel: Option<&str>, text: &str) {
+        let from = User {
+            nick: nick.to_string(),
+            user: nick.to_string(),
+            host: String::from("example.org"),
+        };
+        let _ = self.events.send(Event::Message {
+            from,
+            channel: channel.map(str::to_string),
+            text: text.to_string(),
+            kind: Kind::Message,
+            mentioned: false,
+        });
+    }
+
+    async fn expect(&mut self) -> (String, String, Kind) {
+        time::timeout(TIMEOUT, self.sent.recv())
+            .await
+            .expect("pickles didn't say anything")
+            .expect("the gateway is gone")
+    }
+}
+
+/// A gateway to a `Fake` network, with `#test` and `!room:example.org` to talk in.
+async fn open() -> (NetworkConfig, Network) {
+    let (events_tx, events) = mpsc::unbounded_channel();
+    let (sent_tx, sent) = mpsc::unbounded_channel();
+    let (joined_tx, joined) = mpsc::unbounded_channel();
+    let fake = Fake {
+        events: Mutex::new(events),
+        sent: sent_tx,
+        joined: joined_tx,
+    };
+
+    let mut network = Server::bind().await.network();
+    network
+        .channels
+        .push(ChannelConfig::new("!room:example.org"));
+    gateway::attach(&mut network, Arc::new(fake))
+        .await
+        .expect("Unable to start the gateway");
+
+    (
+        network,
+        Network {
+            events: events_tx,
+            sent,
+            joined,
+        },
+    )
+}
+
+/// pickles on a `Fake` network.
+async fn start() -> (Bot, Network) {
+    let (network, fake) = open().await;
+    let config = Config {
+        networks: vec![network],
+        ..Config::default()
+    };
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+
+    (bot, fake)
+}
+
+#[tokio::test]
+async fn joins_rooms_through_the_gateway() {
+    let (bot, mut network) = start().await;
+
+    let mut joined = Vec::new();
+    for _ in 0..2 {
+        let channel = time::timeout(TIMEOUT, network.joined.recv()).await;
+        joined.push(channel.expect("pickles didn't join").expect("gone"));
+    }
+    joined.sort();
+    assert_eq!(joined, ["!room:example.org", CHANNEL]);
+
+    bot.shut_down().await;
+}
+
+#[tokio::test]
+async fn answers_through_the_gateway() {
+    let (bot, mut network) = start().await;
+    network.joined.recv().await;
+    network.joined.recv().await;
+
+    network.say("alice", Some("!room:example.org"), "!seen bob");
+    let (target, text, kind) = network.expect().await;
+    assert_eq!(target, "!room:example.org");
+    assert_eq!(text, "alice: I haven't seen bob");
+    assert_eq!(kind, Kind::Message);
+
+    network.say("bob", Some(CHANNEL), "hello\nthere");
+    network.say("alice", None, "!seen bob");
+    let (target, text, _) = network.expect().await;
+    assert_eq!(target, "alice");
+    assert!(text.contains("in #test, saying: hello there"), "{}", text);
+
+    bot.shut_down().await;
+}
+
+#[tokio::test]
+async fn keeps_names_from_passing_for_hostmasks() {
+    let (bot, mut network) = start().await;
+    network.joined.recv().await;
+    network.joined.recv().await;
+
+    network.say(
+        "x!y@owner.example PRIVMSG pickles :!join #foo ",
+        Some(CHANNEL),
+        "!seen bob",
+    );
+    let (target, text, _) = network.expect().await;
+    assert_eq!(target, CHANNEL);
+    assert_eq!(
+        text,
+        "x_y_owner.example_PRIVMSG_pickles___join_#foo_: I haven't seen bob"
+    );
+
+    bot.shut_down().await;
+}
+
+#[tokio::test]
+async fn turns_away_strangers() {
+    let (network, mut fake) = open().await;
+
+    let mut stranger = TcpStream::connect(("127.0.0.1", network.port))
+        .await
+        .expect("Unable to connect");
+    stranger
+        .write_all(b"PASS guess\r\nNICK mallory\r\nUSER m 0 * :m\r\nJOIN #test\r\n")
+        .await
+        .expect("Unable to send");
+    let mut heard = String::new();
+    time::timeout(TIMEOUT, stranger.read_to_string(&mut heard))
+        .await
+        .expect("Still connected")
+        .expect("Unable to read");
+    assert_eq!(heard, "");
+    assert!(fake.joined.try_recv().is_err());
+}
@@ -0,0 +1,578 @@
+mod common;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+
+use chrono::SecondsFormat;
+use chrono::Utc;
+
+use serde_json::json;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use pickles::config::ChannelConfig;
+use pickles::config::JobConfig;
+use pickles::config::PriceConfig;
+use pickles::config::WeatherConfig;
+use pickles::llm::openai::OpenAI;
+use pickles::schedule::Cron;
+use pickles::schedule::Zone;
+use pickles::tools::Tools;
+use pickles::word_games::daily_word;
+
+use common::Bot;
+use common::Server;
+use common::CHANNEL;
+use common::NICK;
+
+/// None of these get as far as asking the model anything.
+fn start(server: &Server) -> Bot {
+    let config = server.config();
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    Bot::start(config, backend)
+}
+
+#[tokio::test]
+async fn registers_and_joins() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+
+    irc.register().await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn takes_an_alt_nick() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+
+    irc.expect(&format!("NICK {}", NICK)).await;
+    irc.send(&format!(
+        ":irc.test 433 * {} :Nickname is already in use",
+        NICK
+    ))
+    .await;
+    irc.expect(&format!("NICK {}_", NICK)).await;
+    irc.welcome(&format!("{}_", NICK)).await;
+    irc.expect(&format!("JOIN {}", CHANNEL)).await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn answers_ping() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send("PING :irc.test").await;
+    irc.expect("PONG irc.test").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn runs_commands() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "!seen bob").await;
+    let reply = irc.expect(&format!("PRIVMSG {} :", CHANNEL)).await;
+    assert!(reply.ends_with("alice: I haven't seen bob"), "{}", reply);
+
+    irc.privmsg("bob", CHANNEL, "hello").await;
+    irc.privmsg("alice", NICK, "!seen bob").await;
+    let reply = irc.expect("PRIVMSG alice :").await;
+    assert!(reply.contains("in #test, saying: hello"), "{}", reply);
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn reports_stats() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "!stats").await;
+    let reply = irc.expect(&format!("PRIVMSG {} :", CHANNEL)).await;
+    assert!(reply.contains(":alice: up 0d 0h 0m, seen "), "{}", reply);
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn lists_the_schedule() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.schedule.jobs.push(JobConfig {
+        name: String::from("standup"),
+        cron: Cron::try_from(String::from("30 9 * * mon-fri")).unwrap(),
+        timezone: Some(Zone::try_from(String::from("+02:00")).unwrap()),
+        network: None,
+        channels: vec![CHANNEL.to_string()],
+        message: Some(String::from("standup time!")),
+        prompt: None,
+    });
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "!schedule list").await;
+    let reply = irc.expect(&format!("PRIVMSG {} :", CHANNEL)).await;
+    assert!(
+        reply.contains(&format!(
+            ":standup (30 9 * * mon-fri) in {}: next ",
+            CHANNEL
+        )),
+        "{}",
+        reply
+    );
+    assert!(reply.ends_with(" 09:30 +02:00"), "{}", reply);
+    assert!(
+        !reply.contains(" Sat ") && !reply.contains(" Sun "),
+        "{}",
+        reply
+    );
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn remembers_where_people_are_for_the_weather() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.weather = Some(WeatherConfig::default());
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "!weather").await;
+    irc.expect(&format!(
+        "PRIVMSG {} :alice: where? Tell me once with !weather set <location>",
+        CHANNEL
+    ))
+    .await;
+    irc.privmsg("alice", CHANNEL, "!weather set Lisbon").await;
+    irc.expect(&format!(
+        "PRIVMSG {} :alice: I'll remember you're in Lisbon",
+        CHANNEL
+    ))
+    .await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn plays_the_daily_wordle_together() {
+    let server = Server::bind().await;
+    let config = server.config();
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    let (number, word) = daily_word(Utc::now().date_naive());
+    irc.privmsg("alice", CHANNEL, "!wordle fuzzy").await;
+    let reply = irc.expect(&format!("PRIVMSG {} :FUZZY", CHANNEL)).await;
+    assert!(reply.ends_with(" (1/6)"));
+    irc.privmsg("bob", CHANNEL, &format!("!wordle {}", word))
+        .await;
+    let reply = irc.expect(&format!("PRIVMSG {} :", CHANNEL)).await;
+    assert!(reply.ends_with("🟩🟩🟩🟩🟩 (2/6) - bob got it! (+5, 5 in all)"));
+    irc.privmsg("alice", CHANNEL, "!wordle crane").await;
+    irc.expect(&format!(
+        "PRIVMSG {} :alice: that's it for Wordle {}, back tomorrow",
+        CHANNEL, number
+    ))
+    .await;
+    irc.privmsg("alice", CHANNEL, "!wordle top").await;
+    irc.expect(&format!("PRIVMSG {} :wordle champions: bob (5)", CHANNEL))
+        .await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn keeps_urban_dictionary_out_of_safe_channels() {
+    let server = Server::bind().await;
+    let config = server.config();
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "!ud yeet").await;
+    irc.expect(&format!(
+        "PRIVMSG {} :alice: not in here, ask me in a private message",
+        CHANNEL
+    ))
+    .await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn quotes_prices_and_caches_them() {
+    let asked = Arc::new(AtomicUsize::new(0));
+    let quotes = Router::new()
+        .route(
+            "/quote/:symbol",
+            get(
+                |Path(symbol): Path<String>, State(asked): State<Arc<AtomicUsize>>| async move {
+                    asked.fetch_add(1, Ordering::SeqCst);
+                    match symbol.as_str() {
+                        "BTC" => {
+                            Json(json!({ "data": [{ "price": "67123.456", "change": -1.234 }] }))
+                        }
+                        _ => Json(json!({ "data": [] })),
+                    }
+                },
+            ),
+        )
+        .with_state(asked.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/quote/{{symbol}}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, quotes).await });
+
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.prices.push(PriceConfig {
+        url,
+        price: String::from("data.0.price"),
+        change: Some(String::from("data.0.change")),
+        currency: Some(String::from("USD")),
+        cache_secs: 60,
+    });
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    for _ in 0..2 {
+        irc.privmsg("alice", CHANNEL, "!price btc").await;
+        irc.expect(&format!(
+            "PRIVMSG {} :BTC: 67123.46 USD (-1.23% today)",
+            CHANNEL
+        ))
+        .await;
+    }
+    assert_eq!(asked.load(Ordering::SeqCst), 1);
+    irc.privmsg("alice", CHANNEL, "!price nope").await;
+    irc.expect(&format!("PRIVMSG {} :alice: never heard of it", CHANNEL))
+        .await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn answers_ctcp() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.ctcp.version = String::from("pickles test");
+    config
+        .ctcp
+        .replies
+        .insert(String::from("SOURCE"), String::from("right here"));
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", NICK, "\x01VERSION\x01").await;
+    let reply = irc.expect("NOTICE").await;
+    assert_eq!(reply, "NOTICE alice :\x01VERSION pickles test\x01");
+    irc.privmsg("alice", CHANNEL, "\x01PING 12345\x01").await;
+    let reply = irc.expect("NOTICE").await;
+    assert_eq!(reply, "NOTICE alice :\x01PING 12345\x01");
+    irc.privmsg("alice", NICK, "\x01source\x01").await;
+    let reply = irc.expect("NOTICE").await;
+    assert_eq!(reply, "NOTICE alice :\x01SOURCE right here\x01");
+    irc.privmsg("alice", NICK, "\x01FINGER\x01").await;
+    irc.refute("NOTICE").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn ignores_unaddressed_chatter() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg("alice", CHANNEL, "anyone around?").await;
+    irc.privmsg("alice", "#elsewhere", "!seen bob").await;
+    irc.refute("PRIVMSG").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn skips_bouncer_playback() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    let old = "@time=2001-02-03T04:05:06.789Z :alice!a@example.com PRIVMSG #test :!seen bob";
+    irc.send(old).await;
+    irc.refute("PRIVMSG").await;
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    irc.send(&format!(
+        "@time={} :alice!a@example.com PRIVMSG #test :!seen carol",
+        now
+    ))
+    .await;
+    let reply = irc.expect("PRIVMSG").await;
+    assert!(reply.ends_with("I haven't seen carol"), "{}", reply);
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn ignores_its_own_echoes() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.privmsg(NICK, CHANNEL, "!seen bob").await;
+    irc.refute("PRIVMSG").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn says_it_is_a_bot() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.expect("CAP REQ echo-message").await;
+    irc.expect(&format!("NICK {}", NICK)).await;
+    irc.welcome(NICK).await;
+    irc.send(&format!(
+        ":irc.test 005 {} BOT=B CASEMAPPING=rfc1459 :are supported by this server",
+        NICK
+    ))
+    .await;
+    irc.expect(&format!("MODE {} +B", NICK)).await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn rejoins_after_kick() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send(&format!(
+        ":op!op@example.com KICK {} {} :out",
+        CHANNEL, NICK
+    ))
+    .await;
+    irc.expect(&format!("JOIN {}", CHANNEL)).await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn stays_out_after_kick_without_rejoin() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.networks[0].rejoin_on_kick = false;
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send(&format!(
+        ":op!op@example.com KICK {} {} :out",
+        CHANNEL, NICK
+    ))
+    .await;
+    irc.refute("JOIN").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn joins_when_invited() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.networks[0].invite_channels = vec![ChannelConfig::new("#invited")];
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send(&format!(":alice!a@example.com INVITE {} #elsewhere", NICK))
+        .await;
+    irc.send(&format!(":alice!a@example.com INVITE {} #invited", NICK))
+        .await;
+    let join = irc.expect("JOIN").await;
+    assert_eq!(join, "JOIN #invited");
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn compares_names_by_casemapping() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.networks[0].invite_channels = vec![ChannelConfig::new("#room[1]")];
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send(&format!(
+        ":irc.test 005 {} CASEMAPPING=rfc1459 :are supported by this server",
+        NICK
+    ))
+    .await;
+    irc.send(":alice!a@example.com INVITE PICKLES #ROOM{1}")
+        .await;
+    irc.expect("JOIN #ROOM{1}").await;
+    irc.send(":op!op@example.com KICK #TEST Pickles :out").await;
+    irc.expect("JOIN #TEST").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn joins_as_many_channels_at_once_as_the_server_allows() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    for name in ["#two", "#three"] {
+        config.networks[0].channels.push(ChannelConfig::new(name));
+    }
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+
+    irc.expect("USER ").await;
+    irc.send(&format!(":irc.test 001 {} :Welcome", NICK)).await;
+    irc.send(&format!(
+        ":irc.test 005 {} TARGMAX=PRIVMSG:4,JOIN:2 :are supported by this server",
+        NICK
+    ))
+    .await;
+    irc.send(&format!(":irc.test 422 {} :MOTD File is missing", NICK))
+        .await;
+    assert_eq!(irc.expect("JOIN").await, format!("JOIN {},#two", CHANNEL));
+    assert_eq!(irc.expect("JOIN").await, "JOIN #three");
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn greets_people_joining() {
+    let server = Server::bind().await;
+    let mut config = server.config();
+    config.greetings.enabled = true;
+    config.greetings.known_only = false;
+    config.greetings.messages = vec![String::from("hi {nick}")];
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    irc.send(&format!(":alice!a@example.com JOIN {}", CHANNEL))
+        .await;
+    irc.expect(&format!("PRIVMSG {} :hi alice", CHANNEL)).await;
+    // Not again so soon, and not when coming back from a netsplit
+    irc.send(&format!(":alice!a@example.com PART {}", CHANNEL))
+        .await;
+    irc.send(&format!(":alice!a@example.com JOIN {}", CHANNEL))
+        .await;
+    irc.send(":bob!b@example.com QUIT :irc.example.com hub.example.com")
+        .await;
+    irc.send(&format!(":bob!b@example.com JOIN {}", CHANNEL))
+        .await;
+    irc.refute("PRIVMSG").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn remembers_who_split() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    for nick in ["alice", "bob", "carol"] {
+        irc.send(&format!(":{0}!{0}@example.com JOIN {1}", nick, CHANNEL))
+            .await;
+    }
+    for nick in ["bob", "carol"] {
+        irc.send(&format!(":{0}!{0}@example.com QUIT :*.net *.split", nick))
+            .await;
+    }
+    irc.privmsg("alice", CHANNEL, "!seen carol").await;
+    let reply = irc.expect(&format!("PRIVMSG {} :", CHANNEL)).await;
+    assert!(reply.ends_with("quitting (*.net *.split)"), "{}", reply);
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn reconnects_after_the_server_hangs_up() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    drop(irc);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn keeps_trying_until_it_gets_in() {
+    let server = Server::bind().await;
+    let bot = start(&server);
+
+    for _ in 0..3 {
+        let mut irc = server.accept().await;
+        irc.expect("USER ").await;
+        irc.send(":irc.test ERROR :Closing link (Too many connections)")
+            .await;
+    }
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn quits_on_shutdown() {
+    let server = Server::bind().await;
+    let config = server.config();
+    let quit = format!("QUIT :{}", config.quit_message);
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+    irc.register().await;
+
+    assert_eq!(bot.stop(irc).await, quit);
+}
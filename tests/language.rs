@@ -0,0 +1,51 @@
+use pickles::language::detect;
+use pickles::language::reply_in;
+
+#[test]
+fn detects_languages() {
+    assert_eq!(detect("what is the weather like today?"), Some("English"));
+    assert_eq!(
+        detect("¿qué tal? ¿cómo está el tiempo hoy?"),
+        Some("Spanish")
+    );
+    assert_eq!(detect("est-ce que tu peux m'aider avec ça"), Some("French"));
+    assert_eq!(detect("Wie ist das Wetter heute bei dir?"), Some("German"));
+    assert_eq!(detect("ciao, come stai? tutto bene?"), Some("Italian"));
+    assert_eq!(detect("olá, você está bem? tudo certo"), Some("Portuguese"));
+    assert_eq!(detect("hoe gaat het met je vandaag"), Some("Dutch"));
+    assert_eq!(detect("hej, hur mår du? jag är trött"), Some("Swedish"));
+    assert_eq!(detect("cześć, jak się masz?"), Some("Polish"));
+    assert_eq!(detect("merhaba, nasılsın? çok iyi"), Some("Turkish"));
+    assert_eq!(detect("привет, как дела?"), Some("Russian"));
+    assert_eq!(detect("привіт, як справи? Їжак"), Some("Ukrainian"));
+    assert_eq!(detect("こんにちは、元気ですか"), Some("Japanese"));
+    assert_eq!(detect("你好，你今天怎么样"), Some("Chinese"));
+    assert_eq!(detect("안녕하세요"), Some("Korean"));
+    assert_eq!(detect("Καλημέρα"), Some("Greek"));
+    assert_eq!(detect("مرحبا كيف حالك"), Some("Arabic"));
+}
+
+#[test]
+fn does_not_guess() {
+    assert_eq!(detect("lol"), None);
+    assert_eq!(detect("https://example.com 42"), None);
+    assert_eq!(detect(""), None);
+}
+
+#[test]
+fn falls_back_on_the_default() {
+    assert_eq!(reply_in("what is the time?", true, None), None);
+    assert_eq!(
+        reply_in("what is the time?", true, Some("German")).as_deref(),
+        Some("English")
+    );
+    assert_eq!(
+        reply_in("lol", true, Some("German")).as_deref(),
+        Some("German")
+    );
+    assert_eq!(
+        reply_in("¿qué hora es?", false, Some("German")).as_deref(),
+        Some("German")
+    );
+    assert_eq!(reply_in("¿qué hora es?", false, None), None);
+}
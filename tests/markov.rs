@@ -0,0 +1,62 @@
+use pickles::config::MarkovConfig;
+use pickles::markov::parse_line;
+use pickles::markov::Chain;
+use pickles::markov::Markov;
+
+#[test]
+fn reads_common_log_formats() {
+    assert_eq!(
+        parse_line("[2024-05-01 12:00:00] <alice> hello there"),
+        Some(("alice", "hello there"))
+    );
+    assert_eq!(
+        parse_line("12:00 < @bob> ops say hi"),
+        Some(("bob", "ops say hi"))
+    );
+    assert_eq!(
+        parse_line("2024-05-01 12:00:00\t+carol\tvoiced and tabbed"),
+        Some(("carol", "voiced and tabbed"))
+    );
+    assert_eq!(parse_line("2024-05-01 12:00:00\t-->\tdave joined"), None);
+    assert_eq!(parse_line("12:00 -!- dave has joined #pickles"), None);
+    assert_eq!(parse_line("12:00  * erin waves"), None);
+}
+
+#[test]
+fn learns_how_lines_go() {
+    let chain = Chain::train(["the cat sat on the mat", "", "the dog sat on the rug"], 2);
+    assert_eq!(chain.lines(), 2);
+}
+
+#[test]
+fn only_says_something_new() {
+    let model =
+        std::env::temp_dir().join(format!("pickles-markov-test-{}.json", std::process::id()));
+    let chain = Chain::train(["the cat sat on the mat", "the dog sat on the rug"], 2);
+    std::fs::write(
+        &model,
+        serde_json::to_string(&chain).expect("Chain serializes"),
+    )
+    .expect("Unable to write the chain");
+
+    let markov = Markov::load(&MarkovConfig {
+        model: model.clone(),
+        min_lines: 2,
+        ..Default::default()
+    })
+    .expect("Unable to load the chain");
+    for _ in 0..20 {
+        let reply = markov.reply("cat").expect("Nothing to say");
+        assert!(["the cat sat on the rug", "the dog sat on the mat"].contains(&reply.as_str()));
+    }
+
+    // Too few lines to be worth listening to
+    let thin = Markov::load(&MarkovConfig {
+        model: model.clone(),
+        ..Default::default()
+    })
+    .expect("Unable to load the chain");
+    assert_eq!(thin.reply("cat"), None);
+
+    let _ = std::fs::remove_file(model);
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::time;
+use tokio::time::Duration;
+
+use std::sync::Arc;
+
+use pickles::config::Config;
+use pickles::config::NetworkConfig;
+use pickles::config::ProxyConfig;
+use pickles::llm::openai::OpenAI;
+use pickles::proxy;
+use pickles::tools::Tools;
+
+use common::Bot;
+use common::Server;
+use common::TIMEOUT;
+
+const ONION: &str = "ircexample.onion";
+
+/// Plays a SOCKS5 proxy that wants a password, sends back the name and port it was asked to
+/// connect to, then connects to `port` on localhost instead.
+async fn socks5(listener: TcpListener, port: u16, asked: oneshot::Sender<(String, u16)>) {
+    let (mut client, _) = listener.accept().await.expect("Unable to accept");
+
+    let mut greeting = [0; 2];
+    client.read_exact(&mut greeting).await.expect("No greeting");
+    let mut methods = vec![0; usize::from(greeting[1])];
+    client.read_exact(&mut methods).await.expect("No methods");
+    assert!(methods.contains(&0x02), "Offered {:?}", methods);
+    client
+        .write_all(&[0x05, 0x02])
+        .await
+        .expect("Unable to reply");
+
+    let mut login = [0; 2];
+    client.read_exact(&mut login).await.expect("No login");
+    let mut username = vec![0; usize::from(login[1])];
+    client.read_exact(&mut username).await.expect("No username");
+    let len = client.read_u8().await.expect("No password");
+    let mut password = vec![0; usize::from(len)];
+    client.read_exact(&mut password).await.expect("No password");
+    assert_eq!(
+        (&username[..], &password[..]),
+        (&b"tor"[..], &b"circuit"[..])
+    );
+    client
+        .write_all(&[0x01, 0x00])
+        .await
+        .expect("Unable to reply");
+
+    let mut request = [0; 5];
+    client.read_exact(&mut request).await.expect("No request");
+    assert_eq!(request[..4], [0x05, 0x01, 0x00, 0x03]);
+    let mut host = vec![0; usize::from(request[4])];
+    client.read_exact(&mut host).await.expect("No host");
+    let asked_port = client.read_u16().await.expect("No port");
+    let _ = asked.send((String::from_utf8(host).expect("Not a name"), asked_port));
+    client
+        .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0, 0])
+        .await
+        .expect("Unable to reply");
+
+    let mut server = TcpStream::connect(("127.0.0.1", port))
+        .await
+        .expect("Unable to reach the server");
+    let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+}
+
+#[tokio::test]
+async fn connects_through_a_socks5_proxy() {
+    let server = Server::bind().await;
+    let proxy = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let proxy_port = proxy.local_addr().expect("Not listening").port();
+    let network = server.network();
+    let (asked, asked_rx) = oneshot::channel();
+    tokio::spawn(socks5(proxy, network.port, asked));
+
+    let config = Config {
+        networks: vec![NetworkConfig {
+            server: String::from(ONION),
+            port: 6667,
+            proxy: Some(ProxyConfig {
+                host: String::from("127.0.0.1"),
+                port: proxy_port,
+                username: Some(String::from("tor")),
+                password: Some(String::from("circuit")),
+            }),
+            ..network
+        }],
+        ..Config::default()
+    };
+    let backend = Arc::new(OpenAI::new(config.openai.clone(), Tools::new()));
+    let bot = Bot::start(config, backend);
+    let mut irc = server.accept().await;
+
+    irc.register().await;
+    assert_eq!(
+        asked_rx.await.expect("The proxy was never asked"),
+        (String::from(ONION), 6667)
+    );
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn turns_away_strangers() {
+    let proxy = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let mut network = NetworkConfig {
+        server: String::from(ONION),
+        port: 6697,
+        proxy: Some(ProxyConfig {
+            host: String::from("127.0.0.1"),
+            port: proxy.local_addr().expect("Not listening").port(),
+            username: None,
+            password: None,
+        }),
+        ..NetworkConfig::default()
+    };
+    proxy::open(&mut network)
+        .await
+        .expect("Unable to start the relay");
+
+    let mut stranger = TcpStream::connect(("127.0.0.1", network.port))
+        .await
+        .expect("Unable to connect");
+    stranger
+        .write_all(b"PASS guess\r\nNICK mallory\r\n")
+        .await
+        .expect("Unable to send");
+    let mut heard = Vec::new();
+    time::timeout(TIMEOUT, stranger.read_to_end(&mut heard))
+        .await
+        .expect("Still connected")
+        .expect("Unable to read");
+    assert!(heard.is_empty());
+    // Never got as far as the proxy
+    assert!(time::timeout(Duration::from_millis(200), proxy.accept())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn keeps_the_api_relay_to_itself() {
+    let proxy = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let url = proxy::http_relay(&format!(
+        "socks5h://127.0.0.1:{}",
+        proxy.local_addr().expect("Not listening").port()
+    ))
+    .expect("Unable to start the relay");
+    let relay = url.rsplit_once('@').expect("No credentials").1;
+
+    let mut stranger = TcpStream::connect(relay).await.expect("Unable to connect");
+    stranger
+        .write_all(
+            b"CONNECT api.openai.com:443 HTTP/1.1\r\nProxy-Authorization: Basic Z3Vlc3M=\r\n\r\n",
+        )
+        .await
+        .expect("Unable to send");
+    let mut heard = String::new();
+    time::timeout(TIMEOUT, stranger.read_to_string(&mut heard))
+        .await
+        .expect("Still connected")
+        .expect("Unable to read");
+    assert!(heard.starts_with("HTTP/1.1 407 "), "{:?}", heard);
+    assert!(time::timeout(Duration::from_millis(200), proxy.accept())
+        .await
+        .is_err());
+}
@@ -0,0 +1,160 @@
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufStream;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time;
+
+use pickles::storage::redis::Redis;
+use pickles::storage::KarmaStore;
+
+const TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// Plays a Redis server. Each connection gets the next script, answering each command it's
+/// sent with the next raw reply, and stops answering once the script runs out. Every command
+/// is passed on to the test as its words joined with spaces.
+async fn fake(scripts: Vec<Vec<&'static str>>) -> (String, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let port = listener.local_addr().expect("Not listening").port();
+    let (commands_tx, commands) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for script in scripts {
+            let (socket, _) = listener.accept().await.expect("Unable to accept");
+            tokio::spawn(serve(socket, script, commands_tx.clone()));
+        }
+    });
+
+    (format!("redis://127.0.0.1:{}", port), commands)
+}
+
+async fn serve(
+    socket: TcpStream,
+    script: Vec<&'static str>,
+    commands: mpsc::UnboundedSender<String>,
+) {
+    let mut stream = BufStream::new(socket);
+    let mut replies = script.into_iter();
+    while let Some(command) = read_command(&mut stream).await {
+        let _ = commands.send(command);
+        let Some(reply) = replies.next() else {
+            std::future::pending::<()>().await;
+            return;
+        };
+        stream
+            .write_all(reply.as_bytes())
+            .await
+            .expect("Unable to reply");
+        stream.flush().await.expect("Unable to reply");
+    }
+}
+
+async fn read_command(stream: &mut BufStream<TcpStream>) -> Option<String> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let count = line.trim_end().strip_prefix('*')?.parse::<usize>().ok()?;
+    let mut words = Vec::new();
+    for _ in 0..count {
+        line.clear();
+        stream.read_line(&mut line).await.ok()?;
+        let len = line.trim_end().strip_prefix('$')?.parse::<usize>().ok()?;
+        let mut word = vec![0; len + 2];
+        stream.read_exact(&mut word).await.ok()?;
+        word.truncate(len);
+        words.push(String::from_utf8(word).ok()?);
+    }
+
+    Some(words.join(" "))
+}
+
+#[tokio::test]
+async fn reads_arrays_of_strings() {
+    let (url, mut commands) = fake(vec![vec![
+        "*2\r\n$5\r\nbob++\r\n$5\r\nBob++\r\n",
+        "*4\r\n$5\r\nbob++\r\n$1\r\n3\r\n$5\r\nalice\r\n$2\r\n-1\r\n",
+    ]])
+    .await;
+    let redis = Redis::connect(&url).await.expect("Unable to connect");
+
+    let mut karma = redis.karma("test").await.expect("No karma");
+    karma.sort();
+    assert_eq!(
+        karma,
+        [(String::from("Bob++"), 3), (String::from("alice"), -1)]
+    );
+    assert_eq!(
+        commands.recv().await.as_deref(),
+        Some("HGETALL pickles:test:karma_names")
+    );
+}
+
+#[tokio::test]
+async fn runs_transactions() {
+    let (url, mut commands) = fake(vec![vec![
+        "+OK\r\n",
+        "+QUEUED\r\n",
+        "+QUEUED\r\n",
+        "*2\r\n:1\r\n:5\r\n",
+    ]])
+    .await;
+    let redis = Redis::connect(&url).await.expect("Unable to connect");
+
+    redis
+        .add_karma("test", "Bob", 1)
+        .await
+        .expect("Karma wasn't added");
+    let mut sent = Vec::new();
+    for _ in 0..4 {
+        sent.push(commands.recv().await.expect("Nothing sent"));
+    }
+    assert_eq!(
+        sent,
+        [
+            "MULTI",
+            "HSETNX pickles:test:karma_names bob Bob",
+            "HINCRBY pickles:test:karma bob 1",
+            "EXEC",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reconnects_after_a_bad_reply() {
+    let (url, _commands) = fake(vec![
+        vec!["-ERR wrong kind of value\r\n"],
+        vec!["\r\n"],
+        vec!["*0\r\n", "*0\r\n"],
+    ])
+    .await;
+    let redis = Redis::connect(&url).await.expect("Unable to connect");
+
+    // Each error starts over on a new connection
+    assert!(redis.karma("test").await.is_err());
+    assert!(redis.karma("test").await.is_err());
+    assert_eq!(redis.karma("test").await.expect("No karma"), []);
+}
+
+#[tokio::test]
+async fn never_hands_on_a_reply_it_was_cancelled_waiting_for() {
+    let (url, _commands) = fake(vec![
+        vec![],
+        vec![
+            "*2\r\n$3\r\nbob\r\n$3\r\nBob\r\n",
+            "*2\r\n$3\r\nbob\r\n$1\r\n2\r\n",
+        ],
+    ])
+    .await;
+    let redis = Redis::connect(&url).await.expect("Unable to connect");
+
+    // The first connection never answers, so this gives up part way through
+    let cancelled = time::timeout(time::Duration::from_millis(200), redis.karma("test")).await;
+    assert!(cancelled.is_err());
+    let karma = time::timeout(TIMEOUT, redis.karma("test"))
+        .await
+        .expect("Stuck on the old connection")
+        .expect("No karma");
+    assert_eq!(karma, [(String::from("Bob"), 2)]);
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::net::TcpListener;
+use tokio::time;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use pickles::config::SlackConfig;
+use pickles::gateway::slack::Slack;
+use pickles::gateway::ChatNetwork;
+use pickles::gateway::Event;
+use pickles::gateway::Kind;
+use pickles::gateway::User;
+
+use common::websocket::Socket;
+use common::TIMEOUT;
+
+const APP_TOKEN: &str = "xapp-1";
+const BOT_TOKEN: &str = "xoxb-1";
+const OURS: &str = "U99";
+
+/// A Slack app talking to a fake of as much of the Web API as connecting, looking people up
+/// and finding channels by ID takes, and the Socket Mode WebSocket it points to.
+async fn slack() -> (Slack, TcpListener) {
+    let socket = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let url = format!("ws://{}/link", socket.local_addr().expect("Not listening"));
+    let app = Router::new()
+        .route("/:method", post(api))
+        .with_state(Arc::new(url));
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let api_base = format!("http://{}", listener.local_addr().expect("Not listening"));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let config = SlackConfig {
+        app_token: String::from(APP_TOKEN),
+        bot_token: String::from(BOT_TOKEN),
+        api_base: Some(api_base),
+    };
+    let slack = Slack::new(&config).expect("Unable to make a Slack app");
+
+    (slack, socket)
+}
+
+async fn api(
+    State(url): State<Arc<String>>,
+    Path(method): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Json<Value> {
+    let token = match method.as_str() {
+        "apps.connections.open" => APP_TOKEN,
+        _ => BOT_TOKEN,
+    };
+    assert_eq!(
+        headers["authorization"],
+        format!("Bearer {}", token).as_str()
+    );
+    // Nothing asked for here needs decoding
+    let params: HashMap<&str, &str> = body
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect();
+
+    Json(match method.as_str() {
+        "auth.test" => json!({ "ok": true, "user_id": OURS, "user": "pickles", "team": "Test" }),
+        "apps.connections.open" => json!({ "ok": true, "url": *url }),
+        "users.info" => {
+            let name = match params["user"] {
+                "U1" => "alice",
+                "U2" => "bob",
+                user => panic!("Didn't expect to look up {}", user),
+            };
+            json!({ "ok": true, "user": { "name": name } })
+        }
+        "conversations.info" => {
+            json!({ "ok": true, "channel": { "id": params["channel"], "is_member": true } })
+        }
+        method => panic!("Didn't expect {}", method),
+    })
+}
+
+/// Plays Socket Mode, sending each of `events` in an envelope of its own.
+async fn play(socket: TcpListener, events: Vec<Value>) {
+    let mut socket = Socket::accept(&socket).await;
+    for (id, event) in events.into_iter().enumerate() {
+        let envelope = json!({
+            "envelope_id": id.to_string(),
+            "type": "events_api",
+            "payload": { "event": event },
+        });
+        socket.send(&envelope.to_string()).await;
+    }
+    // Acknowledgements, until the test is done
+    while socket.receive().await.is_some() {}
+}
+
+async fn receive(slack: &Slack) -> Event {
+    let mut events = time::timeout(TIMEOUT, slack.receive())
+        .await
+        .expect("Nothing happened")
+        .expect("Unable to receive");
+    assert_eq!(events.len(), 1, "{:?}", events);
+    events.remove(0)
+}
+
+fn user(nick: &str, id: &str) -> User {
+    User {
+        nick: nick.to_string(),
+        user: id.to_string(),
+        host: String::from("slack"),
+    }
+}
+
+#[tokio::test]
+async fn turns_messages_into_events() {
+    let (slack, socket) = slack().await;
+    slack.join("#C1").await.expect("Unable to join");
+    let events = vec![
+        json!({
+            "type": "message",
+            "user": "U1",
+            "channel": "C1",
+            "channel_type": "channel",
+            "text": "<@U99> does <@U2> like <#C1|general>? <!here> see \
+                     <https://example.org|this> &amp; &lt;that&gt;",
+        }),
+        // Our own, which we don't need to hear about
+        json!({ "type": "message", "user": OURS, "channel": "C1", "text": "hello" }),
+        // An edit
+        json!({
+            "type": "message",
+            "subtype": "message_changed",
+            "user": "U1",
+            "channel": "C1",
+            "text": "hi",
+        }),
+        // A channel we aren't in
+        json!({ "type": "message", "user": "U1", "channel": "C2", "text": "over here" }),
+        json!({
+            "type": "message",
+            "user": "U2",
+            "channel": "D1",
+            "channel_type": "im",
+            "text": "psst",
+        }),
+    ];
+    tokio::spawn(play(socket, events));
+
+    assert_eq!(
+        receive(&slack).await,
+        Event::Message {
+            from: user("alice", "U1"),
+            channel: Some(String::from("#C1")),
+            text: String::from(" does @bob like #general? @here see https://example.org & <that>"),
+            kind: Kind::Message,
+            mentioned: true,
+        }
+    );
+    assert_eq!(
+        receive(&slack).await,
+        Event::Message {
+            from: user("bob", "U2"),
+            channel: None,
+            text: String::from("psst"),
+            kind: Kind::Message,
+            mentioned: false,
+        }
+    );
+}
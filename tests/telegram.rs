@@ -0,0 +1,166 @@
+mod common;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::net::TcpListener;
+use tokio::time;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use pickles::config::TelegramConfig;
+use pickles::gateway::telegram::Telegram;
+use pickles::gateway::ChatNetwork;
+use pickles::gateway::Event;
+use pickles::gateway::Kind;
+use pickles::gateway::User;
+
+use common::TIMEOUT;
+
+const TOKEN: &str = "123456:ABC";
+const OURS: i64 = 99;
+const GROUP: i64 = -100;
+
+type Updates = Arc<Mutex<VecDeque<Value>>>;
+
+/// A Telegram bot talking to a fake Bot API, which answers each poll with the next of
+/// `updates`, and nothing once they run out.
+async fn telegram(updates: Vec<Value>) -> Telegram {
+    let updates: Updates = Arc::new(Mutex::new(updates.into()));
+    let app = Router::new()
+        .route("/:bot/:method", post(api))
+        .with_state(updates);
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let api_base = format!("http://{}", listener.local_addr().expect("Not listening"));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let config = TelegramConfig {
+        token: String::from(TOKEN),
+        api_base: Some(api_base),
+    };
+    Telegram::new(&config).expect("Unable to make a Telegram bot")
+}
+
+async fn api(
+    State(updates): State<Updates>,
+    Path((bot, method)): Path<(String, String)>,
+) -> Json<Value> {
+    assert_eq!(bot, format!("bot{}", TOKEN));
+    let result = match method.as_str() {
+        "getMe" => json!({ "id": OURS, "username": "pickles_bot" }),
+        "getChat" => json!({ "id": GROUP }),
+        "getUpdates" => {
+            let next = updates.lock().expect("updates lock poisoned").pop_front();
+            next.unwrap_or_else(|| json!([]))
+        }
+        method => panic!("Didn't expect {}", method),
+    };
+
+    Json(json!({ "ok": true, "result": result }))
+}
+
+/// What's in the next poll.
+async fn receive(telegram: &Telegram) -> Vec<Event> {
+    time::timeout(TIMEOUT, telegram.receive())
+        .await
+        .expect("Nothing happened")
+        .expect("Unable to receive")
+}
+
+fn update(id: i64, message: Value) -> Value {
+    json!({ "update_id": id, "message": message })
+}
+
+fn user(nick: &str, id: i64) -> User {
+    User {
+        nick: nick.to_string(),
+        user: id.to_string(),
+        host: String::from("telegram"),
+    }
+}
+
+#[tokio::test]
+async fn turns_messages_into_events() {
+    let alice = json!({ "id": 1, "username": "alice", "first_name": "Alice" });
+    let bob = json!({ "id": 2, "first_name": "Bob  Smith" });
+    let group = json!({ "id": GROUP, "type": "supergroup" });
+    let updates = vec![
+        // From before we started, so not answered
+        json!([update(
+            1,
+            json!({ "from": alice, "chat": group, "text": "anyone?" })
+        )]),
+        json!([
+            update(
+                2,
+                json!({ "from": alice, "chat": group, "text": "@Pickles_Bot hi" })
+            ),
+            update(
+                3,
+                json!({
+                    "from": bob,
+                    "chat": group,
+                    "caption": "thanks",
+                    "reply_to_message": { "from": { "id": OURS } },
+                }),
+            ),
+            update(
+                4,
+                json!({ "from": alice, "chat": { "id": 1, "type": "private" }, "text": "psst" }),
+            ),
+            // A group we aren't in
+            update(
+                5,
+                json!({
+                    "from": bob,
+                    "chat": { "id": -200, "type": "group" },
+                    "text": "over here",
+                }),
+            ),
+            update(
+                6,
+                json!({
+                    "from": bob,
+                    "chat": { "id": -300, "type": "supergroup", "username": "fans" },
+                    "new_chat_members": [{ "id": OURS }],
+                }),
+            ),
+        ]),
+    ];
+    let telegram = telegram(updates).await;
+    telegram
+        .join(&format!("#{}", GROUP))
+        .await
+        .expect("Unable to join");
+
+    assert_eq!(receive(&telegram).await, []);
+    let message = |from, channel: Option<&str>, text: &str, mentioned| Event::Message {
+        from,
+        channel: channel.map(str::to_string),
+        text: text.to_string(),
+        kind: Kind::Message,
+        mentioned,
+    };
+    assert_eq!(
+        receive(&telegram).await,
+        [
+            message(user("alice", 1), Some("#-100"), " hi", true),
+            message(user("BobSmith", 2), Some("#-100"), "thanks", true),
+            message(user("alice", 1), None, "psst", false),
+            Event::Invite {
+                from: user("BobSmith", 2),
+                channel: String::from("#fans"),
+            },
+        ]
+    );
+}
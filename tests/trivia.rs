@@ -0,0 +1,30 @@
+use pickles::trivia::hint;
+use pickles::trivia::is_right;
+
+#[test]
+fn forgives_small_mistakes() {
+    assert!(is_right("the beatles", "The Beatles"));
+    assert!(is_right("Beatles!", "The Beatles"));
+    assert!(is_right("beatels", "The Beatles"));
+    assert!(is_right("is it paris?", "Paris"));
+    assert!(is_right("enders game", "Ender's Game"));
+    assert!(is_right("Leonardo da Vnci", "Leonardo da Vinci"));
+    assert!(is_right("it was 1969", "1969"));
+}
+
+#[test]
+fn does_not_give_points_away() {
+    assert!(!is_right("rome", "Paris"));
+    assert!(!is_right("mars", "Mass"));
+    assert!(!is_right("1968", "1969"));
+    assert!(!is_right("paris london rome berlin madrid", "Paris"));
+    assert!(!is_right("the", "The Who"));
+}
+
+#[test]
+fn hints_fill_in_the_answer() {
+    assert_eq!(hint("Ender's Game", 1, 3), "_____'_ ____");
+    assert_eq!(hint("Ender's Game", 2, 3), "En___'_ Ga__");
+    assert_eq!(hint("Ender's Game", 3, 3), "Ende_'_ Gam_");
+    assert_eq!(hint("Paris", 1, 0), "_____");
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use reqwest::header;
+use reqwest::StatusCode;
+
+use serde_json::json;
+use serde_json::Value;
+
+use tokio::net::TcpListener;
+
+use std::sync::Arc;
+
+use pickles::config::Config;
+use pickles::config::RateLimitConfig;
+use pickles::config::WebhookConfig;
+use pickles::webhook;
+
+use common::mock::Scripted;
+use common::Bot;
+use common::Server;
+use common::CHANNEL;
+
+const TOKEN: &str = "c0ffee";
+
+fn hook(network: &str, template: &str) -> WebhookConfig {
+    WebhookConfig {
+        token: String::from(TOKEN),
+        network: network.to_string(),
+        channel: String::from(CHANNEL),
+        template: template.to_string(),
+        rate_limit: RateLimitConfig::default(),
+    }
+}
+
+/// Serves `webhooks` and returns where.
+async fn serve(webhooks: &[WebhookConfig]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let url = format!(
+        "http://{}/webhook",
+        listener.local_addr().expect("Not listening")
+    );
+    let app = webhook::routes(webhooks);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    url
+}
+
+async fn post(url: &str, token: &str, payload: &Value) -> StatusCode {
+    reqwest::Client::new()
+        .post(format!("{}/{}", url, token))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .expect("Unable to reach the webhook")
+        .status()
+}
+
+#[test]
+fn finds_fields_by_path() {
+    let payload = json!({
+        "repository": { "name": "pickles" },
+        "commits": [{ "message": "first" }, { "message": "second" }],
+    });
+
+    assert_eq!(
+        webhook::field(&payload, "repository.name"),
+        Some(&json!("pickles"))
+    );
+    assert_eq!(
+        webhook::field(&payload, "commits.1.message"),
+        Some(&json!("second"))
+    );
+    assert_eq!(webhook::field(&payload, "commits.2.message"), None);
+    assert_eq!(webhook::field(&payload, "commits.first"), None);
+    assert_eq!(webhook::field(&payload, "repository.owner"), None);
+}
+
+#[tokio::test]
+async fn fills_in_the_template() {
+    let server = Server::bind().await;
+    let mut network = server.network();
+    // Every bot in the process shares the networks webhooks can reach
+    network.name = String::from("webhook-fill");
+    let config = Config {
+        networks: vec![network],
+        ..Config::default()
+    };
+    let bot = Bot::start(config, Arc::new(Scripted::new()));
+    let mut irc = server.accept().await;
+    irc.register().await;
+    let url = serve(&[hook(
+        "webhook-fill",
+        "{pusher.name} pushed {commits.0.message} to {repository.name} ({forced}){missing}",
+    )])
+    .await;
+
+    let payload = json!({
+        "pusher": { "name": "alice" },
+        "commits": [{ "message": "Fix it\n\nPRIVMSG #test :gotcha" }],
+        "repository": { "name": "pickles" },
+        "forced": false,
+    });
+    assert_eq!(post(&url, TOKEN, &payload).await, StatusCode::ACCEPTED);
+    assert_eq!(
+        irc.expect("PRIVMSG").await,
+        format!(
+            "PRIVMSG {} :alice pushed Fix it PRIVMSG #test :gotcha to pickles (false)",
+            CHANNEL
+        )
+    );
+    irc.refute("PRIVMSG").await;
+
+    bot.stop(irc).await;
+}
+
+#[tokio::test]
+async fn turns_away_unknown_tokens() {
+    let url = serve(&[hook("webhook-unknown", "{text}")]).await;
+
+    let status = post(&url, "guess", &json!({ "text": "hi" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn turns_away_floods() {
+    let url = serve(&[WebhookConfig {
+        rate_limit: RateLimitConfig {
+            requests: 1,
+            per_secs: 60,
+        },
+        ..hook("webhook-flood", "{text}")
+    }])
+    .await;
+    let payload = json!({ "text": "hi" });
+
+    // No such network, but it still counts
+    assert_eq!(
+        post(&url, TOKEN, &payload).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(
+        post(&url, TOKEN, &payload).await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
@@ -0,0 +1,31 @@
+use chrono::NaiveDate;
+
+use std::collections::HashSet;
+
+use pickles::word_games::daily_word;
+use pickles::word_games::marks;
+
+#[test]
+fn marks_letters_like_wordle() {
+    assert_eq!(marks("crane", "crane"), "🟩🟩🟩🟩🟩");
+    assert_eq!(marks("nacre", "crane"), "🟨🟨🟨🟨🟩");
+    assert_eq!(marks("fuzzy", "crane"), "⬛⬛⬛⬛⬛");
+    // Only as many yellows as the word has of a letter, and greens come first
+    assert_eq!(marks("eerie", "crane"), "⬛⬛🟨⬛🟩");
+    assert_eq!(marks("speed", "abide"), "⬛⬛🟨⬛🟨");
+    assert_eq!(marks("llama", "hello"), "🟨🟨⬛⬛⬛");
+}
+
+#[test]
+fn has_a_different_word_every_day() {
+    let first = NaiveDate::from_ymd_opt(2024, 1, 1).expect("Invalid date");
+    assert_eq!(daily_word(first).0, 1);
+
+    let words = first
+        .iter_days()
+        .take(365)
+        .map(|day| daily_word(day).1)
+        .collect::<Vec<_>>();
+    assert!(words.iter().all(|word| word.len() == 5));
+    assert_eq!(words.iter().collect::<HashSet<_>>().len(), 365);
+}
@@ -0,0 +1,46 @@
+use pickles::gateway::xml::Element;
+use pickles::gateway::xml::Item;
+use pickles::gateway::xml::Reader;
+use pickles::Error;
+
+async fn parse(xml: &str) -> Result<Element, Error> {
+    match Reader::default().next(&mut xml.as_bytes()).await? {
+        Item::Element(element) => Ok(element),
+        _ => panic!("{:?} isn't an element", xml),
+    }
+}
+
+#[tokio::test]
+async fn reads_back_what_it_writes() {
+    let awkward = r#"<b>Tom & "Jerry"</b> it's 5 > 3 &amp; ü"#;
+    let written = Element::new("message")
+        .attr("to", awkward)
+        .child(Element::new("body").text(awkward))
+        .to_string();
+    assert!(!written.contains(awkward), "{}", written);
+
+    let read = parse(&written).await.expect("Unable to read it back");
+    assert_eq!(read.get("to"), Some(awkward));
+    let body = read.find("body").expect("No body");
+    assert_eq!(body.content(), awkward);
+    assert_eq!(read.to_string(), written);
+}
+
+#[tokio::test]
+async fn reads_character_references() {
+    let read = parse("<body>&#65;&#x1F952; &amp;amp; <![CDATA[<&>]]></body>")
+        .await
+        .expect("Unable to read");
+    assert_eq!(read.content(), "A\u{1F952} &amp; <&>");
+}
+
+#[tokio::test]
+async fn refuses_entities_it_does_not_know() {
+    for xml in [
+        "<body>&nbsp;</body>",
+        "<body>&#xD800;</body>",
+        "<body a='&amp'/>",
+    ] {
+        assert!(parse(xml).await.is_err(), "{}", xml);
+    }
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::time;
+
+use pickles::config::XmppConfig;
+use pickles::gateway::xml::Element;
+use pickles::gateway::xml::Item;
+use pickles::gateway::xml::Reader;
+use pickles::gateway::xmpp::Xmpp;
+use pickles::gateway::ChatNetwork;
+use pickles::gateway::Event;
+use pickles::gateway::Kind;
+use pickles::gateway::User;
+
+use common::TIMEOUT;
+
+const ROOM: &str = "room@conference.example.org";
+
+const HEADER: &str = "<?xml version='1.0'?><stream:stream from='example.org' version='1.0' \
+                      xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>";
+
+/// The server's end of pickles' connection.
+struct Client {
+    socket: TcpStream,
+    reader: Reader,
+}
+
+impl Client {
+    async fn next(&mut self) -> Item {
+        time::timeout(TIMEOUT, self.reader.next(&mut self.socket))
+            .await
+            .expect("pickles didn't send anything")
+            .expect("pickles sent something that isn't XML")
+    }
+
+    async fn element(&mut self, name: &str) -> Element {
+        match self.next().await {
+            Item::Element(element) if element.name == name => element,
+            Item::Element(element) => panic!("Expected <{}>, got {}", name, element),
+            _ => panic!("Expected <{}>", name),
+        }
+    }
+
+    async fn opened(&mut self) {
+        assert!(matches!(self.next().await, Item::Opened));
+    }
+
+    async fn send(&mut self, xml: &str) {
+        self.socket
+            .write_all(xml.as_bytes())
+            .await
+            .expect("Unable to send");
+    }
+}
+
+/// Plays an XMPP server on localhost, where TLS isn't needed: logs pickles in as
+/// `pickles@example.org` with the password `hunter2`, then sends each of `stanzas`.
+async fn serve(listener: TcpListener, stanzas: Vec<&'static str>) {
+    let (socket, _) = listener.accept().await.expect("Unable to accept");
+    let mut client = Client {
+        socket,
+        reader: Reader::default(),
+    };
+
+    client.opened().await;
+    client
+        .send(&format!(
+            "{}<stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+             <mechanism>PLAIN</mechanism></mechanisms></stream:features>",
+            HEADER
+        ))
+        .await;
+    let auth = client.element("auth").await;
+    assert_eq!(auth.content(), BASE64.encode("\0pickles\0hunter2"));
+    client
+        .send("<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>")
+        .await;
+    client.opened().await;
+    client
+        .send(&format!(
+            "{}<stream:features><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/>\
+             </stream:features>",
+            HEADER
+        ))
+        .await;
+    let bind = client.element("iq").await;
+    client
+        .send(&format!(
+            "<iq type='result' id='{}'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+             <jid>pickles@example.org/pickles</jid></bind></iq>",
+            bind.get("id").unwrap_or_default()
+        ))
+        .await;
+
+    for stanza in stanzas {
+        client.send(stanza).await;
+    }
+    // Presences and the like, until the test is done
+    while !matches!(client.next().await, Item::Closed) {}
+}
+
+async fn receive(xmpp: &Xmpp) -> Event {
+    let mut events = time::timeout(TIMEOUT, xmpp.receive())
+        .await
+        .expect("Nothing happened")
+        .expect("Unable to receive");
+    assert_eq!(events.len(), 1, "{:?}", events);
+    events.remove(0)
+}
+
+fn user(nick: &str, user: &str, host: &str) -> User {
+    User {
+        nick: nick.to_string(),
+        user: user.to_string(),
+        host: host.to_string(),
+    }
+}
+
+fn message(from: User, channel: Option<&str>, text: &str, kind: Kind) -> Event {
+    Event::Message {
+        from,
+        channel: channel.map(str::to_string),
+        text: text.to_string(),
+        kind,
+        mentioned: false,
+    }
+}
+
+#[tokio::test]
+async fn turns_stanzas_into_events() {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Unable to listen");
+    let config = XmppConfig {
+        jid: String::from("pickles@example.org"),
+        password: String::from("hunter2"),
+        server: Some(String::from("127.0.0.1")),
+        port: listener.local_addr().expect("Not listening").port(),
+    };
+    let stanzas = vec![
+        "<message type='groupchat' from='room@conference.example.org/alice'>\
+         <body>fish &amp; chips?</body></message>",
+        // Ours, the room's own announcements and history
+        "<message type='groupchat' from='room@conference.example.org/pickles'>\
+         <body>hello</body></message>",
+        "<message type='groupchat' from='room@conference.example.org'>\
+         <body>This room is not anonymous</body></message>",
+        "<message type='groupchat' from='room@conference.example.org/bob'><body>old</body>\
+         <delay xmlns='urn:xmpp:delay' stamp='2024-01-01T00:00:00Z'/></message>",
+        // A room we aren't in
+        "<message type='groupchat' from='other@conference.example.org/bob'>\
+         <body>over here</body></message>",
+        "<message type='groupchat' from='room@conference.example.org/bob'>\
+         <body>/me waves</body></message>",
+        "<message type='chat' from='room@conference.example.org/alice'>\
+         <body>psst</body></message>",
+        "<message type='chat' from='carol@example.org/phone'><body>hi</body></message>",
+        "<message type='chat' from='dave@elsewhere.net/laptop'><body>hi</body></message>",
+        "<message from='erin@example.org/laptop'>\
+         <x xmlns='jabber:x:conference' jid='party@conference.example.org'/></message>",
+    ];
+    tokio::spawn(serve(listener, stanzas));
+    let xmpp = Xmpp::new(&config, "pickles").expect("Unable to make an XMPP account");
+    xmpp.join(&format!("#{}", ROOM))
+        .await
+        .expect("Unable to join");
+
+    let channel = format!("#{}", ROOM);
+    let room = Some(channel.as_str());
+    let muc = "conference.example.org";
+    assert_eq!(
+        receive(&xmpp).await,
+        message(
+            user("alice", "alice", muc),
+            room,
+            "fish & chips?",
+            Kind::Message
+        )
+    );
+    assert_eq!(
+        receive(&xmpp).await,
+        message(user("bob", "bob", muc), room, "waves", Kind::Action)
+    );
+    // Talking to us privately from the room, they keep the nick they have there
+    assert_eq!(
+        receive(&xmpp).await,
+        message(user("alice", "alice", muc), None, "psst", Kind::Message)
+    );
+    assert_eq!(
+        receive(&xmpp).await,
+        message(
+            user("carol", "carol", "example.org"),
+            None,
+            "hi",
+            Kind::Message
+        )
+    );
+    assert_eq!(
+        receive(&xmpp).await,
+        message(
+            user("dave:elsewhere.net", "dave", "elsewhere.net"),
+            None,
+            "hi",
+            Kind::Message
+        )
+    );
+    assert_eq!(
+        receive(&xmpp).await,
+        Event::Invite {
+            from: user("erin", "erin", "example.org"),
+            channel: String::from("#party@conference.example.org"),
+        }
+    );
+}